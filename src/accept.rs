@@ -0,0 +1,161 @@
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use netstat2::{AddressFamilyFlags, ProtocolFlags, ProtocolSocketInfo, TcpState};
+
+use crate::{SocketEntry, parse_addr_port, socket_table, zone};
+
+// Incoming load per TCP listener (`--full`): the connections accepted per second,
+// counted as the connections to the listener's port that appear between two reads
+// of the TCP table around the sampling window (in any state, so ones accepted and
+// already closed again as TIME_WAIT count too), and the half-open connections in
+// SYN_RECEIVED at the end of the window where the OS lists them (Linux, Windows).
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AcceptRate {
+    pub per_sec: f64,
+    pub syn_received: u32,
+}
+
+impl AcceptRate {
+    // The ACCEPTS cell: "12.5/s", "12.5/s, 3 syn".
+    pub fn label(&self) -> String {
+        match self.syn_received {
+            0 => format!("{:.1}/s", self.per_sec),
+            n => format!("{:.1}/s, {} syn", self.per_sec, n),
+        }
+    }
+}
+
+type Endpoint = (IpAddr, u16);
+
+// One TCP connection: local and remote endpoints, and whether it is still in the
+// handshake.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Conn {
+    local: Endpoint,
+    remote: Endpoint,
+    half_open: bool,
+}
+
+// The TCP connections open now, without their owners.
+pub fn connections(af_flags: AddressFamilyFlags) -> Result<Vec<Conn>, netstat2::error::Error> {
+    Ok(socket_table(af_flags, ProtocolFlags::TCP)?
+        .into_iter()
+        .filter_map(|s| match s.protocol_socket_info {
+            ProtocolSocketInfo::Tcp(t) if t.state != TcpState::Listen => Some(Conn {
+                local: (t.local_addr, t.local_port),
+                remote: (t.remote_addr, t.remote_port),
+                half_open: t.state == TcpState::SynReceived,
+            }),
+            _ => None,
+        })
+        .collect())
+}
+
+// Fills `accept` of the listener rows from the tables read before and after a window
+// of `elapsed`.
+pub fn fill(entries: &mut [SocketEntry], before: &[Conn], after: &[Conn], elapsed: Duration) {
+    let known: HashSet<(Endpoint, Endpoint)> = before
+        .iter()
+        .filter(|c| !c.half_open)
+        .map(|c| (c.local, c.remote))
+        .collect();
+    let secs = elapsed.as_secs_f64().max(0.001);
+    for e in entries
+        .iter_mut()
+        .filter(|e| e.proto == "TCP" && e.state == "Listen")
+    {
+        let (host, port) = parse_addr_port(&e.local_addr);
+        let Some(ip) = zone::parse_ip(host) else {
+            continue;
+        };
+        // A dual-stack `[::]` listener's IPv4 clients arrive as IPv4-mapped addresses.
+        let serves = |c: &&Conn| {
+            c.local.1 == port
+                && match ip.is_unspecified() {
+                    true => c.local.0.is_ipv4() == ip.is_ipv4(),
+                    false => c.local.0.to_canonical() == ip.to_canonical(),
+                }
+        };
+        let mut accepted = 0;
+        let mut syn_received = 0;
+        for c in after.iter().filter(serves) {
+            if c.half_open {
+                syn_received += 1;
+            } else if !known.contains(&(c.local, c.remote)) {
+                accepted += 1;
+            }
+        }
+        e.accept = Some(AcceptRate {
+            per_sec: accepted as f64 / secs,
+            syn_received,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conn(local: &str, remote: &str, half_open: bool) -> Conn {
+        let endpoint = |v: &str| {
+            let addr: std::net::SocketAddr = v.parse().unwrap();
+            (addr.ip(), addr.port())
+        };
+        Conn {
+            local: endpoint(local),
+            remote: endpoint(remote),
+            half_open,
+        }
+    }
+
+    fn listener(local: &str) -> SocketEntry {
+        SocketEntry {
+            proto: "TCP".to_string(),
+            local_addr: local.to_string(),
+            remote_addr: "0.0.0.0:0".to_string(),
+            state: "Listen".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn new_connections_on_a_listeners_port_count_as_accepted() {
+        let before = [
+            conn("10.0.0.1:443", "203.0.113.5:50000", false),
+            conn("10.0.0.1:443", "203.0.113.6:50000", true),
+        ];
+        let after = [
+            // Already open before the window.
+            conn("10.0.0.1:443", "203.0.113.5:50000", false),
+            // The handshake finished during the window.
+            conn("10.0.0.1:443", "203.0.113.6:50000", false),
+            conn("10.0.0.1:443", "203.0.113.7:50000", false),
+            conn("10.0.0.1:443", "203.0.113.8:50000", true),
+            // Through the dual-stack listener.
+            conn("[::ffff:10.0.0.1]:443", "[::ffff:203.0.113.9]:50000", false),
+            conn("10.0.0.1:22", "203.0.113.5:50001", false),
+            conn("127.0.0.1:8080", "127.0.0.1:50002", false),
+        ];
+        let mut rows = vec![
+            listener("0.0.0.0:443"),
+            listener("10.0.0.1:22"),
+            listener("[::]:443"),
+            listener("127.0.0.1:8080"),
+        ];
+        fill(&mut rows, &before, &after, Duration::from_secs(2));
+        assert_eq!(
+            rows[0].accept,
+            Some(AcceptRate {
+                per_sec: 1.0,
+                syn_received: 1
+            })
+        );
+        assert_eq!(rows[0].accept.unwrap().label(), "1.0/s, 1 syn");
+        assert_eq!(rows[1].accept.unwrap().per_sec, 0.5);
+        assert_eq!(rows[2].accept.unwrap().per_sec, 0.5);
+        assert_eq!(rows[3].accept.unwrap().label(), "0.5/s");
+    }
+}
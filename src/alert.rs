@@ -0,0 +1,353 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::{SocketEntry, json, parse_addr_port};
+
+// Rate-of-change alerts for --watch: a process whose established-connection or
+// listener count moves by at least the threshold between two refreshes is reported
+// on stderr (e.g. a connection leak right after a deploy). The first refresh only
+// records a baseline. The limiter below throttles these and the other alerts.
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Thresholds {
+    pub established: Option<usize>,
+    pub listen: Option<usize>,
+}
+
+impl Thresholds {
+    pub fn is_empty(&self) -> bool {
+        self.established.is_none() && self.listen.is_none()
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Counts {
+    established: usize,
+    listen: usize,
+}
+
+#[derive(Default)]
+pub struct Tracker {
+    previous: Option<HashMap<u32, (String, Counts)>>,
+}
+
+fn counts(entries: &[SocketEntry]) -> HashMap<u32, (String, Counts)> {
+    let mut out: HashMap<u32, (String, Counts)> = HashMap::new();
+    for e in entries {
+        for &pid in &e.pids {
+            let (_, c) = out
+                .entry(pid)
+                .or_insert_with(|| (e.process_info.clone(), Counts::default()));
+            match e.state.as_str() {
+                "Established" => c.established += 1,
+                "Listen" => c.listen += 1,
+                _ => {}
+            }
+        }
+    }
+    out
+}
+
+impl Tracker {
+    // Compares `entries` with the previous refresh and returns alert messages.
+    pub fn update(&mut self, t: Thresholds, entries: &[SocketEntry]) -> Vec<(String, String)> {
+        let current = counts(entries);
+        let mut alerts = Vec::new();
+        if let Some(previous) = &self.previous {
+            let mut pids: Vec<&u32> = current.keys().chain(previous.keys()).collect();
+            pids.sort();
+            pids.dedup();
+            for pid in pids {
+                let (process, before) = previous
+                    .get(pid)
+                    .map(|(p, c)| (p.as_str(), *c))
+                    .unwrap_or(("", Counts::default()));
+                let (process, now) = current
+                    .get(pid)
+                    .map(|(p, c)| (p.as_str(), *c))
+                    .unwrap_or((process, Counts::default()));
+                let checks = [
+                    (
+                        "established",
+                        t.established,
+                        before.established,
+                        now.established,
+                    ),
+                    ("listeners", t.listen, before.listen, now.listen),
+                ];
+                for (what, threshold, before, now) in checks {
+                    if let Some(threshold) = threshold
+                        && before.abs_diff(now) >= threshold
+                    {
+                        let sign = if now >= before { '+' } else { '-' };
+                        alerts.push((
+                            process.to_string(),
+                            format!(
+                                "{} {}{} ({} -> {})",
+                                what,
+                                sign,
+                                before.abs_diff(now),
+                                before,
+                                now
+                            ),
+                        ));
+                    }
+                }
+            }
+        }
+        self.previous = Some(current);
+        alerts
+    }
+}
+
+// `--alert-every WINDOW`: every alert source (count changes, host policy, data cap)
+// asks before reporting, and each key (the rule with the remote address, or with the
+// process for count changes) is reported at most once per window. Repeats inside the
+// window are held back and counted; the count goes out with the key's next report, or
+// on its own once the window has passed. A port scan then costs one line per remote
+// and window instead of one per connection and refresh.
+pub struct Limiter {
+    // None: everything is reported.
+    window: Option<Duration>,
+    // Per key: when it was last reported and the repeats held back since.
+    keys: BTreeMap<String, (Instant, usize)>,
+    // When the first window with repeats held back ends, at the earliest: a key
+    // reported again since keeps it early until the next `expired` recomputes it.
+    due: Option<Instant>,
+}
+
+impl Limiter {
+    pub const fn new(window: Option<Duration>) -> Limiter {
+        Limiter {
+            window,
+            keys: BTreeMap::new(),
+            due: None,
+        }
+    }
+
+    // Whether an alert under `key` is reported at `now`, with the number of repeats
+    // held back before it.
+    pub fn admit(&mut self, key: &str, now: Instant) -> Option<usize> {
+        let Some(window) = self.window else {
+            return Some(0);
+        };
+        match self.keys.get_mut(key) {
+            Some((since, held)) if now.duration_since(*since) < window => {
+                *held += 1;
+                let ends = *since + window;
+                self.due = Some(self.due.map_or(ends, |due| due.min(ends)));
+                None
+            }
+            Some((since, held)) => {
+                *since = now;
+                Some(std::mem::take(held))
+            }
+            None => {
+                self.keys.insert(key.to_string(), (now, 0));
+                Some(0)
+            }
+        }
+    }
+
+    // The keys whose window has passed with repeats held back, and their counts;
+    // every key whose window has passed is forgotten.
+    pub fn expired(&mut self, now: Instant) -> Vec<(String, usize)> {
+        let Some(window) = self.window else {
+            return Vec::new();
+        };
+        let mut out = Vec::new();
+        self.keys.retain(|key, (since, held)| {
+            let open = now.duration_since(*since) < window;
+            if !open && *held > 0 {
+                out.push((key.clone(), *held));
+            }
+            open
+        });
+        self.due = self
+            .keys
+            .values()
+            .filter(|(_, held)| *held > 0)
+            .map(|(since, _)| *since + window)
+            .min();
+        out
+    }
+
+    // Whether a window with repeats held back has ended by `now`.
+    pub fn due(&self, now: Instant) -> bool {
+        self.due.is_some_and(|due| now >= due)
+    }
+}
+
+static LIMITER: Mutex<Limiter> = Mutex::new(Limiter::new(None));
+
+pub fn limit(window: Duration) {
+    if let Ok(mut l) = LIMITER.lock() {
+        *l = Limiter::new(Some(window));
+    }
+}
+
+// See `Limiter::admit`.
+pub fn admit(key: &str) -> Option<usize> {
+    LIMITER
+        .lock()
+        .map_or(Some(0), |mut l| l.admit(key, Instant::now()))
+}
+
+// The host part of "203.0.113.5:443" or "[2001:db8::1]:443", for alert keys.
+pub fn remote_host(addr: &str) -> &str {
+    parse_addr_port(addr).0
+}
+
+// " (+3 held back)" after a text alert.
+pub fn held_back_note(held: usize) -> String {
+    if held == 0 {
+        String::new()
+    } else {
+        format!(" (+{} held back)", held)
+    }
+}
+
+// Reports the repeats held back for keys whose window ended; called once per refresh,
+// it does nothing until the first such window is over.
+pub fn emit_held_back(as_json: bool) {
+    let now = Instant::now();
+    let (expired, window) = match LIMITER.lock() {
+        Ok(mut l) if l.due(now) => (l.expired(now), l.window.unwrap_or_default()),
+        _ => return,
+    };
+    for (key, held) in expired {
+        if as_json {
+            eprintln!(
+                "{}",
+                json::object(&[
+                    ("level", json::str("alert")),
+                    ("rule", json::str("held_back")),
+                    ("key", json::str(&key)),
+                    ("count", held.to_string()),
+                    ("window_secs", json::num(window.as_secs_f64())),
+                ])
+            );
+        } else {
+            eprintln!(
+                "netstatw: alert: {}: {} more held back within {}s",
+                key,
+                held,
+                window.as_secs_f64()
+            );
+        }
+    }
+}
+
+pub fn emit(alerts: &[(String, String)], interval: Duration, as_json: bool) {
+    for (process, change) in alerts {
+        let what = change.split(' ').next().unwrap_or_default();
+        let Some(held) = admit(&format!("{} {}", what, process)) else {
+            continue;
+        };
+        if as_json {
+            eprintln!(
+                "{}",
+                json::object(&[
+                    ("level", json::str("alert")),
+                    ("process", json::str(process)),
+                    ("change", json::str(change)),
+                    ("interval_secs", json::num(interval.as_secs_f64())),
+                    ("held_back", held.to_string()),
+                ])
+            );
+        } else {
+            eprintln!(
+                "netstatw: alert: {}: {} within {}s{}",
+                process,
+                change,
+                interval.as_secs_f64(),
+                held_back_note(held)
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(pid: u32, established: usize) -> Vec<SocketEntry> {
+        (0..established)
+            .map(|_| SocketEntry {
+                state: "Established".to_string(),
+                pids: vec![pid],
+                process_info: format!("{}: app", pid),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn update_first_refresh_is_baseline_only() {
+        let mut tracker = Tracker::default();
+        let t = Thresholds {
+            established: Some(1),
+            listen: None,
+        };
+        assert!(tracker.update(t, &entries(7, 50)).is_empty());
+    }
+
+    #[test]
+    fn update_reports_changes_at_or_above_threshold() {
+        let mut tracker = Tracker::default();
+        let t = Thresholds {
+            established: Some(10),
+            listen: None,
+        };
+        tracker.update(t, &entries(7, 5));
+        assert!(tracker.update(t, &entries(7, 14)).is_empty());
+        let alerts = tracker.update(t, &entries(7, 30));
+        assert_eq!(
+            alerts,
+            vec![(
+                "7: app".to_string(),
+                "established +16 (14 -> 30)".to_string()
+            )]
+        );
+        let alerts = tracker.update(t, &[]);
+        assert_eq!(alerts[0].1, "established -30 (30 -> 0)");
+    }
+
+    #[test]
+    fn limiter_reports_each_key_once_per_window_and_counts_the_rest() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut l = Limiter::new(Some(Duration::from_secs(300)));
+        assert_eq!(l.admit("policy ads 203.0.113.5", at(0)), Some(0));
+        assert_eq!(l.admit("policy ads 203.0.113.5", at(10)), None);
+        assert_eq!(l.admit("policy ads 203.0.113.5", at(20)), None);
+        // Other remotes have their own window.
+        assert_eq!(l.admit("policy ads 203.0.113.6", at(20)), Some(0));
+        assert!(!l.due(at(100)));
+        assert!(l.expired(at(100)).is_empty());
+        // The next report carries the count.
+        assert_eq!(l.admit("policy ads 203.0.113.5", at(300)), Some(2));
+        assert_eq!(l.admit("policy ads 203.0.113.5", at(301)), None);
+        // Without a next report, the count goes out once the window has passed.
+        assert!(l.expired(at(599)).is_empty());
+        assert!(!l.due(at(599)) && l.due(at(600)));
+        assert_eq!(
+            l.expired(at(600)),
+            vec![("policy ads 203.0.113.5".to_string(), 1)]
+        );
+        assert!(!l.due(at(601)));
+        assert_eq!(l.admit("policy ads 203.0.113.5", at(601)), Some(0));
+
+        let mut off = Limiter::new(None);
+        assert_eq!(off.admit("x", at(0)), Some(0));
+        assert_eq!(off.admit("x", at(0)), Some(0));
+    }
+
+    #[test]
+    fn remote_host_drops_the_port_of_either_family() {
+        assert_eq!(remote_host("203.0.113.5:443"), "203.0.113.5");
+        assert_eq!(remote_host("[2001:db8::1]:443"), "2001:db8::1");
+        assert_eq!(remote_host("[fe80::1%eth0]:22"), "fe80::1%eth0");
+    }
+}
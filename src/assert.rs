@@ -0,0 +1,274 @@
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use sysinfo::System;
+
+use crate::config::Config;
+use crate::filter::{self, Cidr, PortPattern};
+use crate::{
+    AddressFamilyFlags, ProtocolFlags, SocketEntry, cli, collect_entries, forensic, interrupt,
+    json, parse_addr_port, warn, zone,
+};
+
+// `netstatw assert`: a check for CI and integration tests that a container or VM only
+// opened the sockets it should. Listeners (TCP listeners and unconnected UDP sockets)
+// must be on allowed ports, and outgoing connections must go to allowed networks. A
+// connection is outgoing unless its local port is one of the host's listening ports;
+// loopback peers are never egress. With `--duration` the tables are polled for the
+// whole window, so short-lived sockets are caught too. Exits 1 with the violations.
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Violation {
+    // "listener" or "egress".
+    kind: &'static str,
+    proto: String,
+    local: String,
+    remote: String,
+    process: String,
+}
+
+struct Rules {
+    // None: listeners aren't checked.
+    listen: Option<Vec<PortPattern>>,
+    egress: Option<Vec<Cidr>>,
+}
+
+fn is_listener(e: &SocketEntry) -> bool {
+    e.state == "Listen" || (e.proto == "UDP" && parse_addr_port(&e.remote_addr).1 == 0)
+}
+
+fn check(entries: &[SocketEntry], rules: &Rules) -> Vec<Violation> {
+    let listening: HashSet<(&str, u16)> = entries
+        .iter()
+        .filter(|e| is_listener(e))
+        .map(|e| (e.proto.as_str(), parse_addr_port(&e.local_addr).1))
+        .collect();
+    let violation = |kind, e: &SocketEntry| Violation {
+        kind,
+        proto: e.proto.clone(),
+        local: e.local_addr.clone(),
+        remote: e.remote_addr.clone(),
+        process: e.process_info.clone(),
+    };
+    let mut out = Vec::new();
+    for e in entries {
+        if is_listener(e) {
+            let port = parse_addr_port(&e.local_addr).1;
+            if let Some(allowed) = &rules.listen
+                && !allowed.iter().any(|p| p.matches(port))
+            {
+                out.push(violation("listener", e));
+            }
+            continue;
+        }
+        let Some(allowed) = &rules.egress else {
+            continue;
+        };
+        let (host, port) = parse_addr_port(&e.remote_addr);
+        let Some(ip) = zone::parse_ip(host) else {
+            continue;
+        };
+        let local_port = parse_addr_port(&e.local_addr).1;
+        let inbound = listening.contains(&(e.proto.as_str(), local_port));
+        let local_peer = ip.to_canonical().is_loopback() || ip.is_unspecified();
+        if port != 0 && !inbound && !local_peer && !allowed.iter().any(|n| n.contains(ip)) {
+            out.push(violation("egress", e));
+        }
+    }
+    out
+}
+
+fn to_json(violations: &[Violation]) -> String {
+    let items: Vec<String> = violations
+        .iter()
+        .map(|v| {
+            json::object(&[
+                ("kind", json::str(v.kind)),
+                ("proto", json::str(&v.proto)),
+                ("local", json::str(&v.local)),
+                ("remote", json::str(&v.remote)),
+                ("process", json::str(&v.process)),
+            ])
+        })
+        .collect();
+    json::object(&[
+        ("ok", violations.is_empty().to_string()),
+        ("violations", json::array(&items)),
+    ])
+}
+
+fn parse_nets(v: &str) -> Result<Vec<Cidr>, String> {
+    if v.eq_ignore_ascii_case("none") {
+        return Ok(Vec::new());
+    }
+    filter::parse_nets(v)
+}
+
+fn parse_listen_ports(v: &str) -> Result<Vec<PortPattern>, String> {
+    if v.eq_ignore_ascii_case("none") {
+        return Ok(Vec::new());
+    }
+    let mut cfg: Option<Config> = None;
+    filter::parse_ports(v, |name| {
+        cfg.get_or_insert_with(|| Config::load().unwrap_or_default())
+            .port_template(name)
+    })
+}
+
+struct Options {
+    rules: Rules,
+    duration: Duration,
+    interval: Duration,
+    as_json: bool,
+}
+
+fn parse_args(mut args: cli::Args) -> Result<Options, String> {
+    let mut opts = Options {
+        rules: Rules {
+            listen: None,
+            egress: None,
+        },
+        duration: Duration::ZERO,
+        interval: Duration::from_millis(500),
+        as_json: false,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--no-listeners-except" => {
+                let ports = parse_listen_ports(&args.value(&arg)?);
+                opts.rules.listen = Some(ports.map_err(|e| format!("{}: {}", arg, e))?);
+            }
+            "--no-egress-except" => {
+                let nets = parse_nets(&args.value(&arg)?);
+                opts.rules.egress = Some(nets.map_err(|e| format!("{}: {}", arg, e))?);
+            }
+            "--duration" | "-d" => opts.duration = args.secs(&arg)?,
+            "--interval" | "-i" => {
+                opts.interval = args.secs(&arg)?.max(Duration::from_millis(50));
+            }
+            "--json" => opts.as_json = true,
+            other => return Err(format!("unexpected argument '{}'", other)),
+        }
+    }
+    if opts.rules.listen.is_none() && opts.rules.egress.is_none() {
+        return Err(
+            "nothing to check (use --no-listeners-except and/or --no-egress-except)".to_string(),
+        );
+    }
+    Ok(opts)
+}
+
+pub fn run(args: Vec<String>) -> i32 {
+    let Options {
+        rules,
+        duration,
+        interval,
+        as_json,
+    } = match cli::parse_command(&cli::ASSERT, args, parse_args) {
+        Ok(opts) => opts,
+        Err(status) => return status,
+    };
+
+    interrupt::install();
+    let until = Instant::now() + duration;
+    let mut system = System::new();
+    let mut violations: Vec<Violation> = Vec::new();
+    loop {
+        forensic::record("sysinfo::System::refresh_processes (process names)");
+        system.refresh_processes();
+        match collect_entries(
+            &system,
+            None,
+            AddressFamilyFlags::IPV4 | AddressFamilyFlags::IPV6,
+            ProtocolFlags::TCP | ProtocolFlags::UDP,
+        ) {
+            Ok(entries) => {
+                for v in check(&entries, &rules) {
+                    if !violations.contains(&v) {
+                        violations.push(v);
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("netstatw assert: {}", e);
+                return 2;
+            }
+        }
+        let left = until.saturating_duration_since(Instant::now());
+        if left.is_zero() || !interrupt::sleep(interval.min(left)) || interrupt::should_stop() {
+            break;
+        }
+    }
+
+    if as_json {
+        println!("{}", to_json(&violations));
+    } else {
+        for v in &violations {
+            let remote = if v.kind == "egress" {
+                format!(" -> {}", v.remote)
+            } else {
+                String::new()
+            };
+            println!(
+                "{} {} {}{} ({})",
+                v.kind, v.proto, v.local, remote, v.process
+            );
+        }
+        if violations.is_empty() {
+            eprintln!("netstatw assert: ok");
+        } else {
+            eprintln!("netstatw assert: {} violation(s)", violations.len());
+        }
+    }
+    warn::emit(false);
+    i32::from(!violations.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(proto: &str, local: &str, remote: &str, state: &str) -> SocketEntry {
+        SocketEntry {
+            proto: proto.to_string(),
+            local_addr: local.to_string(),
+            remote_addr: remote.to_string(),
+            state: state.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn listeners_and_egress_outside_the_allowlists_are_violations() {
+        let entries = [
+            row("TCP", "0.0.0.0:22", "0.0.0.0:0", "Listen"),
+            row("TCP", "0.0.0.0:8080", "0.0.0.0:0", "Listen"),
+            row("UDP", "0.0.0.0:5353", "*:*", "-"),
+            // Inbound to the SSH listener.
+            row("TCP", "10.0.0.1:22", "203.0.113.9:51000", "Established"),
+            row("TCP", "10.0.0.1:40000", "10.1.2.3:443", "Established"),
+            row("TCP", "10.0.0.1:40001", "203.0.113.5:443", "TimeWait"),
+            row("TCP", "[::1]:40002", "[::1]:8080", "Established"),
+        ];
+        let rules = Rules {
+            listen: Some(parse_listen_ports("22,443").unwrap()),
+            egress: Some(parse_nets("10.0.0.0/8").unwrap()),
+        };
+        let found = check(&entries, &rules);
+        let found: Vec<(&str, &str)> = found.iter().map(|v| (v.kind, v.local.as_str())).collect();
+        assert_eq!(
+            found,
+            [
+                ("listener", "0.0.0.0:8080"),
+                ("listener", "0.0.0.0:5353"),
+                ("egress", "10.0.0.1:40001"),
+            ]
+        );
+        let only_egress = Rules {
+            listen: None,
+            egress: Some(parse_nets("none").unwrap()),
+        };
+        assert_eq!(check(&entries, &only_egress).len(), 2);
+        assert_eq!(to_json(&[]), r#"{"ok":true,"violations":[]}"#);
+    }
+}
@@ -0,0 +1,84 @@
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::json;
+
+// `--audit-log FILE`: every action netstatw takes on the system (closing a connection,
+// adding a firewall block) is appended to FILE as one JSON line, whether it worked or
+// not, with the time, the row it acted on and the rule or command that triggered it:
+//
+//   {"timestamp":1760500000,"action":"close","local":"10.0.0.1:40000",
+//    "remote":"203.0.113.5:443","process":"4242: /usr/bin/rsync",
+//    "trigger":"data_cap 2.0 GB/600s","result":"ok","detail":null}
+//
+// `--dry-run` doesn't act and so doesn't log.
+
+pub struct Entry<'a> {
+    // "close" or "block".
+    pub action: &'a str,
+    pub local: &'a str,
+    pub remote: &'a str,
+    pub process: &'a str,
+    pub trigger: &'a str,
+    // What was done (e.g. the firewall rule name), or the error.
+    pub result: Result<&'a str, &'a str>,
+}
+
+fn line(timestamp: u64, e: &Entry) -> String {
+    let (result, detail) = match e.result {
+        Ok(done) => ("ok", Some(done).filter(|d| !d.is_empty())),
+        Err(err) => ("error", Some(err)),
+    };
+    format!(
+        "{}\n",
+        json::object(&[
+            ("timestamp", timestamp.to_string()),
+            ("action", json::str(e.action)),
+            ("local", json::str(e.local)),
+            ("remote", json::str(e.remote)),
+            ("process", json::str(e.process)),
+            ("trigger", json::str(e.trigger)),
+            ("result", json::str(result)),
+            ("detail", json::opt_str(detail)),
+        ])
+    )
+}
+
+pub fn append(path: &Path, entry: &Entry) -> io::Result<()> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(line(now, entry).as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_are_single_json_lines() {
+        let entry = Entry {
+            action: "block",
+            local: "10.0.0.1:40000",
+            remote: "203.0.113.5:443",
+            process: "7: /usr/bin/rsync",
+            trigger: "data_cap 1.0 MB/10s",
+            result: Err("iptables: Permission denied\n(you must be root)"),
+        };
+        let text = line(100, &entry);
+        assert_eq!(text.lines().count(), 1);
+        let v = json::parse(&text).unwrap();
+        assert_eq!(
+            v.get("timestamp").and_then(json::Value::as_f64),
+            Some(100.0)
+        );
+        assert_eq!(v.get("result").and_then(json::Value::as_str), Some("error"));
+        assert_eq!(
+            v.get("detail").and_then(json::Value::as_str),
+            Some("iptables: Permission denied\n(you must be root)")
+        );
+    }
+}
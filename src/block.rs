@@ -0,0 +1,398 @@
+use std::net::IpAddr;
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{cap, cli, forensic, json};
+
+// Firewall blocks: drops all traffic to and from a remote address with the platform
+// firewall (iptables/ip6tables on Linux, `netsh advfirewall` on Windows), which needs
+// root / an elevated prompt. Every rule netstatw adds is named
+// `netstatw-block-<ip>-<unix time>` (an iptables comment, a Windows rule name) so it
+// can be told apart from the rest of the firewall and traced back to netstatw, and
+// `netstatw blocks list` / `netstatw blocks clear` find and remove them again.
+
+pub const RULE_PREFIX: &str = "netstatw-block-";
+
+pub fn rule_name(ip: IpAddr, created: u64) -> String {
+    format!("{}{}-{}", RULE_PREFIX, ip, created)
+}
+
+// `netstatw-block-10.0.0.5-1760500000` -> ("10.0.0.5", 1760500000).
+pub fn parse_rule_name(name: &str) -> Option<(&str, u64)> {
+    let (ip, created) = name.strip_prefix(RULE_PREFIX)?.rsplit_once('-')?;
+    ip.parse::<IpAddr>().ok()?;
+    Some((ip, created.parse().ok()?))
+}
+
+// Runs a firewall command and returns its output; its stderr is the error.
+fn run(command: &[String]) -> Result<String, String> {
+    let (program, args) = command.split_first().ok_or("empty command")?;
+    let out = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| format!("{}: {}", program, e))?;
+    if out.status.success() {
+        Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+    } else {
+        let err = String::from_utf8_lossy(&out.stderr);
+        let err = if err.trim().is_empty() {
+            String::from_utf8_lossy(&out.stdout).into_owned()
+        } else {
+            err.into_owned()
+        };
+        Err(format!("{}: {}", program, err.trim()))
+    }
+}
+
+fn command(args: &[&str]) -> Vec<String> {
+    args.iter().map(|a| a.to_string()).collect()
+}
+
+// The firewall commands that block `ip` in both directions under rule `name`.
+#[cfg(target_os = "linux")]
+fn commands(ip: IpAddr, name: &str) -> Result<Vec<Vec<String>>, String> {
+    let program = if ip.is_ipv6() {
+        "ip6tables"
+    } else {
+        "iptables"
+    };
+    let ip = ip.to_string();
+    Ok([("OUTPUT", "-d"), ("INPUT", "-s")]
+        .iter()
+        .map(|(chain, side)| {
+            command(&[
+                program,
+                "-w",
+                "-I",
+                chain,
+                side,
+                &ip,
+                "-m",
+                "comment",
+                "--comment",
+                name,
+                "-j",
+                "DROP",
+            ])
+        })
+        .collect())
+}
+
+#[cfg(windows)]
+fn commands(ip: IpAddr, name: &str) -> Result<Vec<Vec<String>>, String> {
+    Ok(["out", "in"]
+        .iter()
+        .map(|dir| {
+            command(&[
+                "netsh",
+                "advfirewall",
+                "firewall",
+                "add",
+                "rule",
+                &format!("name={}", name),
+                &format!("dir={}", dir),
+                "action=block",
+                &format!("remoteip={}", ip),
+            ])
+        })
+        .collect())
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+fn commands(_: IpAddr, _: &str) -> Result<Vec<Vec<String>>, String> {
+    Err("blocking is supported on Windows and Linux only".to_string())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+// What `block(ip)` would run, one command per line, for `--dry-run`.
+pub(crate) fn plan(ip: IpAddr) -> Result<String, String> {
+    let lines: Vec<String> = commands(ip, &rule_name(ip, now()))?
+        .iter()
+        .map(|c| c.join(" "))
+        .collect();
+    Ok(lines.join("; "))
+}
+
+// Blocks `ip` in both directions and returns the rule name.
+pub(crate) fn block(ip: IpAddr) -> Result<String, String> {
+    let name = rule_name(ip, now());
+    for c in commands(ip, &name)? {
+        forensic::record_change("firewall rule add (block remote)");
+        run(&c)?;
+    }
+    Ok(name)
+}
+
+// A block netstatw added, as found in the firewall.
+pub struct Block {
+    pub name: String,
+    pub ip: String,
+    pub created: u64,
+    // The commands that delete it (one per direction on Linux).
+    removal: Vec<Vec<String>>,
+}
+
+// Groups (rule name, delete command) pairs into blocks, in first-seen order; names
+// netstatw didn't make are skipped.
+fn group(found: Vec<(String, Vec<String>)>) -> Vec<Block> {
+    let mut blocks: Vec<Block> = Vec::new();
+    for (name, removal) in found {
+        let Some((ip, created)) = parse_rule_name(&name) else {
+            continue;
+        };
+        let Some(block) = blocks.iter_mut().find(|b| b.name == name) else {
+            blocks.push(Block {
+                ip: ip.to_string(),
+                created,
+                name,
+                removal: vec![removal],
+            });
+            continue;
+        };
+        if !block.removal.contains(&removal) {
+            block.removal.push(removal);
+        }
+    }
+    blocks
+}
+
+// `iptables -S` lines carrying a netstatw comment, each with its delete command
+// (`-D` plus the rule spec).
+#[cfg(any(target_os = "linux", test))]
+fn parse_iptables(program: &str, text: &str) -> Vec<(String, Vec<String>)> {
+    let mut out = Vec::new();
+    for line in text.lines() {
+        let words: Vec<String> = line
+            .split_whitespace()
+            .map(|w| w.trim_matches('"').to_string())
+            .collect();
+        let Some(spec) = words.strip_prefix(&["-A".to_string()]) else {
+            continue;
+        };
+        let comment = words
+            .iter()
+            .position(|w| w == "--comment")
+            .and_then(|i| words.get(i + 1));
+        if let Some(name) = comment.filter(|n| n.starts_with(RULE_PREFIX)) {
+            let mut delete = command(&[program, "-w", "-D"]);
+            delete.extend(spec.iter().cloned());
+            out.push((name.clone(), delete));
+        }
+    }
+    out
+}
+
+#[cfg(target_os = "linux")]
+pub fn list() -> Result<Vec<Block>, String> {
+    let mut found = Vec::new();
+    for program in ["iptables", "ip6tables"] {
+        forensic::record("iptables/ip6tables -S (firewall rules)");
+        found.extend(parse_iptables(
+            program,
+            &run(&command(&[program, "-w", "-S"]))?,
+        ));
+    }
+    Ok(group(found))
+}
+
+#[cfg(windows)]
+pub fn list() -> Result<Vec<Block>, String> {
+    forensic::record("netsh advfirewall firewall show rule (firewall rules)");
+    let text = run(&command(&[
+        "netsh",
+        "advfirewall",
+        "firewall",
+        "show",
+        "rule",
+        "name=all",
+    ]))?;
+    // "Rule Name:    netstatw-block-...", whatever the label's language.
+    let found = text
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(_, value)| value.trim())
+        .filter(|name| name.starts_with(RULE_PREFIX))
+        .map(|name| {
+            let delete = command(&[
+                "netsh",
+                "advfirewall",
+                "firewall",
+                "delete",
+                "rule",
+                &format!("name={}", name),
+            ]);
+            (name.to_string(), delete)
+        })
+        .collect();
+    Ok(group(found))
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+pub fn list() -> Result<Vec<Block>, String> {
+    Err("blocking is supported on Windows and Linux only".to_string())
+}
+
+fn remove(block: &Block) -> Result<(), String> {
+    for c in &block.removal {
+        forensic::record_change("firewall rule delete (unblock remote)");
+        run(c)?;
+    }
+    Ok(())
+}
+
+fn age(secs: u64) -> String {
+    match secs {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}m", s / 60),
+        s if s < 86400 => format!("{}h{:02}m", s / 3600, s % 3600 / 60),
+        s => format!("{}d{:02}h", s / 86400, s % 86400 / 3600),
+    }
+}
+
+struct Options {
+    clear: bool,
+    as_json: bool,
+    older_than: Duration,
+    dry_run: bool,
+    forensic: bool,
+}
+
+fn parse_args(mut args: cli::Args) -> Result<Options, String> {
+    let mut action = None;
+    let mut opts = Options {
+        clear: false,
+        as_json: false,
+        older_than: Duration::ZERO,
+        dry_run: false,
+        forensic: false,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => opts.as_json = true,
+            "--older-than" => {
+                opts.older_than = cap::parse_duration(&args.value(&arg)?)
+                    .map_err(|e| format!("{}: {}", arg, e))?;
+            }
+            "--dry-run" => opts.dry_run = true,
+            "--forensic" => opts.forensic = true,
+            other if action.is_none() && !other.starts_with('-') => action = Some(arg),
+            other => return Err(format!("unexpected argument '{}'", other)),
+        }
+    }
+    opts.clear = match action.as_deref() {
+        Some("list") => false,
+        Some("clear") => true,
+        _ => return Err("expected `list` or `clear` (see `blocks --help`)".to_string()),
+    };
+    if opts.clear && opts.forensic && !opts.dry_run {
+        return Err(
+            "clearing modifies the firewall and is not allowed with --forensic".to_string(),
+        );
+    }
+    Ok(opts)
+}
+
+pub fn run_blocks(args: Vec<String>) -> i32 {
+    let Options {
+        clear,
+        as_json,
+        older_than,
+        dry_run,
+        forensic,
+    } = match cli::parse_command(&cli::BLOCKS, args, parse_args) {
+        Ok(opts) => opts,
+        Err(status) => return status,
+    };
+    let blocks = match list() {
+        Ok(blocks) => blocks,
+        Err(e) => {
+            eprintln!("netstatw blocks: {}", e);
+            return 1;
+        }
+    };
+    let now = now();
+    if !clear {
+        if as_json {
+            let items: Vec<String> = blocks
+                .iter()
+                .map(|b| {
+                    json::object(&[
+                        ("name", json::str(&b.name)),
+                        ("ip", json::str(&b.ip)),
+                        ("created", b.created.to_string()),
+                    ])
+                })
+                .collect();
+            println!("{}", json::array(&items));
+        } else {
+            for b in &blocks {
+                println!(
+                    "{:<48} {:<39} {} ago",
+                    b.name,
+                    b.ip,
+                    age(now.saturating_sub(b.created))
+                );
+            }
+        }
+        if forensic {
+            forensic::print_report();
+        }
+        return 0;
+    }
+    let mut failed = false;
+    for b in &blocks {
+        if now.saturating_sub(b.created) < older_than.as_secs() {
+            continue;
+        }
+        if dry_run {
+            for c in &b.removal {
+                println!("would remove {}: {}", b.name, c.join(" "));
+            }
+            continue;
+        }
+        match remove(b) {
+            Ok(()) => println!("removed {} ({})", b.name, b.ip),
+            Err(e) => {
+                eprintln!("netstatw blocks: {}: {}", b.name, e);
+                failed = true;
+            }
+        }
+    }
+    if forensic {
+        forensic::print_report();
+    }
+    i32::from(failed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iptables_rules_are_grouped_by_netstatw_name() {
+        let text = "-P OUTPUT ACCEPT\n\
+            -A OUTPUT -d 203.0.113.5/32 -m comment --comment netstatw-block-203.0.113.5-1760500000 -j DROP\n\
+            -A INPUT -s 203.0.113.5/32 -m comment --comment netstatw-block-203.0.113.5-1760500000 -j DROP\n\
+            -A INPUT -s 10.0.0.0/8 -m comment --comment \"office lan\" -j ACCEPT\n\
+            -A INPUT -s 198.51.100.1/32 -m comment --comment netstatw-block-garbage -j DROP\n";
+        let blocks = group(parse_iptables("iptables", text));
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].ip, "203.0.113.5");
+        assert_eq!(blocks[0].created, 1760500000);
+        assert_eq!(blocks[0].removal.len(), 2);
+        assert_eq!(
+            blocks[0].removal[0].join(" "),
+            "iptables -w -D OUTPUT -d 203.0.113.5/32 -m comment --comment \
+             netstatw-block-203.0.113.5-1760500000 -j DROP"
+        );
+        assert_eq!(
+            parse_rule_name(&rule_name("2001:db8::1".parse().unwrap(), 5)),
+            Some(("2001:db8::1", 5))
+        );
+    }
+}
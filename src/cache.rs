@@ -0,0 +1,102 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use crate::{AddressFamilyFlags, ProtocolFlags, SocketEntry, record};
+
+// `--max-age DUR`: a warm-start cache for one-shot runs polled in quick succession (a
+// dashboard script calling `netstatw --json` every second). The socket rows of the last
+// enumeration, owners resolved, are kept in a per-user state file; a run finding one
+// younger than DUR uses it instead of walking the socket tables and the process list
+// again. Only the enumeration is reused: filters, enrichment and `--full` sampling run
+// as usual. Files are keyed by the address families, protocols and `--top` limit they
+// were read with, so e.g. `-4 --tcp` runs don't see rows of a full run.
+
+fn dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".cache")))
+    };
+    base.map(|b| b.join("netstatw"))
+}
+
+// "snapshot-46-tu-all.json" for the default tables.
+fn file_name(
+    families: AddressFamilyFlags,
+    protocols: ProtocolFlags,
+    top_n: Option<usize>,
+) -> String {
+    let flag = |on: bool, c: &'static str| if on { c } else { "" };
+    format!(
+        "snapshot-{}{}-{}{}-{}.json",
+        flag(families.contains(AddressFamilyFlags::IPV4), "4"),
+        flag(families.contains(AddressFamilyFlags::IPV6), "6"),
+        flag(protocols.contains(ProtocolFlags::TCP), "t"),
+        flag(protocols.contains(ProtocolFlags::UDP), "u"),
+        top_n.map_or("all".to_string(), |n| n.to_string())
+    )
+}
+
+// The cached rows, if a file for these tables is younger than `max_age`.
+pub fn load(
+    max_age: Duration,
+    families: AddressFamilyFlags,
+    protocols: ProtocolFlags,
+    top_n: Option<usize>,
+) -> Option<Vec<SocketEntry>> {
+    let path = dir()?.join(file_name(families, protocols, top_n));
+    let modified = fs::metadata(&path).ok()?.modified().ok()?;
+    // A timestamp in the future (clock change) counts as stale.
+    if SystemTime::now().duration_since(modified).ok()? > max_age {
+        return None;
+    }
+    record::read(&path).ok()?.pop().map(|s| s.entries)
+}
+
+// Replaces the cached rows for these tables. Written to a temporary file first, so a
+// concurrent run never reads half a snapshot.
+pub fn store(
+    entries: &[SocketEntry],
+    families: AddressFamilyFlags,
+    protocols: ProtocolFlags,
+    top_n: Option<usize>,
+) -> Result<(), String> {
+    let dir = dir().ok_or("no cache directory (HOME/LOCALAPPDATA not set)")?;
+    fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let path = dir.join(file_name(families, protocols, top_n));
+    let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+    let text = record::line(record::now(), entries);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    // Rows name other users' processes; keep them private.
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(&tmp)
+        .and_then(|mut f| std::io::Write::write_all(&mut f, text.as_bytes()))
+        .and_then(|()| fs::rename(&tmp, &path))
+        .map_err(|e| {
+            let _ = fs::remove_file(&tmp);
+            format!("{}: {}", path.display(), e)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_files_are_keyed_by_the_tables_read() {
+        let all = AddressFamilyFlags::IPV4 | AddressFamilyFlags::IPV6;
+        let both = ProtocolFlags::TCP | ProtocolFlags::UDP;
+        assert_eq!(file_name(all, both, None), "snapshot-46-tu-all.json");
+        assert_eq!(
+            file_name(AddressFamilyFlags::IPV4, ProtocolFlags::TCP, Some(3)),
+            "snapshot-4-t-3.json"
+        );
+    }
+}
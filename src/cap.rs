@@ -0,0 +1,340 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::{ConnKey, SocketEntry, alert, audit, block, human_readable_rate, json, kill, zone};
+
+// `--data-cap BYTES/WINDOW`: a watchdog for --watch that fires when a single TCP
+// connection transfers (receives plus sends) more than BYTES within WINDOW, a simple
+// guard against bulk exfiltration or a runaway upload. Each refresh reads the
+// kernel's cumulative per-connection byte counters (keyed by address pair, as for the
+// Rx/Tx columns) and compares them with the oldest reading inside the window; bytes a
+// connection moved before netstatw first saw it don't count. A connection fires once,
+// and `--cap-action` can then close it or block its remote address; with `--dry-run`
+// the alert shows what the action would run instead, and `--audit-log` records every
+// action taken.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Alert,
+    Kill,
+    Block,
+}
+
+impl Action {
+    pub fn parse(v: &str) -> Option<Action> {
+        match v.to_ascii_lowercase().as_str() {
+            "alert" => Some(Action::Alert),
+            "kill" => Some(Action::Kill),
+            "block" => Some(Action::Block),
+            _ => None,
+        }
+    }
+}
+
+// "500M/60s" -> (500 MiB, 60 s). Sizes take K, M, G, T (binary, like the rate
+// columns, with an optional B); windows take s, m, h and default to seconds.
+pub fn parse_cap(v: &str) -> Result<(u64, Duration), String> {
+    let (size, window) = v
+        .split_once('/')
+        .ok_or_else(|| format!("expected BYTES/WINDOW like 500M/60s, got '{}'", v))?;
+    Ok((parse_size(size)?, parse_duration(window)?))
+}
+
+// "4096", "500M", "1.5GB".
+pub fn parse_size(v: &str) -> Result<u64, String> {
+    let size = v.trim().to_ascii_uppercase();
+    let digits = size.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let scale: u64 = match size[digits.len()..].trim_end_matches('B') {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        unit => return Err(format!("unknown size unit '{}'", unit)),
+    };
+    let bytes = digits
+        .parse::<f64>()
+        .ok()
+        .filter(|n| *n > 0.0 && n.is_finite())
+        .ok_or_else(|| format!("'{}' is not a size", size))?;
+    Ok((bytes * scale as f64) as u64)
+}
+
+// "90", "90s", "5m", "1.5h".
+pub fn parse_duration(v: &str) -> Result<Duration, String> {
+    let v = v.trim().to_ascii_lowercase();
+    let digits = v.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let unit: u64 = match &v[digits.len()..] {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        unit => return Err(format!("unknown time unit '{}'", unit)),
+    };
+    let n = digits
+        .parse::<f64>()
+        .ok()
+        .filter(|n| *n > 0.0 && n.is_finite())
+        .ok_or_else(|| format!("'{}' is not a duration", v))?;
+    Duration::try_from_secs_f64(n * unit as f64).map_err(|_| format!("'{}' is too long", v))
+}
+
+pub(crate) fn human_bytes(bytes: u64) -> String {
+    human_readable_rate(bytes as f64)
+        .trim_end_matches("/s")
+        .to_string()
+}
+
+// A connection over the cap.
+pub struct Breach {
+    pub local: String,
+    pub remote: String,
+    pub process: String,
+    pub bytes: u64,
+    // What the action did, or why it failed.
+    pub outcome: Option<Result<String, String>>,
+}
+
+#[derive(Default)]
+struct State {
+    // Readings (time, total bytes) per connection, oldest first.
+    history: HashMap<ConnKey, VecDeque<(Instant, u64)>>,
+    fired: HashSet<ConnKey>,
+}
+
+pub struct Watchdog {
+    pub bytes: u64,
+    pub window: Duration,
+    pub action: Action,
+    pub dry_run: bool,
+    pub audit_log: Option<PathBuf>,
+    state: Mutex<State>,
+}
+
+impl Watchdog {
+    pub fn new(bytes: u64, window: Duration, action: Action) -> Watchdog {
+        Watchdog {
+            bytes,
+            window,
+            action,
+            dry_run: false,
+            audit_log: None,
+            state: Mutex::default(),
+        }
+    }
+
+    // Records the counters read at `now` and returns the listed connections that went
+    // over the cap (without running the action). Connections no longer in `counters`
+    // are forgotten.
+    fn observe(
+        &self,
+        now: Instant,
+        entries: &[SocketEntry],
+        counters: &HashMap<ConnKey, (u64, u64)>,
+    ) -> Vec<Breach> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let State { history, fired } = &mut *state;
+        history.retain(|key, _| counters.contains_key(key));
+        fired.retain(|key| counters.contains_key(key));
+        for (key, (rx, tx)) in counters {
+            let readings = history.entry(key.clone()).or_default();
+            readings.push_back((now, rx.saturating_add(*tx)));
+            // Keep one reading at or before the window's start as the baseline.
+            while readings.len() > 1 && now.duration_since(readings[1].0) >= self.window {
+                readings.pop_front();
+            }
+        }
+        let mut out = Vec::new();
+        for e in entries.iter().filter(|e| e.proto == "TCP") {
+            let key = (zone::strip(&e.local_addr), zone::strip(&e.remote_addr));
+            let Some(readings) = history.get(&key) else {
+                continue;
+            };
+            let (Some(first), Some(last)) = (readings.front(), readings.back()) else {
+                continue;
+            };
+            let bytes = last.1.saturating_sub(first.1);
+            if bytes > self.bytes && fired.insert(key) {
+                out.push(Breach {
+                    local: e.local_addr.clone(),
+                    remote: e.remote_addr.clone(),
+                    process: e.process_info.clone(),
+                    bytes,
+                    outcome: None,
+                });
+            }
+        }
+        out
+    }
+
+    // Reads the counters, checks the listed connections and runs the action on the
+    // ones over the cap.
+    pub fn check(&self, entries: &[SocketEntry]) -> Result<Vec<Breach>, String> {
+        let counters = counters()?;
+        let mut breaches = self.observe(Instant::now(), entries, &counters);
+        for b in &mut breaches {
+            let (local, remote) = (zone::socket_addr(&b.local), zone::socket_addr(&b.remote));
+            let (Some(local), Some(remote)) = (local, remote) else {
+                continue;
+            };
+            let (name, outcome) = match (self.action, self.dry_run) {
+                (Action::Alert, _) => continue,
+                (Action::Kill, true) => {
+                    b.outcome = Some(Ok(format!("would close ({})", kill::plan(local, remote))));
+                    continue;
+                }
+                (Action::Block, true) => {
+                    b.outcome =
+                        Some(block::plan(remote.ip()).map(|p| format!("would block ({})", p)));
+                    continue;
+                }
+                (Action::Kill, false) => {
+                    ("close", kill::close(local, remote).map(|()| String::new()))
+                }
+                (Action::Block, false) => ("block", block::block(remote.ip())),
+            };
+            if let Some(path) = &self.audit_log {
+                let trigger = format!(
+                    "data_cap {}/{}s",
+                    human_bytes(self.bytes),
+                    self.window.as_secs_f64()
+                );
+                let entry = audit::Entry {
+                    action: name,
+                    local: &b.local,
+                    remote: &b.remote,
+                    process: &b.process,
+                    trigger: &trigger,
+                    result: outcome.as_deref().map_err(String::as_str),
+                };
+                if let Err(e) = audit::append(path, &entry) {
+                    crate::warn::warn(
+                        "audit_log",
+                        format!("cannot write audit log {}: {}", path.display(), e),
+                    );
+                }
+            }
+            b.outcome = Some(outcome.map(|rule| match name {
+                "block" => format!("blocked ({})", rule),
+                _ => "closed".to_string(),
+            }));
+        }
+        Ok(breaches)
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn counters() -> Result<HashMap<ConnKey, (u64, u64)>, String> {
+    crate::forensic::record("netlink sock_diag (TCP tcp_info)");
+    crate::linux_net::byte_counters().map_err(|e| format!("netlink sock_diag: {}", e))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn counters() -> Result<HashMap<ConnKey, (u64, u64)>, String> {
+    Err("per-connection byte counters are read from tcp_info (Linux only)".to_string())
+}
+
+pub fn emit(breaches: &[Breach], cap: &Watchdog, as_json: bool) {
+    for b in breaches {
+        // Only plain alerts are held back; an action taken is always reported.
+        let held = match b.outcome {
+            None => match alert::admit(&format!("data_cap {}", alert::remote_host(&b.remote))) {
+                Some(held) => held,
+                None => continue,
+            },
+            Some(_) => 0,
+        };
+        let (action, error) = match &b.outcome {
+            None => ("alert".to_string(), None),
+            Some(Ok(done)) => (done.clone(), None),
+            Some(Err(e)) => ("failed".to_string(), Some(e.as_str())),
+        };
+        if as_json {
+            eprintln!(
+                "{}",
+                json::object(&[
+                    ("level", json::str("alert")),
+                    ("rule", json::str("data_cap")),
+                    ("local", json::str(&b.local)),
+                    ("remote", json::str(&b.remote)),
+                    ("process", json::str(&b.process)),
+                    ("bytes", json::num(b.bytes as f64)),
+                    ("cap_bytes", json::num(cap.bytes as f64)),
+                    ("window_secs", json::num(cap.window.as_secs_f64())),
+                    ("action", json::str(&action)),
+                    ("error", json::opt_str(error)),
+                    ("held_back", held.to_string()),
+                ])
+            );
+        } else {
+            eprintln!(
+                "netstatw: alert: {}: {} -> {} moved {} within {}s (cap {}){}{}",
+                b.process,
+                b.local,
+                b.remote,
+                human_bytes(b.bytes),
+                cap.window.as_secs_f64(),
+                human_bytes(cap.bytes),
+                match (&b.outcome, error) {
+                    (_, Some(e)) => format!("; action failed: {}", e),
+                    (Some(_), None) => format!("; {}", action),
+                    (None, None) => String::new(),
+                },
+                alert::held_back_note(held)
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cap_parses_sizes_and_windows() {
+        assert_eq!(
+            parse_cap("500M/60s"),
+            Ok((500 << 20, Duration::from_secs(60)))
+        );
+        assert_eq!(
+            parse_cap("1.5gb/5m"),
+            Ok((3 << 29, Duration::from_secs(300)))
+        );
+        assert_eq!(parse_cap("4096/10"), Ok((4096, Duration::from_secs(10))));
+        assert!(parse_cap("500M").is_err());
+        assert!(parse_cap("500X/1m").is_err());
+        assert!(parse_cap("0/1m").is_err());
+        assert_eq!(
+            parse_duration("1e300h").unwrap_err(),
+            "'1e300h' is too long"
+        );
+    }
+
+    #[test]
+    fn watchdog_counts_bytes_within_the_window_once() {
+        let row = SocketEntry {
+            proto: "TCP".to_string(),
+            local_addr: "10.0.0.1:40000".to_string(),
+            remote_addr: "203.0.113.5:443".to_string(),
+            state: "Established".to_string(),
+            ..Default::default()
+        };
+        let key = ("10.0.0.1:40000".to_string(), "203.0.113.5:443".to_string());
+        let dog = Watchdog::new(1000, Duration::from_secs(10), Action::Alert);
+        let t0 = Instant::now();
+        let at = |secs| t0 + Duration::from_secs(secs);
+        let read = |rx: u64, tx: u64| HashMap::from([(key.clone(), (rx, tx))]);
+        let rows = std::slice::from_ref(&row);
+
+        // Bytes from before the first reading don't count.
+        assert!(dog.observe(at(0), rows, &read(50_000, 0)).is_empty());
+        assert!(dog.observe(at(5), rows, &read(50_600, 0)).is_empty());
+        // 900 bytes in the last 10 s (baseline: the reading at 5 s).
+        assert!(dog.observe(at(15), rows, &read(50_900, 600)).is_empty());
+        let breach = dog.observe(at(18), rows, &read(51_000, 900));
+        assert_eq!(breach.len(), 1);
+        assert_eq!(breach[0].bytes, 1300);
+        assert!(dog.observe(at(19), rows, &read(90_000, 900)).is_empty());
+    }
+}
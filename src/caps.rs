@@ -0,0 +1,252 @@
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+use netstat2::{AddressFamilyFlags, ProtocolFlags, get_sockets_info};
+use sysinfo::{Pid, System, get_current_pid};
+
+use crate::{forensic, json};
+
+// What the current user/token can actually see. `--capabilities` prints the whole
+// matrix; the collectors consult the same probes (e.g. `elevated`) to pick what to
+// attempt, and their warnings point here instead of failing silently.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Yes,
+    Partial,
+    No,
+}
+
+impl Status {
+    fn as_str(self) -> &'static str {
+        match self {
+            Status::Yes => "yes",
+            Status::Partial => "partial",
+            Status::No => "no",
+        }
+    }
+}
+
+pub struct Capability {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+}
+
+static ELEVATED: OnceLock<bool> = OnceLock::new();
+
+// Running as root / with an elevated administrator token.
+pub fn elevated() -> bool {
+    *ELEVATED.get_or_init(|| {
+        #[cfg(unix)]
+        {
+            // SAFETY: geteuid has no preconditions.
+            unsafe { libc::geteuid() == 0 }
+        }
+        #[cfg(windows)]
+        {
+            crate::win_api::is_elevated()
+        }
+        #[cfg(not(any(unix, windows)))]
+        {
+            false
+        }
+    })
+}
+
+const ELEVATE: &str = if cfg!(windows) {
+    "run elevated (Administrator)"
+} else {
+    "run as root"
+};
+
+// "yes" when all `total` items were usable, "partial" when some were, "no" when
+// none were (an empty set counts as "yes").
+fn coverage(ok: usize, total: usize) -> Status {
+    if ok == total {
+        Status::Yes
+    } else if ok == 0 {
+        Status::No
+    } else {
+        Status::Partial
+    }
+}
+
+fn per_connection_rates() -> Capability {
+    let name = "per-connection Rx/Tx";
+    #[cfg(windows)]
+    {
+        let (status, detail) = if crate::win_api::estats().is_none() {
+            (
+                Status::No,
+                "GetPerTcpConnectionEStats is not exported on this system".to_string(),
+            )
+        } else if elevated() {
+            (
+                Status::Yes,
+                "EStats collection can be enabled on any TCP connection".to_string(),
+            )
+        } else {
+            (
+                Status::Partial,
+                format!(
+                    "only connections whose EStats collection is already on; {}",
+                    ELEVATE
+                ),
+            )
+        };
+        Capability {
+            name,
+            status,
+            detail,
+        }
+    }
+    #[cfg(target_os = "linux")]
+    {
+        let (status, detail) = match crate::linux_net::counters_available() {
+            Ok(true) => (
+                Status::Yes,
+                "TCP byte counters via netlink sock_diag".to_string(),
+            ),
+            Ok(false) => (
+                Status::No,
+                "kernel tcp_info has no byte counters (needs Linux 4.1+)".to_string(),
+            ),
+            Err(e) => (Status::No, format!("netlink sock_diag: {}", e)),
+        };
+        Capability {
+            name,
+            status,
+            detail,
+        }
+    }
+    #[cfg(not(any(windows, target_os = "linux")))]
+    Capability {
+        name,
+        status: Status::No,
+        detail: "Windows and Linux only (TCP EStats, sock_diag)".to_string(),
+    }
+}
+
+pub fn probe(system: &System) -> Vec<Capability> {
+    let mut caps = Vec::new();
+    caps.push(Capability {
+        name: "elevated",
+        status: if elevated() { Status::Yes } else { Status::No },
+        detail: if elevated() {
+            String::new()
+        } else {
+            format!("{} for full visibility", ELEVATE)
+        },
+    });
+
+    forensic::record("netstat2::get_sockets_info (capability probe)");
+    let sockets = get_sockets_info(
+        AddressFamilyFlags::IPV4 | AddressFamilyFlags::IPV6,
+        ProtocolFlags::TCP | ProtocolFlags::UDP,
+    );
+    match &sockets {
+        Ok(sockets) => {
+            caps.push(Capability {
+                name: "socket tables",
+                status: Status::Yes,
+                detail: format!("{} sockets", sockets.len()),
+            });
+            let owned = sockets
+                .iter()
+                .filter(|s| !s.associated_pids.is_empty())
+                .count();
+            let status = coverage(owned, sockets.len());
+            caps.push(Capability {
+                name: "socket owner PIDs",
+                status,
+                detail: match status {
+                    Status::Yes => format!("all {} sockets attributed", sockets.len()),
+                    _ if elevated() => format!(
+                        "{} of {} sockets attributed; the rest have no owner (e.g. TIME_WAIT)",
+                        owned,
+                        sockets.len()
+                    ),
+                    _ => format!(
+                        "{} of {} sockets attributed; {} for the rest",
+                        owned,
+                        sockets.len(),
+                        ELEVATE
+                    ),
+                },
+            });
+        }
+        Err(e) => caps.push(Capability {
+            name: "socket tables",
+            status: Status::No,
+            detail: e.to_string(),
+        }),
+    }
+
+    // Executable paths of socket owners run by other users.
+    let me = get_current_pid()
+        .ok()
+        .and_then(|p| system.process(p)?.user_id().cloned());
+    let owners: HashSet<u32> = sockets
+        .iter()
+        .flatten()
+        .flat_map(|s| s.associated_pids.iter().copied())
+        .collect();
+    let others: Vec<_> = owners
+        .iter()
+        .filter_map(|&pid| system.process(Pid::from(pid as usize)))
+        .filter(|p| me.is_none() || p.user_id() != me.as_ref())
+        .collect();
+    let resolved = others.iter().filter(|p| p.exe().is_some()).count();
+    let status = coverage(resolved, others.len());
+    caps.push(Capability {
+        name: "other users' process paths",
+        status,
+        detail: match status {
+            Status::Yes => format!("{} socket owners of other users resolved", others.len()),
+            _ => format!(
+                "{} of {} socket owners of other users resolved (others show the name only); {}",
+                resolved,
+                others.len(),
+                ELEVATE
+            ),
+        },
+    });
+
+    caps.push(per_connection_rates());
+    caps
+}
+
+pub fn print(caps: &[Capability], as_json: bool) {
+    if as_json {
+        let rows: Vec<String> = caps
+            .iter()
+            .map(|c| {
+                json::object(&[
+                    ("capability", json::str(c.name)),
+                    ("status", json::str(c.status.as_str())),
+                    ("detail", json::str(&c.detail)),
+                ])
+            })
+            .collect();
+        println!("{}", json::array(&rows));
+        return;
+    }
+    println!("{:<28} {:<8} DETAIL", "CAPABILITY", "STATUS");
+    for c in caps {
+        println!("{:<28} {:<8} {}", c.name, c.status.as_str(), c.detail);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coverage_grades_partial_access() {
+        assert_eq!(coverage(0, 0), Status::Yes);
+        assert_eq!(coverage(3, 3), Status::Yes);
+        assert_eq!(coverage(1, 3), Status::Partial);
+        assert_eq!(coverage(0, 3), Status::No);
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+use crate::SocketEntry;
+
+// `--watch` as a connection monitor: rows are keyed by protocol and local and remote
+// address between refreshes. A row whose key wasn't listed last time is marked opened
+// (`+`); a key that went away comes back for one refresh as a closed row (`-`) with the
+// values it last had. State changes of a connection are not churn. The comparison is
+// with the previous listing, so a connection leaving the filters counts as closed.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Change {
+    Opened,
+    Closed,
+}
+
+impl Change {
+    // The CHANGE cell.
+    pub fn marker(self) -> &'static str {
+        match self {
+            Change::Opened => "+",
+            Change::Closed => "-",
+        }
+    }
+}
+
+type Key = (String, String, String);
+
+fn key(e: &SocketEntry) -> Key {
+    (e.proto.clone(), e.local_addr.clone(), e.remote_addr.clone())
+}
+
+#[derive(Default)]
+pub struct Tracker {
+    // None until the first refresh, which has nothing to compare with.
+    previous: Option<HashMap<Key, SocketEntry>>,
+}
+
+impl Tracker {
+    // Marks the rows opened since the last refresh and returns the ones closed since,
+    // unsorted.
+    pub fn update(&mut self, entries: &mut [SocketEntry]) -> Vec<SocketEntry> {
+        let current: HashMap<Key, SocketEntry> =
+            entries.iter().map(|e| (key(e), e.clone())).collect();
+        let closed = match &self.previous {
+            None => Vec::new(),
+            Some(previous) => {
+                for e in entries.iter_mut() {
+                    e.change = (!previous.contains_key(&key(e))).then_some(Change::Opened);
+                }
+                previous
+                    .iter()
+                    .filter(|(k, _)| !current.contains_key(*k))
+                    .map(|(_, e)| SocketEntry {
+                        change: Some(Change::Closed),
+                        ..e.clone()
+                    })
+                    .collect()
+            }
+        };
+        self.previous = Some(current);
+        closed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(remote: &str, state: &str) -> SocketEntry {
+        SocketEntry {
+            proto: "TCP".to_string(),
+            local_addr: "10.0.0.1:40000".to_string(),
+            remote_addr: remote.to_string(),
+            state: state.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn refreshes_mark_opened_rows_and_return_closed_ones_once() {
+        let mut tracker = Tracker::default();
+        let mut first = vec![
+            row("10.0.0.5:5432", "Established"),
+            row("10.0.0.6:443", "Established"),
+        ];
+        assert!(tracker.update(&mut first).is_empty());
+        assert!(first.iter().all(|e| e.change.is_none()));
+
+        let mut second = vec![
+            // A state change is the same connection.
+            row("10.0.0.5:5432", "CloseWait"),
+            row("10.0.0.7:22", "SynSent"),
+        ];
+        let closed = tracker.update(&mut second);
+        assert_eq!(second[0].change, None);
+        assert_eq!(second[1].change, Some(Change::Opened));
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].remote_addr, "10.0.0.6:443");
+        assert_eq!(closed[0].change.map(Change::marker), Some("-"));
+
+        let mut third = second.clone();
+        assert!(tracker.update(&mut third).is_empty());
+        assert!(third.iter().all(|e| e.change.is_none()));
+    }
+}
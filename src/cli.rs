@@ -0,0 +1,1891 @@
+use std::fmt::Display;
+use std::iter::Peekable;
+use std::str::FromStr;
+use std::time::Duration;
+use std::vec::IntoIter;
+
+use crate::json;
+
+// The command line as data: every command with its usage, description and options.
+// `--help` and `--help-json` are both rendered from these tables.
+
+// What an option's value is, for `--help-json`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Type {
+    Number,
+    Path,
+    // `2s`, `30m`, `1h`.
+    Duration,
+    // `4096`, `500M`, `1.5GB`.
+    Size,
+    // A network in CIDR notation.
+    Network,
+    // A port, a wildcard over its digits (`3??0`, `80*`) or a template name from
+    // `[ports]` in the config file.
+    Port,
+    // `HOST:PORT`.
+    Address,
+    Text,
+}
+
+impl Type {
+    fn name(self) -> &'static str {
+        match self {
+            Type::Number => "number",
+            Type::Path => "path",
+            Type::Duration => "duration",
+            Type::Size => "size",
+            Type::Network => "network",
+            Type::Port => "port",
+            Type::Address => "address",
+            Type::Text => "string",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Value {
+    pub placeholder: &'static str,
+    pub kind: Type,
+    // Comma-separated.
+    pub list: bool,
+    // May be left out (`--watch [SECS]`).
+    pub optional: bool,
+    pub default: Option<&'static str>,
+    // The accepted values when they are a fixed set.
+    pub choices: &'static [&'static str],
+    // May start with a dash (`--sort -cpu`); other values that look like options are
+    // taken for the next option.
+    pub dashed: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Opt {
+    pub names: &'static [&'static str],
+    pub value: Option<Value>,
+    // Continuation lines after `\n`.
+    pub help: &'static str,
+}
+
+pub const fn flag(names: &'static [&'static str], help: &'static str) -> Opt {
+    Opt {
+        names,
+        value: None,
+        help,
+    }
+}
+
+pub const fn opt(
+    names: &'static [&'static str],
+    placeholder: &'static str,
+    kind: Type,
+    help: &'static str,
+) -> Opt {
+    Opt {
+        names,
+        value: Some(Value {
+            placeholder,
+            kind,
+            list: false,
+            optional: false,
+            default: None,
+            choices: &[],
+            dashed: false,
+        }),
+        help,
+    }
+}
+
+// Settings of an option's value, for the table rows.
+impl Opt {
+    const fn default(mut self, default: &'static str) -> Opt {
+        if let Some(v) = &mut self.value {
+            v.default = Some(default);
+        }
+        self
+    }
+
+    const fn list(mut self) -> Opt {
+        if let Some(v) = &mut self.value {
+            v.list = true;
+        }
+        self
+    }
+
+    const fn optional(mut self) -> Opt {
+        if let Some(v) = &mut self.value {
+            v.optional = true;
+        }
+        self
+    }
+
+    const fn choices(mut self, choices: &'static [&'static str]) -> Opt {
+        if let Some(v) = &mut self.value {
+            v.choices = choices;
+        }
+        self
+    }
+
+    const fn dashed(mut self) -> Opt {
+        if let Some(v) = &mut self.value {
+            v.dashed = true;
+        }
+        self
+    }
+}
+
+pub struct Section {
+    pub title: &'static str,
+    // Printed after the title, in parentheses.
+    pub note: &'static str,
+    pub options: &'static [Opt],
+}
+
+pub struct Command {
+    pub name: &'static str,
+    // The line in the main help's list of commands, with `summary`.
+    pub synopsis: &'static str,
+    // After the program name.
+    pub usage: &'static [&'static str],
+    pub summary: &'static str,
+    // Paragraphs, wrapped at `\n`.
+    pub about: &'static [&'static str],
+    pub sections: &'static [Section],
+    // Paragraphs after the options.
+    pub notes: &'static [&'static str],
+}
+
+impl Command {
+    pub fn options(&self) -> impl Iterator<Item = &'static Opt> + use<> {
+        let sections: &'static [Section] = self.sections;
+        sections.iter().flat_map(|s| s.options.iter())
+    }
+}
+
+// `--sort` keys and `--columns` names; main pairs them with its own enums in this
+// order.
+pub const SORT_KEYS: [&str; 13] = [
+    "cpu", "r", "w", "rx", "tx", "state", "proto", "laddr", "raddr", "lport", "rport", "pid",
+    "process",
+];
+pub const COLUMNS: [&str; 28] = [
+    "id", "proto", "laddr", "raddr", "state", "pid", "user", "exe", "process", "cpu", "r", "w",
+    "rx", "tx", "accept", "new", "idle", "env-hint", "corr", "inode", "scope", "fd", "ping",
+    "path", "tags", "geo", "asn", "change",
+];
+
+const HELP: Opt = flag(&["-h", "--help"], "Show this help and exit");
+
+// Options of `list` that `watch` and `stats` show too.
+const SORT: Opt = opt(
+    &["-s", "--sort"],
+    "KEYS",
+    Type::Text,
+    "Sort keys, comma-separated or repeated: cpu | R | W | Rx | Tx |\n\
+     state | proto | laddr | raddr | lport | rport | pid | process;\n\
+     -KEY descending, +KEY ascending (default: metrics descending)",
+)
+.list()
+.choices(&SORT_KEYS)
+.dashed();
+const SAMPLE_INTERVAL: Opt = opt(
+    &["-i", "--sample-interval"],
+    "MS",
+    Type::Number,
+    "Sampling interval in milliseconds (default: 800)",
+)
+.default("800");
+const TOP: Opt = opt(
+    &["-t", "--top"],
+    "N",
+    Type::Number,
+    "Limit number of PIDs shown and included per row",
+);
+const TUI: Opt = flag(
+    &["--tui"],
+    "Interactive live view (sort, search, details; q to quit)",
+);
+const NO_CHANGES: Opt = flag(
+    &["--no-changes"],
+    "With --watch: don't mark opened (+) and closed (-) connections",
+);
+const ALERT_CONN: Opt = opt(
+    &["--alert-conn-change"],
+    "N",
+    Type::Number,
+    "With --watch: alert when a process's established count moves by N",
+);
+const ALERT_LISTEN: Opt = opt(
+    &["--alert-listen-change"],
+    "N",
+    Type::Number,
+    "With --watch: alert when a process's listener count moves by N",
+);
+const ALERT_EVERY: Opt = opt(
+    &["--alert-every"],
+    "DUR",
+    Type::Duration,
+    "Report each alert at most once per DUR per rule and remote (or process);\n\
+     repeats are counted and reported with the next one",
+);
+const DATA_CAP: Opt = opt(
+    &["--data-cap"],
+    "BYTES/WIN",
+    Type::Text,
+    "With --watch: alert when one TCP connection moves more than BYTES\n\
+     in WIN (500M/60s)",
+);
+const CAP_ACTION: Opt = opt(
+    &["--cap-action"],
+    "ACTION",
+    Type::Text,
+    "alert (default), kill (close the connection) or block (firewall its\n\
+     remote)",
+)
+.default("alert")
+.choices(&["alert", "kill", "block"]);
+const DRY_RUN_CAP: Opt = flag(
+    &["--dry-run"],
+    "With --cap-action: show the operations instead of running them",
+);
+const AUDIT_LOG_CAP: Opt = opt(
+    &["--audit-log"],
+    "FILE",
+    Type::Path,
+    "With --cap-action: append every action taken to FILE (JSON lines)",
+);
+const IDLE_AFTER: Opt = opt(
+    &["--idle-after"],
+    "N",
+    Type::Number,
+    "With --watch: mark TCP connections IDLE once their byte counters stood\n\
+     still for N refreshes (Linux; default: 3)",
+)
+.default("3");
+const IDLE_ONLY: Opt = flag(
+    &["--idle-only"],
+    "With --watch: list only IDLE connections (see --idle-after)",
+);
+const ACTIVE_ONLY: Opt = flag(
+    &["--active-only"],
+    "With --watch: list only connections that moved bytes in the last\n\
+     --idle-after refreshes",
+);
+const TIMEOUT: Opt = opt(
+    &["--timeout"],
+    "SECS",
+    Type::Number,
+    "Abandon pending work (sampling, lookups) after SECS and print what is\n\
+     available; ends --watch",
+);
+const CORRELATE: Opt = flag(
+    &["--correlate"],
+    "Flag processes whose disk writes track net Rx (or reads track Tx)",
+);
+const OVERVIEW: Opt = flag(
+    &["--overview"],
+    "Top 5 processes by Tx, Rx and connections, and busiest listeners",
+);
+const PORT_REPORT: Opt = flag(
+    &["--port-report"],
+    "Summarize connections and Rx/Tx by destination port category",
+);
+const BY_USER: Opt = flag(
+    &["--by-user"],
+    "Summarize connections, listeners and Rx/Tx per user account",
+);
+const GROUP_BY: Opt = opt(
+    &["--group-by"],
+    "KEY",
+    Type::Text,
+    "One line per process, remote-host, remote-port, state or tag with totals",
+)
+.choices(&["process", "remote-host", "remote-port", "state", "tag"]);
+const COMPARE_RATES: Opt = opt(
+    &["--compare-rates"],
+    "FILE",
+    Type::Path,
+    "Per process, Rx/Tx now against a snapshot written with --full --output\n\
+     FILE, with the change in percent",
+);
+const SUMMARY: Opt = flag(
+    &["--summary"],
+    "Print totals per state and protocol, unique remote hosts and Rx/Tx\n\
+     after the table",
+);
+const COLUMNS_OPT: Opt = opt(
+    &["--columns"],
+    "LIST",
+    Type::Text,
+    "Print exactly these columns, in this order (proto,laddr,raddr,state,\n\
+     pid,exe,cpu,rx,tx; also id, user, process, r, w, accept, new, idle,\n\
+     env-hint, corr, inode, scope, fd, ping, path, tags, geo, asn, change)",
+)
+.list()
+.choices(&COLUMNS);
+
+pub static LIST: Command = Command {
+    name: "list",
+    synopsis: "[list] [OPTIONS]",
+    usage: &["[list] [OPTIONS]"],
+    summary: "List sockets with their owning processes",
+    about: &[
+        "Installed as `lsportw` it starts as `inventory`; as `nettopw`, as `--tui --sort tx`.",
+    ],
+    sections: &[
+        Section {
+            title: "Options",
+            note: "",
+            options: &[
+                HELP,
+                flag(
+                    &["--help-json"],
+                    "Describe every command and option as JSON and exit",
+                ),
+                flag(
+                    &["-f", "--full"],
+                    "Show CPU/Disk/IO and per-process net columns",
+                ),
+                SORT,
+                SAMPLE_INTERVAL,
+                TOP,
+                TUI,
+                opt(
+                    &["-w", "--watch"],
+                    "[SECS]",
+                    Type::Number,
+                    "Refresh the output every SECS seconds (default: 2) until Ctrl-C",
+                )
+                .optional()
+                .default("2"),
+                NO_CHANGES,
+                ALERT_CONN,
+                ALERT_LISTEN,
+                ALERT_EVERY,
+                DATA_CAP,
+                CAP_ACTION,
+                DRY_RUN_CAP,
+                AUDIT_LOG_CAP,
+                IDLE_AFTER,
+                IDLE_ONLY,
+                ACTIVE_ONLY,
+                TIMEOUT,
+                flag(
+                    &["--forensic"],
+                    "Read-only: refuse state-modifying options; list the APIs queried",
+                ),
+                flag(
+                    &["--json-warnings"],
+                    "Write warnings to stderr as JSON lines",
+                ),
+                flag(
+                    &["--capabilities"],
+                    "Show what this user can see (PIDs, paths, Rx/Tx) and exit",
+                ),
+            ],
+        },
+        Section {
+            title: "Output",
+            note: "",
+            options: &[
+                flag(&["--ids"], "Show a short stable ID for each connection"),
+                flag(
+                    &["--resolve"],
+                    "Show remote host names (reverse DNS, at most 1s; numeric on timeout)",
+                ),
+                flag(
+                    &["--no-idn"],
+                    "With --resolve: keep xn-- (punycode) names as they are, not decoded",
+                ),
+                flag(
+                    &["--names"],
+                    "Show service names for ports, e.g. :443 (https)",
+                ),
+                flag(
+                    &["--probe-rtt"],
+                    "PING column: TCP handshake round trip to each remote host (up to 32)",
+                ),
+                flag(
+                    &["--path-hints"],
+                    "PATH column: flag TCP connections that look like MTU blackholes or\n\
+                     stalls (Linux)",
+                ),
+                opt(
+                    &["--geoip"],
+                    "FILE",
+                    Type::Path,
+                    "GEO and ASN columns from a MaxMind DB (GeoLite2 Country/City/ASN);\n\
+                     repeatable",
+                ),
+                opt(
+                    &["--tag-rules"],
+                    "FILE",
+                    Type::Path,
+                    "Tag rules ([tag.NAME] sections); default: those in the config file",
+                ),
+                opt(
+                    &["--host-policy"],
+                    "[FILE]",
+                    Type::Path,
+                    "Alert on connections to host names a [policy.NAME] rule denies\n\
+                     (implies --resolve; default: the rules in the config file)",
+                )
+                .optional(),
+                flag(&["--inode"], "Show the socket inode (Linux)"),
+                flag(
+                    &["--scope"],
+                    "Show SCOPE: whether the remote is loopback, link-local, private or\n\
+                     public",
+                ),
+                flag(
+                    &["--fd"],
+                    "Show the owning process's fd number(s) for the socket (Linux)",
+                ),
+                opt(
+                    &["--seen-db"],
+                    "FILE",
+                    Type::Path,
+                    "Track remote hosts in FILE and mark never-seen remotes NEW",
+                ),
+                flag(
+                    &["--env-hints"],
+                    "For your own listeners, compare the port with PORT/ASPNETCORE_URLS",
+                ),
+                flag(
+                    &["--hide-synth"],
+                    "Hide connections made by `netstatw synth`",
+                ),
+                flag(
+                    &["--merge-dualstack"],
+                    "Show 0.0.0.0:P and [::]:P listeners of one process as `*:P (v4+v6)`",
+                ),
+                PORT_REPORT,
+                BY_USER,
+                GROUP_BY,
+                COMPARE_RATES,
+                SUMMARY,
+                COLUMNS_OPT,
+                opt(
+                    &["--max-age"],
+                    "DUR",
+                    Type::Duration,
+                    "Reuse the socket list of a run less than DUR old (e.g. 2s), for\n\
+                     polling scripts",
+                ),
+                flag(
+                    &["--include-self"],
+                    "Keep netstatw's own sockets (RTT probes), hidden by default",
+                ),
+                flag(
+                    &["--cmdline"],
+                    "Show each process's full command line instead of its path (PROCESS,\n\
+                     EXE)",
+                ),
+                opt(
+                    &["--color"],
+                    "WHEN",
+                    Type::Text,
+                    "Color the table: auto (default; a terminal, honoring NO_COLOR,\n\
+                     CLICOLOR, CLICOLOR_FORCE and TERM=dumb), always, never",
+                )
+                .default("auto")
+                .choices(&["auto", "always", "never"]),
+                opt(
+                    &["--theme"],
+                    "NAME",
+                    Type::Text,
+                    "Colors for the table and the TUI: dark, light, high-contrast,\n\
+                     no-color (default: `theme` in the config file's [display]\n\
+                     section, else dark)",
+                )
+                .choices(&crate::color::THEMES),
+                opt(
+                    &["--color-cpu"],
+                    "PCT",
+                    Type::Number,
+                    "Highlight CPU% above PCT (default: 50)",
+                )
+                .default("50"),
+                opt(
+                    &["--color-rate"],
+                    "RATE",
+                    Type::Size,
+                    "Highlight Rx/Tx above RATE per second (default: 1M)",
+                )
+                .default("1M"),
+                OVERVIEW,
+                CORRELATE,
+                flag(
+                    &["--json"],
+                    "Print the rows as a JSON array instead of the table",
+                ),
+                flag(
+                    &["--oneshot-json"],
+                    "Print one snapshot document (as for --output and diff) and exit",
+                ),
+                flag(
+                    &["--csv"],
+                    "Print the rows as CSV with a header line instead of the table",
+                ),
+                opt(
+                    &["--delimiter"],
+                    "CHAR",
+                    Type::Text,
+                    "Field separator for --csv and --format csv (default: ','; 'tab' for\n\
+                     tabs)",
+                )
+                .default(","),
+                opt(
+                    &["-o", "--output"],
+                    "FILE",
+                    Type::Path,
+                    "Also write the rows to FILE (the table still prints)",
+                ),
+                opt(
+                    &["--record"],
+                    "FILE",
+                    Type::Path,
+                    "Append each snapshot to FILE (JSON Lines), e.g. with --watch",
+                ),
+                opt(
+                    &["--replay"],
+                    "FILE",
+                    Type::Path,
+                    "Show the snapshots recorded in FILE instead of live sockets",
+                ),
+                opt(
+                    &["--format"],
+                    "FMT",
+                    Type::Text,
+                    "Format for --output: json (default) or csv",
+                )
+                .default("json")
+                .choices(&["json", "csv"]),
+            ],
+        },
+        Section {
+            title: "Filters",
+            note: "different filters must all match; repeating one matches any of its values",
+            options: &[
+                opt(&["--id"], "ID", Type::Text, "Connection ID or ID prefix"),
+                opt(
+                    &["--process"],
+                    "NAME",
+                    Type::Text,
+                    "Process path/name contains NAME (case-insensitive)",
+                ),
+                opt(
+                    &["--tag"],
+                    "NAME",
+                    Type::Text,
+                    "Row carries tag NAME (see --tag-rules)",
+                ),
+                opt(
+                    &["--country"],
+                    "CC[,CC...]",
+                    Type::Text,
+                    "Remote address is in one of these countries (ISO codes; needs\n\
+                     --geoip)",
+                )
+                .list(),
+                opt(
+                    &["--user"],
+                    "NAME",
+                    Type::Text,
+                    "Owning account is NAME (case-insensitive; DOMAIN\\ optional)",
+                ),
+                opt(
+                    &["--port"],
+                    "PORT[,PORT...]",
+                    Type::Port,
+                    "Local or remote port is one of the listed ports; also wildcards\n\
+                     (`3??0`, `80*`) and template names from [ports] in the config",
+                )
+                .list(),
+                opt(
+                    &["--lport"],
+                    "PORT[,PORT...]",
+                    Type::Port,
+                    "Local port is one of the listed ports",
+                )
+                .list(),
+                opt(
+                    &["--rport"],
+                    "PORT[,PORT...]",
+                    Type::Port,
+                    "Remote port is one of the listed ports",
+                )
+                .list(),
+                opt(
+                    &["--local-net"],
+                    "NET[,NET...]",
+                    Type::Network,
+                    "Local address is in one of these networks (10.0.0.0/8, fd00::/8)",
+                )
+                .list(),
+                opt(
+                    &["--remote-net"],
+                    "NET[,NET...]",
+                    Type::Network,
+                    "Remote address is in one of these networks",
+                )
+                .list(),
+                flag(&["-4", "-6"], "Only IPv4 / only IPv6 sockets"),
+                opt(
+                    &["--zone"],
+                    "IF",
+                    Type::Text,
+                    "Link-local IPv6 sockets on interface IF (fe80::1%IF)",
+                ),
+                flag(&["--tcp", "--udp"], "Only TCP / only UDP sockets"),
+                opt(
+                    &["-p", "--proto"],
+                    "P[,P...]",
+                    Type::Text,
+                    "Only these protocols: tcp, udp",
+                )
+                .list()
+                .choices(&["tcp", "udp"]),
+                flag(
+                    &["-l", "--listening"],
+                    "Only TCP listeners and bound UDP sockets",
+                ),
+                flag(
+                    &["--no-loopback"],
+                    "Drop traffic within the host (127.0.0.0/8, ::1 local or remote)",
+                ),
+                flag(
+                    &["--external-only"],
+                    "Only connections to other hosts beyond the link (no loopback,\n\
+                     link-local or unconnected sockets)",
+                ),
+                opt(
+                    &["--state"],
+                    "S[,S...]",
+                    Type::Text,
+                    "TCP state is one of the listed, e.g. established,time_wait",
+                )
+                .list()
+                .choices(&crate::filter::TCP_STATES),
+                flag(
+                    &["--not"],
+                    "Exclude rows matching the filter that follows, e.g. `--not --port 22`",
+                ),
+                flag(
+                    &["--stdin"],
+                    "Read connection IDs from stdin (e.g. piped `--ids` output)",
+                ),
+            ],
+        },
+        Section {
+            title: "Actions",
+            note: "",
+            options: &[
+                opt(
+                    &["-e", "--exec"],
+                    "CMD",
+                    Type::Text,
+                    "Run CMD for each listed row (with --watch, each row new since\n\
+                     the last refresh); placeholders: {proto} {laddr} {lip}\n\
+                     {lport} {raddr} {rip} {rport} {state} {pid} {process}",
+                ),
+                opt(
+                    &["--exec-max"],
+                    "N",
+                    Type::Number,
+                    "Maximum concurrently running --exec commands (default: 4)",
+                )
+                .default("4"),
+                opt(
+                    &["--exec-throttle"],
+                    "MS",
+                    Type::Number,
+                    "Delay between --exec command launches (default: 200)",
+                )
+                .default("200"),
+            ],
+        },
+    ],
+    notes: &[],
+};
+
+pub static WATCH: Command = Command {
+    name: "watch",
+    synopsis: "watch [SECS] [OPTIONS]",
+    usage: &["watch [SECS] [OPTIONS]"],
+    summary: "Refresh until Ctrl-C (see `watch --help`)",
+    about: &[
+        "Refresh the listing every SECS seconds (default: 2) until Ctrl-C; the same as `--watch`.",
+    ],
+    sections: &[Section {
+        title: "Options",
+        note: "",
+        options: &[
+            HELP,
+            ALERT_CONN,
+            ALERT_LISTEN,
+            ALERT_EVERY,
+            DATA_CAP,
+            CAP_ACTION,
+            DRY_RUN_CAP,
+            AUDIT_LOG_CAP,
+            NO_CHANGES,
+            IDLE_AFTER,
+            IDLE_ONLY,
+            ACTIVE_ONLY,
+            TIMEOUT,
+            TUI,
+        ],
+    }],
+    notes: &["Every option of `netstatw list` applies as well (see `netstatw --help`)."],
+};
+
+pub static STATS: Command = Command {
+    name: "stats",
+    synopsis: "stats [OPTIONS]",
+    usage: &["stats [OPTIONS]"],
+    summary: "With CPU/disk/net rates (see `stats --help`)",
+    about: &[
+        "List the sockets with per-process CPU, disk and network rates; the same as `--full`.",
+    ],
+    sections: &[Section {
+        title: "Options",
+        note: "",
+        options: &[
+            HELP,
+            SORT,
+            SAMPLE_INTERVAL,
+            TOP,
+            CORRELATE,
+            OVERVIEW,
+            PORT_REPORT,
+            BY_USER,
+            GROUP_BY,
+            COMPARE_RATES,
+            SUMMARY,
+            COLUMNS_OPT,
+        ],
+    }],
+    notes: &["Every option of `netstatw list` applies as well (see `netstatw --help`)."],
+};
+
+pub static VIEW: Command = Command {
+    name: "view",
+    synopsis: "view [NAME] [ARGS...]",
+    usage: &["view [NAME] [ARGS...]"],
+    summary: "Run a view from the config file",
+    about: &[
+        "Run netstatw with the arguments stored in `[view.NAME]` of the config file, followed\n\
+         by ARGS. Without NAME, list the views.",
+    ],
+    sections: &[Section {
+        title: "Options",
+        note: "",
+        options: &[HELP],
+    }],
+    notes: &[],
+};
+
+pub static SYNTH: Command = Command {
+    name: "synth",
+    synopsis: "synth --connect HOST:PORT [--rate N]",
+    usage: &["synth --connect HOST:PORT [OPTIONS]"],
+    summary: "Synthetic connections (see `synth --help`)",
+    about: &["Open TCP connections at a fixed rate to exercise the connection monitors."],
+    sections: &[Section {
+        title: "Options",
+        note: "",
+        options: &[
+            HELP,
+            opt(
+                &["-c", "--connect"],
+                "HOST:PORT",
+                Type::Address,
+                "Target to connect to (required)",
+            ),
+            opt(
+                &["-r", "--rate"],
+                "N",
+                Type::Number,
+                "New connections per second (default: 10)",
+            )
+            .default("10"),
+            opt(
+                &["--hold"],
+                "MS",
+                Type::Number,
+                "Keep each connection open for MS ms (default: 1000)",
+            )
+            .default("1000"),
+            opt(
+                &["-d", "--duration"],
+                "SECS",
+                Type::Number,
+                "Stop after SECS seconds (default: run until killed)",
+            ),
+        ],
+    }],
+    notes: &[],
+};
+
+pub static INVENTORY: Command = Command {
+    name: "inventory",
+    synopsis: "inventory [--json]",
+    usage: &["inventory [--json | --format ini|yaml]"],
+    summary: "Listening ports per process, with exe SHA-256",
+    about: &[
+        "List each process with listening sockets: executable, its SHA-256, owning user\n\
+         and the ports it listens on (TCP listeners and bound UDP sockets).",
+    ],
+    sections: &[Section {
+        title: "Options",
+        note: "",
+        options: &[
+            HELP,
+            flag(
+                &["--json"],
+                "Print a compact JSON document instead of a table",
+            ),
+            opt(
+                &["--format"],
+                "FMT",
+                Type::Text,
+                "Listeners by port for Ansible: a facts.d file (ini) or an\n\
+                 inventory with a netstatw_listeners host variable (yaml); json as --json",
+            )
+            .choices(&["ini", "yaml", "json"]),
+        ],
+    }],
+    notes: &[],
+};
+
+pub static INVENTORY_DIFF: Command = Command {
+    name: "inventory-diff",
+    synopsis: "inventory-diff BASE.json OTHER.json",
+    usage: &["inventory-diff BASELINE.json OTHER.json [--json]"],
+    summary: "Listener drift between two inventories",
+    about: &[
+        "Compare two `netstatw inventory --json` documents (two hosts, or a host against\n\
+         a golden image) listener by listener. Listeners are matched by protocol, port and\n\
+         scope (*, loopback or a specific address), not by PID. Reports listeners missing\n\
+         from OTHER, extra on OTHER, and listeners owned by a different program or by an\n\
+         executable with a different SHA-256. Exits 0 without drift, 1 with drift.",
+    ],
+    sections: &[Section {
+        title: "Options",
+        note: "",
+        options: &[
+            HELP,
+            flag(&["--json"], "Print the drift as JSON instead of a table"),
+        ],
+    }],
+    notes: &[],
+};
+
+pub static DIFF: Command = Command {
+    name: "diff",
+    synopsis: "diff BASE.json [NEW.json]",
+    usage: &["diff BASELINE.json [CURRENT.json] [--json]"],
+    summary: "Connections opened/closed since a snapshot",
+    about: &[
+        "Compare two snapshots written by `netstatw --output FILE` (or `--json` output), or\n\
+         a snapshot against the sockets open now, and list the connections and listeners\n\
+         that were opened, closed, or changed state. Exits 0 without changes, 1 with.",
+    ],
+    sections: &[Section {
+        title: "Options",
+        note: "",
+        options: &[
+            HELP,
+            flag(&["--json"], "Print the changes as JSON instead of a table"),
+        ],
+    }],
+    notes: &[],
+};
+
+pub static EVENTS: Command = Command {
+    name: "events",
+    synopsis: "events [--json] [-i SECS]",
+    usage: &["events [OPTIONS]"],
+    summary: "One line per connection opened/closed/changed",
+    about: &[
+        "Poll the socket table and print one line per connection opened, closed or changed\n\
+         state, e.g. `netstatw events --json | vector ...`. Runs until Ctrl-C.",
+    ],
+    sections: &[Section {
+        title: "Options",
+        note: "",
+        options: &[
+            HELP,
+            opt(
+                &["-i", "--interval"],
+                "SECS",
+                Type::Number,
+                "Time between polls (default: 1)",
+            )
+            .default("1"),
+            opt(
+                &["-d", "--duration"],
+                "SECS",
+                Type::Number,
+                "Stop after SECS (default: until Ctrl-C)",
+            ),
+            flag(&["--json"], "One JSON object per line instead of text"),
+            flag(&["--tcp"], "Only TCP sockets"),
+            flag(&["--udp"], "Only UDP sockets"),
+            flag(
+                &["--initial"],
+                "Report the sockets open at start as opened too",
+            ),
+            opt(
+                &["--short-lived"],
+                "SECS",
+                Type::Number,
+                "Flag listeners that close within SECS of opening; 0 turns it off (default: 10)",
+            )
+            .default("10"),
+        ],
+    }],
+    notes: &[],
+};
+
+pub static GRAPH: Command = Command {
+    name: "graph",
+    synopsis: "graph --dot|--mermaid [-d SECS]",
+    usage: &["graph --dot|--mermaid [OPTIONS]"],
+    summary: "Process/remote dependency graph",
+    about: &[
+        "Record TCP connections and print a graph of the local processes and the remote\n\
+         endpoints they talk to, e.g. `netstatw graph --dot -d 60 | dot -Tsvg`.",
+    ],
+    sections: &[Section {
+        title: "Options",
+        note: "",
+        options: &[
+            HELP,
+            flag(&["--dot"], "Graphviz DOT output"),
+            flag(
+                &["--mermaid"],
+                "Mermaid flowchart (renders in Markdown docs)",
+            ),
+            opt(
+                &["-d", "--duration"],
+                "SECS",
+                Type::Number,
+                "Recording window (default: 0, a single snapshot)",
+            )
+            .default("0"),
+            opt(
+                &["-i", "--interval"],
+                "SECS",
+                Type::Number,
+                "Time between snapshots (default: 1)",
+            )
+            .default("1"),
+            opt(
+                &["--by"],
+                "NODES",
+                Type::Text,
+                "Remote nodes per endpoint (host:port, the default), per host, or\n\
+                 per tag from the tag rules (untagged: per endpoint)",
+            )
+            .default("endpoint")
+            .choices(&["endpoint", "host", "tag"]),
+            opt(
+                &["--tag-rules"],
+                "FILE",
+                Type::Path,
+                "Tag rules for --by tag (default: the config file)",
+            ),
+        ],
+    }],
+    notes: &[],
+};
+
+pub static KILL: Command = Command {
+    name: "kill",
+    synopsis: "kill LOCAL REMOTE | --id ID",
+    usage: &[
+        "kill LOCAL REMOTE",
+        "kill --id ID",
+        "kill --pid PID --port PORT [--all]",
+        "kill --stdin [--all] < ROWS",
+    ],
+    summary: "Close a TCP connection (see `kill --help`)",
+    about: &[
+        "Close TCP connections, picked by their address pair (as shown in the LOCAL and\n\
+         REMOTE ADDRESS columns), by connection ID (`--ids`), by owning PID and a local\n\
+         or remote port, or as rows piped in from `netstatw --json` or `--ids`. Needs root (Linux) or an elevated prompt (Windows; IPv4 only).",
+    ],
+    sections: &[Section {
+        title: "Options",
+        note: "",
+        options: &[
+            HELP,
+            opt(&["--id"], "ID", Type::Text, "Connection ID or ID prefix"),
+            opt(
+                &["--pid"],
+                "PID",
+                Type::Number,
+                "Connections owned by PID (with --port)",
+            ),
+            opt(
+                &["--port"],
+                "PORT",
+                Type::Number,
+                "... whose local or remote port is PORT",
+            ),
+            flag(
+                &["--stdin"],
+                "The connections on stdin: `netstatw --json` rows (or JSON lines), or\n\
+                 lines starting with an ID from `--ids`",
+            ),
+            flag(
+                &["--all"],
+                "Close every match (by default only a single one)",
+            ),
+            flag(
+                &["--dry-run"],
+                "Print the connections and the calls that would close them",
+            ),
+            opt(
+                &["--audit-log"],
+                "FILE",
+                Type::Path,
+                "Append every close (and its result) to FILE as a JSON line",
+            ),
+            flag(
+                &["--forensic"],
+                "Refused: closing a connection modifies the system",
+            ),
+        ],
+    }],
+    notes: &[],
+};
+
+pub static SERVE: Command = Command {
+    name: "serve",
+    synopsis: "serve --prometheus :PORT",
+    usage: &["serve --prometheus [HOST]:PORT [OPTIONS]"],
+    summary: "Prometheus metrics exporter (see `serve --help`)",
+    about: &[
+        "Serve socket and per-process network metrics for Prometheus on http://ADDR/metrics,\n\
+         e.g. `netstatw serve --prometheus :9184` (this host only). Runs until Ctrl-C.",
+    ],
+    sections: &[Section {
+        title: "Options",
+        note: "",
+        options: &[
+            HELP,
+            opt(
+                &["--prometheus"],
+                "ADDR",
+                Type::Address,
+                "Listen address; `:PORT` is 127.0.0.1:PORT, give `0.0.0.0:PORT`\n\
+                 to expose process names, PIDs and peers to the network",
+            ),
+            opt(
+                &["--interval"],
+                "SECS",
+                Type::Number,
+                "Time between snapshots (default: 15)",
+            )
+            .default("15"),
+            opt(
+                &["-i", "--sample-interval"],
+                "MS",
+                Type::Number,
+                "Rate sampling window per snapshot (default: 800)",
+            )
+            .default("800"),
+            opt(
+                &["--tag-rules"],
+                "FILE",
+                Type::Path,
+                "Tag rules for the per-tag metrics; default: those in the config file",
+            ),
+            opt(
+                &["--exporter-labels"],
+                "LIST",
+                Type::Text,
+                "Labels of netstatw_connections: proto, state, process\n\
+                 (default: proto,state)",
+            )
+            .list()
+            .choices(&["proto", "state", "process"]),
+            opt(
+                &["--top-processes"],
+                "N",
+                Type::Number,
+                "Processes with their own series; the rest are summed as \"other\"\n\
+                 (default: 20, 0 for all)",
+            )
+            .default("20"),
+            flag(
+                &["--include-self"],
+                "Count the exporter's own listener and connections too",
+            ),
+            flag(
+                &["--harden"],
+                "Drop what the exporter never needs before serving: seccomp on\n\
+                 Linux, process mitigation policies on Windows",
+            ),
+            flag(&["--forensic"], "Refused: serving opens a listening socket"),
+        ],
+    }],
+    notes: &[],
+};
+
+pub static BLOCKS: Command = Command {
+    name: "blocks",
+    synopsis: "blocks list | clear [--older-than 1h]",
+    usage: &[
+        "blocks list [--json]",
+        "blocks clear [--older-than DURATION] [--dry-run]",
+    ],
+    summary: "Firewall blocks added by --cap-action block",
+    about: &[
+        "List or remove the firewall rules netstatw added (`--cap-action block`), which are\n\
+         named netstatw-block-<ip>-<unix time>. Other rules are never touched.\n\
+         Needs root (Linux) or an elevated prompt (Windows).",
+    ],
+    sections: &[Section {
+        title: "Options",
+        note: "",
+        options: &[
+            HELP,
+            flag(&["--json"], "list: print the blocks as a JSON array"),
+            opt(
+                &["--older-than"],
+                "DURATION",
+                Type::Duration,
+                "clear: only blocks at least this old (90s, 30m, 1h)",
+            ),
+            flag(
+                &["--dry-run"],
+                "clear: print the blocks and commands instead of running them",
+            ),
+            flag(&["--forensic"], "list only: clearing modifies the firewall"),
+        ],
+    }],
+    notes: &[],
+};
+
+pub static COMPACT: Command = Command {
+    name: "compact",
+    synopsis: "compact FILE [--keep-raw 24h]",
+    usage: &["compact FILE [--keep-raw DURATION] [--rollup DURATION] [--dry-run]"],
+    summary: "Roll up old snapshots of a --record file",
+    about: &[
+        "Shrink a `--record` history: snapshots older than --keep-raw are merged into one per\n\
+         --rollup window, listing every connection seen in it with its average rates.\n\
+         The file is rewritten in place; snapshots recorded meanwhile are kept.",
+    ],
+    sections: &[Section {
+        title: "Options",
+        note: "",
+        options: &[
+            HELP,
+            opt(
+                &["--keep-raw"],
+                "DURATION",
+                Type::Duration,
+                "Keep snapshots younger than this as they are (default: 24h)",
+            )
+            .default("24h"),
+            opt(
+                &["--rollup"],
+                "DURATION",
+                Type::Duration,
+                "One snapshot per window of this length for older ones (default: 5m)",
+            )
+            .default("5m"),
+            flag(
+                &["--dry-run"],
+                "Print how many snapshots would remain without rewriting the file",
+            ),
+            flag(&["--forensic"], "Refused: compacting rewrites the file"),
+        ],
+    }],
+    notes: &[],
+};
+
+pub static ASSERT: Command = Command {
+    name: "assert",
+    synopsis: "assert --no-listeners-except PORTS",
+    usage: &["assert [--no-listeners-except PORTS] [--no-egress-except NETS] [OPTIONS]"],
+    summary: "CI check of open sockets (see `assert --help`)",
+    about: &[
+        "Check that only the expected sockets are open: exit 0 if so, 1 with the list of\n\
+         violations if not, e.g. `netstatw assert --no-listeners-except 22,443\n\
+         --no-egress-except 10.0.0.0/8` at the end of an integration test.",
+    ],
+    sections: &[Section {
+        title: "Options",
+        note: "",
+        options: &[
+            HELP,
+            opt(
+                &["--no-listeners-except"],
+                "PORTS",
+                Type::Port,
+                "Allowed listening ports (comma-separated, wildcards, port\n\
+                 templates); `none` allows no listener",
+            )
+            .list(),
+            opt(
+                &["--no-egress-except"],
+                "NETS",
+                Type::Network,
+                "Allowed networks for outgoing connections (CIDRs,\n\
+                 comma-separated); `none` allows no egress",
+            )
+            .list(),
+            opt(
+                &["-d", "--duration"],
+                "SECS",
+                Type::Number,
+                "Poll the socket tables for SECS (default: 0, one snapshot)",
+            )
+            .default("0"),
+            opt(
+                &["-i", "--interval"],
+                "SECS",
+                Type::Number,
+                "Time between polls (default: 0.5)",
+            )
+            .default("0.5"),
+            flag(
+                &["--json"],
+                "Print {\"ok\":...,\"violations\":[...]} instead of text",
+            ),
+        ],
+    }],
+    notes: &[],
+};
+
+// In the order of the main help's list of commands.
+pub static COMMANDS: [&Command; 15] = [
+    &LIST,
+    &WATCH,
+    &STATS,
+    &SYNTH,
+    &VIEW,
+    &INVENTORY,
+    &INVENTORY_DIFF,
+    &DIFF,
+    &EVENTS,
+    &GRAPH,
+    &KILL,
+    &SERVE,
+    &BLOCKS,
+    &COMPACT,
+    &ASSERT,
+];
+
+// Where option descriptions start.
+const HELP_COLUMN: usize = 29;
+
+// "  -w, --watch [SECS]" as listed in the help.
+fn option_names(o: &Opt) -> String {
+    let mut left = o.names.join(", ");
+    if let Some(v) = &o.value {
+        left.push(' ');
+        left.push_str(v.placeholder);
+    }
+    // Long-only options line up after the short ones.
+    if o.names.first().is_some_and(|n| !n.starts_with("--")) {
+        format!("  {}", left)
+    } else {
+        format!("      {}", left)
+    }
+}
+
+// `cmd`'s `--help` text; `program` is the name usage lines start with.
+pub fn help(cmd: &Command, program: &str) -> String {
+    let mut out = String::new();
+    for (i, usage) in cmd.usage.iter().enumerate() {
+        let prefix = if i == 0 { "Usage:" } else { "      " };
+        out.push_str(&format!("{} {} {}\n", prefix, program, usage));
+    }
+    // The main help lists the other commands.
+    if cmd.name == LIST.name {
+        let width = COMMANDS[1..]
+            .iter()
+            .map(|c| c.synopsis.len())
+            .max()
+            .unwrap_or(0);
+        for c in &COMMANDS[1..] {
+            out.push_str(&format!(
+                "       {} {:<width$}  {}\n",
+                program, c.synopsis, c.summary
+            ));
+        }
+    }
+    for paragraph in cmd.about {
+        out.push('\n');
+        out.push_str(paragraph);
+        out.push('\n');
+    }
+    for section in cmd.sections {
+        out.push('\n');
+        match section.note {
+            "" => out.push_str(&format!("{}:\n", section.title)),
+            note => out.push_str(&format!("{} ({}):\n", section.title, note)),
+        }
+        for o in section.options {
+            let names = option_names(o);
+            let mut lines = o.help.lines();
+            let first = lines.next().unwrap_or_default();
+            if names.len() + 2 > HELP_COLUMN {
+                out.push_str(&format!("{}  {}\n", names, first));
+            } else {
+                out.push_str(&format!("{:<HELP_COLUMN$}{}\n", names, first));
+            }
+            for line in lines {
+                out.push_str(&format!("{:HELP_COLUMN$}{}\n", "", line.trim_start()));
+            }
+        }
+    }
+    for note in cmd.notes {
+        out.push('\n');
+        out.push_str(note);
+        out.push('\n');
+    }
+    out
+}
+
+// A subcommand's `--help`.
+pub fn print_help(cmd: &Command) {
+    print!("{}", help(cmd, "netstatw"));
+}
+
+// Help text lines as one line.
+fn joined(text: &str) -> String {
+    text.lines().map(str::trim).collect::<Vec<_>>().join(" ")
+}
+
+// "[SECS]" -> "SECS", "PORT[,PORT...]" -> "PORT".
+fn item(placeholder: &str) -> &str {
+    let bare = placeholder
+        .strip_prefix('[')
+        .and_then(|p| p.strip_suffix(']'))
+        .unwrap_or(placeholder);
+    bare.split("[,").next().unwrap_or(bare)
+}
+
+fn option_json(o: &Opt, section: &str) -> String {
+    let value = match &o.value {
+        None => "null".to_string(),
+        Some(v) => {
+            let (kind, choices) = if v.choices.is_empty() {
+                (v.kind.name(), "null".to_string())
+            } else {
+                let choices: Vec<String> = v.choices.iter().map(|c| json::str(c)).collect();
+                ("enum", json::array(&choices))
+            };
+            json::object(&[
+                ("name", json::str(item(v.placeholder))),
+                ("type", json::str(kind)),
+                ("list", v.list.to_string()),
+                ("optional", v.optional.to_string()),
+                ("choices", choices),
+            ])
+        }
+    };
+    let names: Vec<String> = o.names.iter().map(|n| json::str(n)).collect();
+    json::object(&[
+        ("names", json::array(&names)),
+        ("value", value),
+        (
+            "default",
+            json::opt_str(o.value.as_ref().and_then(|v| v.default)),
+        ),
+        ("section", json::str(section)),
+        ("description", json::str(&joined(o.help))),
+    ])
+}
+
+fn command_json(cmd: &Command, program: &str) -> String {
+    let usage: Vec<String> = cmd
+        .usage
+        .iter()
+        .map(|u| json::str(&format!("{} {}", program, u)))
+        .collect();
+    let about: Vec<String> = cmd
+        .about
+        .iter()
+        .chain(cmd.notes)
+        .map(|p| json::str(&joined(p)))
+        .collect();
+    let options: Vec<String> = cmd
+        .sections
+        .iter()
+        .flat_map(|s| s.options.iter().map(|o| option_json(o, s.title)))
+        .collect();
+    json::object(&[
+        ("name", json::str(cmd.name)),
+        ("summary", json::str(cmd.summary)),
+        ("usage", json::array(&usage)),
+        ("description", json::array(&about)),
+        ("options", json::array(&options)),
+    ])
+}
+
+// `--help-json`: every command and option as one document, for wrapper GUIs and
+// completion generators.
+pub fn help_json() -> String {
+    let commands: Vec<String> = COMMANDS
+        .iter()
+        .map(|c| command_json(c, "netstatw"))
+        .collect();
+    json::object(&[
+        ("program", json::str("netstatw")),
+        ("version", json::str(env!("CARGO_PKG_VERSION"))),
+        ("commands", json::array(&commands)),
+    ])
+}
+
+// Command-line tokens, read with the command's option table. Every option takes its
+// value the same ways: as the next argument, attached to a long option with `=`
+// (`--top=5`) or attached to a short one (`-t5`, `-scpu`). Short options can be
+// combined (`-lf`, `-lt5`); an optional value (`-w [SECS]`) is only taken when it
+// is one (`-w5`, but `-wl` is `-w -l`). A value that looks like an option is not
+// taken, so `--process --full` is a missing value rather than a process named
+// `--full`. Callers report the errors and exit with status 2.
+
+fn is_option(token: &str) -> bool {
+    token.len() > 1 && token.starts_with('-')
+}
+
+impl Type {
+    // Whether `token` is a value of this type where the value is optional.
+    fn accepts(self, token: &str) -> bool {
+        match self {
+            Type::Number => token.parse::<f64>().is_ok(),
+            _ => !is_option(token),
+        }
+    }
+}
+
+// An option with the value given with it, or a positional argument.
+type Item = (String, Option<String>);
+
+// Splits `argv` into options and their values. Options missing from `options` are
+// errors if `strict`, flags otherwise.
+fn split(options: &[&Opt], strict: bool, argv: Vec<String>) -> Result<Vec<Item>, String> {
+    let find = |name: &str| options.iter().find(|o| o.names.contains(&name)).copied();
+    let mut tokens = argv.into_iter().peekable();
+    // The separate value of `value`'s option, if the next token is one.
+    let next_value = |value: &Value, tokens: &mut Peekable<IntoIter<String>>| {
+        tokens.next_if(|t| {
+            if value.optional {
+                value.kind.accepts(t)
+            } else {
+                !strict || value.dashed || !is_option(t)
+            }
+        })
+    };
+    let mut items = Vec::new();
+    while let Some(arg) = tokens.next() {
+        if !is_option(&arg) {
+            items.push((arg, None));
+            continue;
+        }
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, attached) = match long.split_once('=') {
+                Some((name, value)) => (format!("--{}", name), Some(value.to_string())),
+                None => (arg.clone(), None),
+            };
+            let value = match find(&name) {
+                None if strict => return Err(format!("unknown option '{}'", name)),
+                None => attached,
+                Some(Opt { value: None, .. }) if strict && attached.is_some() => {
+                    return Err(format!("{} takes no value", name));
+                }
+                Some(Opt { value: None, .. }) => None,
+                Some(Opt {
+                    value: Some(value), ..
+                }) => attached.or_else(|| next_value(value, &mut tokens)),
+            };
+            items.push((name, value));
+            continue;
+        }
+        let mut rest = &arg[1..];
+        while let Some(c) = rest.chars().next() {
+            rest = &rest[c.len_utf8()..];
+            let name = format!("-{}", c);
+            let value = match find(&name) {
+                None if strict => return Err(format!("unknown option '{}'", name)),
+                None | Some(Opt { value: None, .. }) => None,
+                Some(Opt {
+                    value: Some(value), ..
+                }) if rest.is_empty() => next_value(value, &mut tokens),
+                // `-w` without a value, followed by more options (`-wl`).
+                Some(Opt {
+                    value: Some(value), ..
+                }) if value.optional && !value.kind.accepts(rest) => None,
+                Some(_) => Some(std::mem::take(&mut rest).to_string()),
+            };
+            items.push((name, value));
+        }
+    }
+    Ok(items)
+}
+
+pub struct Args {
+    items: IntoIter<Item>,
+    // The value given with the option `next` returned last.
+    value: Option<String>,
+}
+
+impl Args {
+    // The arguments of `cmd`; options it doesn't have are errors.
+    pub fn new(cmd: &Command, argv: Vec<String>) -> Result<Args, String> {
+        let options: Vec<&Opt> = cmd.options().collect();
+        Ok(Args {
+            items: split(&options, true, argv)?.into_iter(),
+            value: None,
+        })
+    }
+
+    // Another program's command line (`ssh -fNL 8080:db:5432 host`): `options` are
+    // the ones that take a value, anything else that looks like an option is taken
+    // for a flag.
+    pub fn foreign(options: &[Opt], argv: Vec<String>) -> Args {
+        let options: Vec<&Opt> = options.iter().collect();
+        Args {
+            items: split(&options, false, argv).unwrap_or_default().into_iter(),
+            value: None,
+        }
+    }
+
+    // Whether `-h`/`--help` is among the arguments.
+    pub fn wants_help(&self) -> bool {
+        self.items
+            .as_slice()
+            .iter()
+            .any(|(name, value)| value.is_none() && (name == "-h" || name == "--help"))
+    }
+
+    // The value of `option`.
+    pub fn value(&mut self, option: &str) -> Result<String, String> {
+        self.value
+            .take()
+            .ok_or_else(|| format!("{} needs a value", option))
+    }
+
+    // The value of `option` parsed as `T` (a number, mostly).
+    pub fn parse<T: FromStr>(&mut self, option: &str) -> Result<T, String>
+    where
+        T::Err: Display,
+    {
+        let v = self.value(option)?;
+        v.parse()
+            .map_err(|e| format!("{}: invalid value '{}': {}", option, v, e))
+    }
+
+    // The value of `option`, a number of seconds (at least 0, not beyond what a
+    // Duration holds).
+    pub fn secs(&mut self, option: &str) -> Result<Duration, String> {
+        let v = self.value(option)?;
+        v.parse::<f64>()
+            .ok()
+            .filter(|s| *s >= 0.0)
+            .and_then(|s| Duration::try_from_secs_f64(s).ok())
+            .ok_or_else(|| format!("{}: invalid value '{}' (expected seconds)", option, v))
+    }
+
+    // The value of an option whose value may be left out (`-w [SECS]`).
+    pub fn optional(&mut self) -> Option<String> {
+        self.value.take()
+    }
+}
+
+impl Iterator for Args {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        let (arg, value) = self.items.next()?;
+        self.value = value;
+        Some(arg)
+    }
+}
+
+// Reads a subcommand's arguments with `parse`. `Err` is the status to exit with,
+// after the help (`-h`) or the error is printed.
+pub fn parse_command<T>(
+    cmd: &Command,
+    argv: Vec<String>,
+    parse: impl FnOnce(Args) -> Result<T, String>,
+) -> Result<T, i32> {
+    let parsed = Args::new(cmd, argv).and_then(|args| {
+        if args.wants_help() {
+            Ok(None)
+        } else {
+            parse(args).map(Some)
+        }
+    });
+    match parsed {
+        Ok(Some(opts)) => Ok(opts),
+        Ok(None) => {
+            print_help(cmd);
+            Err(0)
+        }
+        Err(e) => {
+            eprintln!("netstatw {}: {}", cmd.name, e);
+            Err(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn item(name: &str, value: Option<&str>) -> Item {
+        (name.to_string(), value.map(str::to_string))
+    }
+
+    fn split_list(v: &[&str]) -> Result<Vec<Item>, String> {
+        let options: Vec<&Opt> = LIST.options().collect();
+        split(&options, true, args(v))
+    }
+
+    #[test]
+    fn attached_values_and_short_groups_are_split() {
+        assert_eq!(
+            split_list(&["-i500", "--top=3", "-scpu", "-lf", "-4", "--full", "x"]).unwrap(),
+            vec![
+                item("-i", Some("500")),
+                item("--top", Some("3")),
+                item("-s", Some("cpu")),
+                item("-l", None),
+                item("-f", None),
+                item("-4", None),
+                item("--full", None),
+                item("x", None),
+            ]
+        );
+        assert_eq!(
+            split_list(&["--exec=echo a=b", "-lt", "5"]).unwrap(),
+            vec![
+                item("--exec", Some("echo a=b")),
+                item("-l", None),
+                item("-t", Some("5")),
+            ]
+        );
+        assert_eq!(split_list(&["-lq"]).unwrap_err(), "unknown option '-q'");
+        assert_eq!(
+            split_list(&["--bogus"]).unwrap_err(),
+            "unknown option '--bogus'"
+        );
+        assert_eq!(
+            split_list(&["--json=1"]).unwrap_err(),
+            "--json takes no value"
+        );
+    }
+
+    #[test]
+    fn optional_values_are_taken_only_when_they_are_values() {
+        assert_eq!(
+            split_list(&["-wl"]).unwrap(),
+            vec![item("-w", None), item("-l", None)]
+        );
+        assert_eq!(
+            split_list(&["-lw5", "-w", "2", "-w", "--tcp", "--watch=3"]).unwrap(),
+            vec![
+                item("-l", None),
+                item("-w", Some("5")),
+                item("-w", Some("2")),
+                item("-w", None),
+                item("--tcp", None),
+                item("--watch", Some("3")),
+            ]
+        );
+        assert_eq!(
+            split_list(&["--host-policy", "--resolve"]).unwrap(),
+            vec![item("--host-policy", None), item("--resolve", None)]
+        );
+    }
+
+    #[test]
+    fn values_that_look_like_options_are_not_taken() {
+        let mut a = Args::new(&LIST, args(&["--process", "--full"])).unwrap();
+        assert_eq!(a.next().as_deref(), Some("--process"));
+        assert_eq!(a.value("--process").unwrap_err(), "--process needs a value");
+        assert_eq!(a.next().as_deref(), Some("--full"));
+        // Sort keys may start with a dash.
+        assert_eq!(
+            split_list(&["--sort", "-cpu,+lport", "-s", "-tx", "-s-rx"]).unwrap(),
+            vec![
+                item("--sort", Some("-cpu,+lport")),
+                item("-s", Some("-tx")),
+                item("-s", Some("-rx")),
+            ]
+        );
+    }
+
+    #[test]
+    fn subcommands_take_values_every_way() {
+        for (argv, name) in [
+            (&["-i1"][..], "-i"),
+            (&["--interval=1"], "--interval"),
+            (&["-i", "1"], "-i"),
+        ] {
+            let mut a = Args::new(&EVENTS, args(argv)).unwrap();
+            assert_eq!(a.next().as_deref(), Some(name));
+            assert_eq!(a.secs(name), Ok(Duration::from_secs(1)));
+        }
+        for bad in ["x", "inf", "1e300"] {
+            let mut a = Args::new(&EVENTS, args(&["-d", bad])).unwrap();
+            a.next();
+            assert_eq!(
+                a.secs("--duration").unwrap_err(),
+                format!("--duration: invalid value '{}' (expected seconds)", bad)
+            );
+        }
+        assert!(Args::new(&EVENTS, args(&["--full"])).is_err());
+        assert!(
+            Args::new(&EVENTS, args(&["--json", "-h"]))
+                .unwrap()
+                .wants_help()
+        );
+    }
+
+    #[test]
+    fn foreign_command_lines_take_unknown_options_for_flags() {
+        const SSH: [Opt; 2] = [
+            opt(&["-L"], "SPEC", Type::Text, ""),
+            opt(&["-o"], "OPTION", Type::Text, ""),
+        ];
+        let mut a = Args::foreign(&SSH, args(&["-fNL8080:db:5432", "-o", "X=1", "-v", "host"]));
+        let mut items = Vec::new();
+        while let Some(arg) = a.next() {
+            let value = a.optional();
+            items.push((arg, value));
+        }
+        assert_eq!(
+            items,
+            vec![
+                item("-f", None),
+                item("-N", None),
+                item("-L", Some("8080:db:5432")),
+                item("-o", Some("X=1")),
+                item("-v", None),
+                item("host", None),
+            ]
+        );
+    }
+
+    fn option<'a>(command: &'a json::Value, name: &str) -> &'a json::Value {
+        command
+            .get("options")
+            .unwrap()
+            .items()
+            .iter()
+            .find(|o| {
+                o.get("names")
+                    .unwrap()
+                    .items()
+                    .iter()
+                    .any(|n| n.as_str() == Some(name))
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn help_lists_the_commands_and_wraps_descriptions() {
+        let text = help(&LIST, "netstatw");
+        for c in &COMMANDS[1..] {
+            assert!(text.contains(c.synopsis), "{}", c.name);
+        }
+        assert!(text.contains("  -s, --sort KEYS            Sort keys, comma-separated"));
+        // Continuation lines start under the description.
+        assert!(text.contains(&format!("Rx | Tx |\n{:HELP_COLUMN$}state | proto", "")));
+        // Names too long for the column push the description out.
+        assert!(text.contains("      --alert-listen-change N  With --watch:"));
+        // Texts that name constants of other modules.
+        assert!(text.contains(&format!("(up to {})", crate::probe::MAX_HOSTS)));
+        assert!(
+            help(&BLOCKS, "netstatw").contains(&format!("named {}<ip>", crate::block::RULE_PREFIX))
+        );
+    }
+
+    #[test]
+    fn options_are_listed_once_per_command() {
+        for c in COMMANDS {
+            let mut names: Vec<&str> = c.options().flat_map(|o| o.names.iter().copied()).collect();
+            let count = names.len();
+            names.sort_unstable();
+            names.dedup();
+            assert_eq!(names.len(), count, "{}", c.name);
+        }
+    }
+
+    #[test]
+    fn help_json_describes_every_command_and_value() {
+        let doc = json::parse(&help_json()).unwrap();
+        let commands = doc.get("commands").unwrap().items();
+        let names: Vec<&str> = commands
+            .iter()
+            .filter_map(|c| c.get("name").and_then(json::Value::as_str))
+            .collect();
+        assert_eq!(names.len(), COMMANDS.len());
+        assert!(names.contains(&"view"));
+        let list = &commands[0];
+
+        let port = option(list, "--port").get("value").unwrap();
+        assert_eq!(port.get("name").and_then(json::Value::as_str), Some("PORT"));
+        assert_eq!(port.get("type").and_then(json::Value::as_str), Some("port"));
+        assert_eq!(port.get("list").and_then(json::Value::as_bool), Some(true));
+
+        let watch = option(list, "-w");
+        assert_eq!(
+            watch.get("default").and_then(json::Value::as_str),
+            Some("2")
+        );
+        let value = watch.get("value").unwrap();
+        assert_eq!(
+            value.get("name").and_then(json::Value::as_str),
+            Some("SECS")
+        );
+        assert_eq!(
+            value.get("optional").and_then(json::Value::as_bool),
+            Some(true)
+        );
+
+        let sort = option(list, "--sort").get("value").unwrap();
+        assert_eq!(sort.get("type").and_then(json::Value::as_str), Some("enum"));
+        assert_eq!(sort.get("choices").unwrap().items().len(), SORT_KEYS.len());
+        assert_eq!(option(list, "--tcp").get("value"), Some(&json::Value::Null));
+
+        // Usage and description stay apart.
+        let drift = &commands[6];
+        assert_eq!(
+            drift.get("usage").unwrap().items()[0].as_str(),
+            Some("netstatw inventory-diff BASELINE.json OTHER.json [--json]")
+        );
+        assert!(
+            drift.get("description").unwrap().items()[0]
+                .as_str()
+                .unwrap()
+                .starts_with("Compare two")
+        );
+    }
+
+    #[test]
+    fn args_report_missing_and_invalid_values() {
+        let mut a = Args::new(&LIST, args(&["--top", "x"])).unwrap();
+        a.next();
+        assert_eq!(
+            a.parse::<usize>("--top").unwrap_err(),
+            "--top: invalid value 'x': invalid digit found in string"
+        );
+        assert_eq!(a.value("--top").unwrap_err(), "--top needs a value");
+    }
+}
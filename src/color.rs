@@ -0,0 +1,352 @@
+use std::env;
+use std::io::{self, IsTerminal};
+
+// ANSI colors for the table and the TUI: rows tinted by state (listeners green,
+// SYN-SENT yellow, TIME-WAIT dim), the CPU/Rx/Tx cells of busy processes in bold red
+// and, in --watch, the `+` of opened connections in bold green and closed connections
+// dim. Every style comes from the theme's `Styles` below, so a theme (`--theme`, or
+// `theme` in the config file's [display] section) changes all of them at once. `--color auto`
+// (the default) follows the usual conventions, first match wins:
+//   NO_COLOR set (non-empty)        no colors
+//   CLICOLOR_FORCE set, not "0"     colors, even into a pipe
+//   CLICOLOR=0                      no colors
+//   otherwise                       colors when stdout is a terminal and TERM isn't dumb
+// `--color always`/`never` override all of them. CSV, JSON and the reports are never
+// colored. A dumb terminal or a pipe also gets no cursor movement: --watch appends
+// snapshots instead of redrawing, --tui refuses to start, and there's no spinner.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum When {
+    Auto,
+    Always,
+    Never,
+}
+
+impl When {
+    pub fn parse(v: &str) -> Option<When> {
+        match v.to_ascii_lowercase().as_str() {
+            "auto" => Some(When::Auto),
+            "always" => Some(When::Always),
+            "never" => Some(When::Never),
+            _ => None,
+        }
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+const HOT: &str = "\x1b[1;31m";
+const OPENED: &str = "\x1b[1;32m";
+const DIM: &str = "\x1b[2m";
+const INVERSE: &str = "\x1b[7m";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Theme {
+    Dark,
+    Light,
+    HighContrast,
+    NoColor,
+}
+
+pub const THEMES: [&str; 4] = ["dark", "light", "high-contrast", "no-color"];
+
+// What a theme draws each thing with; None leaves it plain.
+struct Styles {
+    listen: Option<&'static str>,
+    syn_sent: Option<&'static str>,
+    time_wait: Option<&'static str>,
+    hot: Option<&'static str>,
+    opened: Option<&'static str>,
+    closed: Option<&'static str>,
+    // The TUI's title bar and selected row.
+    bar: &'static str,
+    // The processes in the TUI's bandwidth graph, busiest first; cycled. Empty leaves
+    // them told apart by their digits only.
+    series: &'static [&'static str],
+}
+
+impl Theme {
+    pub fn parse(v: &str) -> Option<Theme> {
+        match v.to_ascii_lowercase().as_str() {
+            "dark" => Some(Theme::Dark),
+            "light" => Some(Theme::Light),
+            "high-contrast" => Some(Theme::HighContrast),
+            "no-color" => Some(Theme::NoColor),
+            _ => None,
+        }
+    }
+
+    // Dark suits the usual light-on-dark terminal. Light swaps the yellow and dim
+    // styles, which fade on a white background, for blue and grey. High contrast uses
+    // attributes instead of colors (bold, underline, inverse), readable on any
+    // background; no-color is plain text with only the TUI's inverse bars.
+    fn styles(self) -> Styles {
+        match self {
+            Theme::Dark => Styles {
+                listen: Some("\x1b[32m"),
+                syn_sent: Some("\x1b[33m"),
+                time_wait: Some(DIM),
+                hot: Some(HOT),
+                opened: Some(OPENED),
+                closed: Some(DIM),
+                bar: INVERSE,
+                series: &[
+                    "\x1b[36m", "\x1b[33m", "\x1b[35m", "\x1b[32m", "\x1b[34m", "\x1b[31m",
+                    "\x1b[96m", "\x1b[93m", "\x1b[95m", "\x1b[92m",
+                ],
+            },
+            Theme::Light => Styles {
+                listen: Some("\x1b[32m"),
+                syn_sent: Some("\x1b[34m"),
+                time_wait: Some("\x1b[90m"),
+                hot: Some(HOT),
+                opened: Some(OPENED),
+                closed: Some("\x1b[90m"),
+                bar: INVERSE,
+                series: &[
+                    "\x1b[34m", "\x1b[31m", "\x1b[35m", "\x1b[32m", "\x1b[36m", "\x1b[90m",
+                    "\x1b[94m", "\x1b[91m", "\x1b[95m", "\x1b[30m",
+                ],
+            },
+            Theme::HighContrast => Styles {
+                listen: Some("\x1b[1m"),
+                syn_sent: Some("\x1b[4m"),
+                time_wait: None,
+                hot: Some("\x1b[1;7m"),
+                opened: Some("\x1b[1;7m"),
+                closed: Some("\x1b[9m"),
+                bar: "\x1b[1;7m",
+                series: &[],
+            },
+            Theme::NoColor => Styles {
+                listen: None,
+                syn_sent: None,
+                time_wait: None,
+                hot: None,
+                opened: None,
+                closed: None,
+                bar: INVERSE,
+                series: &[],
+            },
+        }
+    }
+}
+
+// Whether to color stdout.
+pub fn enabled(when: When) -> bool {
+    let var = |name: &str| env::var(name).ok();
+    let forced = decide(when, var, false);
+    let on = forced || decide(when, var, interactive(&io::stdout()));
+    on && enable_vt(forced)
+}
+
+// `enabled` given the environment and whether stdout is an interactive terminal;
+// with `tty` false the result says whether color is forced.
+fn decide(when: When, var: impl Fn(&str) -> Option<String>, tty: bool) -> bool {
+    let set = |name| var(name).filter(|v| !v.is_empty());
+    match when {
+        When::Always => true,
+        When::Never => false,
+        When::Auto if set("NO_COLOR").is_some() => false,
+        When::Auto if set("CLICOLOR_FORCE").is_some_and(|v| v != "0") => true,
+        When::Auto if var("CLICOLOR").as_deref() == Some("0") => false,
+        When::Auto => tty,
+    }
+}
+
+fn dumb_term() -> bool {
+    env::var("TERM").is_ok_and(|t| t == "dumb")
+}
+
+// A terminal that understands escape sequences (cursor movement, clearing): the
+// stream is a terminal and TERM isn't `dumb`.
+pub fn interactive(stream: &impl IsTerminal) -> bool {
+    stream.is_terminal() && !dumb_term()
+}
+
+// Windows consoles interpret escape sequences only once asked to.
+#[cfg(windows)]
+fn enable_vt(forced: bool) -> bool {
+    use windows_sys::Win32::System::Console::{
+        ENABLE_VIRTUAL_TERMINAL_PROCESSING, GetConsoleMode, GetStdHandle, STD_OUTPUT_HANDLE,
+        SetConsoleMode,
+    };
+    // SAFETY: the handle comes from GetStdHandle; the mode is a plain integer.
+    let ok = unsafe {
+        let out = GetStdHandle(STD_OUTPUT_HANDLE);
+        let mut mode = 0u32;
+        GetConsoleMode(out, &mut mode) != 0
+            && SetConsoleMode(out, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) != 0
+    };
+    // `always` also colors pipes, which have no console mode.
+    ok || forced
+}
+
+#[cfg(not(windows))]
+fn enable_vt(_: bool) -> bool {
+    true
+}
+
+// What to color, once coloring is on.
+#[derive(Clone, Copy, Debug)]
+pub struct Palette {
+    pub theme: Theme,
+    // CPU% above which the CPU cell is highlighted.
+    pub hot_cpu: f64,
+    // Rx/Tx bytes per second above which those cells are highlighted.
+    pub hot_rate: f64,
+}
+
+impl Palette {
+    pub fn row(&self, state: &str) -> Option<&'static str> {
+        let styles = self.theme.styles();
+        match state {
+            "Listen" => styles.listen,
+            "SynSent" => styles.syn_sent,
+            "TimeWait" => styles.time_wait,
+            _ => None,
+        }
+    }
+
+    // A closed row is styled as a whole; an opened row only gets its marker styled.
+    pub fn closed(&self) -> Option<&'static str> {
+        self.theme.styles().closed
+    }
+
+    pub fn opened(&self) -> Option<&'static str> {
+        self.theme.styles().opened
+    }
+
+    // The style of a metric cell, or None below the limit (and for NaN).
+    pub fn cpu(&self, pct: f64) -> Option<&'static str> {
+        (pct > self.hot_cpu).then_some(self.theme.styles().hot?)
+    }
+
+    pub fn rate(&self, bps: f64) -> Option<&'static str> {
+        (bps > self.hot_rate).then_some(self.theme.styles().hot?)
+    }
+
+    // The color of the i-th process in the bandwidth graph.
+    pub fn series(&self, i: usize) -> Option<&'static str> {
+        let series = self.theme.styles().series;
+        series.get(i % series.len().max(1)).copied()
+    }
+}
+
+// The TUI's title bar and selection: inverse without colors.
+pub fn bar(palette: Option<&Palette>) -> &'static str {
+    palette.map_or(INVERSE, |p| p.theme.styles().bar)
+}
+
+pub fn paint(text: &str, style: Option<&str>) -> String {
+    match style {
+        Some(style) => format!("{}{}{}", style, text, RESET),
+        None => text.to_string(),
+    }
+}
+
+// The visible characters `skip..skip + width` of a painted line, for panning a wide
+// table. Escape sequences are kept wherever they are, so cells cut at the left edge
+// keep their style; a line that had any ends reset.
+pub fn slice(line: &str, skip: usize, width: usize) -> String {
+    let mut out = String::new();
+    let mut styled = false;
+    let mut seen = 0;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            out.push(c);
+            for c in chars.by_ref() {
+                out.push(c);
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+            styled = true;
+            continue;
+        }
+        if seen >= skip && seen < skip + width {
+            out.push(c);
+        }
+        seen += 1;
+    }
+    if styled {
+        out.push_str(RESET);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn states_and_hot_cells_get_styles() {
+        let palette = Palette {
+            theme: Theme::Dark,
+            hot_cpu: 50.0,
+            hot_rate: 1024.0,
+        };
+        assert_eq!(
+            paint("LISTEN", palette.row("Listen")),
+            "\x1b[32mLISTEN\x1b[0m"
+        );
+        assert_eq!(paint("x", palette.row("Established")), "x");
+        assert_eq!(palette.cpu(75.0), Some(HOT));
+        assert_eq!(palette.cpu(f64::NAN), None);
+        assert_eq!(palette.rate(1024.0), None);
+        assert_eq!(When::parse("Always"), Some(When::Always));
+        assert!(!enabled(When::Never));
+    }
+
+    #[test]
+    fn themes_restyle_everything_and_no_color_is_plain() {
+        let palette = |theme| Palette {
+            theme,
+            hot_cpu: 50.0,
+            hot_rate: 1024.0,
+        };
+        let light = palette(Theme::Light);
+        assert_eq!(light.row("SynSent"), Some("\x1b[34m"));
+        assert_eq!(palette(Theme::HighContrast).row("TimeWait"), None);
+        let plain = palette(Theme::NoColor);
+        assert_eq!(plain.row("Listen"), None);
+        assert_eq!(plain.cpu(99.0), None);
+        assert_eq!(plain.opened(), None);
+        assert_eq!(bar(Some(&plain)), INVERSE);
+        assert!(THEMES.iter().all(|t| Theme::parse(t).is_some()));
+    }
+
+    #[test]
+    fn slices_count_only_visible_characters() {
+        let line = format!("ab {} ef", paint("cd", Some(HOT)));
+        assert_eq!(slice(&line, 3, 2), format!("{}cd{}{}", HOT, RESET, RESET));
+        assert_eq!(slice("abcdef", 4, 10), "ef");
+        assert_eq!(slice("abc", 5, 2), "");
+    }
+
+    #[test]
+    fn environment_conventions_pick_the_auto_mode() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(k, _)| *k == name)
+                    .map(|(_, v)| v.to_string())
+            }
+        };
+        assert!(decide(When::Auto, env(&[]), true));
+        assert!(!decide(When::Auto, env(&[]), false));
+        assert!(!decide(When::Auto, env(&[("NO_COLOR", "1")]), true));
+        // An empty NO_COLOR doesn't count.
+        assert!(decide(When::Auto, env(&[("NO_COLOR", "")]), true));
+        assert!(decide(When::Auto, env(&[("CLICOLOR_FORCE", "1")]), false));
+        assert!(!decide(When::Auto, env(&[("CLICOLOR_FORCE", "0")]), false));
+        assert!(!decide(
+            When::Auto,
+            env(&[("NO_COLOR", "1"), ("CLICOLOR_FORCE", "1")]),
+            true
+        ));
+        assert!(!decide(When::Auto, env(&[("CLICOLOR", "0")]), true));
+        assert!(decide(When::Always, env(&[("NO_COLOR", "1")]), false));
+        assert!(!decide(When::Never, env(&[("CLICOLOR_FORCE", "1")]), true));
+    }
+}
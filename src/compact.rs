@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::record::{self, Snapshot};
+use crate::{ProcessStats, SocketEntry, cap, cli};
+
+// `netstatw compact FILE`: keeps a `--record` history from growing without bound on a
+// host that records around the clock. Snapshots younger than `--keep-raw` stay as they
+// are; older ones are merged into one snapshot per `--rollup` window, stamped with the
+// window's last snapshot time. A merged snapshot lists every connection seen in its
+// window with the row it last had and its rates averaged over the snapshots it
+// appeared in, so `--replay` still shows who talked to whom, at a coarser grain. The
+// file is rewritten through a temporary file next to it; lines a running `--record`
+// appends meanwhile are carried over.
+
+struct Options {
+    path: PathBuf,
+    keep_raw: Duration,
+    rollup: Duration,
+    dry_run: bool,
+}
+
+fn parse_args(mut args: cli::Args) -> Result<Options, String> {
+    let mut path: Option<PathBuf> = None;
+    let mut keep_raw = Duration::from_secs(24 * 3600);
+    let mut rollup = Duration::from_secs(300);
+    let mut dry_run = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--keep-raw" | "--rollup" => {
+                let d = cap::parse_duration(&args.value(&arg)?)
+                    .map_err(|e| format!("{}: {}", arg, e))?;
+                match arg.as_str() {
+                    "--keep-raw" => keep_raw = d,
+                    _ => rollup = d.max(Duration::from_secs(1)),
+                }
+            }
+            "--dry-run" => dry_run = true,
+            "--forensic" => {
+                return Err(
+                    "compacting rewrites the file and is not allowed with --forensic".to_string(),
+                );
+            }
+            other if path.is_none() && !other.starts_with('-') => path = Some(PathBuf::from(other)),
+            other => return Err(format!("unexpected argument '{}'", other)),
+        }
+    }
+    let Some(path) = path else {
+        return Err("expected a recording (netstatw compact FILE)".to_string());
+    };
+    Ok(Options {
+        path,
+        keep_raw,
+        rollup,
+        dry_run,
+    })
+}
+
+type Key = (String, String, String);
+
+// A connection within a window: its last row and the stats of every snapshot it was in.
+struct Seen {
+    row: SocketEntry,
+    stats: Vec<ProcessStats>,
+}
+
+// The mean of the finite values; NaN (not measured) when there are none.
+fn mean(values: impl Iterator<Item = f64>) -> f64 {
+    let (sum, n) = values
+        .filter(|v| v.is_finite())
+        .fold((0.0, 0), |(sum, n), v| (sum + v, n + 1));
+    if n == 0 { f64::NAN } else { sum / n as f64 }
+}
+
+fn merge(window: Vec<Snapshot>) -> Snapshot {
+    let timestamp = window.last().map_or(0, |s| s.timestamp);
+    let mut order: Vec<Key> = Vec::new();
+    let mut seen: HashMap<Key, Seen> = HashMap::new();
+    for snapshot in window {
+        for e in snapshot.entries {
+            let key = (e.proto.clone(), e.local_addr.clone(), e.remote_addr.clone());
+            let stats = e.agg_stats.clone();
+            let entry = seen.entry(key.clone()).or_insert_with(|| {
+                order.push(key);
+                Seen {
+                    row: SocketEntry::default(),
+                    stats: Vec::new(),
+                }
+            });
+            entry.row = e;
+            entry.stats.extend(stats);
+        }
+    }
+    let entries = order
+        .iter()
+        .filter_map(|key| seen.remove(key))
+        .map(|Seen { mut row, stats }| {
+            if !stats.is_empty() {
+                let avg = |f: fn(&ProcessStats) -> f64| mean(stats.iter().map(f));
+                row.agg_stats = Some(ProcessStats {
+                    cpu_pct: avg(|s| s.cpu_pct as f64) as f32,
+                    read_rate_bps: avg(|s| s.read_rate_bps),
+                    write_rate_bps: avg(|s| s.write_rate_bps),
+                    net_rx_rate_bps: avg(|s| s.net_rx_rate_bps),
+                    net_tx_rate_bps: avg(|s| s.net_tx_rate_bps),
+                    ..Default::default()
+                });
+            }
+            row
+        })
+        .collect();
+    Snapshot { timestamp, entries }
+}
+
+// The recording with the snapshots taken before `cutoff` merged per `rollup` window
+// (consecutive snapshots in the same window, so the order is kept).
+fn compact(snapshots: Vec<Snapshot>, cutoff: u64, rollup: u64) -> Vec<Snapshot> {
+    let mut out = Vec::new();
+    let mut window: Vec<Snapshot> = Vec::new();
+    for snapshot in snapshots {
+        let old = snapshot.timestamp < cutoff;
+        let same = window
+            .first()
+            .is_some_and(|w| old && w.timestamp / rollup == snapshot.timestamp / rollup);
+        if !same && !window.is_empty() {
+            out.push(merge(std::mem::take(&mut window)));
+        }
+        if old {
+            window.push(snapshot);
+        } else {
+            out.push(snapshot);
+        }
+    }
+    if !window.is_empty() {
+        out.push(merge(window));
+    }
+    out
+}
+
+struct Outcome {
+    before: (usize, u64),
+    after: (usize, u64),
+}
+
+fn compact_file(
+    path: &Path,
+    cutoff: u64,
+    rollup: Duration,
+    dry_run: bool,
+) -> Result<Outcome, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let snapshots = record::parse(&text)?;
+    let count = snapshots.len();
+    let compacted = compact(snapshots, cutoff, rollup.as_secs().max(1));
+    let out: String = compacted
+        .iter()
+        .map(|s| record::line(s.timestamp, &s.entries))
+        .collect();
+    let outcome = Outcome {
+        before: (count, text.len() as u64),
+        after: (compacted.len(), out.len() as u64),
+    };
+    if dry_run || compacted.len() == count {
+        return Ok(outcome);
+    }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".compact");
+    let tmp = PathBuf::from(tmp);
+    let write = || -> Result<(), String> {
+        fs::write(&tmp, &out).map_err(|e| format!("{}: {}", tmp.display(), e))?;
+        // Snapshots recorded while this ran go after the compacted ones.
+        let now = fs::read(path).map_err(|e| e.to_string())?;
+        match now.get(..text.len()) {
+            Some(start) if start == text.as_bytes() => {
+                let mut file = OpenOptions::new()
+                    .append(true)
+                    .open(&tmp)
+                    .map_err(|e| format!("{}: {}", tmp.display(), e))?;
+                file.write_all(&now[text.len()..])
+                    .map_err(|e| format!("{}: {}", tmp.display(), e))?;
+            }
+            _ => return Err("the file was rewritten while compacting; try again".to_string()),
+        }
+        fs::rename(&tmp, path).map_err(|e| e.to_string())
+    };
+    write().inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })?;
+    Ok(outcome)
+}
+
+pub fn run(args: Vec<String>) -> i32 {
+    let Options {
+        path,
+        keep_raw,
+        rollup,
+        dry_run,
+    } = match cli::parse_command(&cli::COMPACT, args, parse_args) {
+        Ok(opts) => opts,
+        Err(status) => return status,
+    };
+    let cutoff = record::now().saturating_sub(keep_raw.as_secs());
+    match compact_file(&path, cutoff, rollup, dry_run) {
+        Ok(Outcome { before, after }) if before.0 == after.0 => {
+            println!(
+                "{}: nothing to compact ({} snapshots)",
+                path.display(),
+                before.0
+            );
+            0
+        }
+        Ok(Outcome { before, after }) => {
+            let verb = if dry_run {
+                "would compact"
+            } else {
+                "compacted"
+            };
+            println!(
+                "{} {}: {} snapshots -> {} ({} -> {})",
+                verb,
+                path.display(),
+                before.0,
+                after.0,
+                cap::human_bytes(before.1),
+                cap::human_bytes(after.1)
+            );
+            0
+        }
+        Err(e) => {
+            eprintln!("netstatw compact: {}: {}", path.display(), e);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(remote: &str, rx: f64) -> SocketEntry {
+        SocketEntry {
+            proto: "TCP".to_string(),
+            local_addr: "10.0.0.1:40000".to_string(),
+            remote_addr: remote.to_string(),
+            state: "Established".to_string(),
+            agg_stats: Some(ProcessStats {
+                net_rx_rate_bps: rx,
+                net_tx_rate_bps: f64::NAN,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn snapshot(timestamp: u64, rows: &[SocketEntry]) -> Snapshot {
+        Snapshot {
+            timestamp,
+            entries: rows.to_vec(),
+        }
+    }
+
+    #[test]
+    fn old_snapshots_merge_per_window_and_recent_ones_stay() {
+        let a = |rx| row("10.0.0.5:5432", rx);
+        let b = row("10.0.0.6:443", 10.0);
+        let recording = vec![
+            snapshot(600, &[a(100.0)]),
+            snapshot(660, &[a(300.0), b.clone()]),
+            snapshot(720, &[]),
+            snapshot(900, &[a(50.0)]),
+            snapshot(1000, &[a(7.0)]),
+            snapshot(1010, &[a(9.0)]),
+        ];
+        let out = compact(recording, 1000, 300);
+        let stamps: Vec<u64> = out.iter().map(|s| s.timestamp).collect();
+        assert_eq!(stamps, [720, 900, 1000, 1010]);
+        let merged = &out[0].entries;
+        assert_eq!(merged.len(), 2);
+        let stats = merged[0].agg_stats.as_ref().unwrap();
+        assert_eq!(stats.net_rx_rate_bps, 200.0);
+        assert!(stats.net_tx_rate_bps.is_nan());
+        assert_eq!(merged[1].remote_addr, "10.0.0.6:443");
+        // Compacting again changes nothing.
+        let again = compact(out, 1000, 300);
+        assert_eq!(again.len(), 4);
+        assert_eq!(again[0].entries.len(), 2);
+    }
+
+    #[test]
+    fn the_file_is_rewritten_in_place() {
+        let path = std::env::temp_dir().join(format!("netstatw-compact-{}", std::process::id()));
+        let lines: String = [0, 60, 120, 400]
+            .iter()
+            .map(|&t| record::line(t, &[row("10.0.0.5:5432", 1.0)]))
+            .collect();
+        fs::write(&path, &lines).unwrap();
+        let dry = compact_file(&path, 1000, Duration::from_secs(300), true).unwrap();
+        assert_eq!((dry.before.0, dry.after.0), (4, 2));
+        assert_eq!(fs::read_to_string(&path).unwrap(), lines);
+        compact_file(&path, 1000, Duration::from_secs(300), false).unwrap();
+        let back = record::read(&path).unwrap();
+        fs::remove_file(&path).ok();
+        let stamps: Vec<u64> = back.iter().map(|s| s.timestamp).collect();
+        assert_eq!(stamps, [120, 400]);
+    }
+}
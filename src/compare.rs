@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::{SocketEntry, human_readable_rate, json};
+
+// `--compare-rates FILE`: the Rx/Tx rate of each process now against a baseline
+// snapshot written earlier with `--full --output FILE` (or `--full --json`), e.g. to
+// see what a deployment did to a service's traffic. Processes are matched by name,
+// since PIDs rarely survive a restart; a process seen in only one of the two has no
+// percentage.
+
+// Rx and Tx bytes per second, per process name.
+pub type Rates = BTreeMap<String, (f64, f64)>;
+
+// "812: /usr/sbin/nginx, 813: /usr/sbin/nginx" -> "/usr/sbin/nginx".
+fn name(process: &str) -> &str {
+    let first = process.split(", ").next().unwrap_or(process);
+    first.split_once(": ").map_or(first, |(_, name)| name)
+}
+
+// Rows of one process carry that process's rates, so each process (each distinct
+// PID list) counts once; processes of the same name add up.
+fn totals<'a>(rows: impl Iterator<Item = (&'a str, f64, f64)>) -> Rates {
+    let mut seen: BTreeMap<&str, (f64, f64)> = BTreeMap::new();
+    for (process, rx, tx) in rows {
+        if rx.is_finite() && tx.is_finite() {
+            seen.insert(process, (rx, tx));
+        }
+    }
+    let mut rates = Rates::new();
+    for (process, (rx, tx)) in seen {
+        let total = rates.entry(name(process).to_string()).or_default();
+        total.0 += rx;
+        total.1 += tx;
+    }
+    rates
+}
+
+// The live rates, from rows collected with statistics.
+pub fn rates(entries: &[SocketEntry]) -> Rates {
+    totals(entries.iter().filter_map(|e| {
+        let s = e.agg_stats.as_ref()?;
+        Some((
+            e.process_info.as_str(),
+            s.net_rx_rate_bps,
+            s.net_tx_rate_bps,
+        ))
+    }))
+}
+
+fn parse(text: &str) -> Result<Rates, String> {
+    let doc = json::parse(text)?;
+    let connections = match &doc {
+        json::Value::Array(_) => &doc,
+        _ => doc
+            .get("connections")
+            .ok_or("not a snapshot (no \"connections\")")?,
+    };
+    let rows: Vec<(&str, f64, f64)> = connections
+        .items()
+        .iter()
+        .filter_map(|c| {
+            Some((
+                c.get("process")?.as_str()?,
+                c.get("rx_bps")?.as_f64()?,
+                c.get("tx_bps")?.as_f64()?,
+            ))
+        })
+        .collect();
+    if rows.is_empty() && !connections.items().is_empty() {
+        return Err("the snapshot has no Rx/Tx rates (write it with --full)".to_string());
+    }
+    Ok(totals(rows.into_iter()))
+}
+
+pub fn load(path: &Path) -> Result<Rates, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// "+25%", "-100%"; None when the baseline had no traffic.
+fn change(now: f64, base: f64) -> Option<f64> {
+    (base > 0.0).then(|| (now - base) / base * 100.0)
+}
+
+fn percent(change: Option<f64>) -> String {
+    change.map_or("-".to_string(), |p| format!("{:+.0}%", p))
+}
+
+struct Row<'a> {
+    process: &'a str,
+    now: Option<(f64, f64)>,
+    base: Option<(f64, f64)>,
+}
+
+// Every process in either, busiest now first; the ones gone since the baseline last.
+fn rows<'a>(now: &'a Rates, base: &'a Rates) -> Vec<Row<'a>> {
+    let mut rows: Vec<Row> = now
+        .keys()
+        .chain(base.keys().filter(|p| !now.contains_key(*p)))
+        .map(|p| Row {
+            process: p,
+            now: now.get(p).copied(),
+            base: base.get(p).copied(),
+        })
+        .collect();
+    let total = |r: &Row| r.now.map_or(-1.0, |(rx, tx)| rx + tx);
+    rows.sort_by(|a, b| total(b).total_cmp(&total(a)).then(a.process.cmp(b.process)));
+    rows
+}
+
+pub fn print(now: &Rates, base: &Rates) {
+    println!(
+        "{:<32} {:>10} {:>10} {:>7} {:>10} {:>10} {:>7}",
+        "PROCESS", "Rx/s", "BASE Rx/s", "Rx %", "Tx/s", "BASE Tx/s", "Tx %"
+    );
+    let rate = |r: Option<f64>| r.map_or("-".to_string(), human_readable_rate);
+    for r in rows(now, base) {
+        let (rx, tx) = (r.now.map(|n| n.0), r.now.map(|n| n.1));
+        let (base_rx, base_tx) = (r.base.map(|b| b.0), r.base.map(|b| b.1));
+        let pct = |n: Option<f64>, b| percent(n.zip(b).and_then(|(n, b)| change(n, b)));
+        println!(
+            "{:<32} {:>10} {:>10} {:>7} {:>10} {:>10} {:>7}",
+            r.process,
+            rate(rx),
+            rate(base_rx),
+            pct(rx, base_rx).as_str(),
+            rate(tx),
+            rate(base_tx),
+            pct(tx, base_tx).as_str()
+        );
+    }
+}
+
+// `[{"process":...,"rx_bps":...,"base_rx_bps":...,"rx_change_pct":...,...}]`, nulls
+// where a side is missing.
+pub fn to_json(now: &Rates, base: &Rates) -> String {
+    let num = |v: Option<f64>| v.map_or("null".to_string(), json::num);
+    let items: Vec<String> = rows(now, base)
+        .iter()
+        .map(|r| {
+            let (rx, tx) = (r.now.map(|n| n.0), r.now.map(|n| n.1));
+            let (base_rx, base_tx) = (r.base.map(|b| b.0), r.base.map(|b| b.1));
+            let pct = |n: Option<f64>, b| num(n.zip(b).and_then(|(n, b)| change(n, b)));
+            format!(
+                "\n{}",
+                json::object(&[
+                    ("process", json::str(r.process)),
+                    ("rx_bps", num(rx)),
+                    ("base_rx_bps", num(base_rx)),
+                    ("rx_change_pct", pct(rx, base_rx)),
+                    ("tx_bps", num(tx)),
+                    ("base_tx_bps", num(base_tx)),
+                    ("tx_change_pct", pct(tx, base_tx)),
+                ])
+            )
+        })
+        .collect();
+    let end = if items.is_empty() { "" } else { "\n" };
+    format!("[{}{}]", items.join(","), end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn baseline_rates_count_each_process_once_and_add_up_by_name() {
+        let base = parse(
+            r#"{"timestamp":1,"connections":[
+{"process":"1: nginx","rx_bps":100,"tx_bps":10},
+{"process":"1: nginx","rx_bps":100,"tx_bps":10},
+{"process":"2: nginx","rx_bps":50,"tx_bps":0},
+{"process":"3: cron","rx_bps":0,"tx_bps":0}
+]}"#,
+        )
+        .unwrap();
+        assert_eq!(base.get("nginx"), Some(&(150.0, 10.0)));
+        assert_eq!(base.get("cron"), Some(&(0.0, 0.0)));
+        assert!(parse(r#"[{"process":"1: nginx"}]"#).is_err());
+    }
+
+    #[test]
+    fn changes_are_relative_to_the_baseline() {
+        let now = Rates::from([
+            ("nginx".to_string(), (300.0, 5.0)),
+            ("new".to_string(), (1.0, 1.0)),
+        ]);
+        let base = Rates::from([
+            ("nginx".to_string(), (150.0, 10.0)),
+            ("old".to_string(), (9.0, 9.0)),
+        ]);
+        let order: Vec<&str> = rows(&now, &base).iter().map(|r| r.process).collect();
+        assert_eq!(order, ["nginx", "new", "old"]);
+        assert_eq!(percent(change(300.0, 150.0)), "+100%");
+        assert_eq!(percent(change(5.0, 10.0)), "-50%");
+        assert_eq!(percent(change(5.0, 0.0)), "-");
+        let doc = json::parse(&to_json(&now, &base)).unwrap();
+        let old = &doc.items()[2];
+        assert_eq!(old.get("rx_bps"), Some(&json::Value::Null));
+        assert_eq!(
+            old.get("base_rx_bps").and_then(json::Value::as_f64),
+            Some(9.0)
+        );
+    }
+}
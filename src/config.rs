@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+// User configuration, read from a small TOML subset:
+//
+//   # comment
+//   [section.name]
+//   key = "string"
+//   flag = true
+//   count = 5
+//   list = [80, 443, "https"]
+//
+// Location: $NETSTATW_CONFIG, else the platform config directory
+// (%APPDATA%\netstatw\config.toml on Windows, $XDG_CONFIG_HOME or ~/.config
+// elsewhere). A missing file is an empty configuration.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Str(String),
+    Bool(bool),
+    Int(i64),
+    List(Vec<Value>),
+}
+
+impl Value {
+    // The value as it would be typed on the command line.
+    pub fn to_arg(&self) -> String {
+        match self {
+            Value::Str(s) => s.clone(),
+            Value::Bool(b) => b.to_string(),
+            Value::Int(i) => i.to_string(),
+            Value::List(items) => items
+                .iter()
+                .map(Value::to_arg)
+                .collect::<Vec<_>>()
+                .join(","),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Config {
+    // Section name -> keys in file order.
+    sections: HashMap<String, Vec<(String, Value)>>,
+    order: Vec<String>,
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    if let Some(p) = env::var_os("NETSTATW_CONFIG") {
+        return Some(PathBuf::from(p));
+    }
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+    };
+    base.map(|b| b.join("netstatw").join("config.toml"))
+}
+
+fn parse_scalar(raw: &str) -> Option<Value> {
+    let raw = raw.trim();
+    if let Some(inner) = raw.strip_prefix('"').and_then(|r| r.strip_suffix('"')) {
+        return Some(Value::Str(
+            inner.replace("\\\"", "\"").replace("\\\\", "\\"),
+        ));
+    }
+    match raw {
+        "true" => Some(Value::Bool(true)),
+        "false" => Some(Value::Bool(false)),
+        _ => raw.parse::<i64>().ok().map(Value::Int),
+    }
+}
+
+// Splits on commas outside double quotes.
+fn split_list(inner: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut in_str = false;
+    for (i, c) in inner.char_indices() {
+        match c {
+            '"' => in_str = !in_str,
+            ',' if !in_str => {
+                parts.push(&inner[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&inner[start..]);
+    parts.into_iter().filter(|p| !p.trim().is_empty()).collect()
+}
+
+fn parse_value(raw: &str) -> Option<Value> {
+    let raw = raw.trim();
+    if let Some(inner) = raw.strip_prefix('[').and_then(|r| r.strip_suffix(']')) {
+        return split_list(inner)
+            .into_iter()
+            .map(parse_scalar)
+            .collect::<Option<Vec<_>>>()
+            .map(Value::List);
+    }
+    parse_scalar(raw)
+}
+
+// Drops a trailing `# comment` that is not inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut in_str = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_str = !in_str,
+            '#' if !in_str => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let mut cfg = Config::default();
+        let mut section = String::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = name.trim().to_string();
+                if !cfg.sections.contains_key(&section) {
+                    cfg.order.push(section.clone());
+                    cfg.sections.insert(section.clone(), Vec::new());
+                }
+                continue;
+            }
+            let (key, raw) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected `key = value`", n + 1))?;
+            let value = parse_value(raw).ok_or_else(|| format!("line {}: invalid value", n + 1))?;
+            if !cfg.sections.contains_key(&section) {
+                cfg.order.push(section.clone());
+            }
+            cfg.sections
+                .entry(section.clone())
+                .or_default()
+                .push((key.trim().to_string(), value));
+        }
+        Ok(cfg)
+    }
+
+    pub fn load() -> Result<Config, String> {
+        let Some(path) = config_path() else {
+            return Ok(Config::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => Config::parse(&text).map_err(|e| format!("{}: {}", path.display(), e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn section(&self, name: &str) -> Option<&[(String, Value)]> {
+        self.sections.get(name).map(Vec::as_slice)
+    }
+
+    // `[ports]` templates: `web = [80, 443, 8080, 8443]` lets `--port web` stand for
+    // "80,443,8080,8443". Items may be wildcards ("3??0").
+    pub fn port_template(&self, name: &str) -> Option<String> {
+        self.section("ports")?
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.to_arg())
+    }
+
+    // Names of sections `<prefix>.<name>`, in file order.
+    pub fn subsections(&self, prefix: &str) -> Vec<&str> {
+        self.order
+            .iter()
+            .filter_map(|s| s.strip_prefix(prefix)?.strip_prefix('.'))
+            .collect()
+    }
+}
+
+// Expands `[view.<name>]` into command-line arguments: each key becomes `--key`;
+// `true` adds the bare flag, `false` omits it, lists repeat the option per item and
+// anything else becomes the option's value. A `not-` prefix negates a filter, so
+// `not-port = 22` is `--not --port 22`.
+pub fn view_args(cfg: &Config, name: &str) -> Option<Vec<String>> {
+    let section = cfg.section(&format!("view.{}", name))?;
+    let mut args = Vec::new();
+    for (key, value) in section {
+        let (negate, key) = match key.strip_prefix("not-") {
+            Some(k) => (true, k),
+            None => (false, key.as_str()),
+        };
+        let flag = format!("--{}", key);
+        let values: Vec<&Value> = match value {
+            Value::List(items) => items.iter().collect(),
+            v => vec![v],
+        };
+        for v in values {
+            if *v == Value::Bool(false) {
+                continue;
+            }
+            if negate {
+                args.push("--not".to_string());
+            }
+            args.push(flag.clone());
+            if *v != Value::Bool(true) {
+                args.push(v.to_arg());
+            }
+        }
+    }
+    Some(args)
+}
@@ -0,0 +1,148 @@
+use crate::SocketEntry;
+
+// CSV output (RFC 4180): a header line, then one line per row. The columns are the
+// same with or without `--full`, so files from different runs line up; unavailable
+// values are empty cells.
+
+const HEADER: [&str; 26] = [
+    "id",
+    "proto",
+    "local",
+    "remote",
+    "remote_host",
+    "state",
+    "pids",
+    "process",
+    "cpu_pct",
+    "read_bps",
+    "write_bps",
+    "rx_bps",
+    "tx_bps",
+    "new_remote",
+    "synth",
+    "forward",
+    "env_hint",
+    "inode",
+    "tags",
+    "rtt_ms",
+    "path_hint",
+    // Added after the others so older files keep their column positions.
+    "user",
+    "country",
+    "asn",
+    "accept_per_sec",
+    "syn_received",
+];
+
+// Quotes a field when it contains the delimiter, a quote or a line break; quotes
+// inside are doubled. IPv6 addresses only get quoted when the delimiter is `:`.
+pub fn field(s: &str, delimiter: char) -> String {
+    if s.contains([delimiter, '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+// NaN ("not available") becomes an empty cell.
+fn num(v: f64) -> String {
+    if v.is_finite() {
+        format!("{}", v)
+    } else {
+        String::new()
+    }
+}
+
+fn line(cells: &[String], delimiter: char) -> String {
+    let quoted: Vec<String> = cells.iter().map(|c| field(c, delimiter)).collect();
+    quoted.join(&delimiter.to_string())
+}
+
+fn row(e: &SocketEntry) -> Vec<String> {
+    let pids: Vec<String> = e.pids.iter().map(u32::to_string).collect();
+    let stat = |f: fn(&crate::ProcessStats) -> f64| {
+        e.agg_stats.as_ref().map_or(String::new(), |s| num(f(s)))
+    };
+    vec![
+        e.conn_id(),
+        e.proto.clone(),
+        e.local_addr.clone(),
+        e.remote_addr.clone(),
+        e.remote_host.clone().unwrap_or_default(),
+        e.state.clone(),
+        pids.join(" "),
+        e.process_info.clone(),
+        stat(|s| s.cpu_pct as f64),
+        stat(|s| s.read_rate_bps),
+        stat(|s| s.write_rate_bps),
+        stat(|s| s.net_rx_rate_bps),
+        stat(|s| s.net_tx_rate_bps),
+        e.new_remote.to_string(),
+        e.synth.to_string(),
+        e.forward.clone().unwrap_or_default(),
+        e.env_hint.clone().unwrap_or_default(),
+        e.inode.map_or(String::new(), |i| i.to_string()),
+        e.tags.join(" "),
+        e.rtt_ms.map_or(String::new(), num),
+        e.path
+            .as_ref()
+            .map_or(String::new(), |p| p.hint().to_string()),
+        e.user.clone().unwrap_or_default(),
+        e.geo
+            .as_ref()
+            .and_then(|g| g.country.clone())
+            .unwrap_or_default(),
+        e.geo
+            .as_ref()
+            .and_then(|g| g.asn)
+            .map_or(String::new(), |n| n.to_string()),
+        e.accept.map_or(String::new(), |a| num(a.per_sec)),
+        e.accept
+            .map_or(String::new(), |a| a.syn_received.to_string()),
+    ]
+}
+
+// Header plus one line per row, each terminated by a newline.
+pub fn entries(entries: &[SocketEntry], delimiter: char) -> String {
+    let header: Vec<String> = HEADER.iter().map(|h| h.to_string()).collect();
+    let mut out = line(&header, delimiter);
+    out.push('\n');
+    for e in entries {
+        out.push_str(&line(&row(e), delimiter));
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_quotes_only_when_needed() {
+        assert_eq!(field("1.2.3.4:80", ','), "1.2.3.4:80");
+        assert_eq!(field(":::443", ':'), "\":::443\"");
+        assert_eq!(field("a,b", ','), "\"a,b\"");
+        assert_eq!(field("say \"hi\"", ';'), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn entries_have_a_header_and_fixed_columns() {
+        let rows = [SocketEntry {
+            proto: "TCP".to_string(),
+            local_addr: "[::1]:80".to_string(),
+            remote_addr: "*:*".to_string(),
+            state: "Listen".to_string(),
+            pids: vec![1, 2],
+            process_info: "1: C:\\Program Files\\app, v2.exe".to_string(),
+            ..Default::default()
+        }];
+        let out = entries(&rows, ',');
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("id,proto,local,remote,"));
+        assert!(lines[1].contains(
+            ",TCP,[::1]:80,*:*,,Listen,1 2,\"1: C:\\Program Files\\app, v2.exe\",,,,,,false,"
+        ));
+    }
+}
@@ -0,0 +1,328 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+use sysinfo::System;
+
+use crate::{
+    AddressFamilyFlags, ProtocolFlags, SocketEntry, cli, collect_entries, forensic, json, warn,
+};
+
+// `netstatw diff`: what opened, closed or changed state between two snapshots, e.g.
+// before and after a deployment. Snapshots are the JSON written by `--output FILE`
+// (or printed by `--json`); without a second file the current sockets are compared
+// against the baseline. Rows are matched by protocol and address pair, so a restarted
+// service keeps its listener and a reconnect with a new local port is closed + opened.
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Key {
+    pub(crate) proto: String,
+    pub(crate) local: String,
+    pub(crate) remote: String,
+}
+
+pub(crate) struct Row {
+    pub(crate) state: String,
+    pub(crate) process: String,
+}
+
+pub(crate) struct Snapshot {
+    // Seconds since the epoch; None for the live state and plain `--json` arrays.
+    pub(crate) timestamp: Option<u64>,
+    pub(crate) rows: BTreeMap<Key, Row>,
+}
+
+impl Snapshot {
+    // The live rows from `collect_entries`.
+    pub(crate) fn of(entries: Vec<SocketEntry>) -> Snapshot {
+        let rows = entries
+            .into_iter()
+            .map(|e| {
+                (
+                    Key {
+                        proto: e.proto,
+                        local: e.local_addr,
+                        remote: e.remote_addr,
+                    },
+                    Row {
+                        state: e.state,
+                        process: e.process_info,
+                    },
+                )
+            })
+            .collect();
+        Snapshot {
+            timestamp: None,
+            rows,
+        }
+    }
+}
+
+fn read_snapshot(text: &str) -> Result<Snapshot, String> {
+    let doc = json::parse(text)?;
+    let (timestamp, connections) = match &doc {
+        json::Value::Array(_) => (None, &doc),
+        _ => match doc.get("connections") {
+            Some(c) => (
+                doc.get("timestamp")
+                    .and_then(json::Value::as_f64)
+                    .map(|t| t as u64),
+                c,
+            ),
+            None => return Err("not a snapshot (no \"connections\")".to_string()),
+        },
+    };
+    let mut rows = BTreeMap::new();
+    for c in connections.items() {
+        let field = |key| {
+            c.get(key)
+                .and_then(json::Value::as_str)
+                .unwrap_or_default()
+                .to_string()
+        };
+        rows.insert(
+            Key {
+                proto: field("proto"),
+                local: field("local"),
+                remote: field("remote"),
+            },
+            Row {
+                state: field("state"),
+                process: field("process"),
+            },
+        );
+    }
+    Ok(Snapshot { timestamp, rows })
+}
+
+fn current() -> Result<Snapshot, String> {
+    forensic::record("sysinfo::System::refresh_processes (connection owners)");
+    let mut system = System::new();
+    system.refresh_processes();
+    let entries = collect_entries(
+        &system,
+        None,
+        AddressFamilyFlags::IPV4 | AddressFamilyFlags::IPV6,
+        ProtocolFlags::TCP | ProtocolFlags::UDP,
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(Snapshot::of(entries))
+}
+
+pub(crate) struct Change<'a> {
+    pub(crate) change: &'static str,
+    pub(crate) key: &'a Key,
+    pub(crate) before: Option<&'a Row>,
+    pub(crate) after: Option<&'a Row>,
+}
+
+impl Change<'_> {
+    pub(crate) fn state(&self) -> String {
+        match (self.before, self.after) {
+            (Some(b), Some(a)) => format!("{} -> {}", b.state, a.state),
+            (Some(r), None) | (None, Some(r)) => r.state.clone(),
+            (None, None) => String::new(),
+        }
+    }
+
+    // The process holding it now, or last.
+    pub(crate) fn process(&self) -> &str {
+        self.after
+            .or(self.before)
+            .map_or("", |r| r.process.as_str())
+    }
+}
+
+pub(crate) fn diff<'a>(before: &'a Snapshot, after: &'a Snapshot) -> Vec<Change<'a>> {
+    let mut changes = Vec::new();
+    for (key, b) in &before.rows {
+        match after.rows.get(key) {
+            None => changes.push(Change {
+                change: "closed",
+                key,
+                before: Some(b),
+                after: None,
+            }),
+            Some(a) if a.state != b.state => changes.push(Change {
+                change: "state",
+                key,
+                before: Some(b),
+                after: Some(a),
+            }),
+            Some(_) => {}
+        }
+    }
+    for (key, a) in &after.rows {
+        if !before.rows.contains_key(key) {
+            changes.push(Change {
+                change: "opened",
+                key,
+                before: None,
+                after: Some(a),
+            });
+        }
+    }
+    changes.sort_by(|x, y| x.key.cmp(y.key));
+    changes
+}
+
+fn to_json(before: &Snapshot, after: &Snapshot, changes: &[Change]) -> String {
+    let timestamp = |s: &Snapshot| s.timestamp.map_or("null".to_string(), |t| t.to_string());
+    let state = |r: Option<&Row>| json::opt_str(r.map(|r| r.state.as_str()));
+    let rows: Vec<String> = changes
+        .iter()
+        .map(|c| {
+            format!(
+                "\n{}",
+                json::object(&[
+                    ("change", json::str(c.change)),
+                    ("proto", json::str(&c.key.proto)),
+                    ("local", json::str(&c.key.local)),
+                    ("remote", json::str(&c.key.remote)),
+                    ("state_before", state(c.before)),
+                    ("state_after", state(c.after)),
+                    ("process", json::str(c.process())),
+                ])
+            )
+        })
+        .collect();
+    let end = if rows.is_empty() { "" } else { "\n" };
+    format!(
+        "{{\"baseline\":{},\"current\":{},\"changes\":[{}{}]}}",
+        timestamp(before),
+        timestamp(after),
+        rows.join(","),
+        end
+    )
+}
+
+fn print_table(changes: &[Change]) {
+    println!(
+        "{:<7} {:<6} {:<33} {:<27} {:<28} PROCESS",
+        "CHANGE", "PROTO", "LOCAL ADDRESS", "REMOTE ADDRESS", "STATE"
+    );
+    println!(
+        "{}  {}  {}  {}  {}  {}",
+        "-".repeat(6),
+        "-".repeat(5),
+        "-".repeat(32),
+        "-".repeat(26),
+        "-".repeat(27),
+        "-".repeat(39)
+    );
+    for c in changes {
+        println!(
+            "{:<7} {:<6} {:<33} {:<27} {:<28} {}",
+            c.change,
+            c.key.proto,
+            c.key.local,
+            c.key.remote,
+            c.state(),
+            c.process()
+        );
+    }
+}
+
+// `--json` and the snapshot paths.
+fn parse_args(args: cli::Args) -> Result<(bool, Vec<String>), String> {
+    let mut as_json = false;
+    let mut paths: Vec<String> = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--json" => as_json = true,
+            other if other.starts_with('-') => {
+                return Err(format!("unexpected argument '{}'", other));
+            }
+            _ => paths.push(arg),
+        }
+    }
+    if paths.is_empty() || paths.len() > 2 {
+        return Err(
+            "expected a baseline snapshot and optionally a second one (see --help)".to_string(),
+        );
+    }
+    Ok((as_json, paths))
+}
+
+pub fn run(args: Vec<String>) -> i32 {
+    let (as_json, paths) = match cli::parse_command(&cli::DIFF, args, parse_args) {
+        Ok(parsed) => parsed,
+        Err(status) => return status,
+    };
+    let mut snapshots = Vec::new();
+    for path in &paths {
+        match fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| read_snapshot(&text))
+        {
+            Ok(s) => snapshots.push(s),
+            Err(e) => {
+                eprintln!("netstatw diff: {}: {}", path, e);
+                return 2;
+            }
+        }
+    }
+    if snapshots.len() == 1 {
+        match current() {
+            Ok(s) => snapshots.push(s),
+            Err(e) => {
+                eprintln!("netstatw diff: {}", e);
+                return 1;
+            }
+        }
+    }
+    let (before, after) = (&snapshots[0], &snapshots[1]);
+    let changes = diff(before, after);
+
+    if as_json {
+        println!("{}", to_json(before, after, &changes));
+    } else if changes.is_empty() {
+        println!("No changes ({} sockets).", after.rows.len());
+    } else {
+        print_table(&changes);
+    }
+    warn::emit(false);
+    if changes.is_empty() { 0 } else { 1 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BEFORE: &str = r#"{"timestamp":100,"connections":[
+{"proto":"TCP","local":"0.0.0.0:80","remote":"0.0.0.0:0","state":"Listen","process":"1: nginx"},
+{"proto":"TCP","local":"10.0.0.1:40000","remote":"10.0.0.5:5432","state":"Established","process":"2: api"},
+{"proto":"TCP","local":"10.0.0.1:40001","remote":"10.0.0.5:5432","state":"SynSent","process":"2: api"}
+]}"#;
+
+    const AFTER: &str = r#"[
+{"proto":"TCP","local":"0.0.0.0:80","remote":"0.0.0.0:0","state":"Listen","process":"9: nginx"},
+{"proto":"TCP","local":"10.0.0.1:40001","remote":"10.0.0.5:5432","state":"Established","process":"2: api"},
+{"proto":"TCP","local":"0.0.0.0:9090","remote":"0.0.0.0:0","state":"Listen","process":"3: exporter"}
+]"#;
+
+    #[test]
+    fn diff_reports_opened_closed_and_state_changes() {
+        let before = read_snapshot(BEFORE).unwrap();
+        let after = read_snapshot(AFTER).unwrap();
+        assert_eq!(before.timestamp, Some(100));
+        let changes: Vec<(&str, &str, String)> = diff(&before, &after)
+            .iter()
+            .map(|c| (c.change, c.key.local.as_str(), c.state()))
+            .collect();
+        // A restarted listener (new PID) is not a change.
+        assert_eq!(
+            changes,
+            [
+                ("opened", "0.0.0.0:9090", "Listen".to_string()),
+                ("closed", "10.0.0.1:40000", "Established".to_string()),
+                (
+                    "state",
+                    "10.0.0.1:40001",
+                    "SynSent -> Established".to_string()
+                ),
+            ]
+        );
+        assert!(diff(&after, &after).is_empty());
+        assert!(read_snapshot(r#"{"processes":[]}"#).is_err());
+    }
+}
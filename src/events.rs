@@ -0,0 +1,302 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use sysinfo::System;
+
+use crate::diff::{self, Change, Key, Snapshot};
+use crate::{
+    AddressFamilyFlags, ProtocolFlags, cli, collect_entries, forensic, interrupt, json,
+    parse_addr_port, record, warn,
+};
+
+// `netstatw events`: the socket table as a stream of changes for log collectors. The
+// table is polled every interval and compared with the previous poll the way `diff`
+// compares two snapshots (rows matched by protocol and address pair), and every
+// connection that opened, closed or changed state since is printed as one line, text
+// or JSON, stamped with the poll's time. Connections that open and close between two
+// polls are not seen.
+//
+// A listener that opens and closes again within `--short-lived` seconds (a port tried
+// and dropped, a debug server started by a script) also gets a `short-lived` line right
+// after its `closed` line, with the owning process and how long it was seen listening.
+
+// "2026-03-01 14:05:09 UTC opened TCP 10.0.0.1:40000 10.0.0.5:5432 SynSent [2: api]", or
+// the fields of a `diff --json` change with the poll's `timestamp`. `short-lived` lines
+// end in "after 2.0s" (`lifetime` in JSON).
+fn line(c: &Change, timestamp: u64, lifetime: Option<Duration>, as_json: bool) -> String {
+    let seconds = lifetime.map(|d| (d.as_secs_f64() * 10.0).round() / 10.0);
+    if as_json {
+        let state = |r: Option<&diff::Row>| json::opt_str(r.map(|r| r.state.as_str()));
+        let mut fields = vec![
+            ("timestamp", timestamp.to_string()),
+            ("change", json::str(c.change)),
+            ("proto", json::str(&c.key.proto)),
+            ("local", json::str(&c.key.local)),
+            ("remote", json::str(&c.key.remote)),
+            ("state_before", state(c.before)),
+            ("state_after", state(c.after)),
+            ("process", json::str(c.process())),
+        ];
+        if let Some(s) = seconds {
+            fields.push(("lifetime", s.to_string()));
+        }
+        return json::object(&fields);
+    }
+    let mut text = format!(
+        "{} {} {} {} {} {}",
+        record::utc(timestamp),
+        c.change,
+        c.key.proto,
+        c.key.local,
+        c.key.remote,
+        c.state()
+    );
+    if !c.process().is_empty() {
+        text.push_str(&format!(" [{}]", c.process()));
+    }
+    if let Some(s) = seconds {
+        text.push_str(&format!(" after {:.1}s", s));
+    }
+    text
+}
+
+fn is_listener(key: &Key, row: &diff::Row) -> bool {
+    row.state == "Listen" || (key.proto == "UDP" && parse_addr_port(&key.remote).1 == 0)
+}
+
+// Listeners that opened while the stream ran, by the poll that first saw them.
+#[derive(Default)]
+struct Listeners {
+    since: BTreeMap<Key, Instant>,
+}
+
+impl Listeners {
+    // How long a tracked listener was seen, when `c` closes it.
+    fn update(&mut self, c: &Change, now: Instant) -> Option<Duration> {
+        match (c.before, c.after) {
+            (None, Some(a)) if is_listener(c.key, a) => {
+                self.since.insert(c.key.clone(), now);
+                None
+            }
+            (Some(_), None) => self
+                .since
+                .remove(c.key)
+                .map(|t| now.saturating_duration_since(t)),
+            (Some(_), Some(a)) if !is_listener(c.key, a) => {
+                self.since.remove(c.key);
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+struct Options {
+    interval: Duration,
+    duration: Option<Duration>,
+    as_json: bool,
+    protocols: ProtocolFlags,
+    initial: bool,
+    // Zero: no `short-lived` lines.
+    short_lived: Duration,
+}
+
+fn parse_args(mut args: cli::Args) -> Result<Options, String> {
+    let mut opts = Options {
+        interval: Duration::from_secs(1),
+        duration: None,
+        as_json: false,
+        protocols: ProtocolFlags::empty(),
+        initial: false,
+        short_lived: Duration::from_secs(10),
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--interval" | "-i" => {
+                opts.interval = args.secs(&arg)?.max(Duration::from_millis(100));
+            }
+            "--duration" | "-d" => opts.duration = Some(args.secs(&arg)?),
+            "--json" => opts.as_json = true,
+            "--tcp" => opts.protocols |= ProtocolFlags::TCP,
+            "--udp" => opts.protocols |= ProtocolFlags::UDP,
+            "--initial" => opts.initial = true,
+            "--short-lived" => opts.short_lived = args.secs(&arg)?,
+            other => return Err(format!("unexpected argument '{}'", other)),
+        }
+    }
+    if opts.protocols.is_empty() {
+        opts.protocols = ProtocolFlags::TCP | ProtocolFlags::UDP;
+    }
+    Ok(opts)
+}
+
+pub fn run(args: Vec<String>) -> i32 {
+    let Options {
+        interval,
+        duration,
+        as_json,
+        protocols,
+        initial,
+        short_lived,
+    } = match cli::parse_command(&cli::EVENTS, args, parse_args) {
+        Ok(opts) => opts,
+        Err(status) => return status,
+    };
+
+    interrupt::install();
+    let until = duration.map(|d| Instant::now() + d);
+    let mut system = System::new();
+    // With --initial the first poll is compared with an empty table.
+    let mut previous = initial.then(|| Snapshot::of(Vec::new()));
+    let mut polled = false;
+    let mut listeners = Listeners::default();
+    let mut out = io::stdout();
+    loop {
+        forensic::record("sysinfo::System::refresh_processes (connection owners)");
+        system.refresh_processes();
+        match collect_entries(
+            &system,
+            None,
+            AddressFamilyFlags::IPV4 | AddressFamilyFlags::IPV6,
+            protocols,
+        ) {
+            Ok(entries) => {
+                let current = Snapshot::of(entries);
+                if let Some(previous) = &previous {
+                    let timestamp = record::now();
+                    let now = Instant::now();
+                    for c in diff::diff(previous, &current) {
+                        let mut text = line(&c, timestamp, None, as_json);
+                        // Listeners already open at start (`--initial`) have no known age.
+                        let lifetime = if polled {
+                            listeners.update(&c, now)
+                        } else {
+                            None
+                        };
+                        if let Some(up) = lifetime
+                            && !short_lived.is_zero()
+                            && up <= short_lived
+                        {
+                            let short = Change {
+                                change: "short-lived",
+                                ..c
+                            };
+                            text.push('\n');
+                            text.push_str(&line(&short, timestamp, Some(up), as_json));
+                        }
+                        // The reader went away (`| head`).
+                        if writeln!(out, "{}", text).is_err() {
+                            return 0;
+                        }
+                    }
+                }
+                previous = Some(current);
+            }
+            Err(e) if !polled => {
+                eprintln!("netstatw events: failed to read socket tables: {}", e);
+                return 1;
+            }
+            // A failed poll is skipped; the next one is compared with the last good one.
+            Err(e) => warn::warn(
+                "socket_tables",
+                format!("failed to read socket tables: {}", e),
+            ),
+        }
+        polled = true;
+        warn::emit(false);
+        let left = until.map_or(interval, |u| u.saturating_duration_since(Instant::now()));
+        if left.is_zero() || !interrupt::sleep(interval.min(left)) || interrupt::should_stop() {
+            break;
+        }
+    }
+    if interrupt::interrupted() { 130 } else { 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SocketEntry;
+
+    fn row(remote: &str, state: &str, process: &str) -> SocketEntry {
+        SocketEntry {
+            proto: "TCP".to_string(),
+            local_addr: "10.0.0.1:40000".to_string(),
+            remote_addr: remote.to_string(),
+            state: state.to_string(),
+            process_info: process.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn options_take_attached_values() {
+        let argv = ["-i0.5", "--duration=3", "--tcp"]
+            .map(String::from)
+            .to_vec();
+        let opts = parse_args(cli::Args::new(&cli::EVENTS, argv).unwrap()).unwrap();
+        assert_eq!(opts.interval, Duration::from_millis(500));
+        assert_eq!(opts.duration, Some(Duration::from_secs(3)));
+        assert_eq!(opts.protocols, ProtocolFlags::TCP);
+    }
+
+    #[test]
+    fn changes_print_as_text_and_json_lines() {
+        let before = Snapshot::of(vec![row("10.0.0.5:5432", "SynSent", "2: api")]);
+        let after = Snapshot::of(vec![row("10.0.0.5:5432", "Established", "2: api")]);
+        let changes = diff::diff(&before, &after);
+        assert_eq!(
+            line(&changes[0], 1_772_373_909, None, false),
+            "2026-03-01 14:05:09 UTC state TCP 10.0.0.1:40000 10.0.0.5:5432 SynSent -> Established [2: api]"
+        );
+        let event = json::parse(&line(&changes[0], 100, None, true)).unwrap();
+        assert_eq!(
+            event.get("timestamp").and_then(json::Value::as_f64),
+            Some(100.0)
+        );
+        assert_eq!(
+            event.get("state_before").and_then(json::Value::as_str),
+            Some("SynSent")
+        );
+
+        let empty = Snapshot::of(Vec::new());
+        let closed = diff::diff(&before, &empty);
+        assert_eq!(closed[0].change, "closed");
+        let event = json::parse(&line(&closed[0], 100, None, true)).unwrap();
+        assert_eq!(event.get("state_after"), Some(&json::Value::Null));
+    }
+
+    #[test]
+    fn listeners_closing_soon_after_opening_report_their_lifetime() {
+        let mut listener = row("0.0.0.0:0", "Listen", "7: nc");
+        listener.local_addr = "0.0.0.0:4444".to_string();
+        let empty = Snapshot::of(Vec::new());
+        let open = Snapshot::of(vec![
+            listener,
+            row("10.0.0.5:5432", "Established", "2: api"),
+        ]);
+        let start = Instant::now();
+        let mut listeners = Listeners::default();
+        for c in diff::diff(&empty, &open) {
+            assert_eq!(listeners.update(&c, start), None);
+        }
+        let later = start + Duration::from_secs(3);
+        let closed = diff::diff(&open, &empty);
+        let lifetimes: Vec<_> = closed.iter().map(|c| listeners.update(c, later)).collect();
+        // Only the listener was tracked.
+        assert_eq!(lifetimes, [Some(Duration::from_secs(3)), None]);
+        let short = Change {
+            change: "short-lived",
+            ..closed[0]
+        };
+        assert_eq!(
+            line(&short, 1_772_373_909, lifetimes[0], false),
+            "2026-03-01 14:05:09 UTC short-lived TCP 0.0.0.0:4444 0.0.0.0:0 Listen [7: nc] after 3.0s"
+        );
+        let event = json::parse(&line(&short, 100, lifetimes[0], true)).unwrap();
+        assert_eq!(
+            event.get("lifetime").and_then(json::Value::as_f64),
+            Some(3.0)
+        );
+    }
+}
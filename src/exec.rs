@@ -0,0 +1,226 @@
+use std::collections::HashSet;
+use std::process::{Child, Command, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use crate::{SocketEntry, parse_addr_port};
+
+pub struct ExecOptions {
+    pub template: String,
+    pub max_concurrency: usize,
+    pub throttle: Duration,
+    // IDs of the rows listed at the previous call: a --watch refresh runs the command
+    // only for rows that weren't.
+    listed: Mutex<Option<HashSet<String>>>,
+}
+
+impl ExecOptions {
+    pub fn new(template: String, max_concurrency: usize, throttle: Duration) -> ExecOptions {
+        ExecOptions {
+            template,
+            max_concurrency,
+            throttle,
+            listed: Mutex::default(),
+        }
+    }
+
+    // The rows not listed at the previous call (all of them the first time).
+    fn fresh<'a>(&self, entries: &'a [SocketEntry]) -> Vec<&'a SocketEntry> {
+        let mut listed = self.listed.lock().unwrap_or_else(|e| e.into_inner());
+        let ids: Vec<String> = entries.iter().map(SocketEntry::conn_id).collect();
+        let fresh = entries
+            .iter()
+            .zip(&ids)
+            .filter(|(_, id)| !listed.as_ref().is_some_and(|l| l.contains(*id)))
+            .map(|(e, _)| e)
+            .collect();
+        *listed = Some(ids.into_iter().collect());
+        fresh
+    }
+}
+
+// Split the template into argv tokens before substitution so values taken from
+// the socket table (process paths, addresses) can never inject extra arguments
+// or shell syntax. Single and double quotes group words; no escapes.
+fn tokenize(template: &str) -> Vec<String> {
+    let mut tokens: Vec<String> = Vec::new();
+    let mut cur = String::new();
+    let mut in_token = false;
+    let mut quote: Option<char> = None;
+    for c in template.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => cur.push(c),
+            None if c == '\'' || c == '"' => {
+                quote = Some(c);
+                in_token = true;
+            }
+            None if c.is_whitespace() => {
+                if in_token {
+                    tokens.push(std::mem::take(&mut cur));
+                    in_token = false;
+                }
+            }
+            None => {
+                cur.push(c);
+                in_token = true;
+            }
+        }
+    }
+    if in_token {
+        tokens.push(cur);
+    }
+    tokens
+}
+
+// One pass over the token, so a value that itself holds `{rip}` or a stray brace (a
+// process path, say) is copied as it is. Unknown placeholders stay literal.
+fn substitute(token: &str, entry: &SocketEntry) -> String {
+    let (lip, lport) = parse_addr_port(&entry.local_addr);
+    let (rip, rport) = parse_addr_port(&entry.remote_addr);
+    let value = |name: &str| -> Option<String> {
+        Some(match name {
+            "proto" => entry.proto.clone(),
+            "laddr" => entry.local_addr.clone(),
+            "lip" => lip.to_string(),
+            "lport" => lport.to_string(),
+            "raddr" => entry.remote_addr.clone(),
+            "rip" => rip.to_string(),
+            "rport" => rport.to_string(),
+            "state" => entry.state.clone(),
+            "pid" => entry.pids.first().map(u32::to_string).unwrap_or_default(),
+            "process" => entry.process_info.clone(),
+            _ => return None,
+        })
+    };
+    let mut out = String::new();
+    let mut rest = token;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let placeholder = after
+            .find('}')
+            .and_then(|close| Some((close, value(&after[..close])?)));
+        match placeholder {
+            Some((close, v)) => {
+                out.push_str(&v);
+                rest = &after[close + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn reap(running: &mut Vec<Child>) {
+    running.retain_mut(|child| !matches!(child.try_wait(), Ok(Some(_)) | Err(_)));
+}
+
+pub fn run_for_entries(opts: &ExecOptions, entries: &[SocketEntry]) {
+    let tokens = tokenize(&opts.template);
+    if tokens.is_empty() {
+        crate::warn::warn("exec_failed", "--exec template is empty".to_string());
+        return;
+    }
+
+    let mut running: Vec<Child> = Vec::new();
+    for (i, entry) in opts.fresh(entries).into_iter().enumerate() {
+        // Respect the concurrency cap before launching another command.
+        reap(&mut running);
+        while running.len() >= opts.max_concurrency {
+            thread::sleep(Duration::from_millis(20));
+            reap(&mut running);
+        }
+        if i > 0 && !opts.throttle.is_zero() {
+            thread::sleep(opts.throttle);
+        }
+
+        let argv: Vec<String> = tokens.iter().map(|t| substitute(t, entry)).collect();
+        match Command::new(&argv[0])
+            .args(&argv[1..])
+            .stdin(Stdio::null())
+            .spawn()
+        {
+            Ok(child) => running.push(child),
+            Err(e) => crate::warn::warn("exec_failed", format!("failed to run {}: {}", argv[0], e)),
+        }
+    }
+
+    for mut child in running {
+        let _ = child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(remote: &str, process: &str) -> SocketEntry {
+        SocketEntry {
+            proto: "TCP".to_string(),
+            local_addr: "10.0.0.1:40000".to_string(),
+            remote_addr: remote.to_string(),
+            state: "Established".to_string(),
+            pids: vec![4242],
+            process_info: process.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn quotes_group_words() {
+        assert_eq!(
+            tokenize(r#"  logger -t 'net watch' "pid {pid}"'' x""y  "#),
+            ["logger", "-t", "net watch", "pid {pid}", "xy"]
+        );
+        assert_eq!(tokenize("a '' b"), ["a", "", "b"]);
+        assert!(tokenize("   ").is_empty());
+    }
+
+    #[test]
+    fn placeholders_fill_in_within_a_token_and_values_stay_whole() {
+        let e = row("[2001:db8::5]:443", "/opt/my {rip} app/run");
+        let argv: Vec<String> = tokenize("probe --to={rip}:{rport} {process} {pid}{nope} {")
+            .iter()
+            .map(|t| substitute(t, &e))
+            .collect();
+        assert_eq!(
+            argv,
+            [
+                "probe",
+                "--to=2001:db8::5:443",
+                "/opt/my {rip} app/run",
+                "4242{nope}",
+                "{"
+            ]
+        );
+    }
+
+    #[test]
+    fn a_refresh_runs_only_for_rows_not_listed_before() {
+        let opts = ExecOptions::new("true".to_string(), 1, Duration::ZERO);
+        let (a, b) = (row("10.0.0.5:443", "a"), row("10.0.0.6:443", "b"));
+        let remotes = |rows: Vec<&SocketEntry>| {
+            rows.iter()
+                .map(|e| e.remote_addr.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            remotes(opts.fresh(std::slice::from_ref(&a))),
+            ["10.0.0.5:443"]
+        );
+        assert_eq!(
+            remotes(opts.fresh(&[a.clone(), b.clone()])),
+            ["10.0.0.6:443"]
+        );
+        assert!(opts.fresh(&[a.clone(), b]).is_empty());
+        // Gone for a refresh, then back: new again.
+        opts.fresh(&[]);
+        assert_eq!(remotes(opts.fresh(&[a])), ["10.0.0.5:443"]);
+    }
+}
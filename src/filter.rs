@@ -0,0 +1,582 @@
+use std::mem::discriminant;
+use std::net::IpAddr;
+
+use crate::{SocketEntry, parse_addr_port, zone};
+
+// Row filters and how they combine:
+// - different kinds of filter are ANDed (`--port 443 --process nginx`: both must match);
+// - repeating a kind ORs its values (`--port 80 --port 443`: either port);
+// - `--not` before a filter option turns it into an exclusion: rows matching any
+//   excluded value are dropped, whatever the other filters say.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Criterion {
+    // Connection ID or a prefix of it (lowercase hex).
+    Id(String),
+    // Case-insensitive substring of the PROCESS column.
+    Process(String),
+    // Local or remote port.
+    Port(PortPattern),
+    // Local port only.
+    LocalPort(PortPattern),
+    // Remote port only.
+    RemotePort(PortPattern),
+    // TCP listeners and bound UDP sockets (`netstat -l`).
+    Listening,
+    // TCP state, normalized by `state_key` ("time_wait" matches "TimeWait").
+    State(String),
+    // Local or remote address inside a network.
+    Cidr(Cidr),
+    // Local address only.
+    LocalNet(Cidr),
+    // Remote address only.
+    RemoteNet(Cidr),
+    // A tag from the tag rules (exact, case-insensitive).
+    Tag(String),
+    // Remote country code from the GeoIP databases, case-insensitive.
+    Country(String),
+    // Owning account, case-insensitive; `svc` also matches `DOMAIN\svc` (Windows).
+    User(String),
+    // IPv6 zone (interface) of the local address, `eth0` in `[fe80::1%eth0]:22`.
+    Zone(String),
+    // Traffic that stays on the host: a loopback local or remote address
+    // (`--no-loopback` excludes it).
+    Loopback,
+    // Connections to other hosts beyond the link: a private or public remote address
+    // (`--external-only`).
+    External,
+}
+
+impl Criterion {
+    fn matches(&self, e: &SocketEntry) -> bool {
+        match self {
+            Criterion::Id(prefix) => e.conn_id().starts_with(prefix.as_str()),
+            Criterion::Process(name) => e
+                .process_info
+                .to_ascii_lowercase()
+                .contains(&name.to_ascii_lowercase()),
+            Criterion::Port(port) => {
+                port.matches(parse_addr_port(&e.local_addr).1)
+                    || port.matches(parse_addr_port(&e.remote_addr).1)
+            }
+            Criterion::LocalPort(port) => port.matches(parse_addr_port(&e.local_addr).1),
+            Criterion::RemotePort(port) => port.matches(parse_addr_port(&e.remote_addr).1),
+            Criterion::Listening => e.state == "Listen" || e.proto == "UDP",
+            Criterion::State(key) => state_key(&e.state) == *key,
+            Criterion::Cidr(net) => [&e.local_addr, &e.remote_addr]
+                .iter()
+                .any(|addr| net.contains_addr(addr)),
+            Criterion::LocalNet(net) => net.contains_addr(&e.local_addr),
+            Criterion::RemoteNet(net) => net.contains_addr(&e.remote_addr),
+            Criterion::Tag(tag) => e.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)),
+            Criterion::Country(code) => e
+                .geo
+                .as_ref()
+                .and_then(|g| g.country.as_deref())
+                .is_some_and(|c| c.eq_ignore_ascii_case(code)),
+            Criterion::User(name) => e.user.as_deref().is_some_and(|user| {
+                user.eq_ignore_ascii_case(name)
+                    || user
+                        .rsplit_once('\\')
+                        .is_some_and(|(_, account)| account.eq_ignore_ascii_case(name))
+            }),
+            Criterion::Zone(name) => zone::split(parse_addr_port(&e.local_addr).0).1 == Some(name),
+            Criterion::Loopback => [&e.local_addr, &e.remote_addr]
+                .iter()
+                .any(|addr| scope(addr) == Some(Scope::Loopback)),
+            Criterion::External => {
+                matches!(scope(&e.remote_addr), Some(Scope::Private | Scope::Public))
+            }
+        }
+    }
+}
+
+// TCP state names as rendered in the STATE column.
+pub const TCP_STATES: [&str; 13] = [
+    "Closed",
+    "Listen",
+    "SynSent",
+    "SynReceived",
+    "Established",
+    "FinWait1",
+    "FinWait2",
+    "CloseWait",
+    "Closing",
+    "LastAck",
+    "TimeWait",
+    "DeleteTcb",
+    "Unknown",
+];
+
+// Case- and separator-insensitive form of a state name: "TIME_WAIT", "time-wait"
+// and "TimeWait" all become "timewait".
+fn state_key(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, '_' | '-' | ' '))
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+// Parses a `--state` value: one or more comma-separated TCP state names. Unknown
+// names are reported with the list of valid ones.
+pub fn parse_states(v: &str) -> Result<Vec<Criterion>, String> {
+    v.split(',')
+        .map(|name| {
+            let key = state_key(name.trim());
+            if TCP_STATES.iter().any(|s| state_key(s) == key) {
+                Ok(Criterion::State(key))
+            } else {
+                Err(format!(
+                    "unknown state '{}' (expected one of: {})",
+                    name.trim(),
+                    TCP_STATES.join(", ")
+                ))
+            }
+        })
+        .collect()
+}
+
+// Parses a port option value: comma-separated ports, wildcards ("3??0") and
+// template names, which `template` expands (from `[ports]` in the config file) to
+// ports and wildcards in turn.
+pub fn parse_ports(
+    v: &str,
+    mut template: impl FnMut(&str) -> Option<String>,
+) -> Result<Vec<PortPattern>, String> {
+    let mut out = Vec::new();
+    for item in v.split(',').map(str::trim) {
+        if let Some(p) = PortPattern::parse(item) {
+            out.push(p);
+            continue;
+        }
+        let is_name = item
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+        let Some(expanded) = template(item).filter(|_| is_name && !item.is_empty()) else {
+            return Err(format!(
+                "'{}' is not a port, wildcard or port template (define templates under [ports] in the config file)",
+                item
+            ));
+        };
+        for p in expanded.split(',').map(str::trim) {
+            out.push(
+                PortPattern::parse(p)
+                    .ok_or_else(|| format!("port template '{}': '{}' is not a port", item, p))?,
+            );
+        }
+    }
+    Ok(out)
+}
+
+// A port value: an exact port or a wildcard over its decimal digits, where `?` is
+// one digit and `*` any number of them ("3??0" matches 3000 and 3990, "80*" 80 and
+// 8080).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PortPattern {
+    Exact(u16),
+    Glob(String),
+}
+
+impl PortPattern {
+    pub fn matches(&self, port: u16) -> bool {
+        match self {
+            PortPattern::Exact(p) => *p == port,
+            PortPattern::Glob(glob) => glob_match(glob.as_bytes(), port.to_string().as_bytes()),
+        }
+    }
+
+    fn parse(v: &str) -> Option<PortPattern> {
+        if let Ok(port) = v.parse::<u16>() {
+            return Some(PortPattern::Exact(port));
+        }
+        let is_glob = !v.is_empty()
+            && v.bytes()
+                .all(|b| b.is_ascii_digit() || b == b'?' || b == b'*')
+            && v.bytes().any(|b| b == b'?' || b == b'*');
+        is_glob.then(|| PortPattern::Glob(v.to_string()))
+    }
+}
+
+// Parses a network option value: comma-separated CIDR blocks or single addresses.
+pub fn parse_nets(v: &str) -> Result<Vec<Cidr>, String> {
+    v.split(',')
+        .map(|n| Cidr::parse(n).ok_or_else(|| format!("'{}' is not a network", n.trim())))
+        .collect()
+}
+
+// An IPv4 or IPv6 network, `10.20.0.0/16` or `2001:db8::/32`; a bare address is a
+// single host. IPv4-mapped IPv6 addresses (`::ffff:10.20.0.5`) match IPv4 networks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cidr {
+    net: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn parse(v: &str) -> Option<Cidr> {
+        let (addr, prefix) = match v.trim().split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix.parse::<u8>().ok()?)),
+            None => (v.trim(), None),
+        };
+        let net: IpAddr = addr.parse().ok()?;
+        let max = if net.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(max);
+        (prefix <= max).then_some(Cidr { net, prefix })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.net, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+
+    // Whether an `addr:port` column value is inside the network; wildcards and `*`
+    // never are.
+    fn contains_addr(&self, addr: &str) -> bool {
+        zone::parse_ip(parse_addr_port(addr).0)
+            .is_some_and(|ip| !ip.is_unspecified() && self.contains(ip))
+    }
+}
+
+// Where an address reaches: the SCOPE column (`--scope`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scope {
+    // 127.0.0.0/8, ::1.
+    Loopback,
+    // 169.254.0.0/16, fe80::/10.
+    LinkLocal,
+    // RFC 1918 networks and IPv6 unique local addresses (fc00::/7).
+    Private,
+    Public,
+}
+
+impl Scope {
+    pub fn label(self) -> &'static str {
+        match self {
+            Scope::Loopback => "loopback",
+            Scope::LinkLocal => "link-local",
+            Scope::Private => "private",
+            Scope::Public => "public",
+        }
+    }
+
+    fn of(ip: IpAddr) -> Scope {
+        match ip.to_canonical() {
+            ip if ip.is_loopback() => Scope::Loopback,
+            IpAddr::V4(ip) if ip.is_link_local() => Scope::LinkLocal,
+            IpAddr::V4(ip) if ip.is_private() => Scope::Private,
+            IpAddr::V6(ip) if ip.is_unicast_link_local() => Scope::LinkLocal,
+            IpAddr::V6(ip) if ip.is_unique_local() => Scope::Private,
+            _ => Scope::Public,
+        }
+    }
+}
+
+// The scope of an `addr:port` column value; `None` for wildcards and `*`.
+pub fn scope(addr: &str) -> Option<Scope> {
+    zone::parse_ip(parse_addr_port(addr).0)
+        .filter(|ip| !ip.is_unspecified())
+        .map(Scope::of)
+}
+
+fn glob_match(glob: &[u8], text: &[u8]) -> bool {
+    match (glob.first(), text.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob_match(&glob[1..], text) || (!text.is_empty() && glob_match(glob, &text[1..]))
+        }
+        (Some(b'?'), Some(_)) => glob_match(&glob[1..], &text[1..]),
+        (Some(g), Some(t)) if g == t => glob_match(&glob[1..], &text[1..]),
+        _ => false,
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    include: Vec<Criterion>,
+    exclude: Vec<Criterion>,
+}
+
+impl Filter {
+    pub fn add(&mut self, criterion: Criterion, negate: bool) {
+        if negate {
+            self.exclude.push(criterion);
+        } else {
+            self.include.push(criterion);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    pub fn matches(&self, e: &SocketEntry) -> bool {
+        if self.exclude.iter().any(|c| c.matches(e)) {
+            return false;
+        }
+        self.include.iter().all(|c| {
+            // Satisfied if any criterion of the same kind matches.
+            self.include
+                .iter()
+                .filter(|other| discriminant(*other) == discriminant(c))
+                .any(|other| other.matches(e))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PortPattern::Exact;
+    use super::*;
+
+    fn entry(local: &str, remote: &str, process: &str) -> SocketEntry {
+        SocketEntry {
+            proto: "TCP".to_string(),
+            local_addr: local.to_string(),
+            remote_addr: remote.to_string(),
+            state: "Established".to_string(),
+            process_info: process.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn filter(criteria: &[(Criterion, bool)]) -> Filter {
+        let mut f = Filter::default();
+        for (c, negate) in criteria {
+            f.add(c.clone(), *negate);
+        }
+        f
+    }
+
+    #[test]
+    fn matches_empty_filter_accepts_everything() {
+        assert!(Filter::default().matches(&entry("10.0.0.1:443", "1.2.3.4:5000", "1: nginx")));
+    }
+
+    #[test]
+    fn matches_different_kinds_are_anded() {
+        let f = filter(&[
+            (Criterion::Port(Exact(443)), false),
+            (Criterion::Process("nginx".into()), false),
+        ]);
+        assert!(f.matches(&entry("10.0.0.1:443", "1.2.3.4:5000", "1: /usr/sbin/nginx")));
+        assert!(!f.matches(&entry("10.0.0.1:443", "1.2.3.4:5000", "2: /usr/bin/curl")));
+        assert!(!f.matches(&entry("10.0.0.1:80", "1.2.3.4:5000", "1: /usr/sbin/nginx")));
+    }
+
+    #[test]
+    fn matches_repeated_kind_is_ored() {
+        let f = filter(&[
+            (Criterion::Port(Exact(80)), false),
+            (Criterion::Port(Exact(443)), false),
+        ]);
+        assert!(f.matches(&entry("10.0.0.1:80", "1.2.3.4:5000", "")));
+        assert!(f.matches(&entry("10.0.0.1:5001", "1.2.3.4:443", "")));
+        assert!(!f.matches(&entry("10.0.0.1:22", "1.2.3.4:5000", "")));
+    }
+
+    #[test]
+    fn matches_negated_criterion_excludes_rows() {
+        let f = filter(&[(Criterion::Port(Exact(22)), true)]);
+        assert!(!f.matches(&entry("10.0.0.1:22", "1.2.3.4:5000", "")));
+        assert!(f.matches(&entry("10.0.0.1:80", "1.2.3.4:5000", "")));
+    }
+
+    #[test]
+    fn matches_exclusion_wins_over_inclusion() {
+        let f = filter(&[
+            (Criterion::Process("nginx".into()), false),
+            (Criterion::Port(Exact(8080)), true),
+        ]);
+        assert!(f.matches(&entry("10.0.0.1:443", "1.2.3.4:5000", "1: nginx")));
+        assert!(!f.matches(&entry("10.0.0.1:8080", "1.2.3.4:5000", "1: nginx")));
+    }
+
+    #[test]
+    fn matches_local_and_remote_port_sides() {
+        let f = filter(&[
+            (Criterion::LocalPort(Exact(5000)), false),
+            (Criterion::RemotePort(Exact(443)), false),
+        ]);
+        assert!(f.matches(&entry("10.0.0.1:5000", "1.2.3.4:443", "")));
+        assert!(!f.matches(&entry("10.0.0.1:443", "1.2.3.4:5000", "")));
+    }
+
+    #[test]
+    fn matches_listening_keeps_listeners_and_udp() {
+        let f = filter(&[(Criterion::Listening, false)]);
+        let mut listen = entry("0.0.0.0:80", "0.0.0.0:0", "");
+        listen.state = "Listen".to_string();
+        let mut udp = entry("0.0.0.0:53", "*:*", "");
+        udp.proto = "UDP".to_string();
+        udp.state = "-".to_string();
+        assert!(f.matches(&listen));
+        assert!(f.matches(&udp));
+        assert!(!f.matches(&entry("10.0.0.1:5000", "1.2.3.4:443", "")));
+    }
+
+    #[test]
+    fn matches_state_ignores_case_and_separators() {
+        let f = filter(
+            &parse_states("established,time_wait")
+                .unwrap()
+                .into_iter()
+                .map(|c| (c, false))
+                .collect::<Vec<_>>(),
+        );
+        let mut closing = entry("10.0.0.1:5000", "1.2.3.4:443", "");
+        assert!(f.matches(&closing));
+        closing.state = "TimeWait".to_string();
+        assert!(f.matches(&closing));
+        closing.state = "CloseWait".to_string();
+        assert!(!f.matches(&closing));
+        assert!(parse_states("established,bogus").is_err());
+    }
+
+    #[test]
+    fn scopes_classify_addresses_and_drive_loopback_and_external_filters() {
+        assert_eq!(scope("127.0.0.53:53"), Some(Scope::Loopback));
+        assert_eq!(scope("[::1]:631"), Some(Scope::Loopback));
+        assert_eq!(scope("[::ffff:127.0.0.1]:80"), Some(Scope::Loopback));
+        assert_eq!(scope("169.254.169.254:80"), Some(Scope::LinkLocal));
+        assert_eq!(scope("[fe80::1%eth0]:22"), Some(Scope::LinkLocal));
+        assert_eq!(scope("172.20.1.5:5432"), Some(Scope::Private));
+        assert_eq!(scope("[fd00::5]:443"), Some(Scope::Private));
+        assert_eq!(scope("8.8.8.8:53"), Some(Scope::Public));
+        assert_eq!(scope("0.0.0.0:0"), None);
+        assert_eq!(scope("*:*"), None);
+
+        let no_loopback = filter(&[(Criterion::Loopback, true)]);
+        assert!(!no_loopback.matches(&entry("127.0.0.1:5000", "127.0.0.1:8080", "")));
+        assert!(!no_loopback.matches(&entry("127.0.0.1:8080", "0.0.0.0:0", "")));
+        assert!(no_loopback.matches(&entry("10.0.0.1:5000", "1.2.3.4:443", "")));
+
+        let external = filter(&[(Criterion::External, false)]);
+        assert!(external.matches(&entry("10.0.0.1:5000", "1.2.3.4:443", "")));
+        assert!(external.matches(&entry("10.0.0.1:5000", "10.0.0.2:22", "")));
+        assert!(!external.matches(&entry("10.0.0.1:5000", "169.254.169.254:80", "")));
+        assert!(!external.matches(&entry("127.0.0.1:5000", "127.0.0.1:8080", "")));
+        assert!(!external.matches(&entry("0.0.0.0:80", "0.0.0.0:0", "")));
+    }
+
+    #[test]
+    fn local_and_remote_nets_match_their_side_only() {
+        let nets = parse_nets("10.0.0.0/8, fd00::/8").unwrap();
+        assert_eq!(nets.len(), 2);
+        assert!(parse_nets("10.0.0.0/8,bogus").is_err());
+
+        let remote = filter(&[
+            (Criterion::RemoteNet(nets[0]), false),
+            (Criterion::RemoteNet(nets[1]), false),
+        ]);
+        assert!(remote.matches(&entry("192.168.1.2:5000", "10.1.2.3:443", "")));
+        assert!(remote.matches(&entry("[2001:db8::1]:5000", "[fd00::5]:443", "")));
+        assert!(!remote.matches(&entry("10.1.2.3:443", "192.168.1.2:5000", "")));
+
+        let local_only = filter(&[
+            (Criterion::LocalNet(nets[0]), false),
+            (
+                Criterion::RemoteNet(Cidr::parse("10.9.0.0/16").unwrap()),
+                true,
+            ),
+        ]);
+        assert!(local_only.matches(&entry("10.1.2.3:5000", "10.8.0.1:443", "")));
+        assert!(!local_only.matches(&entry("10.1.2.3:5000", "10.9.0.1:443", "")));
+        assert!(!local_only.matches(&entry("0.0.0.0:80", "0.0.0.0:0", "")));
+    }
+
+    #[test]
+    fn cidr_matches_either_address() {
+        let net = Cidr::parse("10.20.0.0/16").unwrap();
+        assert!(net.contains("10.20.255.1".parse().unwrap()));
+        assert!(net.contains("::ffff:10.20.0.5".parse().unwrap()));
+        assert!(!net.contains("10.21.0.1".parse().unwrap()));
+        let v6 = Cidr::parse("2001:db8::/32").unwrap();
+        assert!(v6.contains("2001:db8:1::5".parse().unwrap()));
+        assert!(!v6.contains("10.20.0.1".parse().unwrap()));
+        assert!(
+            Cidr::parse("0.0.0.0/0")
+                .unwrap()
+                .contains("8.8.8.8".parse().unwrap())
+        );
+        assert_eq!(Cidr::parse("10.0.0.0/33"), None);
+        assert_eq!(Cidr::parse("db"), None);
+
+        let f = filter(&[(Criterion::Cidr(net), false)]);
+        assert!(f.matches(&entry("192.168.1.2:5000", "10.20.3.4:5432", "")));
+        assert!(f.matches(&entry("10.20.3.4:5432", "192.168.1.2:5000", "")));
+        assert!(!f.matches(&entry("0.0.0.0:5432", "0.0.0.0:0", "")));
+
+        // Zoned link-local addresses parse like any other.
+        let link = filter(&[(Criterion::Cidr(Cidr::parse("fe80::/10").unwrap()), false)]);
+        assert!(link.matches(&entry("[fe80::1%eth0]:22", "[fe80::2%eth0]:51000", "")));
+        let eth0 = filter(&[(Criterion::Zone("eth0".to_string()), false)]);
+        assert!(eth0.matches(&entry("[fe80::1%eth0]:22", "[fe80::2%eth0]:51000", "")));
+        assert!(!eth0.matches(&entry("[fe80::1%eth1]:22", "[fe80::2%eth1]:51000", "")));
+    }
+
+    fn no_templates(_: &str) -> Option<String> {
+        None
+    }
+
+    #[test]
+    fn parse_ports_accepts_lists() {
+        assert_eq!(parse_ports("80", no_templates), Ok(vec![Exact(80)]));
+        assert_eq!(
+            parse_ports("80, 443,8080", no_templates),
+            Ok(vec![Exact(80), Exact(443), Exact(8080)])
+        );
+        assert!(parse_ports("80,http", no_templates).is_err());
+        assert!(parse_ports("70000", no_templates).is_err());
+    }
+
+    #[test]
+    fn parse_ports_expands_templates() {
+        let templates = |name: &str| (name == "web").then(|| "80,443,8?8?".to_string());
+        assert_eq!(
+            parse_ports("web,22", templates),
+            Ok(vec![
+                Exact(80),
+                Exact(443),
+                PortPattern::Glob("8?8?".to_string()),
+                Exact(22)
+            ])
+        );
+        assert!(parse_ports("mail", templates).is_err());
+    }
+
+    #[test]
+    fn port_wildcards_match_digits() {
+        let glob = |g: &str| PortPattern::parse(g).unwrap();
+        assert!(glob("3??0").matches(3000) && glob("3??0").matches(3990));
+        assert!(!glob("3??0").matches(300) && !glob("3??0").matches(3001));
+        assert!(glob("80*").matches(80) && glob("80*").matches(8080));
+        assert!(!glob("80*").matches(180));
+    }
+
+    #[test]
+    fn matches_process_is_case_insensitive() {
+        let f = filter(&[(Criterion::Process("NGINX".into()), false)]);
+        assert!(f.matches(&entry("10.0.0.1:443", "1.2.3.4:5000", "1: nginx.exe")));
+    }
+
+    #[test]
+    fn user_matches_the_account_with_or_without_domain() {
+        let mut row = entry("0.0.0.0:80", "0.0.0.0:0", "4: C:\\svc\\web.exe");
+        row.user = Some("NT AUTHORITY\\NETWORK SERVICE".to_string());
+        let f = |name: &str| filter(&[(Criterion::User(name.into()), false)]);
+        assert!(f("network service").matches(&row));
+        assert!(f("NT AUTHORITY\\Network Service").matches(&row));
+        assert!(!f("network").matches(&row));
+        row.user = None;
+        assert!(!f("network service").matches(&row));
+    }
+}
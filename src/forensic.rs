@@ -0,0 +1,86 @@
+use std::sync::Mutex;
+
+// Record of every system API queried during the run, in first-use order with call
+// counts. `--forensic` prints it so a capture on a machine under legal hold can
+// document exactly what the tool touched. Calls that change system state (closing a
+// connection, firewall rules, switching on EStats) are recorded with `record_change`,
+// so the report's verdict comes from the log rather than from what the options allow.
+static QUERIED: Mutex<Vec<Call>> = Mutex::new(Vec::new());
+
+struct Call {
+    api: &'static str,
+    count: u64,
+    changes_state: bool,
+}
+
+fn log(api: &'static str, changes_state: bool) {
+    let Ok(mut log) = QUERIED.lock() else { return };
+    match log.iter_mut().find(|c| c.api == api) {
+        Some(c) => c.count += 1,
+        None => log.push(Call {
+            api,
+            count: 1,
+            changes_state,
+        }),
+    }
+}
+
+pub fn record(api: &'static str) {
+    log(api, false);
+}
+
+pub fn record_change(api: &'static str) {
+    log(api, true);
+}
+
+// The report's first line, from what was recorded.
+fn verdict(log: &[Call]) -> String {
+    let changes: Vec<&str> = log
+        .iter()
+        .filter(|c| c.changes_state)
+        .map(|c| c.api)
+        .collect();
+    match changes.is_empty() {
+        true => "no state-modifying calls recorded".to_string(),
+        false => format!("state-modifying calls recorded: {}", changes.join(", ")),
+    }
+}
+
+pub fn print_report() {
+    let Ok(log) = QUERIED.lock() else { return };
+    eprintln!("netstatw: forensic mode: {}", verdict(&log));
+    eprintln!("netstatw: calls recorded:");
+    for c in log.iter() {
+        let mark = if c.changes_state {
+            " (modifies state)"
+        } else {
+            ""
+        };
+        eprintln!("  {:<60} x{}{}", c.api, c.count, mark);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verdict_names_the_recorded_state_changes() {
+        let call = |api, changes_state| Call {
+            api,
+            count: 1,
+            changes_state,
+        };
+        assert_eq!(
+            verdict(&[call("netlink sock_diag (TCP tcp_info)", false)]),
+            "no state-modifying calls recorded"
+        );
+        assert_eq!(
+            verdict(&[
+                call("netlink sock_diag (TCP tcp_info)", false),
+                call("netlink SOCK_DESTROY (close TCP connection)", true),
+            ]),
+            "state-modifying calls recorded: netlink SOCK_DESTROY (close TCP connection)"
+        );
+    }
+}
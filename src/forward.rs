@@ -0,0 +1,228 @@
+use sysinfo::{Pid, System};
+
+use crate::cli::{self, Opt, Type, opt};
+
+// Recognizes port-forwarding helpers from their command line, so a local listener
+// owned by `ssh -L` or `kubectl port-forward` is labelled with where its traffic
+// actually goes.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Forward {
+    // Local port the forwarder listens on, when the command line says.
+    pub listen_port: Option<u16>,
+    // Apparent destination, e.g. `db.internal:5432` or `svc/web:80`.
+    pub target: String,
+}
+
+fn fwd(listen_port: Option<u16>, target: String) -> Forward {
+    Forward {
+        listen_port,
+        target,
+    }
+}
+
+// `[bind:]port:host:hostport` (ssh -L/-R). IPv6 hosts may be bracketed.
+fn ssh_spec(spec: &str) -> Option<Forward> {
+    let parts: Vec<&str> = spec.rsplitn(3, ':').collect();
+    if parts.len() < 3 {
+        return None;
+    }
+    let (host_port, host, rest) = (parts[0], parts[1], parts[2]);
+    let listen = rest.rsplit(':').next()?.parse::<u16>().ok();
+    Some(fwd(listen, format!("{}:{}", host, host_port)))
+}
+
+// ssh's options that take a value (`-fNL 8080:db:5432` is `-f -N -L 8080:db:5432`).
+const SSH_OPTIONS: [Opt; 21] = [
+    opt(&["-B"], "IFACE", Type::Text, ""),
+    opt(&["-b"], "ADDR", Type::Text, ""),
+    opt(&["-c"], "CIPHERS", Type::Text, ""),
+    opt(&["-D"], "SPEC", Type::Text, ""),
+    opt(&["-E"], "FILE", Type::Path, ""),
+    opt(&["-e"], "CHAR", Type::Text, ""),
+    opt(&["-F"], "FILE", Type::Path, ""),
+    opt(&["-I"], "LIB", Type::Path, ""),
+    opt(&["-i"], "FILE", Type::Path, ""),
+    opt(&["-J"], "HOST", Type::Text, ""),
+    opt(&["-L"], "SPEC", Type::Text, ""),
+    opt(&["-l"], "USER", Type::Text, ""),
+    opt(&["-m"], "MACS", Type::Text, ""),
+    opt(&["-O"], "CMD", Type::Text, ""),
+    opt(&["-o"], "OPTION", Type::Text, ""),
+    opt(&["-P"], "TAG", Type::Text, ""),
+    opt(&["-p"], "PORT", Type::Number, ""),
+    opt(&["-R"], "SPEC", Type::Text, ""),
+    opt(&["-S"], "PATH", Type::Path, ""),
+    opt(&["-W"], "HOST:PORT", Type::Address, ""),
+    opt(&["-w"], "TUN", Type::Text, ""),
+];
+
+fn ssh(cmd: &[String]) -> Vec<Forward> {
+    let mut out = Vec::new();
+    let mut args = cli::Args::foreign(&SSH_OPTIONS, cmd.iter().skip(1).cloned().collect());
+    while let Some(arg) = args.next() {
+        let Some(spec) = args.optional() else {
+            continue;
+        };
+        let forward = match arg.as_str() {
+            "-D" => {
+                let port = spec.rsplit(':').next().and_then(|p| p.parse().ok());
+                Some(fwd(port, "socks".to_string()))
+            }
+            // -R listens on the remote side; locally ssh only connects out.
+            "-R" => ssh_spec(&spec).map(|f| fwd(None, f.target)),
+            "-L" => ssh_spec(&spec),
+            _ => None,
+        };
+        out.extend(forward);
+    }
+    out
+}
+
+// kubectl's options that take a separate value, whose value must not be read as the
+// resource.
+const KUBECTL_OPTIONS: [Opt; 4] = [
+    opt(&["-n", "--namespace"], "NAMESPACE", Type::Text, ""),
+    opt(&["--address"], "ADDR", Type::Text, ""),
+    opt(&["--context"], "NAME", Type::Text, ""),
+    opt(&["--kubeconfig"], "FILE", Type::Path, ""),
+];
+
+// kubectl port-forward TYPE/NAME [LOCAL:]REMOTE ...
+fn kubectl(cmd: &[String]) -> Vec<Forward> {
+    let Some(pos) = cmd.iter().position(|a| a == "port-forward") else {
+        return Vec::new();
+    };
+    let mut positional = Vec::new();
+    let mut args = cli::Args::foreign(&KUBECTL_OPTIONS, cmd[pos + 1..].to_vec());
+    while let Some(arg) = args.next() {
+        if args.optional().is_none() && !arg.starts_with('-') {
+            positional.push(arg);
+        }
+    }
+    let mut positional = positional.into_iter();
+    let Some(resource) = positional.next() else {
+        return Vec::new();
+    };
+    positional
+        .filter_map(|ports| {
+            let (local, remote) = ports.split_once(':').unwrap_or((&ports, &ports));
+            let remote = remote.parse::<u16>().ok()?;
+            Some(fwd(local.parse().ok(), format!("{}:{}", resource, remote)))
+        })
+        .collect()
+}
+
+// socat TCP-LISTEN:8080,fork TCP:host:80
+fn socat(cmd: &[String]) -> Vec<Forward> {
+    let addrs: Vec<&String> = cmd.iter().skip(1).filter(|a| !a.starts_with('-')).collect();
+    let [from, to] = addrs[..] else {
+        return Vec::new();
+    };
+    let listen = from
+        .to_ascii_uppercase()
+        .contains("LISTEN:")
+        .then(|| from.split([':', ',']).nth(1)?.parse::<u16>().ok())
+        .flatten();
+    let target = to.split(',').next().unwrap_or(to);
+    let target = target.split_once(':').map_or(target, |(_, t)| t);
+    vec![fwd(listen, target.to_string())]
+}
+
+pub fn parse(name: &str, cmd: &[String]) -> Vec<Forward> {
+    let name = name.to_ascii_lowercase();
+    let name = name.trim_end_matches(".exe");
+    match name {
+        "ssh" => ssh(cmd),
+        "kubectl" | "oc" => kubectl(cmd),
+        "socat" => socat(cmd),
+        "code-tunnel" => vec![fwd(None, "vscode tunnel".to_string())],
+        "code" | "code-insiders" if cmd.iter().any(|a| a == "tunnel") => {
+            vec![fwd(None, "vscode tunnel".to_string())]
+        }
+        _ => Vec::new(),
+    }
+}
+
+// Label for a row owned by `pid` with local port `port`: the forward listening on
+// that port, else the process's first forward.
+pub fn label(system: &System, pid: u32, port: u16) -> Option<String> {
+    let process = system.process(Pid::from(pid as usize))?;
+    let forwards = parse(process.name(), process.cmd());
+    let f = forwards
+        .iter()
+        .find(|f| f.listen_port == Some(port))
+        .or(forwards.first())?;
+    Some(f.target.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cmd(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn parse_ssh_local_and_remote_forwards() {
+        let f = parse(
+            "ssh",
+            &cmd(
+                "ssh -N -L 8080:web.internal:80 -L127.0.0.1:5433:db:5432 -R 9000:localhost:3000 bastion",
+            ),
+        );
+        assert_eq!(
+            f,
+            vec![
+                fwd(Some(8080), "web.internal:80".into()),
+                fwd(Some(5433), "db:5432".into()),
+                fwd(None, "localhost:3000".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_ssh_grouped_options() {
+        let f = parse(
+            "ssh",
+            &cmd("ssh -fNL8080:db:5432 -o Port=22 -qD 1080 bastion"),
+        );
+        assert_eq!(
+            f,
+            vec![
+                fwd(Some(8080), "db:5432".into()),
+                fwd(Some(1080), "socks".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_ssh_without_forwards_is_empty() {
+        assert!(parse("ssh", &cmd("ssh -p 2222 host")).is_empty());
+    }
+
+    #[test]
+    fn parse_kubectl_port_forward() {
+        let f = parse(
+            "kubectl",
+            &cmd("kubectl port-forward -n prod svc/web 8080:80 9090"),
+        );
+        assert_eq!(
+            f,
+            vec![
+                fwd(Some(8080), "svc/web:80".into()),
+                fwd(Some(9090), "svc/web:9090".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_socat_listen_to_tcp() {
+        let f = parse(
+            "socat",
+            &cmd("socat TCP-LISTEN:8080,fork,reuseaddr TCP:10.0.0.5:80"),
+        );
+        assert_eq!(f, vec![fwd(Some(8080), "10.0.0.5:80".into())]);
+    }
+}
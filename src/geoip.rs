@@ -0,0 +1,414 @@
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+
+use crate::json::Value;
+use crate::{SocketEntry, parse_addr_port, zone};
+
+// `--geoip FILE`: country and network owner (ASN) of remote addresses, from MaxMind DB
+// files (GeoLite2-Country, -City, -ASN, or compatible). The format is a binary search
+// tree over the address bits whose leaves point into a data section of typed values
+// (https://maxmind.github.io/MaxMind-DB/); this reader loads the whole file and
+// decodes only the records it is asked for. Several files can be given: a country
+// database and an ASN database are usually separate downloads.
+
+const METADATA_MARKER: &[u8] = b"\xAB\xCD\xEFMaxMind.com";
+// The tree and the data section are separated by 16 zero bytes.
+const DATA_SEPARATOR: usize = 16;
+
+pub struct Db {
+    buf: Vec<u8>,
+    node_count: u32,
+    record_size: u32,
+    ip_version: u16,
+    // Start of the data section in `buf`.
+    data_start: usize,
+    // Node reached after the 96 zero bits of ::a.b.c.d, where IPv4 starts in an IPv6
+    // tree.
+    ipv4_start: u32,
+}
+
+// What the databases know about one address.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Geo {
+    // ISO 3166 code, e.g. "NL".
+    pub country: Option<String>,
+    pub asn: Option<u32>,
+    pub as_org: Option<String>,
+}
+
+impl Geo {
+    // "AS64500 Example Networks".
+    pub fn as_label(&self) -> Option<String> {
+        match (self.asn, &self.as_org) {
+            (Some(n), Some(org)) => Some(format!("AS{} {}", n, org)),
+            (Some(n), None) => Some(format!("AS{}", n)),
+            (None, _) => None,
+        }
+    }
+}
+
+impl Db {
+    pub fn open(path: &Path) -> Result<Db, String> {
+        let buf = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Db::parse(buf).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn parse(buf: Vec<u8>) -> Result<Db, String> {
+        // The metadata follows the last marker, within the final 128 KiB.
+        let tail = buf.len().saturating_sub(128 * 1024);
+        let marker = buf[tail..]
+            .windows(METADATA_MARKER.len())
+            .rposition(|w| w == METADATA_MARKER)
+            .map(|i| tail + i)
+            .ok_or("not a MaxMind DB file (no metadata)")?;
+        let meta_start = marker + METADATA_MARKER.len();
+        let (meta, _) = Decoder {
+            buf: &buf,
+            base: meta_start,
+        }
+        .decode(meta_start, 0)?;
+        let field = |key| {
+            meta.get(key)
+                .and_then(Value::as_f64)
+                .ok_or(format!("metadata lacks {}", key))
+        };
+        let node_count = field("node_count")? as u32;
+        let record_size = field("record_size")? as u32;
+        let ip_version = field("ip_version")? as u16;
+        if ![24, 28, 32].contains(&record_size) {
+            return Err(format!("unsupported record size {}", record_size));
+        }
+        let tree_size = node_count as usize * record_size as usize / 4;
+        if tree_size + DATA_SEPARATOR > marker {
+            return Err("search tree runs past the data section".to_string());
+        }
+        let mut db = Db {
+            buf,
+            node_count,
+            record_size,
+            ip_version,
+            data_start: tree_size + DATA_SEPARATOR,
+            ipv4_start: 0,
+        };
+        if ip_version == 6 {
+            let mut node = 0;
+            for _ in 0..96 {
+                if node >= node_count {
+                    break;
+                }
+                node = db.record(node, 0);
+            }
+            db.ipv4_start = node;
+        }
+        Ok(db)
+    }
+
+    // Left (`bit` 0) or right record of a tree node.
+    fn record(&self, node: u32, bit: u8) -> u32 {
+        let size = self.record_size as usize / 4;
+        let b = &self.buf[node as usize * size..][..size];
+        let be = |bytes: &[u8]| bytes.iter().fold(0u32, |n, &b| n << 8 | b as u32);
+        match (self.record_size, bit) {
+            (24, 0) => be(&b[0..3]),
+            (24, _) => be(&b[3..6]),
+            // The middle byte holds the high nibble of both records.
+            (28, 0) => (b[3] as u32 & 0xF0) << 20 | be(&b[0..3]),
+            (28, _) => (b[3] as u32 & 0x0F) << 24 | be(&b[4..7]),
+            (_, 0) => be(&b[0..4]),
+            _ => be(&b[4..8]),
+        }
+    }
+
+    // The record stored for `ip`, if any.
+    pub fn lookup(&self, ip: IpAddr) -> Option<Value> {
+        let (bits, start): (Vec<u8>, u32) = match ip.to_canonical() {
+            IpAddr::V4(v4) => (v4.octets().to_vec(), self.ipv4_start),
+            IpAddr::V6(_) if self.ip_version == 4 => return None,
+            IpAddr::V6(v6) => (v6.octets().to_vec(), 0),
+        };
+        let mut node = start;
+        for i in 0..bits.len() * 8 {
+            if node >= self.node_count {
+                break;
+            }
+            node = self.record(node, bits[i / 8] >> (7 - i % 8) & 1);
+        }
+        if node <= self.node_count {
+            return None;
+        }
+        let offset = (node - self.node_count) as usize - DATA_SEPARATOR;
+        let decoder = Decoder {
+            buf: &self.buf,
+            base: self.data_start,
+        };
+        decoder
+            .decode(self.data_start + offset, 0)
+            .ok()
+            .map(|(v, _)| v)
+    }
+}
+
+struct Decoder<'a> {
+    buf: &'a [u8],
+    // Pointers are relative to this offset (the data section, or the metadata).
+    base: usize,
+}
+
+impl Decoder<'_> {
+    fn bytes(&self, at: usize, n: usize) -> Result<&[u8], String> {
+        self.buf
+            .get(at..at + n)
+            .ok_or_else(|| "record runs past the end of the file".to_string())
+    }
+
+    fn uint(&self, at: usize, n: usize) -> Result<u64, String> {
+        Ok(self
+            .bytes(at, n)?
+            .iter()
+            .fold(0u64, |v, &b| v << 8 | b as u64))
+    }
+
+    // The value at `at` and the offset after it. `depth` bounds nesting (and pointer
+    // chains) in a corrupt file.
+    fn decode(&self, at: usize, depth: usize) -> Result<(Value, usize), String> {
+        if depth > 32 {
+            return Err("data nested too deeply".to_string());
+        }
+        let ctrl = *self.bytes(at, 1)?.first().unwrap_or(&0);
+        let mut pos = at + 1;
+        let mut kind = ctrl >> 5;
+        if kind == 1 {
+            let ss = (ctrl >> 3 & 3) as usize;
+            let high = (ctrl & 7) as u64;
+            let raw = self.uint(pos, ss + 1)?;
+            let target = match ss {
+                0 => high << 8 | raw,
+                1 => (high << 16 | raw) + 2048,
+                2 => (high << 24 | raw) + 526_336,
+                _ => raw,
+            };
+            let (value, _) = self.decode(self.base + target as usize, depth + 1)?;
+            return Ok((value, pos + ss + 1));
+        }
+        if kind == 0 {
+            kind = 7 + self.uint(pos, 1)? as u8;
+            pos += 1;
+        }
+        let mut size = (ctrl & 0x1F) as usize;
+        if size >= 29 {
+            let extra = size - 28;
+            size = match extra {
+                1 => 29 + self.uint(pos, 1)? as usize,
+                2 => 285 + self.uint(pos, 2)? as usize,
+                _ => 65_821 + self.uint(pos, 3)? as usize,
+            };
+            pos += extra;
+        }
+        let value = match kind {
+            2 => Value::Str(String::from_utf8_lossy(self.bytes(pos, size)?).into_owned()),
+            3 => Value::Num(f64::from_bits(self.uint(pos, 8)?)),
+            15 => Value::Num(f32::from_bits(self.uint(pos, 4)? as u32) as f64),
+            // Unsigned integers up to 128 bits (ASNs and IDs fit an f64 exactly).
+            5 | 6 | 9 | 10 => Value::Num(
+                self.bytes(pos, size)?
+                    .iter()
+                    .fold(0f64, |v, &b| v * 256.0 + b as f64),
+            ),
+            8 => Value::Num((self.uint(pos, size)? as u32) as i32 as f64),
+            14 => return Ok((Value::Bool(size != 0), pos)),
+            7 => {
+                let mut fields = Vec::with_capacity(size);
+                for _ in 0..size {
+                    let (key, next) = self.decode(pos, depth + 1)?;
+                    let (value, next) = self.decode(next, depth + 1)?;
+                    fields.push((key.as_str().unwrap_or_default().to_string(), value));
+                    pos = next;
+                }
+                return Ok((Value::Object(fields), pos));
+            }
+            11 => {
+                let mut items = Vec::with_capacity(size);
+                for _ in 0..size {
+                    let (item, next) = self.decode(pos, depth + 1)?;
+                    items.push(item);
+                    pos = next;
+                }
+                return Ok((Value::Array(items), pos));
+            }
+            // Bytes, and the container and end-marker types that don't occur in
+            // records.
+            _ => Value::Null,
+        };
+        Ok((value, pos + size))
+    }
+}
+
+// The databases given with `--geoip`, queried in order.
+#[derive(Default)]
+pub struct Lookup {
+    dbs: Vec<Db>,
+}
+
+impl Lookup {
+    pub fn open(paths: &[impl AsRef<Path>]) -> Result<Lookup, String> {
+        let dbs = paths
+            .iter()
+            .map(|p| Db::open(p.as_ref()))
+            .collect::<Result<_, _>>()?;
+        Ok(Lookup { dbs })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dbs.is_empty()
+    }
+
+    // Country and ASN of a public address; None when no database knows it.
+    pub fn geo(&self, ip: IpAddr) -> Option<Geo> {
+        let mut geo = Geo::default();
+        for db in &self.dbs {
+            let Some(record) = db.lookup(ip) else {
+                continue;
+            };
+            let text = |v: Option<&Value>| v.and_then(Value::as_str).map(str::to_string);
+            // City and Country databases; the registered country covers anycast and
+            // satellite ranges without a physical one.
+            geo.country = geo.country.or_else(|| {
+                text(record.get("country").and_then(|c| c.get("iso_code"))).or_else(|| {
+                    text(
+                        record
+                            .get("registered_country")
+                            .and_then(|c| c.get("iso_code")),
+                    )
+                })
+            });
+            geo.asn = geo.asn.or_else(|| {
+                record
+                    .get("autonomous_system_number")
+                    .and_then(Value::as_f64)
+                    .map(|n| n as u32)
+            });
+            geo.as_org = geo
+                .as_org
+                .or_else(|| text(record.get("autonomous_system_organization")));
+        }
+        (geo != Geo::default()).then_some(geo)
+    }
+
+    // Sets `geo` on every row with a remote address the databases know.
+    pub fn annotate(&self, entries: &mut [SocketEntry]) {
+        if self.dbs.is_empty() {
+            return;
+        }
+        crate::forensic::record("MaxMind DB lookups (--geoip, local files)");
+        for e in entries {
+            let host = parse_addr_port(&e.remote_addr).0;
+            e.geo = zone::parse_ip(host)
+                .filter(|ip| !ip.is_unspecified())
+                .and_then(|ip| self.geo(ip));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Minimal MaxMind DB writer: an IPv4 tree with 24-bit records.
+    fn string(s: &str) -> Vec<u8> {
+        // Lengths from 29 take an extra size byte.
+        let mut out = match s.len() {
+            n if n < 29 => vec![0x40 | n as u8],
+            n => vec![0x40 | 29, (n - 29) as u8],
+        };
+        out.extend(s.as_bytes());
+        out
+    }
+
+    fn uint32(n: u32) -> Vec<u8> {
+        let mut out = vec![0xC4];
+        out.extend(n.to_be_bytes());
+        out
+    }
+
+    fn map(fields: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut out = vec![0xE0 | fields.len() as u8];
+        for (key, value) in fields {
+            out.extend(string(key));
+            out.extend(value);
+        }
+        out
+    }
+
+    fn build(networks: &[([u8; 4], usize, Vec<u8>)]) -> Vec<u8> {
+        // Records: None (not found), Some(Ok(node)) or Some(Err(data offset)).
+        let mut nodes: Vec<[Option<Result<u32, usize>>; 2]> = vec![[None, None]];
+        let mut data: Vec<u8> = Vec::new();
+        for (ip, len, record) in networks {
+            let mut node = 0;
+            for i in 0..*len {
+                let bit = (ip[i / 8] >> (7 - i % 8) & 1) as usize;
+                if i + 1 == *len {
+                    nodes[node][bit] = Some(Err(data.len()));
+                } else if let Some(Ok(next)) = nodes[node][bit] {
+                    node = next as usize;
+                } else {
+                    nodes.push([None, None]);
+                    nodes[node][bit] = Some(Ok(nodes.len() as u32 - 1));
+                    node = nodes.len() - 1;
+                }
+            }
+            data.extend(record);
+        }
+        let count = nodes.len() as u32;
+        let mut out = Vec::new();
+        for node in &nodes {
+            for record in node {
+                let n = match record {
+                    None => count,
+                    Some(Ok(next)) => *next,
+                    Some(Err(offset)) => count + 16 + *offset as u32,
+                };
+                out.extend(&n.to_be_bytes()[1..]);
+            }
+        }
+        out.extend([0; 16]);
+        out.extend(data);
+        out.extend(METADATA_MARKER);
+        out.extend(map(&[
+            ("node_count", uint32(count)),
+            ("record_size", vec![0xA2, 0, 24]),
+            ("ip_version", vec![0xA2, 0, 4]),
+        ]));
+        out
+    }
+
+    #[test]
+    fn lookups_decode_country_and_asn_records() {
+        let country = map(&[("country", map(&[("iso_code", string("NL"))]))]);
+        // The organization is a pointer to the "NL" string in the first record.
+        let asn = map(&[
+            ("autonomous_system_number", uint32(64500)),
+            ("autonomous_system_organization", vec![0x20, 19]),
+        ]);
+        let db = Db::parse(build(&[
+            ([203, 0, 113, 0], 24, country),
+            ([198, 51, 100, 0], 23, asn),
+        ]))
+        .unwrap();
+        let lookup = Lookup { dbs: vec![db] };
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        assert_eq!(
+            lookup.geo(ip("203.0.113.77")),
+            Some(Geo {
+                country: Some("NL".to_string()),
+                ..Default::default()
+            })
+        );
+        let geo = lookup.geo(ip("198.51.101.1")).unwrap();
+        assert_eq!(geo.as_label().as_deref(), Some("AS64500 NL"));
+        assert_eq!(lookup.geo(ip("203.0.114.1")), None);
+        assert_eq!(lookup.geo(ip("::ffff:203.0.113.1")).unwrap().asn, None);
+        assert!(Db::parse(b"not a database".to_vec()).is_err());
+    }
+}
@@ -0,0 +1,338 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use sysinfo::{Pid, System};
+
+use crate::tags::Rules;
+use crate::{
+    AddressFamilyFlags, ProtocolFlags, SocketEntry, cli, collect_entries, forensic, interrupt,
+    parse_addr_port, warn, zone,
+};
+
+// `netstatw graph --dot|--mermaid`: a dependency diagram of the box. TCP connections are
+// recorded over a window and folded into edges between local processes (by name, so
+// restarts and worker pools collapse) and the remote side: outbound connections point
+// at the remote endpoint, host or tag, inbound ones (to a local listener) come from the
+// client host and are labelled with the listening port. Edge labels count the distinct
+// connections seen.
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum By {
+    Endpoint,
+    Host,
+    Tag,
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Node {
+    Process(String),
+    Remote(String),
+}
+
+impl Node {
+    fn id(&self) -> String {
+        match self {
+            Node::Process(name) => format!("p:{}", name),
+            Node::Remote(name) => format!("r:{}", name),
+        }
+    }
+
+    fn label(&self) -> &str {
+        match self {
+            Node::Process(name) | Node::Remote(name) => name,
+        }
+    }
+}
+
+// (from, to, label) -> distinct connections seen.
+type Edges = BTreeMap<(Node, Node, String), BTreeSet<(String, String)>>;
+
+fn is_unspecified(addr: &str) -> bool {
+    let ip = parse_addr_port(addr).0;
+    ip.is_empty() || ip == "*" || zone::parse_ip(ip).is_some_and(|a| a.is_unspecified())
+}
+
+// Folds one snapshot into `edges`. `names` gives a process's name by PID.
+fn record(edges: &mut Edges, entries: &[SocketEntry], by: By, names: &dyn Fn(u32) -> String) {
+    let listening: HashSet<u16> = entries
+        .iter()
+        .filter(|e| e.proto == "TCP" && e.state == "Listen")
+        .map(|e| parse_addr_port(&e.local_addr).1)
+        .collect();
+    for e in entries {
+        if e.proto != "TCP" || e.state == "Listen" || is_unspecified(&e.remote_addr) {
+            continue;
+        }
+        let (remote_ip, remote_port) = parse_addr_port(&e.remote_addr);
+        let local_port = parse_addr_port(&e.local_addr).1;
+        let host = e
+            .remote_host
+            .clone()
+            .unwrap_or_else(|| remote_ip.to_string());
+        let endpoint = crate::join_addr(&host, remote_port);
+        let conn = (e.local_addr.clone(), e.remote_addr.clone());
+        for &pid in &e.pids {
+            let process = Node::Process(names(pid));
+            let edge = if listening.contains(&local_port) {
+                (
+                    Node::Remote(host.clone()),
+                    process,
+                    format!(":{}", local_port),
+                )
+            } else {
+                let remote = match by {
+                    By::Endpoint => endpoint.clone(),
+                    By::Host => host.clone(),
+                    By::Tag if !e.tags.is_empty() => e.tags.join(","),
+                    By::Tag => endpoint.clone(),
+                };
+                (process, Node::Remote(remote), String::new())
+            };
+            edges.entry(edge).or_default().insert(conn.clone());
+        }
+    }
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Every node with an edge, processes first.
+fn nodes(edges: &Edges) -> BTreeSet<&Node> {
+    let mut nodes = BTreeSet::new();
+    for (from, to, _) in edges.keys() {
+        nodes.insert(from);
+        nodes.insert(to);
+    }
+    nodes
+}
+
+fn edge_label(label: &str, conns: &BTreeSet<(String, String)>) -> String {
+    if label.is_empty() {
+        conns.len().to_string()
+    } else {
+        format!("{} ({})", label, conns.len())
+    }
+}
+
+fn dot(edges: &Edges) -> String {
+    let mut out = String::from("digraph netstatw {\n  rankdir=LR;\n  node [shape=box];\n");
+    for node in nodes(edges) {
+        let shape = match node {
+            Node::Process(_) => "",
+            Node::Remote(_) => ", shape=ellipse",
+        };
+        out.push_str(&format!(
+            "  {} [label={}{}];\n",
+            quote(&node.id()),
+            quote(node.label()),
+            shape
+        ));
+    }
+    for ((from, to, label), conns) in edges {
+        out.push_str(&format!(
+            "  {} -> {} [label={}];\n",
+            quote(&from.id()),
+            quote(&to.id()),
+            quote(&edge_label(label, conns))
+        ));
+    }
+    out.push_str("}\n");
+    out
+}
+
+// Mermaid node ids must be plain words, so nodes are numbered; labels are quoted with
+// `"` written as an entity.
+fn mermaid(edges: &Edges) -> String {
+    let text = |s: &str| format!("\"{}\"", s.replace('"', "#quot;"));
+    let mut ids: BTreeMap<&Node, String> = BTreeMap::new();
+    let mut out = String::from("flowchart LR\n");
+    for (i, node) in nodes(edges).into_iter().enumerate() {
+        let id = format!("n{}", i);
+        let label = text(node.label());
+        out.push_str(&match node {
+            Node::Process(_) => format!("  {}[{}]\n", id, label),
+            Node::Remote(_) => format!("  {}([{}])\n", id, label),
+        });
+        ids.insert(node, id);
+    }
+    for ((from, to, label), conns) in edges {
+        out.push_str(&format!(
+            "  {} -->|{}| {}\n",
+            ids[from],
+            text(&edge_label(label, conns)),
+            ids[to]
+        ));
+    }
+    out
+}
+
+struct Options {
+    format: fn(&Edges) -> String,
+    duration: Duration,
+    interval: Duration,
+    by: By,
+    rules_path: Option<PathBuf>,
+}
+
+fn parse_args(mut args: cli::Args) -> Result<Options, String> {
+    let mut format: Option<fn(&Edges) -> String> = None;
+    let mut duration = Duration::ZERO;
+    let mut interval = Duration::from_secs(1);
+    let mut by = By::Endpoint;
+    let mut rules_path: Option<PathBuf> = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dot" => format = Some(dot),
+            "--mermaid" => format = Some(mermaid),
+            "--duration" | "-d" => duration = args.secs(&arg)?,
+            "--interval" | "-i" => interval = args.secs(&arg)?.max(Duration::from_millis(100)),
+            "--by" => {
+                by = match args.value(&arg)?.as_str() {
+                    "endpoint" => By::Endpoint,
+                    "host" => By::Host,
+                    "tag" => By::Tag,
+                    _ => return Err("--by takes endpoint, host or tag".to_string()),
+                }
+            }
+            "--tag-rules" => rules_path = Some(PathBuf::from(args.value(&arg)?)),
+            other => return Err(format!("unexpected argument '{}'", other)),
+        }
+    }
+    let Some(format) = format else {
+        return Err("choose an output format (--dot or --mermaid)".to_string());
+    };
+    Ok(Options {
+        format,
+        duration,
+        interval,
+        by,
+        rules_path,
+    })
+}
+
+pub fn run(args: Vec<String>) -> i32 {
+    let Options {
+        format,
+        duration,
+        interval,
+        by,
+        rules_path,
+    } = match cli::parse_command(&cli::GRAPH, args, parse_args) {
+        Ok(opts) => opts,
+        Err(status) => return status,
+    };
+    let rules = if by == By::Tag {
+        match Rules::load(rules_path.as_deref()) {
+            Ok(rules) => rules,
+            Err(e) => {
+                eprintln!("netstatw graph: tag rules: {}", e);
+                return 2;
+            }
+        }
+    } else {
+        Rules::default()
+    };
+
+    // Ctrl-C ends the window early; the graph of what was recorded is still printed.
+    interrupt::install();
+    let until = Instant::now() + duration;
+    let mut system = System::new();
+    let mut edges = Edges::new();
+    loop {
+        forensic::record("sysinfo::System::refresh_processes (process names)");
+        system.refresh_processes();
+        match collect_entries(
+            &system,
+            None,
+            AddressFamilyFlags::IPV4 | AddressFamilyFlags::IPV6,
+            ProtocolFlags::TCP,
+        ) {
+            Ok(mut entries) => {
+                rules.apply(&mut entries);
+                let names = |pid: u32| {
+                    system
+                        .process(Pid::from(pid as usize))
+                        .map_or_else(|| format!("pid {}", pid), |p| p.name().to_string())
+                };
+                record(&mut edges, &entries, by, &names);
+            }
+            Err(e) => {
+                eprintln!("netstatw graph: {}", e);
+                return 1;
+            }
+        }
+        let left = until.saturating_duration_since(Instant::now());
+        if left.is_zero() || !interrupt::sleep(interval.min(left)) || interrupt::should_stop() {
+            break;
+        }
+    }
+    print!("{}", format(&edges));
+    warn::emit(false);
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tcp(local: &str, remote: &str, state: &str, pid: u32) -> SocketEntry {
+        SocketEntry {
+            proto: "TCP".to_string(),
+            local_addr: local.to_string(),
+            remote_addr: remote.to_string(),
+            state: state.to_string(),
+            pids: vec![pid],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn record_folds_connections_into_process_edges() {
+        let names = |pid: u32| {
+            if pid == 1 {
+                "api".to_string()
+            } else {
+                "nginx".to_string()
+            }
+        };
+        let snapshot = [
+            tcp("0.0.0.0:443", "0.0.0.0:0", "Listen", 2),
+            tcp("10.0.0.1:443", "203.0.113.9:50000", "Established", 2),
+            tcp("10.0.0.1:40000", "10.0.0.5:5432", "Established", 1),
+            tcp("10.0.0.1:40001", "10.0.0.5:5432", "Established", 1),
+        ];
+        let mut edges = Edges::new();
+        record(&mut edges, &snapshot, By::Endpoint, &names);
+        // The same connections again in a later snapshot don't count twice.
+        record(&mut edges, &snapshot, By::Endpoint, &names);
+        let out = dot(&edges);
+        assert!(out.starts_with("digraph netstatw {\n"));
+        assert!(out.contains("  \"p:api\" -> \"r:10.0.0.5:5432\" [label=\"2\"];\n"));
+        assert!(out.contains("  \"r:203.0.113.9\" -> \"p:nginx\" [label=\":443 (1)\"];\n"));
+        assert!(out.contains("  \"r:10.0.0.5:5432\" [label=\"10.0.0.5:5432\", shape=ellipse];\n"));
+        assert_eq!(edges.len(), 2);
+
+        let mut by_host = Edges::new();
+        record(&mut by_host, &snapshot, By::Host, &names);
+        assert!(dot(&by_host).contains("\"p:api\" -> \"r:10.0.0.5\""));
+    }
+
+    #[test]
+    fn mermaid_numbers_nodes_and_quotes_labels() {
+        let mut edges = Edges::new();
+        edges
+            .entry((
+                Node::Process("say \"hi\"".to_string()),
+                Node::Remote("db:5432".to_string()),
+                String::new(),
+            ))
+            .or_default()
+            .insert(("a".to_string(), "b".to_string()));
+        assert_eq!(
+            mermaid(&edges),
+            "flowchart LR\n  n0[\"say #quot;hi#quot;\"]\n  n1([\"db:5432\"])\n  n0 -->|\"1\"| n1\n"
+        );
+    }
+}
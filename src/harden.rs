@@ -0,0 +1,246 @@
+// `serve --harden`: before the exporter starts its threads, give up what a process
+// that only reads the socket table and answers HTTP never needs, so a bug in the
+// request handling can't be turned into running programs or touching other
+// processes. On Linux that is no_new_privs and a seccomp filter that fails the
+// calls below with EPERM (for every thread); on Windows, process mitigation policies
+// (no child processes, no dynamic code, no remote or low-integrity images, no legacy
+// extension points, no win32k calls). Off by default: it can't be undone for the
+// life of the process.
+
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+mod seccomp {
+    use libc::{
+        BPF_ABS, BPF_JEQ, BPF_JGE, BPF_JMP, BPF_K, BPF_LD, BPF_RET, BPF_W, SECCOMP_RET_ALLOW,
+        SECCOMP_RET_DATA, SECCOMP_RET_ERRNO, sock_filter,
+    };
+
+    // Starting programs, inspecting or changing other processes, mounts, modules,
+    // namespaces, kernel keyrings, BPF and perf.
+    const DENIED: &[libc::c_long] = &[
+        libc::SYS_execve,
+        libc::SYS_execveat,
+        libc::SYS_ptrace,
+        libc::SYS_process_vm_readv,
+        libc::SYS_process_vm_writev,
+        libc::SYS_mount,
+        libc::SYS_umount2,
+        libc::SYS_pivot_root,
+        libc::SYS_chroot,
+        libc::SYS_kexec_load,
+        libc::SYS_kexec_file_load,
+        libc::SYS_init_module,
+        libc::SYS_finit_module,
+        libc::SYS_delete_module,
+        libc::SYS_reboot,
+        libc::SYS_swapon,
+        libc::SYS_swapoff,
+        libc::SYS_bpf,
+        libc::SYS_keyctl,
+        libc::SYS_add_key,
+        libc::SYS_request_key,
+        libc::SYS_perf_event_open,
+        libc::SYS_userfaultfd,
+        libc::SYS_unshare,
+        libc::SYS_setns,
+        libc::SYS_open_by_handle_at,
+    ];
+
+    // AUDIT_ARCH_* from <linux/audit.h>; libc doesn't have them.
+    #[cfg(target_arch = "x86_64")]
+    const ARCH: u32 = 0xc000_003e;
+    #[cfg(target_arch = "aarch64")]
+    const ARCH: u32 = 0xc000_00b7;
+    // x86_64 also takes x32 calls, numbered from here.
+    #[cfg(target_arch = "x86_64")]
+    const X32_FIRST: Option<u32> = Some(0x4000_0000);
+    #[cfg(not(target_arch = "x86_64"))]
+    const X32_FIRST: Option<u32> = None;
+
+    // Offsets into struct seccomp_data.
+    const NR: u32 = 0;
+    const AUDIT_ARCH: u32 = 4;
+
+    fn stmt(code: u32, k: u32) -> sock_filter {
+        sock_filter {
+            code: code as u16,
+            jt: 0,
+            jf: 0,
+            k,
+        }
+    }
+
+    fn jump(code: u32, k: u32, jt: u8, jf: u8) -> sock_filter {
+        sock_filter {
+            code: code as u16,
+            jt,
+            jf,
+            k,
+        }
+    }
+
+    // Calls of another architecture (or the x32 ABI) are refused whole; native
+    // calls in `denied` fail, the rest go through. The denied calls jump over the
+    // ALLOW to the last instruction.
+    pub(super) fn program(arch: u32, x32_first: Option<u32>, denied: &[u32]) -> Vec<sock_filter> {
+        let deny = SECCOMP_RET_ERRNO | (libc::EPERM as u32 & SECCOMP_RET_DATA);
+        let n = denied.len();
+        let mut prog = vec![
+            stmt(BPF_LD | BPF_W | BPF_ABS, AUDIT_ARCH),
+            jump(BPF_JMP | BPF_JEQ | BPF_K, arch, 1, 0),
+            stmt(BPF_RET | BPF_K, deny),
+            stmt(BPF_LD | BPF_W | BPF_ABS, NR),
+        ];
+        if let Some(first) = x32_first {
+            prog.push(jump(BPF_JMP | BPF_JGE | BPF_K, first, (n + 1) as u8, 0));
+        }
+        for (i, &nr) in denied.iter().enumerate() {
+            prog.push(jump(BPF_JMP | BPF_JEQ | BPF_K, nr, (n - i) as u8, 0));
+        }
+        prog.push(stmt(BPF_RET | BPF_K, SECCOMP_RET_ALLOW));
+        prog.push(stmt(BPF_RET | BPF_K, deny));
+        prog
+    }
+
+    pub fn install() -> Result<(), String> {
+        let denied: Vec<u32> = DENIED.iter().map(|&nr| nr as u32).collect();
+        let mut filter = program(ARCH, X32_FIRST, &denied);
+        let prog = libc::sock_fprog {
+            len: filter.len() as libc::c_ushort,
+            filter: filter.as_mut_ptr(),
+        };
+        // SAFETY: plain prctl; no pointers.
+        if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+            return Err(format!("no_new_privs: {}", std::io::Error::last_os_error()));
+        }
+        // SAFETY: `prog` points at `filter`, which outlives the call; the kernel
+        // copies the program.
+        let status = unsafe {
+            libc::syscall(
+                libc::SYS_seccomp,
+                libc::SECCOMP_SET_MODE_FILTER,
+                libc::SECCOMP_FILTER_FLAG_TSYNC,
+                &prog as *const libc::sock_fprog,
+            )
+        };
+        match status {
+            0 => Ok(()),
+            // With TSYNC, a positive result is a thread that couldn't be synced.
+            tid if tid > 0 => Err(format!("seccomp: thread {} has its own filter", tid)),
+            _ => Err(format!("seccomp: {}", std::io::Error::last_os_error())),
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        // The verdict of `prog` for one call, the way the kernel runs it.
+        fn run(prog: &[sock_filter], arch: u32, nr: u32) -> u32 {
+            let mut acc = 0;
+            let mut pc = 0;
+            loop {
+                let ins = &prog[pc];
+                let code = ins.code as u32;
+                pc += 1;
+                if code == BPF_LD | BPF_W | BPF_ABS {
+                    acc = if ins.k == AUDIT_ARCH { arch } else { nr };
+                } else if code == BPF_RET | BPF_K {
+                    return ins.k;
+                } else {
+                    let taken = if code == BPF_JMP | BPF_JEQ | BPF_K {
+                        acc == ins.k
+                    } else {
+                        acc >= ins.k
+                    };
+                    pc += if taken { ins.jt } else { ins.jf } as usize;
+                }
+            }
+        }
+
+        #[test]
+        fn denied_and_foreign_calls_fail_and_the_rest_go_through() {
+            let deny = SECCOMP_RET_ERRNO | libc::EPERM as u32;
+            let denied: Vec<u32> = DENIED.iter().map(|&nr| nr as u32).collect();
+            let prog = program(ARCH, Some(0x4000_0000), &denied);
+            for &nr in &denied {
+                assert_eq!(run(&prog, ARCH, nr), deny, "call {}", nr);
+            }
+            for nr in [libc::SYS_read, libc::SYS_socket, libc::SYS_openat] {
+                assert_eq!(run(&prog, ARCH, nr as u32), SECCOMP_RET_ALLOW);
+            }
+            assert_eq!(run(&prog, ARCH, 0x4000_0000 + 59), deny);
+            assert_eq!(run(&prog, 0x4000_0003, libc::SYS_read as u32), deny);
+            let without_x32 = program(ARCH, None, &denied);
+            assert_eq!(run(&without_x32, ARCH, 0x4000_0000), SECCOMP_RET_ALLOW);
+        }
+    }
+}
+
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+pub fn apply() -> Result<(), String> {
+    seccomp::install()
+}
+
+#[cfg(windows)]
+pub fn apply() -> Result<(), String> {
+    use windows_sys::Win32::System::Threading::{
+        ProcessChildProcessPolicy, ProcessDynamicCodePolicy, ProcessExtensionPointDisablePolicy,
+        ProcessImageLoadPolicy, ProcessSystemCallDisablePolicy, SetProcessMitigationPolicy,
+    };
+    // Each PROCESS_MITIGATION_*_POLICY is a single flags word; the bits turned on:
+    // NoChildProcessCreation, ProhibitDynamicCode, NoRemoteImages |
+    // NoLowMandatoryLabelImages, DisableExtensionPoints, DisallowWin32kSystemCalls.
+    let policies = [
+        ("child processes", ProcessChildProcessPolicy, 1u32),
+        ("dynamic code", ProcessDynamicCodePolicy, 1),
+        ("image loads", ProcessImageLoadPolicy, 3),
+        ("extension points", ProcessExtensionPointDisablePolicy, 1),
+        ("win32k calls", ProcessSystemCallDisablePolicy, 1),
+    ];
+    let mut failed = Vec::new();
+    for (name, policy, flags) in policies {
+        // SAFETY: the buffer is a u32 that lives across the call, the size of the
+        // policy structure.
+        let ok = unsafe {
+            SetProcessMitigationPolicy(
+                policy,
+                &flags as *const u32 as *const core::ffi::c_void,
+                std::mem::size_of::<u32>(),
+            )
+        };
+        if ok == 0 {
+            failed.push(format!("{} ({})", name, std::io::Error::last_os_error()));
+        }
+    }
+    match failed.len() {
+        0 => Ok(()),
+        n if n == policies.len() => Err(format!(
+            "no mitigation policy applied: {}",
+            failed.join(", ")
+        )),
+        _ => {
+            crate::warn::warn(
+                "harden",
+                format!("mitigation policies not applied: {}", failed.join(", ")),
+            );
+            Ok(())
+        }
+    }
+}
+
+#[cfg(not(any(
+    windows,
+    all(
+        target_os = "linux",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    )
+)))]
+pub fn apply() -> Result<(), String> {
+    Err("hardening is supported on Windows and on Linux (x86_64, aarch64) only".to_string())
+}
@@ -0,0 +1,90 @@
+use sysinfo::{Pid, System, Uid, get_current_pid};
+
+// Environment variables that commonly carry the port an app was told to listen on.
+// URL-style values may hold several `;`/`,`-separated URLs.
+const PORT_VARS: [&str; 3] = ["PORT", "HTTP_PORT", "SERVER_PORT"];
+const URL_VARS: [&str; 3] = ["ASPNETCORE_URLS", "ASPNETCORE_HTTP_PORTS", "URLS"];
+
+fn url_ports(value: &str) -> Vec<u16> {
+    value
+        .split([';', ','])
+        .filter_map(|url| {
+            let url = url.trim().trim_end_matches('/');
+            let port = url.rsplit(':').next()?;
+            port.parse::<u16>().ok()
+        })
+        .collect()
+}
+
+fn current_user(system: &System) -> Option<Uid> {
+    let pid = get_current_pid().ok()?;
+    system.process(pid)?.user_id().cloned()
+}
+
+// Only processes owned by the current user are inspected: other users' environments
+// are private (and usually unreadable anyway).
+pub struct EnvHints {
+    user: Option<Uid>,
+}
+
+impl EnvHints {
+    pub fn new(system: &System) -> EnvHints {
+        EnvHints {
+            user: current_user(system),
+        }
+    }
+
+    // "PORT=8080 (mismatch)" when `pid` was configured for ports that do not include
+    // the one it listens on, "PORT=8080" when they agree, None when nothing is set.
+    pub fn hint(&self, system: &System, pid: u32, listen_port: u16) -> Option<String> {
+        let process = system.process(Pid::from(pid as usize))?;
+        if self.user.is_none() || process.user_id() != self.user.as_ref() {
+            return None;
+        }
+        let mut found: Vec<(String, Vec<u16>)> = Vec::new();
+        for var in process.environ() {
+            let Some((name, value)) = var.split_once('=') else {
+                continue;
+            };
+            let ports = if PORT_VARS.contains(&name) {
+                value.trim().parse::<u16>().ok().into_iter().collect()
+            } else if URL_VARS.contains(&name) {
+                url_ports(value)
+            } else {
+                continue;
+            };
+            if !ports.is_empty() {
+                found.push((format!("{}={}", name, value), ports));
+            }
+        }
+        if found.is_empty() {
+            return None;
+        }
+        let matched = found.iter().any(|(_, ports)| ports.contains(&listen_port));
+        let text = found
+            .into_iter()
+            .map(|(v, _)| v)
+            .collect::<Vec<_>>()
+            .join(" ");
+        Some(if matched {
+            text
+        } else {
+            format!("{} (mismatch)", text)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn url_ports_parses_each_url() {
+        assert_eq!(
+            url_ports("http://localhost:5000;https://+:5001/"),
+            vec![5000, 5001]
+        );
+        assert_eq!(url_ports("8080,8081"), vec![8080, 8081]);
+        assert_eq!(url_ports("http://localhost"), Vec::<u16>::new());
+    }
+}
@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+
+use crate::{ConnKey, SocketEntry, cap, zone};
+
+// `--watch` as a keep-alive hunter: a TCP connection whose kernel byte counters
+// haven't moved for `--idle-after N` refreshes in a row is IDLE, the way a leaked
+// pooled connection holds a server worker without carrying requests. The counters are
+// the cumulative ones --data-cap reads (Linux), so bytes moved between two refreshes
+// count even when no Rx/Tx sample was running. A connection seen for the first time
+// starts at zero; listeners and UDP sockets are never judged.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Only {
+    Idle,
+    Active,
+}
+
+impl Only {
+    // Rows that can't be judged are left out either way.
+    pub fn keeps(self, e: &SocketEntry) -> bool {
+        e.idle == Some(self == Only::Idle)
+    }
+}
+
+pub struct Tracker {
+    after: u32,
+    // Per connection: its total bytes at the last refresh and the refreshes since
+    // they last moved.
+    quiet: HashMap<ConnKey, (u64, u32)>,
+}
+
+impl Tracker {
+    pub fn new(after: u32) -> Tracker {
+        Tracker {
+            after,
+            quiet: HashMap::new(),
+        }
+    }
+
+    // Counts the refreshes in `counters` and marks the listed connections.
+    // Connections no longer in `counters` are forgotten.
+    fn observe(&mut self, entries: &mut [SocketEntry], counters: &HashMap<ConnKey, (u64, u64)>) {
+        self.quiet = counters
+            .iter()
+            .map(|(key, (rx, tx))| {
+                let total = rx.saturating_add(*tx);
+                let quiet = match self.quiet.get(key) {
+                    Some(&(last, quiet)) if last == total => quiet + 1,
+                    _ => 0,
+                };
+                (key.clone(), (total, quiet))
+            })
+            .collect();
+        for e in entries.iter_mut() {
+            e.idle = None;
+            if e.proto != "TCP" || e.state == "Listen" {
+                continue;
+            }
+            let key = (zone::strip(&e.local_addr), zone::strip(&e.remote_addr));
+            e.idle = self.quiet.get(&key).map(|&(_, quiet)| quiet >= self.after);
+        }
+    }
+
+    // Reads the counters and marks the listed connections idle or not.
+    pub fn update(&mut self, entries: &mut [SocketEntry]) -> Result<(), String> {
+        let counters = cap::counters()?;
+        self.observe(entries, &counters);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(proto: &str, remote: &str, state: &str) -> SocketEntry {
+        SocketEntry {
+            proto: proto.to_string(),
+            local_addr: "10.0.0.1:40000".to_string(),
+            remote_addr: remote.to_string(),
+            state: state.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn key(remote: &str) -> ConnKey {
+        ("10.0.0.1:40000".to_string(), remote.to_string())
+    }
+
+    #[test]
+    fn connections_without_traffic_for_n_refreshes_are_idle() {
+        let mut tracker = Tracker::new(2);
+        let mut rows = [
+            row("TCP", "10.0.0.2:443", "Established"),
+            row("TCP", "10.0.0.3:443", "Established"),
+            row("TCP", "0.0.0.0:0", "Listen"),
+            row("UDP", "10.0.0.4:53", "-"),
+        ];
+        let mut counters = HashMap::from([
+            (key("10.0.0.2:443"), (100, 10)),
+            (key("10.0.0.3:443"), (100, 10)),
+            (key("0.0.0.0:0"), (0, 0)),
+            (key("10.0.0.4:53"), (0, 0)),
+        ]);
+        let marks = |rows: &[SocketEntry]| rows.iter().map(|e| e.idle).collect::<Vec<_>>();
+        tracker.observe(&mut rows, &counters);
+        assert_eq!(marks(&rows), [Some(false), Some(false), None, None]);
+        // Only the second one stays quiet.
+        counters.insert(key("10.0.0.2:443"), (150, 10));
+        tracker.observe(&mut rows, &counters);
+        assert_eq!(marks(&rows)[..2], [Some(false), Some(false)]);
+        counters.insert(key("10.0.0.2:443"), (150, 20));
+        tracker.observe(&mut rows, &counters);
+        assert_eq!(marks(&rows)[..2], [Some(false), Some(true)]);
+        assert!(Only::Idle.keeps(&rows[1]) && !Only::Idle.keeps(&rows[0]));
+        assert!(Only::Active.keeps(&rows[0]) && !Only::Active.keeps(&rows[2]));
+        // A connection whose counters are gone can't be judged.
+        counters.remove(&key("10.0.0.3:443"));
+        tracker.observe(&mut rows, &counters);
+        assert_eq!(rows[1].idle, None);
+    }
+}
@@ -0,0 +1,182 @@
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Internationalized host names from reverse DNS (`--resolve`). DNS carries them as
+// punycode ("xn--mnchen-3ya.de"); tables show them decoded ("münchen.de") unless
+// `--no-idn` asks for the raw form. A decoded label that mixes Latin, Greek and
+// Cyrillic letters, or is spelled only with Cyrillic or Greek letters that look Latin
+// ("аррӏе"), is a likely look-alike of another name: it keeps its xn-- form, the way
+// browsers show such names, and `resolve` warns about it. JSON, CSV and the host name
+// policy always see the raw name. Decoding is off until the binary turns it on.
+
+static DECODE: AtomicBool = AtomicBool::new(false);
+
+pub fn decode_names(on: bool) {
+    DECODE.store(on, Ordering::Relaxed);
+}
+
+// RFC 3492 parameters.
+const BASE: u32 = 36;
+const T_MIN: u32 = 1;
+const T_MAX: u32 = 26;
+const SKEW: u32 = 38;
+const DAMP: u32 = 700;
+const INITIAL_BIAS: u32 = 72;
+const INITIAL_N: u32 = 128;
+
+fn adapt(delta: u32, points: u32, first: bool) -> u32 {
+    let mut delta = if first { delta / DAMP } else { delta / 2 };
+    delta += delta / points;
+    let mut k = 0;
+    while delta > (BASE - T_MIN) * T_MAX / 2 {
+        delta /= BASE - T_MIN;
+        k += BASE;
+    }
+    k + (BASE - T_MIN + 1) * delta / (delta + SKEW)
+}
+
+fn digit(c: char) -> Option<u32> {
+    match c {
+        'a'..='z' => Some(c as u32 - 'a' as u32),
+        'A'..='Z' => Some(c as u32 - 'A' as u32),
+        '0'..='9' => Some(c as u32 - '0' as u32 + 26),
+        _ => None,
+    }
+}
+
+// A punycode label without its "xn--"; None if it is malformed.
+fn punycode(input: &str) -> Option<String> {
+    let (basic, extended) = match input.rfind('-') {
+        Some(i) => (&input[..i], &input[i + 1..]),
+        None => ("", input),
+    };
+    if !basic.is_ascii() {
+        return None;
+    }
+    let mut output: Vec<char> = basic.chars().collect();
+    let (mut n, mut i, mut bias) = (INITIAL_N, 0u32, INITIAL_BIAS);
+    let mut chars = extended.chars().peekable();
+    while chars.peek().is_some() {
+        let old = i;
+        let mut w = 1u32;
+        let mut k = BASE;
+        loop {
+            let d = digit(chars.next()?)?;
+            i = i.checked_add(d.checked_mul(w)?)?;
+            let t = if k <= bias {
+                T_MIN
+            } else if k >= bias + T_MAX {
+                T_MAX
+            } else {
+                k - bias
+            };
+            if d < t {
+                break;
+            }
+            w = w.checked_mul(BASE - t)?;
+            k += BASE;
+        }
+        let points = output.len() as u32 + 1;
+        bias = adapt(i - old, points, old == 0);
+        n = n.checked_add(i / points)?;
+        i %= points;
+        output.insert(i as usize, char::from_u32(n)?);
+        i += 1;
+    }
+    Some(output.into_iter().collect())
+}
+
+// The host name with its xn-- labels decoded; None if it has none (or a bad one).
+pub fn decode(host: &str) -> Option<String> {
+    let mut any = false;
+    let mut labels = Vec::new();
+    for label in host.split('.') {
+        match label.get(..4) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("xn--") => {
+                labels.push(punycode(&label[4..])?);
+                any = true;
+            }
+            _ => labels.push(label.to_string()),
+        }
+    }
+    any.then(|| labels.join("."))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+}
+
+fn script(c: char) -> Option<Script> {
+    match c {
+        'a'..='z' | 'A'..='Z' | '\u{c0}'..='\u{24f}' => Some(Script::Latin),
+        '\u{370}'..='\u{3ff}' => Some(Script::Greek),
+        '\u{400}'..='\u{52f}' => Some(Script::Cyrillic),
+        _ => None,
+    }
+}
+
+// Cyrillic and Greek letters drawn like Latin ones.
+const LOOKALIKES: &str = "аеорсухіјѕһӏԁԛԝАВЕКМНОРСТХІЈЅαικνορτυχΑΒΕΗΙΚΜΝΟΡΤΥΧΖ";
+
+// Whether a decoded name likely imitates another one.
+pub fn homograph(name: &str) -> bool {
+    name.split('.').any(|label| {
+        let scripts: Vec<Script> = label.chars().filter_map(script).collect();
+        let Some(&first) = scripts.first() else {
+            return false;
+        };
+        let mixed = scripts.iter().any(|&s| s != first);
+        let imitation = first != Script::Latin
+            && label
+                .chars()
+                .filter(|&c| script(c).is_some())
+                .all(|c| LOOKALIKES.contains(c));
+        mixed || imitation
+    })
+}
+
+// The host name as tables show it.
+pub fn display(host: &str) -> Cow<'_, str> {
+    if !DECODE.load(Ordering::Relaxed) {
+        return Cow::Borrowed(host);
+    }
+    match decode(host) {
+        Some(name) if !homograph(&name) => Cow::Owned(name),
+        _ => Cow::Borrowed(host),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn punycode_labels_decode() {
+        assert_eq!(decode("xn--mnchen-3ya.de").as_deref(), Some("münchen.de"));
+        assert_eq!(
+            decode("www.XN--bcher-kva.example").as_deref(),
+            Some("www.bücher.example")
+        );
+        assert_eq!(decode("xn--r8jz45g.jp").as_deref(), Some("例え.jp"));
+        assert_eq!(decode("example.com"), None);
+        assert_eq!(decode("xn--a-!.com"), None);
+    }
+
+    #[test]
+    fn look_alike_names_are_flagged() {
+        // Cyrillic "а" before Latin "pple".
+        assert_eq!(
+            decode("xn--pple-43d.com").as_deref(),
+            Some("\u{430}pple.com")
+        );
+        assert!(homograph("\u{430}pple.com"));
+        // All Cyrillic, all drawn like Latin letters.
+        assert!(homograph(&decode("xn--80ak6aa92e.com").unwrap()));
+        assert!(!homograph("münchen.de"));
+        assert!(!homograph("例え.jp"));
+        assert!(!homograph("пример.рф"));
+    }
+}
@@ -0,0 +1,99 @@
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Ctrl-C handling for the one-shot and watch modes. The first Ctrl-C only sets a
+// flag: sampling sleeps wake up early, the rows are printed with whatever was
+// measured so far and the program exits. A second Ctrl-C terminates immediately.
+// Without `install` the flag is never set and `sleep` is a plain sleep.
+//
+// `--timeout` sets a deadline with the same effect minus the exit status: work still
+// pending when it passes is abandoned and the rows are printed as they are.
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static DEADLINE: OnceLock<Instant> = OnceLock::new();
+
+// How often an interruptible sleep checks the flag.
+const POLL: Duration = Duration::from_millis(50);
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+pub fn set_deadline(at: Instant) {
+    let _ = DEADLINE.set(at);
+}
+
+pub fn deadline_passed() -> bool {
+    DEADLINE.get().is_some_and(|&at| Instant::now() >= at)
+}
+
+// Time left before the deadline; None without one.
+pub fn remaining() -> Option<Duration> {
+    DEADLINE
+        .get()
+        .map(|&at| at.saturating_duration_since(Instant::now()))
+}
+
+// Ctrl-C or the deadline: pending work should wrap up.
+pub fn should_stop() -> bool {
+    interrupted() || deadline_passed()
+}
+
+// Sleeps for `d` unless interrupted or the deadline passes first. Returns false if
+// cut short.
+pub fn sleep(d: Duration) -> bool {
+    let until = Instant::now() + d;
+    loop {
+        if should_stop() {
+            return false;
+        }
+        let left = until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return true;
+        }
+        thread::sleep(left.min(POLL));
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn on_sigint(_: libc::c_int) {
+    if INTERRUPTED.swap(true, Ordering::Relaxed) {
+        // SAFETY: _exit is async-signal-safe.
+        unsafe { libc::_exit(130) };
+    }
+}
+
+#[cfg(unix)]
+pub fn install() {
+    // SAFETY: the handler only touches an atomic and calls _exit.
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            on_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+}
+
+#[cfg(windows)]
+unsafe extern "system" fn on_ctrl(ctrl_type: u32) -> windows_sys::Win32::Foundation::BOOL {
+    use windows_sys::Win32::System::Console::{CTRL_BREAK_EVENT, CTRL_C_EVENT};
+    if ctrl_type != CTRL_C_EVENT && ctrl_type != CTRL_BREAK_EVENT {
+        return 0;
+    }
+    // Handled (TRUE) the first time; the second press falls through to the default
+    // handler, which terminates the process.
+    (!INTERRUPTED.swap(true, Ordering::Relaxed)) as windows_sys::Win32::Foundation::BOOL
+}
+
+#[cfg(windows)]
+pub fn install() {
+    // SAFETY: registers a handler that only touches an atomic.
+    unsafe {
+        windows_sys::Win32::System::Console::SetConsoleCtrlHandler(Some(on_ctrl), 1);
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn install() {}
@@ -0,0 +1,642 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use sysinfo::{Pid, System, Users};
+
+use crate::{
+    AddressFamilyFlags, ProtocolFlags, cli, collect_entries, forensic, json, parse_addr_port,
+    process_user, sha256, warn, zone,
+};
+
+// `netstatw inventory`: what listens on this host, per process, for configuration
+// management and fleet inventories. Unlike the main view it ignores connections and
+// identifies each program by the SHA-256 of its executable. `inventory-diff` compares
+// two of its `--json` documents.
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Table,
+    Json,
+    Ini,
+    Yaml,
+}
+
+struct Listener {
+    proto: &'static str,
+    addr: String,
+    port: u16,
+    wildcard: bool,
+}
+
+struct ProcessInventory {
+    name: String,
+    exe: Option<String>,
+    sha256: Option<String>,
+    user: Option<String>,
+    listeners: Vec<Listener>,
+}
+
+fn collect() -> BTreeMap<u32, ProcessInventory> {
+    forensic::record("sysinfo::System::refresh_all (process table)");
+    let mut system = System::new_all();
+    system.refresh_all();
+    let users = Users::new_with_refreshed_list();
+    let entries = collect_entries(
+        &system,
+        None,
+        AddressFamilyFlags::IPV4 | AddressFamilyFlags::IPV6,
+        ProtocolFlags::TCP | ProtocolFlags::UDP,
+    )
+    .unwrap_or_default();
+
+    // Several processes often share one executable; hash each file once.
+    let mut hashes: HashMap<String, Option<String>> = HashMap::new();
+    let mut inventory: BTreeMap<u32, ProcessInventory> = BTreeMap::new();
+    for e in entries {
+        let proto = match (e.proto.as_str(), e.state.as_str()) {
+            ("TCP", "Listen") => "tcp",
+            ("UDP", _) => "udp",
+            _ => continue,
+        };
+        let (ip, port) = parse_addr_port(&e.local_addr);
+        let wildcard = zone::parse_ip(ip).is_some_and(|a| a.is_unspecified());
+        for &pid in &e.pids {
+            let item = inventory.entry(pid).or_insert_with(|| {
+                let process = system.process(Pid::from(pid as usize));
+                let exe = process
+                    .and_then(|p| p.exe())
+                    .map(|p| p.display().to_string());
+                let sha256 = exe.as_ref().and_then(|path| {
+                    hashes
+                        .entry(path.clone())
+                        .or_insert_with(|| sha256::file_hex(Path::new(path)).ok())
+                        .clone()
+                });
+                ProcessInventory {
+                    name: process.map(|p| p.name().to_string()).unwrap_or_default(),
+                    exe,
+                    sha256,
+                    user: process_user(&system, &users, pid),
+                    listeners: Vec::new(),
+                }
+            });
+            item.listeners.push(Listener {
+                proto,
+                addr: ip.to_string(),
+                port,
+                wildcard,
+            });
+        }
+    }
+    for item in inventory.values_mut() {
+        item.listeners
+            .sort_by(|a, b| (a.proto, a.port, &a.addr).cmp(&(b.proto, b.port, &b.addr)));
+    }
+    inventory
+}
+
+fn to_json(inventory: &BTreeMap<u32, ProcessInventory>) -> String {
+    let processes: Vec<String> = inventory
+        .iter()
+        .map(|(pid, p)| {
+            let listeners: Vec<String> = p
+                .listeners
+                .iter()
+                .map(|l| {
+                    json::object(&[
+                        ("proto", json::str(l.proto)),
+                        ("addr", json::str(&l.addr)),
+                        ("port", l.port.to_string()),
+                        ("wildcard", l.wildcard.to_string()),
+                    ])
+                })
+                .collect();
+            format!(
+                "\n{}",
+                json::object(&[
+                    ("pid", pid.to_string()),
+                    ("process", json::str(&p.name)),
+                    ("exe", json::opt_str(p.exe.as_deref())),
+                    ("sha256", json::opt_str(p.sha256.as_deref())),
+                    ("user", json::opt_str(p.user.as_deref())),
+                    ("listening", json::array(&listeners)),
+                ])
+            )
+        })
+        .collect();
+    let end = if processes.is_empty() { "" } else { "\n" };
+    format!(
+        "{{\"host\":{},\"processes\":[{}{}]}}",
+        json::opt_str(System::host_name().as_deref()),
+        processes.join(","),
+        end
+    )
+}
+
+// `--format ini|yaml`: the listeners keyed by `<proto>_<port>`, so a playbook can
+// assert what owns a port. The INI form is an Ansible local facts file (saved as
+// `/etc/ansible/facts.d/netstatw.fact` it reads as
+// `ansible_local.netstatw.tcp_9100.process`); the YAML form is an inventory giving the
+// host a `netstatw_listeners` variable of the same shape. When several processes share
+// a port, the first by PID describes it and `pids` lists them all.
+struct PortFacts<'a> {
+    owner: &'a ProcessInventory,
+    pids: Vec<u32>,
+    // `*` for a wildcard address.
+    addrs: Vec<&'a str>,
+}
+
+fn by_port(inventory: &BTreeMap<u32, ProcessInventory>) -> BTreeMap<String, PortFacts<'_>> {
+    let mut ports: BTreeMap<(&str, u16), PortFacts> = BTreeMap::new();
+    for (pid, p) in inventory {
+        for l in &p.listeners {
+            let facts = ports.entry((l.proto, l.port)).or_insert_with(|| PortFacts {
+                owner: p,
+                pids: Vec::new(),
+                addrs: Vec::new(),
+            });
+            if !facts.pids.contains(pid) {
+                facts.pids.push(*pid);
+            }
+            let addr = if l.wildcard { "*" } else { l.addr.as_str() };
+            if !facts.addrs.contains(&addr) {
+                facts.addrs.push(addr);
+            }
+        }
+    }
+    ports
+        .into_iter()
+        .map(|((proto, port), facts)| (format!("{}_{}", proto, port), facts))
+        .collect()
+}
+
+fn to_ini(inventory: &BTreeMap<u32, ProcessInventory>) -> String {
+    let mut out = String::new();
+    for (key, f) in by_port(inventory) {
+        let pids: Vec<String> = f.pids.iter().map(u32::to_string).collect();
+        let _ = writeln!(out, "[{}]", key);
+        let _ = writeln!(out, "process={}", f.owner.name);
+        let _ = writeln!(out, "pids={}", pids.join(","));
+        let _ = writeln!(out, "addrs={}", f.addrs.join(","));
+        let _ = writeln!(out, "exe={}", f.owner.exe.as_deref().unwrap_or(""));
+        let _ = writeln!(out, "sha256={}", f.owner.sha256.as_deref().unwrap_or(""));
+        let _ = writeln!(out, "user={}", f.owner.user.as_deref().unwrap_or(""));
+        out.push('\n');
+    }
+    out
+}
+
+// Strings are written as JSON strings, which YAML reads as double-quoted scalars.
+fn to_yaml(host: &str, inventory: &BTreeMap<u32, ProcessInventory>) -> String {
+    let ports = by_port(inventory);
+    let mut out = format!("all:\n  hosts:\n    {}:\n", json::str(host));
+    if ports.is_empty() {
+        out.push_str("      netstatw_listeners: {}\n");
+        return out;
+    }
+    out.push_str("      netstatw_listeners:\n");
+    for (key, f) in ports {
+        let pids: Vec<String> = f.pids.iter().map(u32::to_string).collect();
+        let addrs: Vec<String> = f.addrs.iter().map(|a| json::str(a)).collect();
+        let _ = writeln!(out, "        {}:", key);
+        let _ = writeln!(out, "          process: {}", json::str(&f.owner.name));
+        let _ = writeln!(out, "          pids: [{}]", pids.join(", "));
+        let _ = writeln!(out, "          addrs: [{}]", addrs.join(", "));
+        let _ = writeln!(
+            out,
+            "          exe: {}",
+            json::opt_str(f.owner.exe.as_deref())
+        );
+        let _ = writeln!(
+            out,
+            "          sha256: {}",
+            json::opt_str(f.owner.sha256.as_deref())
+        );
+        let _ = writeln!(
+            out,
+            "          user: {}",
+            json::opt_str(f.owner.user.as_deref())
+        );
+    }
+    out
+}
+
+fn print_table(inventory: &BTreeMap<u32, ProcessInventory>) {
+    println!(
+        "{:<8} {:<16} {:<12} {:<16} {:<30}",
+        "PID", "USER", "SHA256", "PROCESS", "LISTENING"
+    );
+    println!(
+        "{}  {}  {}  {}  {}",
+        "-".repeat(7),
+        "-".repeat(15),
+        "-".repeat(11),
+        "-".repeat(15),
+        "-".repeat(29)
+    );
+    for (pid, p) in inventory {
+        let ports: Vec<String> = p
+            .listeners
+            .iter()
+            .map(|l| {
+                let scope = if l.wildcard { "*" } else { l.addr.as_str() };
+                format!("{}/{}:{}", l.proto, scope, l.port)
+            })
+            .collect();
+        let hash = p.sha256.as_deref().map(|h| &h[..12]).unwrap_or("N/A");
+        println!(
+            "{:<8} {:<16} {:<12} {:<16} {}",
+            pid,
+            p.user.as_deref().unwrap_or("?"),
+            hash,
+            p.name,
+            ports.join(" ")
+        );
+    }
+}
+
+fn parse_args(mut args: cli::Args) -> Result<Format, String> {
+    let mut format = Format::Table;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => format = Format::Json,
+            "--format" => {
+                format = match args.value(&arg)?.to_ascii_lowercase().as_str() {
+                    "json" => Format::Json,
+                    "ini" => Format::Ini,
+                    "yaml" | "yml" => Format::Yaml,
+                    _ => return Err("--format needs ini, yaml or json".to_string()),
+                }
+            }
+            other => return Err(format!("unexpected argument '{}'", other)),
+        }
+    }
+    Ok(format)
+}
+
+pub fn run(args: Vec<String>) -> i32 {
+    let format = match cli::parse_command(&cli::INVENTORY, args, parse_args) {
+        Ok(format) => format,
+        Err(status) => return status,
+    };
+    let inventory = collect();
+    match format {
+        Format::Table => print_table(&inventory),
+        Format::Json => println!("{}", to_json(&inventory)),
+        Format::Ini => print!("{}", to_ini(&inventory)),
+        Format::Yaml => {
+            let host = System::host_name().unwrap_or_else(|| "localhost".to_string());
+            print!("{}", to_yaml(&host, &inventory));
+        }
+    }
+    warn::emit(false);
+    0
+}
+
+// What a listener is matched on across hosts: the same service on another host has
+// another PID and usually another address, so a specific address only counts as such.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct ListenerKey {
+    proto: String,
+    port: u16,
+    scope: &'static str,
+}
+
+impl ListenerKey {
+    fn label(&self) -> String {
+        format!("{} {}:{}", self.proto, self.scope, self.port)
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Owner {
+    process: String,
+    exe: Option<String>,
+    sha256: Option<String>,
+}
+
+impl Owner {
+    fn label(&self) -> String {
+        let mut text = self.process.clone();
+        if let Some(exe) = &self.exe {
+            text.push_str(&format!(" ({})", exe));
+        }
+        if let Some(hash) = &self.sha256 {
+            text.push_str(&format!(" [{}]", &hash[..hash.len().min(12)]));
+        }
+        text
+    }
+}
+
+struct Document {
+    host: String,
+    listeners: BTreeMap<ListenerKey, BTreeSet<Owner>>,
+}
+
+fn scope(addr: &str, wildcard: bool) -> &'static str {
+    match zone::parse_ip(addr) {
+        _ if wildcard => "*",
+        Some(ip) if ip.is_loopback() => "loopback",
+        _ => "addr",
+    }
+}
+
+fn read_document(text: &str) -> Result<Document, String> {
+    let doc = json::parse(text)?;
+    let Some(processes) = doc.get("processes") else {
+        return Err("not an inventory document (no \"processes\")".to_string());
+    };
+    let mut listeners: BTreeMap<ListenerKey, BTreeSet<Owner>> = BTreeMap::new();
+    for p in processes.items() {
+        let text = |key| p.get(key).and_then(json::Value::as_str).map(str::to_string);
+        let owner = Owner {
+            process: text("process").unwrap_or_default(),
+            exe: text("exe"),
+            sha256: text("sha256"),
+        };
+        for l in p
+            .get("listening")
+            .map(json::Value::items)
+            .unwrap_or_default()
+        {
+            let str_field = |key| l.get(key).and_then(json::Value::as_str).unwrap_or_default();
+            let key = ListenerKey {
+                proto: str_field("proto").to_string(),
+                port: l.get("port").and_then(json::Value::as_f64).unwrap_or(0.0) as u16,
+                scope: scope(
+                    str_field("addr"),
+                    l.get("wildcard").and_then(json::Value::as_bool) == Some(true),
+                ),
+            };
+            listeners.entry(key).or_default().insert(owner.clone());
+        }
+    }
+    Ok(Document {
+        host: doc
+            .get("host")
+            .and_then(json::Value::as_str)
+            .unwrap_or("?")
+            .to_string(),
+        listeners,
+    })
+}
+
+struct Drift<'a> {
+    change: &'static str,
+    key: &'a ListenerKey,
+    base: Option<&'a BTreeSet<Owner>>,
+    other: Option<&'a BTreeSet<Owner>>,
+}
+
+// "binary" when the owning programs differ, "hash" when the same executables have a
+// different SHA-256. A hash missing on either side (unreadable executable) is not
+// counted as drift.
+fn owner_change(base: &BTreeSet<Owner>, other: &BTreeSet<Owner>) -> Option<&'static str> {
+    let programs = |set: &BTreeSet<Owner>| -> BTreeSet<(String, Option<String>)> {
+        set.iter()
+            .map(|o| (o.process.clone(), o.exe.clone()))
+            .collect()
+    };
+    if programs(base) != programs(other) {
+        return Some("binary");
+    }
+    let hash_differs = base.iter().any(|a| {
+        other.iter().any(|b| {
+            a.process == b.process
+                && a.exe == b.exe
+                && a.sha256.is_some()
+                && b.sha256.is_some()
+                && a.sha256 != b.sha256
+        })
+    });
+    hash_differs.then_some("hash")
+}
+
+fn diff<'a>(base: &'a Document, other: &'a Document) -> Vec<Drift<'a>> {
+    let keys: BTreeSet<&ListenerKey> = base
+        .listeners
+        .keys()
+        .chain(other.listeners.keys())
+        .collect();
+    keys.into_iter()
+        .filter_map(|key| {
+            let (b, o) = (base.listeners.get(key), other.listeners.get(key));
+            let change = match (b, o) {
+                (Some(_), None) => "missing",
+                (None, Some(_)) => "extra",
+                (Some(b), Some(o)) => owner_change(b, o)?,
+                (None, None) => return None,
+            };
+            Some(Drift {
+                change,
+                key,
+                base: b,
+                other: o,
+            })
+        })
+        .collect()
+}
+
+fn owners_label(owners: Option<&BTreeSet<Owner>>) -> String {
+    match owners {
+        Some(set) => set.iter().map(Owner::label).collect::<Vec<_>>().join(", "),
+        None => "-".to_string(),
+    }
+}
+
+fn owners_json(owners: Option<&BTreeSet<Owner>>) -> String {
+    let Some(set) = owners else {
+        return "null".to_string();
+    };
+    let items: Vec<String> = set
+        .iter()
+        .map(|o| {
+            json::object(&[
+                ("process", json::str(&o.process)),
+                ("exe", json::opt_str(o.exe.as_deref())),
+                ("sha256", json::opt_str(o.sha256.as_deref())),
+            ])
+        })
+        .collect();
+    json::array(&items)
+}
+
+// `--json` and the two inventory paths.
+fn parse_diff_args(args: cli::Args) -> Result<(bool, [String; 2]), String> {
+    let mut as_json = false;
+    let mut paths: Vec<String> = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--json" => as_json = true,
+            other if other.starts_with('-') => {
+                return Err(format!("unexpected argument '{}'", other));
+            }
+            _ => paths.push(arg),
+        }
+    }
+    let paths: [String; 2] = paths
+        .try_into()
+        .map_err(|_| "expected two inventory files (see --help)".to_string())?;
+    Ok((as_json, paths))
+}
+
+pub fn run_diff(args: Vec<String>) -> i32 {
+    let (as_json, [base_path, other_path]) =
+        match cli::parse_command(&cli::INVENTORY_DIFF, args, parse_diff_args) {
+            Ok(parsed) => parsed,
+            Err(status) => return status,
+        };
+    let mut docs = Vec::new();
+    for path in [&base_path, &other_path] {
+        match fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| read_document(&text))
+        {
+            Ok(doc) => docs.push(doc),
+            Err(e) => {
+                eprintln!("netstatw inventory-diff: {}: {}", path, e);
+                return 2;
+            }
+        }
+    }
+    let (base, other) = (&docs[0], &docs[1]);
+    let drift = diff(base, other);
+
+    if as_json {
+        let rows: Vec<String> = drift
+            .iter()
+            .map(|d| {
+                format!(
+                    "\n{}",
+                    json::object(&[
+                        ("change", json::str(d.change)),
+                        ("proto", json::str(&d.key.proto)),
+                        ("scope", json::str(d.key.scope)),
+                        ("port", d.key.port.to_string()),
+                        ("baseline", owners_json(d.base)),
+                        ("other", owners_json(d.other)),
+                    ])
+                )
+            })
+            .collect();
+        let end = if rows.is_empty() { "" } else { "\n" };
+        println!(
+            "{{\"baseline\":{},\"other\":{},\"drift\":[{}{}]}}",
+            json::str(&base.host),
+            json::str(&other.host),
+            rows.join(","),
+            end
+        );
+    } else if drift.is_empty() {
+        println!(
+            "No drift between {} and {} ({} listeners).",
+            base.host,
+            other.host,
+            base.listeners.len()
+        );
+    } else {
+        println!(
+            "Baseline: {} ({}), other: {} ({})",
+            base.host, base_path, other.host, other_path
+        );
+        println!();
+        println!(
+            "{:<8} {:<20} {:<40} OTHER",
+            "CHANGE", "LISTENER", "BASELINE"
+        );
+        println!(
+            "{}  {}  {}  {}",
+            "-".repeat(7),
+            "-".repeat(19),
+            "-".repeat(39),
+            "-".repeat(39)
+        );
+        for d in &drift {
+            println!(
+                "{:<8} {:<20} {:<40} {}",
+                d.change,
+                d.key.label(),
+                owners_label(d.base),
+                owners_label(d.other)
+            );
+        }
+    }
+    if drift.is_empty() { 0 } else { 1 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = r#"{"host":"golden","processes":[
+{"pid":1,"process":"nginx","exe":"/usr/sbin/nginx","sha256":"aa","user":"root","listening":[{"proto":"tcp","addr":"0.0.0.0","port":80,"wildcard":true}]},
+{"pid":2,"process":"sshd","exe":"/usr/sbin/sshd","sha256":"bb","user":"root","listening":[{"proto":"tcp","addr":"10.0.0.1","port":22,"wildcard":false}]},
+{"pid":3,"process":"redis","exe":"/usr/bin/redis","sha256":null,"user":"redis","listening":[{"proto":"tcp","addr":"127.0.0.1","port":6379,"wildcard":false}]}
+]}"#;
+
+    const OTHER: &str = r#"{"host":"web-02","processes":[
+{"pid":9,"process":"nginx","exe":"/usr/sbin/nginx","sha256":"cc","user":"root","listening":[{"proto":"tcp","addr":"0.0.0.0","port":80,"wildcard":true}]},
+{"pid":8,"process":"sshd","exe":"/usr/sbin/sshd","sha256":"bb","user":"root","listening":[{"proto":"tcp","addr":"10.0.0.2","port":22,"wildcard":false}]},
+{"pid":7,"process":"redis","exe":"/usr/bin/redis","sha256":"dd","user":"redis","listening":[{"proto":"tcp","addr":"127.0.0.1","port":6379,"wildcard":false}]},
+{"pid":6,"process":"nc","exe":"/usr/bin/nc","sha256":"ee","user":"bob","listening":[{"proto":"tcp","addr":"0.0.0.0","port":4444,"wildcard":true}]}
+]}"#;
+
+    #[test]
+    fn diff_matches_listeners_across_hosts() {
+        let base = read_document(BASE).unwrap();
+        let other = read_document(OTHER).unwrap();
+        let drift: Vec<(&str, String)> = diff(&base, &other)
+            .iter()
+            .map(|d| (d.change, d.key.label()))
+            .collect();
+        // sshd on another address is the same listener; redis without a baseline hash
+        // is not drift.
+        assert_eq!(
+            drift,
+            [
+                ("hash", "tcp *:80".to_string()),
+                ("extra", "tcp *:4444".to_string())
+            ]
+        );
+        assert!(diff(&base, &base).is_empty());
+        assert_eq!(diff(&other, &base)[1].change, "missing");
+    }
+
+    #[test]
+    fn ansible_formats_key_listeners_by_port() {
+        let exporter = |pid: u32, addr: &str, wildcard: bool| {
+            (
+                pid,
+                ProcessInventory {
+                    name: "node_exporter".to_string(),
+                    exe: Some("/usr/bin/node_exporter".to_string()),
+                    sha256: None,
+                    user: Some("prometheus".to_string()),
+                    listeners: vec![Listener {
+                        proto: "tcp",
+                        addr: addr.to_string(),
+                        port: 9100,
+                        wildcard,
+                    }],
+                },
+            )
+        };
+        let inventory =
+            BTreeMap::from([exporter(812, "::", true), exporter(813, "10.0.0.1", false)]);
+        assert_eq!(
+            to_ini(&inventory),
+            "[tcp_9100]\nprocess=node_exporter\npids=812,813\naddrs=*,10.0.0.1\n\
+             exe=/usr/bin/node_exporter\nsha256=\nuser=prometheus\n\n"
+        );
+        let yaml = to_yaml("web-01", &inventory);
+        assert!(yaml.starts_with("all:\n  hosts:\n    \"web-01\":\n      netstatw_listeners:\n"));
+        assert!(yaml.contains("        tcp_9100:\n          process: \"node_exporter\"\n"));
+        assert!(
+            yaml.contains("          pids: [812, 813]\n          addrs: [\"*\", \"10.0.0.1\"]\n")
+        );
+        assert!(yaml.contains("          sha256: null\n"));
+        assert!(to_yaml("web-01", &BTreeMap::new()).ends_with("netstatw_listeners: {}\n"));
+    }
+}
@@ -0,0 +1,416 @@
+use crate::SocketEntry;
+
+// Minimal JSON writing for machine output; values are built as strings since the
+// documents are flat and small. `parse` reads such documents back.
+
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+pub fn str(s: &str) -> String {
+    format!("\"{}\"", escape(s))
+}
+
+pub fn opt_str(s: Option<&str>) -> String {
+    s.map(str).unwrap_or_else(|| "null".to_string())
+}
+
+// NaN (our "not available") and infinities have no JSON form and become null.
+pub fn num(v: f64) -> String {
+    if v.is_finite() {
+        format!("{}", v)
+    } else {
+        "null".to_string()
+    }
+}
+
+pub fn object(fields: &[(&str, String)]) -> String {
+    let body: Vec<String> = fields
+        .iter()
+        .map(|(k, v)| format!("{}:{}", str(k), v))
+        .collect();
+    format!("{{{}}}", body.join(","))
+}
+
+pub fn array(items: &[String]) -> String {
+    format!("[{}]", items.join(","))
+}
+
+pub fn entry(e: &SocketEntry) -> String {
+    let pids: Vec<String> = e.pids.iter().map(u32::to_string).collect();
+    let mut fields = vec![
+        ("id", str(&e.conn_id())),
+        ("proto", str(&e.proto)),
+        ("local", str(&e.local_addr)),
+        ("remote", str(&e.remote_addr)),
+        ("remote_host", opt_str(e.remote_host.as_deref())),
+        ("state", str(&e.state)),
+        ("pids", array(&pids)),
+        ("process", str(&e.process_info)),
+        ("user", opt_str(e.user.as_deref())),
+    ];
+    if let Some(s) = &e.agg_stats {
+        fields.extend([
+            ("cpu_pct", num(s.cpu_pct as f64)),
+            ("read_bps", num(s.read_rate_bps)),
+            ("write_bps", num(s.write_rate_bps)),
+            ("rx_bps", num(s.net_rx_rate_bps)),
+            ("tx_bps", num(s.net_tx_rate_bps)),
+            ("disk_net", opt_str(s.disk_net_flag().as_deref())),
+        ]);
+    }
+    fields.extend([
+        ("new_remote", e.new_remote.to_string()),
+        ("synth", e.synth.to_string()),
+        ("forward", opt_str(e.forward.as_deref())),
+        ("env_hint", opt_str(e.env_hint.as_deref())),
+        (
+            "inode",
+            e.inode.map_or("null".to_string(), |i| i.to_string()),
+        ),
+    ]);
+    let tags: Vec<String> = e.tags.iter().map(|t| str(t)).collect();
+    fields.push(("tags", array(&tags)));
+    fields.push(("rtt_ms", e.rtt_ms.map_or("null".to_string(), num)));
+    fields.push((
+        "path",
+        e.path.as_ref().map_or("null".to_string(), |p| {
+            object(&[
+                ("hint", str(p.hint())),
+                ("mss", p.mss.to_string()),
+                ("pmtu", p.pmtu.to_string()),
+                ("cwnd", p.cwnd.to_string()),
+                ("unacked", p.unacked.to_string()),
+                ("lost", p.lost.to_string()),
+                ("retransmits", p.retransmits.to_string()),
+                ("total_retrans", p.total_retrans.to_string()),
+                (
+                    "segs_out",
+                    p.segs_out.map_or("null".to_string(), |n| n.to_string()),
+                ),
+            ])
+        }),
+    ));
+    fields.push((
+        "geo",
+        e.geo.as_ref().map_or("null".to_string(), |g| {
+            object(&[
+                ("country", opt_str(g.country.as_deref())),
+                ("asn", g.asn.map_or("null".to_string(), |n| n.to_string())),
+                ("as_org", opt_str(g.as_org.as_deref())),
+            ])
+        }),
+    ));
+    fields.push((
+        "accept",
+        e.accept.as_ref().map_or("null".to_string(), |a| {
+            object(&[
+                ("per_sec", num(a.per_sec)),
+                ("syn_received", a.syn_received.to_string()),
+            ])
+        }),
+    ));
+    if !e.fds.is_empty() {
+        let fds: Vec<String> = e
+            .fds
+            .iter()
+            .map(|&(pid, fd)| object(&[("pid", pid.to_string()), ("fd", fd.to_string())]))
+            .collect();
+        fields.push(("fds", array(&fds)));
+    }
+    object(&fields)
+}
+
+// A JSON array of rows, one row per line.
+pub fn entries(entries: &[SocketEntry]) -> String {
+    let rows: Vec<String> = entries.iter().map(|e| format!("\n{}", entry(e))).collect();
+    let end = if rows.is_empty() { "" } else { "\n" };
+    format!("[{}{}]", rows.join(","), end)
+}
+
+// The snapshot document: `{"timestamp":<unix secs>,"connections":[...]}`, one
+// connection per line so the file stays diff- and grep-friendly.
+pub fn snapshot(rows: &[SocketEntry]) -> String {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    format!(
+        "{{\"timestamp\":{},\"connections\":{}}}\n",
+        timestamp,
+        entries(rows)
+    )
+}
+
+// Parsed JSON, for reading documents back in (e.g. `inventory-diff`). Objects keep
+// their key order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Num(f64),
+    Str(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Num(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    // Array items; empty for anything else.
+    pub fn items(&self) -> &[Value] {
+        match self {
+            Value::Array(items) => items,
+            _ => &[],
+        }
+    }
+}
+
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut p = Parser {
+        s: text.as_bytes(),
+        pos: 0,
+    };
+    let v = p.value()?;
+    p.ws();
+    if p.pos != p.s.len() {
+        return Err(p.error("trailing characters"));
+    }
+    Ok(v)
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, what: &str) -> String {
+        format!("{} at byte {}", what, self.pos)
+    }
+
+    fn ws(&mut self) {
+        while self.pos < self.s.len() && self.s[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, lit: &str) -> bool {
+        if self.s[self.pos..].starts_with(lit.as_bytes()) {
+            self.pos += lit.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.ws();
+        match self.s.get(self.pos) {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => self.string().map(Value::Str),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ if self.eat("null") => Ok(Value::Null),
+            _ if self.eat("true") => Ok(Value::Bool(true)),
+            _ if self.eat("false") => Ok(Value::Bool(false)),
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.pos += 1;
+        let mut fields = Vec::new();
+        self.ws();
+        if self.eat("}") {
+            return Ok(Value::Object(fields));
+        }
+        loop {
+            self.ws();
+            if self.s.get(self.pos) != Some(&b'"') {
+                return Err(self.error("expected a key"));
+            }
+            let key = self.string()?;
+            self.ws();
+            if !self.eat(":") {
+                return Err(self.error("expected ':'"));
+            }
+            fields.push((key, self.value()?));
+            self.ws();
+            if self.eat("}") {
+                return Ok(Value::Object(fields));
+            }
+            if !self.eat(",") {
+                return Err(self.error("expected ',' or '}'"));
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        self.ws();
+        if self.eat("]") {
+            return Ok(Value::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.ws();
+            if self.eat("]") {
+                return Ok(Value::Array(items));
+            }
+            if !self.eat(",") {
+                return Err(self.error("expected ',' or ']'"));
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        while self.pos < self.s.len()
+            && matches!(
+                self.s[self.pos],
+                b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9'
+            )
+        {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.s[start..self.pos])
+            .ok()
+            .and_then(|n| n.parse().ok())
+            .map(Value::Num)
+            .ok_or_else(|| self.error("invalid number"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self
+            .s
+            .get(self.pos..self.pos + 4)
+            .and_then(|d| std::str::from_utf8(d).ok())
+            .and_then(|d| u32::from_str_radix(d, 16).ok())
+            .ok_or_else(|| self.error("invalid \\u escape"))?;
+        self.pos += 4;
+        Ok(digits)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.pos += 1;
+        let mut out: Vec<u8> = Vec::new();
+        loop {
+            let Some(&b) = self.s.get(self.pos) else {
+                return Err(self.error("unterminated string"));
+            };
+            self.pos += 1;
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    let Some(&esc) = self.s.get(self.pos) else {
+                        return Err(self.error("unterminated string"));
+                    };
+                    self.pos += 1;
+                    let c = match esc {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let mut code = self.hex4()?;
+                            // A high surrogate pairs with the following \uXXXX.
+                            if (0xd800..0xdc00).contains(&code) && self.eat("\\u") {
+                                let low = self.hex4()?;
+                                code = 0x10000
+                                    + ((code - 0xd800) << 10)
+                                    + (low.wrapping_sub(0xdc00) & 0x3ff);
+                            }
+                            char::from_u32(code).unwrap_or('\u{fffd}')
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    let mut buf = [0; 4];
+                    out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                _ => out.push(b),
+            }
+        }
+        String::from_utf8(out).map_err(|_| self.error("invalid UTF-8"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_quotes_and_control_characters() {
+        assert_eq!(escape("a\"b\\c\n\u{1}"), "a\\\"b\\\\c\\n\\u0001");
+    }
+
+    #[test]
+    fn num_maps_nan_to_null() {
+        assert_eq!(num(f64::NAN), "null");
+        assert_eq!(num(1.5), "1.5");
+    }
+
+    #[test]
+    fn parse_reads_back_written_documents() {
+        let doc = object(&[
+            ("host", str("web \"01\"\n")),
+            ("n", num(-1.5e3)),
+            ("none", opt_str(None)),
+            ("list", array(&["true".to_string(), "false".to_string()])),
+        ]);
+        let v = parse(&format!(" {} ", doc)).unwrap();
+        assert_eq!(v.get("host").and_then(Value::as_str), Some("web \"01\"\n"));
+        assert_eq!(v.get("n").and_then(Value::as_f64), Some(-1500.0));
+        assert_eq!(v.get("none"), Some(&Value::Null));
+        assert_eq!(v.get("list").map(|l| l.items().len()), Some(2));
+        assert_eq!(
+            parse("\"\\u00e9\\ud83d\\ude00\"").unwrap(),
+            Value::Str("é😀".to_string())
+        );
+        assert!(parse("{\"a\":1,}").is_err());
+        assert!(parse("[1] x").is_err());
+    }
+}
@@ -0,0 +1,359 @@
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use sysinfo::System;
+
+use crate::{
+    AddressFamilyFlags, ProtocolFlags, SocketEntry, audit, cli, collect_entries, forensic, json,
+    parse_addr_port, warn, zone::socket_addr,
+};
+
+// `netstatw kill`: closes TCP connections, on Linux with SOCK_DESTROY (sock_diag
+// netlink), on Windows with SetTcpEntry(MIB_TCP_STATE_DELETE_TCB, IPv4 only). Both
+// need root / an elevated token. Only the connection is torn down; its process keeps
+// running and sees a reset.
+
+enum Selector {
+    Pair(SocketAddr, SocketAddr),
+    Id(String),
+    PidPort(u32, u16),
+    // `--stdin`: any of the rows read.
+    Rows(Vec<Selector>),
+}
+
+fn matches(e: &SocketEntry, sel: &Selector) -> bool {
+    match sel {
+        Selector::Pair(local, remote) => {
+            socket_addr(&e.local_addr) == Some(*local)
+                && socket_addr(&e.remote_addr) == Some(*remote)
+        }
+        Selector::Id(id) => e.conn_id().starts_with(id.as_str()),
+        Selector::PidPort(pid, port) => {
+            e.pids.contains(pid)
+                && (parse_addr_port(&e.local_addr).1 == *port
+                    || parse_addr_port(&e.remote_addr).1 == *port)
+        }
+        Selector::Rows(rows) => rows.iter().any(|sel| matches(e, sel)),
+    }
+}
+
+// TCP connections (not listeners or half-bound sockets) matching `sel`.
+fn select<'a>(entries: &'a [SocketEntry], sel: &Selector) -> Vec<&'a SocketEntry> {
+    entries
+        .iter()
+        .filter(|e| e.proto == "TCP" && e.state != "Listen")
+        .filter(|e| socket_addr(&e.remote_addr).is_some_and(|r| !r.ip().is_unspecified()))
+        .filter(|e| matches(e, sel))
+        .collect()
+}
+
+// The rows piped to `--stdin`: the output of `netstatw --json` (an array, a snapshot
+// document or one object per line, e.g. after `jq -c`), matched by address pair, or
+// of `--ids`, by the ID starting each line (other lines, like the table's header, are
+// skipped).
+fn stdin_rows(text: &str) -> Result<Vec<Selector>, String> {
+    let text = text.trim();
+    if !text.starts_with(['[', '{']) {
+        return Ok(text
+            .lines()
+            .filter_map(|line| {
+                let first = line.split_whitespace().next()?.to_ascii_lowercase();
+                (first.len() == 8 && first.bytes().all(|b| b.is_ascii_hexdigit()))
+                    .then_some(Selector::Id(first))
+            })
+            .collect());
+    }
+    let docs = match json::parse(text) {
+        Ok(doc) => vec![doc],
+        Err(_) => text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+            .map(|(i, line)| json::parse(line).map_err(|e| format!("line {}: {}", i + 1, e)))
+            .collect::<Result<_, _>>()?,
+    };
+    let mut rows = Vec::new();
+    for doc in &docs {
+        let items = match doc {
+            json::Value::Array(items) => items.as_slice(),
+            _ => match doc.get("connections") {
+                Some(connections) => connections.items(),
+                None => std::slice::from_ref(doc),
+            },
+        };
+        for row in items {
+            let addr = |key| row.get(key)?.as_str().and_then(socket_addr);
+            match (addr("local"), addr("remote")) {
+                (Some(local), Some(remote)) => rows.push(Selector::Pair(local, remote)),
+                _ => return Err("a row without \"local\" and \"remote\" addresses".to_string()),
+            }
+        }
+    }
+    Ok(rows)
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn close(local: SocketAddr, remote: SocketAddr) -> Result<(), String> {
+    crate::linux_net::destroy(local, remote).map_err(|e| match e.raw_os_error() {
+        Some(libc::EPERM) => format!("{} (needs root or CAP_NET_ADMIN)", e),
+        Some(libc::EOPNOTSUPP) => format!("{} (kernel without CONFIG_INET_DIAG_DESTROY)", e),
+        _ => e.to_string(),
+    })
+}
+
+#[cfg(windows)]
+pub(crate) fn close(local: SocketAddr, remote: SocketAddr) -> Result<(), String> {
+    match (local, remote) {
+        (SocketAddr::V4(l), SocketAddr::V4(r)) => crate::win_net::close_tcp(l, r).map_err(|e| {
+            format!(
+                "SetTcpEntry: {} (run from an elevated prompt)",
+                std::io::Error::from_raw_os_error(e as i32)
+            )
+        }),
+        _ => Err("Windows can only close IPv4 connections (SetTcpEntry)".to_string()),
+    }
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+pub(crate) fn close(_: SocketAddr, _: SocketAddr) -> Result<(), String> {
+    Err("closing connections is supported on Windows and Linux only".to_string())
+}
+
+// The call `close` makes, for `--dry-run`.
+pub(crate) fn plan(local: SocketAddr, remote: SocketAddr) -> String {
+    let call = if cfg!(windows) {
+        "SetTcpEntry(MIB_TCP_STATE_DELETE_TCB)"
+    } else if cfg!(target_os = "linux") {
+        "netlink SOCK_DESTROY"
+    } else {
+        "(unsupported)"
+    };
+    format!("{} {} -> {}", call, local, remote)
+}
+
+struct Options {
+    sel: Selector,
+    all: bool,
+    dry_run: bool,
+    audit_log: Option<PathBuf>,
+}
+
+fn parse_args(mut args: cli::Args) -> Result<Options, String> {
+    let mut id: Option<String> = None;
+    let mut pid: Option<u32> = None;
+    let mut port: Option<u16> = None;
+    let mut all = false;
+    let mut dry_run = false;
+    let mut audit_log: Option<PathBuf> = None;
+    let mut stdin = false;
+    let mut pair: Vec<String> = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--id" => id = Some(args.value(&arg)?.to_ascii_lowercase()),
+            "--pid" => pid = Some(args.parse(&arg)?),
+            "--port" => port = Some(args.parse(&arg)?),
+            "--all" => all = true,
+            "--stdin" => stdin = true,
+            "--dry-run" => dry_run = true,
+            "--audit-log" => audit_log = Some(PathBuf::from(args.value(&arg)?)),
+            "--forensic" => {
+                return Err("closing connections is not allowed with --forensic".to_string());
+            }
+            other if other.starts_with('-') => {
+                return Err(format!("unexpected argument '{}'", other));
+            }
+            _ => pair.push(arg),
+        }
+    }
+    let sel = match (pair.as_slice(), id, pid, port) {
+        ([], None, None, None) if stdin => {
+            let text = io::read_to_string(io::stdin()).map_err(|e| format!("--stdin: {}", e))?;
+            Selector::Rows(stdin_rows(&text).map_err(|e| format!("--stdin: {}", e))?)
+        }
+        _ if stdin => return Err("--stdin takes the connections from stdin only".to_string()),
+        ([local, remote], None, None, None) => {
+            let parse = |s: &str| s.parse::<SocketAddr>().ok().or_else(|| socket_addr(s));
+            match (parse(local), parse(remote)) {
+                (Some(l), Some(r)) => Selector::Pair(l, r),
+                _ => return Err("expected two addresses like 10.0.0.1:5432".to_string()),
+            }
+        }
+        ([], Some(id), None, None) => Selector::Id(id),
+        ([], None, Some(pid), Some(port)) => Selector::PidPort(pid, port),
+        _ => {
+            return Err(
+                "pick connections by LOCAL REMOTE, --id, --pid with --port, or --stdin".to_string(),
+            );
+        }
+    };
+    Ok(Options {
+        sel,
+        all,
+        dry_run,
+        audit_log,
+    })
+}
+
+pub fn run(args: Vec<String>) -> i32 {
+    let trigger = format!("netstatw kill {}", args.join(" "));
+    let Options {
+        sel,
+        all,
+        dry_run,
+        audit_log,
+    } = match cli::parse_command(&cli::KILL, args, parse_args) {
+        Ok(opts) => opts,
+        Err(status) => return status,
+    };
+
+    forensic::record("sysinfo::System::refresh_processes (connection owners)");
+    let mut system = System::new();
+    system.refresh_processes();
+    let entries = match collect_entries(
+        &system,
+        None,
+        AddressFamilyFlags::IPV4 | AddressFamilyFlags::IPV6,
+        ProtocolFlags::TCP,
+    ) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("netstatw kill: {}", e);
+            return 1;
+        }
+    };
+    let targets = select(&entries, &sel);
+    if targets.is_empty() {
+        eprintln!("netstatw kill: no matching TCP connection");
+        return 1;
+    }
+    if targets.len() > 1 && !all {
+        eprintln!(
+            "netstatw kill: {} connections match; narrow the selection or pass --all:",
+            targets.len()
+        );
+        for e in &targets {
+            eprintln!(
+                "  {} {} -> {} {}",
+                e.conn_id(),
+                e.local_addr,
+                e.remote_addr,
+                e.process_info
+            );
+        }
+        return 2;
+    }
+    let mut failed = false;
+    for e in targets {
+        let (Some(local), Some(remote)) = (socket_addr(&e.local_addr), socket_addr(&e.remote_addr))
+        else {
+            continue;
+        };
+        if dry_run {
+            println!(
+                "would close {} -> {} ({}): {}",
+                e.local_addr,
+                e.remote_addr,
+                e.process_info,
+                plan(local, remote)
+            );
+            continue;
+        }
+        let result = close(local, remote);
+        match &result {
+            Ok(()) => println!(
+                "closed {} -> {} ({})",
+                e.local_addr, e.remote_addr, e.process_info
+            ),
+            Err(err) => {
+                eprintln!(
+                    "netstatw kill: {} -> {}: {}",
+                    e.local_addr, e.remote_addr, err
+                );
+                failed = true;
+            }
+        }
+        if let Some(path) = &audit_log {
+            let entry = audit::Entry {
+                action: "close",
+                local: &e.local_addr,
+                remote: &e.remote_addr,
+                process: &e.process_info,
+                trigger: &trigger,
+                result: result.as_ref().map(|()| "").map_err(String::as_str),
+            };
+            if let Err(err) = audit::append(path, &entry) {
+                eprintln!("netstatw kill: audit log {}: {}", path.display(), err);
+                failed = true;
+            }
+        }
+    }
+    warn::emit(false);
+    if failed { 1 } else { 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tcp(local: &str, remote: &str, state: &str, pid: u32) -> SocketEntry {
+        SocketEntry {
+            proto: "TCP".to_string(),
+            local_addr: local.to_string(),
+            remote_addr: remote.to_string(),
+            state: state.to_string(),
+            pids: vec![pid],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn select_matches_connections_only() {
+        let rows = [
+            tcp("0.0.0.0:5432", "0.0.0.0:0", "Listen", 7),
+            tcp("10.0.0.1:5432", "10.0.0.9:40000", "Established", 7),
+            tcp("10.0.0.1:5432", "10.0.0.9:40001", "Established", 7),
+            tcp("[::1]:8080", "[::1]:51000", "Established", 8),
+        ];
+        let pair = Selector::Pair(
+            "10.0.0.1:5432".parse().unwrap(),
+            "10.0.0.9:40001".parse().unwrap(),
+        );
+        assert_eq!(select(&rows, &pair).len(), 1);
+        assert_eq!(select(&rows, &Selector::PidPort(7, 5432)).len(), 2);
+        let v6 = Selector::Pair(
+            "[::1]:8080".parse().unwrap(),
+            "[::1]:51000".parse().unwrap(),
+        );
+        assert_eq!(select(&rows, &v6)[0].pids, [8]);
+        let id = rows[3].conn_id();
+        assert_eq!(select(&rows, &Selector::Id(id[..4].to_string())).len(), 1);
+    }
+
+    #[test]
+    fn stdin_takes_json_rows_and_ids() {
+        let rows = [
+            tcp("10.0.0.1:5432", "10.0.0.9:40000", "Established", 7),
+            tcp("10.0.0.1:5432", "10.0.0.9:40001", "Established", 7),
+            tcp("[::1]:8080", "[::1]:51000", "Established", 8),
+        ];
+        let picked = |text: &str| {
+            let sel = Selector::Rows(stdin_rows(text).unwrap());
+            select(&rows, &sel)
+                .iter()
+                .map(|e| e.remote_addr.as_str())
+                .collect::<Vec<_>>()
+        };
+        let array = json::entries(&rows[1..]);
+        assert_eq!(picked(&array), ["10.0.0.9:40001", "[::1]:51000"]);
+        let snapshot = json::snapshot(&rows[..1]);
+        assert_eq!(picked(&snapshot), ["10.0.0.9:40000"]);
+        let lines = format!("{}\n{}\n", json::entry(&rows[0]), json::entry(&rows[2]));
+        assert_eq!(picked(&lines), ["10.0.0.9:40000", "[::1]:51000"]);
+        let ids = format!("ID        PROTO\n{}  TCP\n", rows[1].conn_id());
+        assert_eq!(picked(&ids), ["10.0.0.9:40001"]);
+        assert!(picked("").is_empty());
+        assert!(stdin_rows(r#"[{"proto":"TCP"}]"#).is_err());
+        assert!(stdin_rows("{\"local\":").is_err());
+    }
+}
@@ -0,0 +1,929 @@
+// netstatw as a library: socket collection enriched with owning processes and
+// per-process CPU, disk and (on Windows and Linux) network rates.
+//
+//     let snap = netstatw::snapshot(&netstatw::SnapshotOptions::default())?;
+//     for e in &snap.entries {
+//         println!("{} {} -> {} {}", e.proto, e.local_addr, e.remote_addr, e.process_info);
+//     }
+//
+// The remaining modules back the `netstatw` binary and are not a stable API.
+
+use netstat2::*;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, Instant};
+use sysinfo::{Pid, System, Users};
+
+pub use netstat2::{AddressFamilyFlags, ProtocolFlags};
+
+use source::SocketSource;
+
+#[doc(hidden)]
+pub mod accept;
+#[doc(hidden)]
+pub mod alert;
+#[doc(hidden)]
+pub mod assert;
+#[doc(hidden)]
+pub mod audit;
+#[doc(hidden)]
+pub mod block;
+#[doc(hidden)]
+pub mod cache;
+#[doc(hidden)]
+pub mod cap;
+#[doc(hidden)]
+pub mod caps;
+#[doc(hidden)]
+pub mod changes;
+#[doc(hidden)]
+pub mod cli;
+#[doc(hidden)]
+pub mod color;
+#[doc(hidden)]
+pub mod compact;
+#[doc(hidden)]
+pub mod compare;
+#[doc(hidden)]
+pub mod config;
+#[doc(hidden)]
+pub mod csv;
+#[doc(hidden)]
+pub mod diff;
+#[doc(hidden)]
+pub mod events;
+#[doc(hidden)]
+pub mod exec;
+#[doc(hidden)]
+pub mod filter;
+#[doc(hidden)]
+pub mod forensic;
+#[doc(hidden)]
+pub mod forward;
+#[doc(hidden)]
+pub mod geoip;
+#[doc(hidden)]
+pub mod graph;
+#[doc(hidden)]
+pub mod harden;
+#[doc(hidden)]
+pub mod hints;
+#[doc(hidden)]
+pub mod idle;
+#[doc(hidden)]
+pub mod idn;
+#[doc(hidden)]
+pub mod interrupt;
+#[doc(hidden)]
+pub mod inventory;
+#[doc(hidden)]
+pub mod json;
+#[doc(hidden)]
+pub mod kill;
+#[cfg(target_os = "linux")]
+mod linux_net;
+#[doc(hidden)]
+pub mod own;
+#[doc(hidden)]
+pub mod pager;
+#[doc(hidden)]
+pub mod path;
+#[doc(hidden)]
+pub mod policy;
+#[doc(hidden)]
+pub mod probe;
+#[cfg(target_os = "linux")]
+#[doc(hidden)]
+pub mod procfd;
+#[cfg(target_os = "linux")]
+#[doc(hidden)]
+pub mod procnet;
+#[doc(hidden)]
+pub mod progress;
+#[doc(hidden)]
+pub mod record;
+#[doc(hidden)]
+pub mod report;
+#[doc(hidden)]
+pub mod resolve;
+#[doc(hidden)]
+pub mod seen;
+#[doc(hidden)]
+pub mod serve;
+#[doc(hidden)]
+pub mod services;
+mod sha256;
+mod source;
+#[doc(hidden)]
+pub mod synth;
+#[doc(hidden)]
+pub mod tags;
+#[doc(hidden)]
+pub mod tui;
+#[doc(hidden)]
+pub mod warn;
+#[cfg(windows)]
+mod win_api;
+#[cfg(windows)]
+mod win_net;
+#[cfg(any(windows, test))]
+mod win_table;
+#[doc(hidden)]
+pub mod zone;
+
+#[derive(Clone, Default)]
+pub struct SocketEntry {
+    pub proto: String,
+    pub local_addr: String,
+    pub remote_addr: String,
+    pub state: String,
+    pub process_info: String,
+    pub pids: Vec<u32>,
+    // Account owning the first PID (the USER column, `--user`).
+    pub user: Option<String>,
+    pub agg_stats: Option<ProcessStats>,
+    pub new_remote: bool,
+    pub synth: bool,
+    pub env_hint: Option<String>,
+    pub forward: Option<String>,
+    // Socket inode (Linux; None elsewhere and for sockets without one, e.g. TIME_WAIT).
+    pub inode: Option<u32>,
+    // (pid, fd) holding the socket, filled on request by `procfd::fill` (Linux).
+    pub fds: Vec<(u32, u32)>,
+    // Reverse DNS name of the remote address (`--resolve`).
+    pub remote_host: Option<String>,
+    // Service names of the local and remote ports (`--names`).
+    pub local_service: Option<String>,
+    pub remote_service: Option<String>,
+    // Tags from the tag rules, in rule order.
+    pub tags: Vec<String>,
+    // Round trip to the remote host in milliseconds (`--probe-rtt`).
+    pub rtt_ms: Option<f64>,
+    // tcp_info path fields (`--path-hints`, Linux).
+    pub path: Option<path::PathInfo>,
+    // Country and ASN of the remote address (`--geoip`).
+    pub geo: Option<geoip::Geo>,
+    // Accept rate and half-open connections of a TCP listener (`--full`).
+    pub accept: Option<accept::AcceptRate>,
+    // Opened or closed since the previous `--watch` refresh.
+    pub change: Option<changes::Change>,
+    // Whether the connection's byte counters stood still for `--idle-after` --watch
+    // refreshes; None where they aren't read or don't apply.
+    pub idle: Option<bool>,
+}
+
+pub fn get_process_info(system: &System, pid: u32) -> String {
+    #[cfg_attr(not(windows), allow(unused_mut))]
+    let mut info = system
+        .process(Pid::from(pid as usize))
+        .map(|process| {
+            let full_path = process.exe().unwrap_or_else(|| process.name().as_ref());
+            format!("{}: {}", pid, full_path.display())
+        })
+        .unwrap_or_else(|| format!("{}: Unknown", pid));
+    // A shared svchost.exe says little on its own: name the services it hosts.
+    #[cfg(windows)]
+    {
+        let services = win_net::services_in(pid);
+        if !services.is_empty() {
+            info.push_str(&format!(" [{}]", services.join(", ")));
+        }
+    }
+    info
+}
+
+// A command line as one string: arguments separated by spaces, the ones containing
+// whitespace (or nothing) in double quotes so they stay apart.
+pub fn join_cmdline(args: &[String]) -> String {
+    let quoted: Vec<String> = args
+        .iter()
+        .map(|a| {
+            if a.is_empty() || a.contains(char::is_whitespace) {
+                format!("\"{}\"", a)
+            } else {
+                a.clone()
+            }
+        })
+        .collect();
+    quoted.join(" ")
+}
+
+// `--cmdline`: each row's PROCESS as `PID: full command line` instead of the
+// executable path, to tell apart the many `java` or `python` processes. Keeps the
+// path for processes whose command line can't be read (kernel threads, or protected
+// processes on Windows).
+pub fn show_cmdlines(entries: &mut [SocketEntry], system: &System) {
+    for e in entries.iter_mut().filter(|e| !e.pids.is_empty()) {
+        let parts: Vec<String> = e
+            .pids
+            .iter()
+            .map(|&pid| match system.process(Pid::from(pid as usize)) {
+                Some(p) if !p.cmd().is_empty() => format!("{}: {}", pid, join_cmdline(p.cmd())),
+                _ => get_process_info(system, pid),
+            })
+            .collect();
+        e.process_info = parts.join(", ");
+    }
+}
+
+// Account name owning `pid`, falling back to the raw user ID when it has no name.
+pub fn process_user(system: &System, users: &Users, pid: u32) -> Option<String> {
+    // sysinfo only knows local accounts on Windows; service accounts (SYSTEM,
+    // NETWORK SERVICE, virtual accounts) need the token's SID looked up.
+    #[cfg(windows)]
+    if let Some(name) = win_api::process_account(pid) {
+        return Some(name);
+    }
+    let uid = system.process(Pid::from(pid as usize))?.user_id()?;
+    Some(
+        users
+            .get_user_by_id(uid)
+            .map(|u| u.name().to_string())
+            .unwrap_or_else(|| uid.to_string()),
+    )
+}
+
+fn state_sort_order(state: &str) -> u8 {
+    // Reverse order - higher priority states get lower numbers for reverse sorting
+    match state {
+        "TimeWait" => 2,
+        "LastAck" => 3,
+        "Closing" => 4,
+        "CloseWait" => 5,
+        "FinWait2" => 6,
+        "FinWait1" => 7,
+        "SynReceived" => 8,
+        "SynSent" => 9,
+        "Established" => 10,
+        "Listen" => 11,
+        "-" => 1, // For UDP
+        _ => 0,   // Unknown states
+    }
+}
+
+// An address column value, `host:port`. IPv6 hosts are bracketed (`[::1]:8080`,
+// `[fe80::1%eth0]:22`) so the port is unambiguous for CSV consumers and scripts.
+pub fn join_addr(host: &str, port: u16) -> String {
+    if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+// The address column of a socket, as `SocketAddr` displays it.
+pub fn addr_string(ip: IpAddr, port: u16) -> String {
+    SocketAddr::new(ip, port).to_string()
+}
+
+// Splits an address column into host (without brackets) and port. Unbracketed IPv6
+// (`::1:8080`, as older snapshots and recordings have it) splits at the last colon;
+// anything without a port gives port 0.
+pub fn parse_addr_port(addr: &str) -> (&str, u16) {
+    if let Some(rest) = addr.strip_prefix('[')
+        && let Some((host, port)) = rest.split_once("]:")
+    {
+        return (host, port.parse().unwrap_or(0));
+    }
+    if let Some((ip, port)) = addr.rsplit_once(':')
+        && let Ok(port) = port.parse::<u16>()
+    {
+        return (ip, port);
+    }
+    (addr, 0) // fallback
+}
+
+// For display: folds each IPv4 wildcard listener (`0.0.0.0:P`) and its IPv6 twin
+// (`[::]:P`, same protocol and PIDs) into one row labelled `*:P (v4+v6)`. Machine
+// output keeps both rows.
+pub fn merge_dualstack(entries: &[SocketEntry]) -> Vec<SocketEntry> {
+    let mut twins: HashMap<usize, usize> = HashMap::new();
+    for (i, e) in entries.iter().enumerate() {
+        let (ip, port) = parse_addr_port(&e.local_addr);
+        if ip != "0.0.0.0" || !(e.state == "Listen" || e.proto == "UDP") {
+            continue;
+        }
+        let v6_addr = addr_string(Ipv6Addr::UNSPECIFIED.into(), port);
+        let twin = entries.iter().enumerate().position(|(j, t)| {
+            t.local_addr == v6_addr
+                && t.proto == e.proto
+                && t.state == e.state
+                && t.pids == e.pids
+                && !twins.values().any(|&v| v == j)
+        });
+        if let Some(j) = twin {
+            twins.insert(i, j);
+        }
+    }
+    entries
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !twins.values().any(|v| v == i))
+        .map(|(i, e)| {
+            let mut row = e.clone();
+            if twins.contains_key(&i) {
+                row.local_addr = format!("*:{} (v4+v6)", parse_addr_port(&e.local_addr).1);
+            }
+            row
+        })
+        .collect()
+}
+
+// An address column in numeric order: IPv4 before IPv6 (hosts that aren't IPs, like
+// the `*` of merged listeners, first by name), then zone and port.
+pub type AddrSortKey<'a> = ((Option<IpAddr>, &'a str), Option<&'a str>, u16);
+
+pub fn addr_sort_key(addr: &str) -> AddrSortKey<'_> {
+    let (host, port) = parse_addr_port(addr);
+    let (ip, zone) = zone::split(host);
+    let ip = match ip.parse::<IpAddr>() {
+        Ok(addr) => (Some(addr), ""),
+        Err(_) => (None, ip),
+    };
+    (ip, zone, port)
+}
+
+impl SocketEntry {
+    // State, protocol, then the local address.
+    pub fn sort_key(&self) -> (u8, &str, AddrSortKey<'_>) {
+        (
+            state_sort_order(&self.state),
+            &self.proto,
+            addr_sort_key(&self.local_addr),
+        )
+    }
+
+    // Short ID for the connection tuple, stable across runs and hosts (FNV-1a, so
+    // it does not depend on std's randomly seeded hasher).
+    pub fn conn_id(&self) -> String {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for part in [&self.proto, &self.local_addr, &self.remote_addr] {
+            for b in part.bytes().chain([b'|']) {
+                hash ^= b as u64;
+                hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+            }
+        }
+        format!("{:08x}", (hash ^ (hash >> 32)) as u32)
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct ProcessStats {
+    pub cpu_pct: f32,
+    pub read_rate_bps: f64,
+    pub write_rate_bps: f64,
+    pub net_rx_rate_bps: f64,
+    pub net_tx_rate_bps: f64,
+    pub total_read_bytes: u64,
+    pub total_written_bytes: u64,
+    // A row's Rx/Tx are its own connection's rather than its processes' totals (a TCP
+    // row measured on its own), so they can be summed over rows.
+    pub net_per_conn: bool,
+}
+
+// (local, remote) as rendered in the address columns.
+pub(crate) type ConnKey = (String, String);
+
+// Rx/Tx rates in bytes/s from one TCP sampling window (Windows EStats, Linux
+// sock_diag).
+#[derive(Default)]
+pub(crate) struct TcpRates {
+    // Per connection, keyed by its 4-tuple.
+    pub per_conn: HashMap<ConnKey, (f64, f64)>,
+    // Sum over each process's connections.
+    pub per_pid: HashMap<u32, (f64, f64)>,
+}
+
+// Below this, matching disk and network rates are noise rather than a pattern.
+const CORRELATION_MIN_BPS: f64 = 64.0 * 1024.0;
+
+impl ProcessStats {
+    // Flags a process whose disk writes roughly track its network Rx (downloading to
+    // disk) or whose disk reads roughly track its Tx (sending files out): both rates
+    // above CORRELATION_MIN_BPS and within a factor of two. The number is disk/net.
+    pub fn disk_net_flag(&self) -> Option<String> {
+        let pairs = [
+            ("rx->disk", self.net_rx_rate_bps, self.write_rate_bps),
+            ("disk->tx", self.net_tx_rate_bps, self.read_rate_bps),
+        ];
+        let flags: Vec<String> = pairs
+            .iter()
+            .filter(|(_, net, disk)| *net >= CORRELATION_MIN_BPS && *disk >= CORRELATION_MIN_BPS)
+            .filter_map(|(label, net, disk)| {
+                let ratio = disk / net;
+                (0.5..=2.0)
+                    .contains(&ratio)
+                    .then(|| format!("{} {:.2}", label, ratio))
+            })
+            .collect();
+        (!flags.is_empty()).then(|| flags.join(" "))
+    }
+}
+
+pub fn human_readable_rate(bps: f64) -> String {
+    if !bps.is_finite() || bps < 0.0 {
+        return "N/A".to_string();
+    }
+    const UNITS: [&str; 5] = ["B/s", "KB/s", "MB/s", "GB/s", "TB/s"];
+    let mut v = bps;
+    let mut idx = 0usize;
+    while v >= 1024.0 && idx < UNITS.len() - 1 {
+        v /= 1024.0;
+        idx += 1;
+    }
+    if idx == 0 {
+        format!("{:.0} {}", v, UNITS[idx])
+    } else {
+        format!("{:.1} {}", v, UNITS[idx])
+    }
+}
+
+pub fn collect_process_stats(
+    system: &mut System,
+    pids: &HashSet<u32>,
+    interval: Duration,
+) -> HashMap<u32, ProcessStats> {
+    // sysinfo notes:
+    // - Process CPU% becomes meaningful after at least two refreshes.
+    // - Disk usage totals are cumulative; compute deltas over `interval` for per-second rates.
+    // - Some platforms may not expose all counters; such values may remain 0.
+    // Initial refresh to capture baseline totals.
+    forensic::record("sysinfo::System::refresh_processes");
+    system.refresh_processes();
+
+    let mut base_totals: HashMap<u32, (u64, u64)> = HashMap::new();
+    for &pid in pids {
+        if let Some(proc_) = system.process(Pid::from(pid as usize)) {
+            let du = proc_.disk_usage();
+            base_totals.insert(pid, (du.total_read_bytes, du.total_written_bytes));
+        }
+    }
+
+    let start = Instant::now();
+    let sleep_dur = if interval.is_zero() {
+        Duration::from_millis(1)
+    } else {
+        interval
+    };
+    interrupt::sleep(sleep_dur);
+
+    // Second refresh to compute deltas; also makes cpu_usage meaningful.
+    forensic::record("sysinfo::System::refresh_processes");
+    system.refresh_processes();
+
+    let elapsed = start.elapsed().as_secs_f64().max(0.001);
+    let mut out: HashMap<u32, ProcessStats> = HashMap::new();
+
+    for &pid in pids {
+        if let Some(proc_) = system.process(Pid::from(pid as usize)) {
+            let cpu = proc_.cpu_usage();
+            let du = proc_.disk_usage();
+            let (base_r, base_w) = base_totals
+                .get(&pid)
+                .copied()
+                .unwrap_or((du.total_read_bytes, du.total_written_bytes));
+            let read_delta = du.total_read_bytes.saturating_sub(base_r) as f64;
+            let write_delta = du.total_written_bytes.saturating_sub(base_w) as f64;
+            let read_rate = read_delta / elapsed;
+            let write_rate = write_delta / elapsed;
+            out.insert(
+                pid,
+                ProcessStats {
+                    cpu_pct: cpu,
+                    read_rate_bps: read_rate,
+                    write_rate_bps: write_rate,
+                    net_rx_rate_bps: 0.0,
+                    net_tx_rate_bps: 0.0,
+                    total_read_bytes: du.total_read_bytes,
+                    total_written_bytes: du.total_written_bytes,
+                    net_per_conn: false,
+                },
+            );
+        }
+    }
+
+    out
+}
+
+pub fn build_socket_entries(
+    sockets_info: Vec<SocketInfo>,
+    system: &System,
+    top_n: Option<usize>,
+) -> Vec<SocketEntry> {
+    let mut entries: Vec<SocketEntry> = Vec::new();
+    let mut unresolved: HashSet<u32> = HashSet::new();
+    let mut ownerless: u64 = 0;
+    // Loaded on the first owned socket; one lookup per PID.
+    let mut users: Option<Users> = None;
+    let mut owners: HashMap<u32, Option<String>> = HashMap::new();
+    for si in sockets_info {
+        // TIME_WAIT sockets belong to no process on any OS; other ownerless rows
+        // usually mean processes this user can't see.
+        let time_wait = matches!(
+            &si.protocol_socket_info,
+            ProtocolSocketInfo::Tcp(t) if t.state == TcpState::TimeWait
+        );
+        if si.associated_pids.is_empty() && !time_wait {
+            ownerless += 1;
+        }
+        for &pid in &si.associated_pids {
+            if system.process(Pid::from(pid as usize)).is_none() {
+                unresolved.insert(pid);
+            }
+        }
+        let process_info_list: Vec<String> = si
+            .associated_pids
+            .iter()
+            .take(top_n.unwrap_or(usize::MAX))
+            .map(|&pid| get_process_info(system, pid))
+            .collect();
+        let process_info = if process_info_list.is_empty() {
+            "Unknown".to_string()
+        } else {
+            process_info_list.join(", ")
+        };
+        let pids: Vec<u32> = si
+            .associated_pids
+            .iter()
+            .cloned()
+            .take(top_n.unwrap_or(usize::MAX))
+            .collect();
+        let user = pids.first().and_then(|&pid| {
+            owners
+                .entry(pid)
+                .or_insert_with(|| {
+                    let users = users.get_or_insert_with(|| {
+                        forensic::record("sysinfo::Users (account names)");
+                        Users::new_with_refreshed_list()
+                    });
+                    process_user(system, users, pid)
+                })
+                .clone()
+        });
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let inode = Some(si.inode).filter(|&i| i != 0);
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        let inode = None;
+
+        // The columns the socket tables give; the rest is filled in by later stages.
+        let (proto, local_addr, remote_addr, state) = match si.protocol_socket_info {
+            ProtocolSocketInfo::Tcp(t) => (
+                "TCP",
+                addr_string(t.local_addr, t.local_port),
+                addr_string(t.remote_addr, t.remote_port),
+                format!("{:?}", t.state),
+            ),
+            ProtocolSocketInfo::Udp(u) => (
+                "UDP",
+                addr_string(u.local_addr, u.local_port),
+                "*:*".to_string(),
+                "-".to_string(),
+            ),
+        };
+        entries.push(SocketEntry {
+            proto: proto.to_string(),
+            local_addr,
+            remote_addr,
+            state,
+            process_info,
+            pids,
+            user,
+            inode,
+            ..Default::default()
+        });
+    }
+
+    warn::warn_count(
+        "pid_unresolved",
+        unresolved.len() as u64,
+        "PIDs could not be resolved to a process",
+    );
+    // An elevated run sees every owner, so what is left is sockets being torn down.
+    if !caps::elevated() {
+        warn::warn_count(
+            "no_owner",
+            ownerless,
+            "sockets with no owning process (insufficient privileges; see --capabilities)",
+        );
+    }
+    entries
+}
+
+// The raw socket tables of the given address families and protocols, from this build's
+// table source.
+pub(crate) fn socket_table(
+    af_flags: AddressFamilyFlags,
+    proto_flags: ProtocolFlags,
+) -> Result<Vec<SocketInfo>, netstat2::error::Error> {
+    source::TABLE.sockets(af_flags, proto_flags)
+}
+
+// Reads the socket tables of the given address families and protocols and resolves
+// each socket's owning processes.
+pub fn collect_entries(
+    system: &System,
+    top_n: Option<usize>,
+    af_flags: AddressFamilyFlags,
+    proto_flags: ProtocolFlags,
+) -> Result<Vec<SocketEntry>, netstat2::error::Error> {
+    #[cfg_attr(not(windows), allow(unused_mut))]
+    let mut sockets_info = socket_table(af_flags, proto_flags)?;
+
+    // Cross-check UDP rows against the Windows UDP owner tables (IPv4 and IPv6) and
+    // attribute the ones the netstat2 crate returned without a PID.
+    #[cfg(windows)]
+    match source::UdpOwnerTable.sockets(af_flags, proto_flags) {
+        Ok(owners) => source::attribute(&mut sockets_info, &owners),
+        Err(e) => warn::warn(
+            source::UdpOwnerTable.name(),
+            format!("{}; UDP PID cross-check skipped", e),
+        ),
+    }
+
+    // Collect all socket entries
+    let mut socket_entries: Vec<SocketEntry> = build_socket_entries(sockets_info, system, top_n);
+    zone::apply(&mut socket_entries);
+    // Only the netstatw binary turns this on.
+    own::exclude(&mut socket_entries);
+
+    Ok(socket_entries)
+}
+
+// Samples CPU, disk and (on Windows and Linux) network rates over `interval` for the PIDs in
+// `entries`, fills each row's `agg_stats` and returns the per-PID figures. Unavailable
+// network rates are NaN. `enable_estats` allows turning on the Windows per-connection
+// statistics collection, which changes system state.
+pub fn sample_stats(
+    system: &mut System,
+    entries: &mut [SocketEntry],
+    interval: Duration,
+    enable_estats: bool,
+) -> HashMap<u32, ProcessStats> {
+    let mut pid_set: HashSet<u32> = HashSet::new();
+    for e in entries.iter() {
+        for &p in &e.pids {
+            pid_set.insert(p);
+        }
+    }
+    if pid_set.is_empty() {
+        return HashMap::new();
+    }
+    // Windows-specific TCP network sampling, per connection and per process. The EStats
+    // sampler only reads the TCP tables of the address families that are listed (`-4`,
+    // `-6`). Without an elevated token enabling collection is refused for every
+    // connection, so don't attempt it.
+    #[cfg(windows)]
+    let enable_estats = if enable_estats && !caps::elevated() {
+        warn::warn(
+            "estats_not_elevated",
+            "not elevated: Rx/Tx only for connections with EStats already enabled \
+             (see --capabilities)"
+                .to_string(),
+        );
+        false
+    } else {
+        enable_estats
+    };
+    let tcp_family = |v6: bool| {
+        entries.iter().any(|e| {
+            e.proto == "TCP"
+                && zone::parse_ip(parse_addr_port(&e.local_addr).0)
+                    .is_some_and(|ip| ip.is_ipv6() == v6)
+        })
+    };
+    let (any_v4, any_v6) = (tcp_family(false), tcp_family(true));
+    #[cfg(windows)]
+    let rates = if !any_v4 && !any_v6 {
+        Default::default()
+    } else {
+        match win_net::sample_tcp_estats(interval, enable_estats, any_v4, any_v6) {
+            Ok(rates) => rates,
+            Err(e) => {
+                warn::warn("estats_unavailable", format!("{}; Rx/Tx unavailable", e));
+                Default::default()
+            }
+        }
+    };
+    // Linux: sock_diag byte counters, read-only, so `enable_estats` doesn't apply.
+    #[cfg(target_os = "linux")]
+    let _ = enable_estats;
+    #[cfg(target_os = "linux")]
+    let rates = if !any_v4 && !any_v6 {
+        Default::default()
+    } else {
+        match linux_net::sample_tcp(interval, &pid_set, any_v4, any_v6) {
+            Ok(rates) => rates,
+            Err(e) => {
+                warn::warn(
+                    "sock_diag_unavailable",
+                    format!("netlink sock_diag: {}; Rx/Tx unavailable", e),
+                );
+                Default::default()
+            }
+        }
+    };
+    #[cfg(not(any(windows, target_os = "linux")))]
+    let _ = (enable_estats, any_v4, any_v6);
+    #[cfg(not(any(windows, target_os = "linux")))]
+    let rates = TcpRates::default();
+    let TcpRates {
+        per_pid: net_rates,
+        per_conn: conn_rates,
+    } = rates;
+    let mut pid_stats = collect_process_stats(system, &pid_set, interval);
+    if interrupt::interrupted() {
+        warn::warn(
+            "sampling_interrupted",
+            "interrupted: rates are partial (measured over a shortened window)".to_string(),
+        );
+    } else if interrupt::deadline_passed() {
+        warn::warn(
+            "deadline",
+            "--timeout reached: rates are partial (measured over a shortened window)".to_string(),
+        );
+    }
+    for entry in entries.iter_mut() {
+        let mut agg = ProcessStats::default();
+        let mut any = false;
+        let mut net_any = false;
+        for &p in &entry.pids {
+            if let Some(s) = pid_stats.get(&p) {
+                any = true;
+                agg.cpu_pct += s.cpu_pct;
+                agg.read_rate_bps += s.read_rate_bps;
+                agg.write_rate_bps += s.write_rate_bps;
+                agg.total_read_bytes = agg.total_read_bytes.saturating_add(s.total_read_bytes);
+                agg.total_written_bytes = agg
+                    .total_written_bytes
+                    .saturating_add(s.total_written_bytes);
+            }
+            if let Some((rx, tx)) = net_rates.get(&p) {
+                net_any = true;
+                agg.net_rx_rate_bps += *rx;
+                agg.net_tx_rate_bps += *tx;
+            }
+        }
+        // A TCP row measured on its own shows its connection's rates rather than the
+        // process total.
+        if entry.proto == "TCP"
+            && let Some(&(rx, tx)) = conn_rates.get(&(
+                zone::strip(&entry.local_addr),
+                zone::strip(&entry.remote_addr),
+            ))
+        {
+            net_any = true;
+            agg.net_rx_rate_bps = rx;
+            agg.net_tx_rate_bps = tx;
+            agg.net_per_conn = true;
+        }
+        if !net_any {
+            // Mark network as not available so formatting shows N/A
+            agg.net_rx_rate_bps = f64::NAN;
+            agg.net_tx_rate_bps = f64::NAN;
+        }
+        if any {
+            entry.agg_stats = Some(agg);
+        }
+    }
+    // Keep the per-PID view (network rates merged in) for reports.
+    for (pid, s) in pid_stats.iter_mut() {
+        let (rx, tx) = net_rates.get(pid).copied().unwrap_or((f64::NAN, f64::NAN));
+        s.net_rx_rate_bps = rx;
+        s.net_tx_rate_bps = tx;
+    }
+    pid_stats
+}
+
+#[derive(Clone, Debug)]
+pub struct SnapshotOptions {
+    // Sample per-process rates (takes `sample_interval`).
+    pub stats: bool,
+    pub sample_interval: Duration,
+    // Keep at most this many PIDs per socket.
+    pub top_n: Option<usize>,
+    pub families: AddressFamilyFlags,
+    pub protocols: ProtocolFlags,
+}
+
+impl Default for SnapshotOptions {
+    fn default() -> Self {
+        SnapshotOptions {
+            stats: false,
+            sample_interval: Duration::from_millis(800),
+            top_n: None,
+            families: AddressFamilyFlags::IPV4 | AddressFamilyFlags::IPV6,
+            protocols: ProtocolFlags::TCP | ProtocolFlags::UDP,
+        }
+    }
+}
+
+pub struct Snapshot {
+    // Sorted by state, protocol and local address.
+    pub entries: Vec<SocketEntry>,
+    // Per-PID rates when `stats` was requested.
+    pub pid_stats: HashMap<u32, ProcessStats>,
+}
+
+// One enriched snapshot of the host's sockets. Read-only: Windows per-connection
+// statistics are used only if already enabled. Every socket is listed, the calling
+// program's own included; leaving out its own sockets is the netstatw binary's
+// `--include-self` default and isn't applied here.
+pub fn snapshot(opts: &SnapshotOptions) -> Result<Snapshot, netstat2::error::Error> {
+    let mut system = System::new_all();
+    system.refresh_all();
+    let mut entries = collect_entries(&system, opts.top_n, opts.families, opts.protocols)?;
+    let pid_stats = if opts.stats {
+        sample_stats(&mut system, &mut entries, opts.sample_interval, false)
+    } else {
+        HashMap::new()
+    };
+    entries.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+    Ok(Snapshot { entries, pid_stats })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listener(proto: &str, local: &str, pid: u32) -> SocketEntry {
+        SocketEntry {
+            proto: proto.to_string(),
+            local_addr: local.to_string(),
+            remote_addr: "0.0.0.0:0".to_string(),
+            state: if proto == "TCP" { "Listen" } else { "-" }.to_string(),
+            pids: vec![pid],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn merge_dualstack_folds_matching_wildcard_pairs() {
+        let rows = vec![
+            listener("TCP", "0.0.0.0:80", 7),
+            listener("TCP", "[::]:80", 7),
+            listener("TCP", "[::]:22", 9),
+            listener("TCP", "0.0.0.0:443", 7),
+            listener("TCP", "[::]:443", 8),
+        ];
+        let merged: Vec<String> = merge_dualstack(&rows)
+            .into_iter()
+            .map(|e| e.local_addr)
+            .collect();
+        assert_eq!(
+            merged,
+            vec!["*:80 (v4+v6)", "[::]:22", "0.0.0.0:443", "[::]:443"]
+        );
+    }
+
+    #[test]
+    fn addresses_bracket_ipv6_and_parse_back() {
+        let v6 = addr_string("2001:db8::1".parse().unwrap(), 443);
+        assert_eq!(v6, "[2001:db8::1]:443");
+        assert_eq!(parse_addr_port(&v6), ("2001:db8::1", 443));
+        assert_eq!(join_addr("fe80::1%eth0", 22), "[fe80::1%eth0]:22");
+        assert_eq!(parse_addr_port("[fe80::1%eth0]:22"), ("fe80::1%eth0", 22));
+        assert_eq!(join_addr("db.internal", 5432), "db.internal:5432");
+        assert_eq!(parse_addr_port("10.0.0.1:80"), ("10.0.0.1", 80));
+        // Older recordings have unbracketed IPv6.
+        assert_eq!(parse_addr_port("::1:8080"), ("::1", 8080));
+        assert_eq!(parse_addr_port("*:*"), ("*:*", 0));
+    }
+
+    #[test]
+    fn cmdlines_quote_arguments_with_spaces() {
+        let args: Vec<String> = ["java", "-jar", "/opt/My App/app.jar", ""]
+            .iter()
+            .map(|a| a.to_string())
+            .collect();
+        assert_eq!(
+            join_cmdline(&args),
+            "java -jar \"/opt/My App/app.jar\" \"\""
+        );
+    }
+
+    #[test]
+    fn sort_key_orders_addresses_numerically() {
+        let mut rows: Vec<SocketEntry> = [
+            "[::1]:22",
+            "10.0.0.2:80",
+            "1.2.3.4:80",
+            "10.0.0.2:9",
+            "9.9.9.9:1",
+        ]
+        .iter()
+        .map(|local| listener("TCP", local, 1))
+        .collect();
+        rows.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+        let order: Vec<&str> = rows.iter().map(|e| e.local_addr.as_str()).collect();
+        assert_eq!(
+            order,
+            [
+                "1.2.3.4:80",
+                "9.9.9.9:1",
+                "10.0.0.2:9",
+                "10.0.0.2:80",
+                "[::1]:22"
+            ]
+        );
+    }
+}
@@ -0,0 +1,428 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::mem::size_of;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::time::{Duration, Instant};
+
+use crate::path::PathInfo;
+use crate::{ConnKey, TcpRates, forensic, procfd};
+
+// Linux only: per-connection TCP throughput from the kernel's socket diagnostics
+// (netlink NETLINK_SOCK_DIAG, the interface behind `ss -ti`). Each dump carries the
+// `tcp_info` of every TCP socket, whose `tcpi_bytes_received`/`tcpi_bytes_acked`
+// counters (kernel 4.1+) are the Rx/Tx counterparts of the EStats ThruBytes on
+// Windows; its MSS, cwnd and retransmission fields feed `--path-hints`. Reading them
+// needs no privileges and changes no state.
+
+const SOCK_DIAG_BY_FAMILY: u16 = 20;
+const SOCK_DESTROY: u16 = 21;
+const NLMSG_ERROR: u16 = 2;
+const NLMSG_DONE: u16 = 3;
+const INET_DIAG_INFO: u16 = 2;
+const TCP_LISTEN: u32 = 10;
+const NLMSG_HDR_LEN: usize = 16;
+// struct inet_diag_msg: family, state, timer, retrans, then inet_diag_sockid (ports,
+// two 16-byte addresses, interface, cookie), expires, rqueue, wqueue, uid, inode.
+const DIAG_MSG_LEN: usize = 72;
+// Offsets into struct tcp_info of the two u64 byte counters.
+const TCPI_BYTES_ACKED: usize = 120;
+const TCPI_BYTES_RECEIVED: usize = 128;
+// ... and of the path fields (u8 retransmits, u32 for the rest).
+const TCPI_RETRANSMITS: usize = 2;
+const TCPI_SND_MSS: usize = 16;
+const TCPI_UNACKED: usize = 24;
+const TCPI_LOST: usize = 32;
+const TCPI_PMTU: usize = 60;
+const TCPI_SND_CWND: usize = 80;
+const TCPI_TOTAL_RETRANS: usize = 100;
+const TCPI_SEGS_OUT: usize = 136;
+
+// One TCP socket from a sock_diag dump.
+#[derive(Clone, Debug, PartialEq)]
+struct DiagConn {
+    key: ConnKey,
+    inode: u32,
+    // (received, acked) bytes; None on kernels whose tcp_info predates the counters.
+    counters: Option<(u64, u64)>,
+    path: Option<PathInfo>,
+}
+
+fn align4(n: usize) -> usize {
+    (n + 3) & !3
+}
+
+fn u16_at(buf: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_ne_bytes(buf.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn u32_at(buf: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_ne_bytes(buf.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn u64_at(buf: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_ne_bytes(buf.get(at..at + 8)?.try_into().ok()?))
+}
+
+// nlmsghdr + inet_diag_req_v2 for TCP sockets of `family`: message `kind` with
+// netlink `flags`, the `ext` attributes requested, a state bitmask and the socket ID
+// (all zero for no filter).
+fn request(kind: u16, flags: i32, family: u8, ext: u8, states: u32, id: &[u8; 48]) -> Vec<u8> {
+    let len = NLMSG_HDR_LEN + 56;
+    let mut req = Vec::with_capacity(len);
+    req.extend_from_slice(&(len as u32).to_ne_bytes());
+    req.extend_from_slice(&kind.to_ne_bytes());
+    req.extend_from_slice(&(flags as u16).to_ne_bytes());
+    req.extend_from_slice(&[0; 8]); // sequence number, port ID
+    req.extend_from_slice(&[family, libc::IPPROTO_TCP as u8, ext, 0]);
+    req.extend_from_slice(&states.to_ne_bytes());
+    req.extend_from_slice(id);
+    req
+}
+
+// Every TCP socket of `family` with its tcp_info attached. Every state but LISTEN:
+// listeners show their processes' totals, as on Windows.
+fn dump_request(family: u8) -> Vec<u8> {
+    request(
+        SOCK_DIAG_BY_FAMILY,
+        libc::NLM_F_REQUEST | libc::NLM_F_DUMP,
+        family,
+        1 << (INET_DIAG_INFO - 1),
+        !(1 << TCP_LISTEN),
+        &[0; 48],
+    )
+}
+
+// SOCK_DESTROY for the connection `local` -> `remote`; None when the families differ.
+fn destroy_request(local: SocketAddr, remote: SocketAddr) -> Option<Vec<u8>> {
+    let (family, src, dst) = match (local.ip(), remote.ip()) {
+        (IpAddr::V4(l), IpAddr::V4(r)) => (libc::AF_INET, l.octets().to_vec(), r.octets().to_vec()),
+        (IpAddr::V6(l), IpAddr::V6(r)) => {
+            (libc::AF_INET6, l.octets().to_vec(), r.octets().to_vec())
+        }
+        _ => return None,
+    };
+    // inet_diag_sockid: ports (network order), addresses (IPv4 in the first four bytes),
+    // interface, and INET_DIAG_NOCOOKIE.
+    let mut id = [0u8; 48];
+    id[0..2].copy_from_slice(&local.port().to_be_bytes());
+    id[2..4].copy_from_slice(&remote.port().to_be_bytes());
+    id[4..4 + src.len()].copy_from_slice(&src);
+    id[20..20 + dst.len()].copy_from_slice(&dst);
+    id[40..48].fill(0xff);
+    Some(request(
+        SOCK_DESTROY,
+        libc::NLM_F_REQUEST | libc::NLM_F_ACK,
+        family as u8,
+        0,
+        u32::MAX,
+        &id,
+    ))
+}
+
+// "addr:port" as rendered in the address columns.
+fn render(family: u8, addr: &[u8], port: u16) -> Option<String> {
+    let ip = match family as i32 {
+        libc::AF_INET => IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(&addr[..4]).ok()?)),
+        libc::AF_INET6 => IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(addr).ok()?)),
+        _ => return None,
+    };
+    Some(crate::addr_string(ip, port))
+}
+
+fn path_fields(info: &[u8]) -> Option<PathInfo> {
+    Some(PathInfo {
+        mss: u32_at(info, TCPI_SND_MSS)?,
+        pmtu: u32_at(info, TCPI_PMTU)?,
+        cwnd: u32_at(info, TCPI_SND_CWND)?,
+        unacked: u32_at(info, TCPI_UNACKED)?,
+        lost: u32_at(info, TCPI_LOST)?,
+        retransmits: *info.get(TCPI_RETRANSMITS)?,
+        total_retrans: u32_at(info, TCPI_TOTAL_RETRANS)?,
+        segs_out: u32_at(info, TCPI_SEGS_OUT),
+    })
+}
+
+// One inet_diag_msg and its attributes.
+fn parse_diag(msg: &[u8]) -> Option<DiagConn> {
+    let family = *msg.first()?;
+    let sport = u16::from_be_bytes(msg.get(4..6)?.try_into().ok()?);
+    let dport = u16::from_be_bytes(msg.get(6..8)?.try_into().ok()?);
+    let local = render(family, msg.get(8..24)?, sport)?;
+    let remote = render(family, msg.get(24..40)?, dport)?;
+    let inode = u32_at(msg, 68)?;
+    let mut counters = None;
+    let mut path = None;
+    let mut at = DIAG_MSG_LEN;
+    while let (Some(len), Some(kind)) = (u16_at(msg, at), u16_at(msg, at + 2)) {
+        let len = len as usize;
+        if len < 4 || at + len > msg.len() {
+            break;
+        }
+        if kind == INET_DIAG_INFO {
+            let info = &msg[at + 4..at + len];
+            counters = u64_at(info, TCPI_BYTES_RECEIVED).zip(u64_at(info, TCPI_BYTES_ACKED));
+            path = path_fields(info);
+        }
+        at += align4(len);
+    }
+    Some(DiagConn {
+        key: (local, remote),
+        inode,
+        counters,
+        path,
+    })
+}
+
+// Appends the sockets in one recv buffer to `out`; true once the dump is complete (or
+// the request acknowledged).
+fn parse_messages(buf: &[u8], out: &mut Vec<DiagConn>) -> io::Result<bool> {
+    let mut at = 0;
+    while let (Some(len), Some(kind)) = (u32_at(buf, at), u16_at(buf, at + 4)) {
+        let len = len as usize;
+        if len < NLMSG_HDR_LEN || at + len > buf.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "truncated netlink message",
+            ));
+        }
+        let body = &buf[at + NLMSG_HDR_LEN..at + len];
+        match kind {
+            NLMSG_DONE => return Ok(true),
+            // An error code of 0 acknowledges a request sent with NLM_F_ACK.
+            NLMSG_ERROR => match u32_at(body, 0).map_or(0, |e| e as i32) {
+                0 => return Ok(true),
+                errno => return Err(io::Error::from_raw_os_error(-errno)),
+            },
+            SOCK_DIAG_BY_FAMILY => out.extend(parse_diag(body)),
+            _ => {}
+        }
+        at += align4(len);
+    }
+    Ok(false)
+}
+
+fn open() -> io::Result<OwnedFd> {
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+            libc::NETLINK_SOCK_DIAG,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+// Sends `req` to the kernel and reads the replies until the dump is complete or the
+// request acknowledged.
+fn exchange(req: &[u8]) -> io::Result<Vec<DiagConn>> {
+    let fd = open()?;
+    let mut kernel: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+    kernel.nl_family = libc::AF_NETLINK as u16;
+    let sent = unsafe {
+        libc::sendto(
+            fd.as_raw_fd(),
+            req.as_ptr().cast(),
+            req.len(),
+            0,
+            (&kernel as *const libc::sockaddr_nl).cast(),
+            size_of::<libc::sockaddr_nl>() as u32,
+        )
+    };
+    if sent < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut out = Vec::new();
+    let mut buf = vec![0u8; 32 * 1024];
+    loop {
+        let n = unsafe { libc::recv(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0) };
+        if n < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+        if n == 0 || parse_messages(&buf[..n as usize], &mut out)? {
+            return Ok(out);
+        }
+    }
+}
+
+// Every TCP socket of `family` (AF_INET or AF_INET6).
+fn dump(family: i32) -> io::Result<Vec<DiagConn>> {
+    forensic::record("netlink sock_diag (TCP tcp_info)");
+    exchange(&dump_request(family as u8))
+}
+
+// Closes the TCP connection `local` -> `remote` (SOCK_DESTROY). Needs CAP_NET_ADMIN
+// and a kernel built with CONFIG_INET_DIAG_DESTROY.
+pub fn destroy(local: SocketAddr, remote: SocketAddr) -> io::Result<()> {
+    let req = destroy_request(local, remote).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "addresses of different families",
+        )
+    })?;
+    forensic::record_change("netlink SOCK_DESTROY (close TCP connection)");
+    exchange(&req).map(drop)
+}
+
+fn tcp_connections(ipv4: bool, ipv6: bool) -> io::Result<Vec<DiagConn>> {
+    let mut conns = Vec::new();
+    if ipv4 {
+        conns.extend(dump(libc::AF_INET)?);
+    }
+    if ipv6 {
+        conns.extend(dump(libc::AF_INET6)?);
+    }
+    Ok(conns)
+}
+
+// Whether this kernel reports the byte counters: true when a dump works and carries
+// them (an empty table counts as supported).
+pub fn counters_available() -> io::Result<bool> {
+    let conns = tcp_connections(true, true)?;
+    Ok(conns.is_empty() || conns.iter().any(|c| c.counters.is_some()))
+}
+
+// tcp_info path fields of every TCP connection, by address pair.
+pub fn path_info() -> io::Result<HashMap<ConnKey, PathInfo>> {
+    Ok(tcp_connections(true, true)?
+        .into_iter()
+        .filter_map(|c| Some((c.key, c.path?)))
+        .collect())
+}
+
+// Cumulative (received, acked) bytes of every TCP connection, by address pair.
+pub fn byte_counters() -> io::Result<HashMap<ConnKey, (u64, u64)>> {
+    Ok(tcp_connections(true, true)?
+        .into_iter()
+        .filter_map(|c| Some((c.key, c.counters?)))
+        .collect())
+}
+
+// Same strategy as the Windows EStats sampler: counters of every connection at T0 and
+// T1, deltas over the elapsed time, and only sockets present at both reads (matched
+// by inode, so a reused 4-tuple isn't mistaken for the old connection). A process's
+// rate is the sum over the sockets it holds among `pids`.
+pub fn sample_tcp(
+    interval: Duration,
+    pids: &HashSet<u32>,
+    ipv4: bool,
+    ipv6: bool,
+) -> io::Result<TcpRates> {
+    let counted = |conns: Vec<DiagConn>| -> HashMap<u32, (ConnKey, u64, u64)> {
+        conns
+            .into_iter()
+            .filter(|c| c.inode != 0)
+            .filter_map(|c| c.counters.map(|(rx, tx)| (c.inode, (c.key, rx, tx))))
+            .collect()
+    };
+    let base = counted(tcp_connections(ipv4, ipv6)?);
+    let started = Instant::now();
+    crate::interrupt::sleep(if interval.is_zero() {
+        Duration::from_millis(1)
+    } else {
+        interval
+    });
+    let now = counted(tcp_connections(ipv4, ipv6)?);
+    let secs = started.elapsed().as_secs_f64().max(0.001);
+
+    let holders = procfd::socket_fds(pids);
+    let mut rates = TcpRates::default();
+    for (inode, (key, b_rx, b_tx)) in base {
+        let Some((n_key, n_rx, n_tx)) = now.get(&inode) else {
+            continue;
+        };
+        if *n_key != key {
+            continue;
+        }
+        let rx = n_rx.saturating_sub(b_rx) as f64 / secs;
+        let tx = n_tx.saturating_sub(b_tx) as f64 / secs;
+        let mut owners: Vec<u32> = holders
+            .get(&inode)
+            .map(|fds| fds.iter().map(|&(pid, _)| pid).collect())
+            .unwrap_or_default();
+        owners.sort_unstable();
+        owners.dedup();
+        for pid in owners {
+            let sum = rates.per_pid.entry(pid).or_insert((0.0, 0.0));
+            sum.0 += rx;
+            sum.1 += tx;
+        }
+        rates.per_conn.insert(key, (rx, tx));
+    }
+    Ok(rates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An inet_diag_msg for 127.0.0.1:5432 -> 127.0.0.1:40000 with a tcp_info attribute.
+    fn diag_message(received: u64, acked: u64) -> Vec<u8> {
+        let mut msg = vec![0u8; DIAG_MSG_LEN];
+        msg[0] = libc::AF_INET as u8;
+        msg[1] = 1; // TCP_ESTABLISHED
+        msg[4..6].copy_from_slice(&5432u16.to_be_bytes());
+        msg[6..8].copy_from_slice(&40000u16.to_be_bytes());
+        msg[8..12].copy_from_slice(&[127, 0, 0, 1]);
+        msg[24..28].copy_from_slice(&[127, 0, 0, 1]);
+        msg[68..72].copy_from_slice(&4242u32.to_ne_bytes());
+        let mut info = vec![0u8; 232];
+        info[TCPI_BYTES_ACKED..TCPI_BYTES_ACKED + 8].copy_from_slice(&acked.to_ne_bytes());
+        info[TCPI_BYTES_RECEIVED..TCPI_BYTES_RECEIVED + 8].copy_from_slice(&received.to_ne_bytes());
+        msg.extend_from_slice(&((4 + info.len()) as u16).to_ne_bytes());
+        msg.extend_from_slice(&INET_DIAG_INFO.to_ne_bytes());
+        msg.extend_from_slice(&info);
+        msg
+    }
+
+    fn netlink(kind: u16, body: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&((NLMSG_HDR_LEN + body.len()) as u32).to_ne_bytes());
+        out.extend_from_slice(&kind.to_ne_bytes());
+        out.extend_from_slice(&[0; 10]);
+        out.extend_from_slice(body);
+        out.resize(align4(out.len()), 0);
+        out
+    }
+
+    #[test]
+    fn parse_messages_reads_sockets_until_done() {
+        let mut buf = netlink(SOCK_DIAG_BY_FAMILY, &diag_message(1000, 250));
+        let mut out = Vec::new();
+        assert!(!parse_messages(&buf, &mut out).unwrap());
+        buf.extend(netlink(NLMSG_DONE, &[0; 4]));
+        out.clear();
+        assert!(parse_messages(&buf, &mut out).unwrap());
+        assert_eq!(
+            out,
+            [DiagConn {
+                key: ("127.0.0.1:5432".to_string(), "127.0.0.1:40000".to_string()),
+                inode: 4242,
+                counters: Some((1000, 250)),
+                path: Some(PathInfo {
+                    segs_out: Some(0),
+                    ..Default::default()
+                }),
+            }]
+        );
+
+        // A tcp_info from before the byte counters: the socket is listed without them.
+        let mut old = diag_message(1, 1);
+        old.truncate(DIAG_MSG_LEN + 4 + 104);
+        old[DIAG_MSG_LEN..DIAG_MSG_LEN + 2].copy_from_slice(&108u16.to_ne_bytes());
+        out.clear();
+        parse_messages(&netlink(SOCK_DIAG_BY_FAMILY, &old), &mut out).unwrap();
+        assert_eq!(out[0].counters, None);
+        assert_eq!(out[0].path.as_ref().unwrap().segs_out, None);
+
+        let err = netlink(NLMSG_ERROR, &(-libc::EPERM).to_ne_bytes());
+        assert!(parse_messages(&err, &mut out).is_err());
+    }
+}
@@ -1,428 +1,1455 @@
-extern crate netstat2;
-
-use netstat2::*;
+use netstatw::filter::Criterion;
+use netstatw::{
+    AddressFamilyFlags, ProcessStats, ProtocolFlags, SocketEntry, accept, addr_sort_key, alert,
+    assert, block, cache, cap, caps, changes, cli, collect_entries, color, compact, compare,
+    config, diff, events, exec, filter, forensic, forward, geoip, graph, hints,
+    human_readable_rate, idle, idn, interrupt, inventory, join_addr, json, kill, own, pager,
+    parse_addr_port, path, policy, probe, progress, record, report, resolve, sample_stats, seen,
+    serve, services, show_cmdlines, synth, tags, tui, warn,
+};
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
 use std::env;
-use std::thread;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
-use sysinfo::{Pid, System};
-#[cfg(windows)]
-mod win_net;
-
-
-#[derive(Clone)]
-struct SocketEntry {
-    proto: String,
-    local_addr: String,
-    remote_addr: String,
-    state: String,
-    process_info: String,
-    pids: Vec<u32>,
-    agg_stats: Option<ProcessStats>,
-}
+use sysinfo::System;
 
-fn get_process_info(system: &System, pid: u32) -> String {
-    system
-        .process(Pid::from(pid as usize))
-        .map(|process| {
-            let full_path = process.exe().unwrap_or_else(|| process.name().as_ref());
-            format!("{}: {}", pid, full_path.display())
-        })
-        .unwrap_or_else(|| format!("{}: Unknown", pid))
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SortKeyKind {
+    Cpu,
+    R,
+    W,
+    Rx,
+    Tx,
+    State,
+    Proto,
+    Laddr,
+    Raddr,
+    Lport,
+    Rport,
+    Pid,
+    Process,
 }
 
-fn state_sort_order(state: &str) -> u8 {
-    // Reverse order - higher priority states get lower numbers for reverse sorting
-    match state {
-        "TimeWait" => 2,
-        "LastAck" => 3,
-        "Closing" => 4,
-        "CloseWait" => 5,
-        "FinWait2" => 6,
-        "FinWait1" => 7,
-        "SynReceived" => 8,
-        "SynSent" => 9,
-        "Established" => 10,
-        "Listen" => 11,
-        "-" => 1, // For UDP
-        _ => 0,   // Unknown states
+// `--sort` keys in the order of `cli::SORT_KEYS`.
+const SORT_KEY_KINDS: [SortKeyKind; cli::SORT_KEYS.len()] = [
+    SortKeyKind::Cpu,
+    SortKeyKind::R,
+    SortKeyKind::W,
+    SortKeyKind::Rx,
+    SortKeyKind::Tx,
+    SortKeyKind::State,
+    SortKeyKind::Proto,
+    SortKeyKind::Laddr,
+    SortKeyKind::Raddr,
+    SortKeyKind::Lport,
+    SortKeyKind::Rport,
+    SortKeyKind::Pid,
+    SortKeyKind::Process,
+];
+
+impl SortKeyKind {
+    fn is_metric(self) -> bool {
+        matches!(
+            self,
+            SortKeyKind::Cpu | SortKeyKind::R | SortKeyKind::W | SortKeyKind::Rx | SortKeyKind::Tx
+        )
     }
-}
 
-fn parse_addr_port(addr: &str) -> (&str, u16) {
-    if let Some(last_colon) = addr.rfind(':') {
-        let ip = &addr[..last_colon];
-        let port_str = &addr[last_colon + 1..];
-        if let Ok(port) = port_str.parse::<u16>() {
-            return (ip, port);
+    // The metric of a row (NaN when not sampled).
+    fn metric(self, e: &SocketEntry) -> f64 {
+        match (self, &e.agg_stats) {
+            (SortKeyKind::Cpu, Some(s)) => s.cpu_pct as f64,
+            (SortKeyKind::R, Some(s)) => s.read_rate_bps,
+            (SortKeyKind::W, Some(s)) => s.write_rate_bps,
+            (SortKeyKind::Rx, Some(s)) => s.net_rx_rate_bps,
+            (SortKeyKind::Tx, Some(s)) => s.net_tx_rate_bps,
+            _ => f64::NAN,
         }
     }
-    (addr, 0) // fallback
-}
 
-impl SocketEntry {
-    fn sort_key(&self) -> (u8, &str, &str, u16) {
-        let (ip, port) = parse_addr_port(&self.local_addr);
-        (state_sort_order(&self.state), &self.proto, ip, port)
+    // Ascending order of two rows by this key.
+    fn compare(self, a: &SocketEntry, b: &SocketEntry) -> std::cmp::Ordering {
+        let port = |addr: &str| parse_addr_port(addr).1;
+        match self {
+            SortKeyKind::State => (a.sort_key().0, &a.state).cmp(&(b.sort_key().0, &b.state)),
+            SortKeyKind::Proto => a.proto.cmp(&b.proto),
+            SortKeyKind::Laddr => addr_sort_key(&a.local_addr).cmp(&addr_sort_key(&b.local_addr)),
+            SortKeyKind::Raddr => addr_sort_key(&a.remote_addr).cmp(&addr_sort_key(&b.remote_addr)),
+            SortKeyKind::Lport => port(&a.local_addr).cmp(&port(&b.local_addr)),
+            SortKeyKind::Rport => port(&a.remote_addr).cmp(&port(&b.remote_addr)),
+            // Rows without an owner last.
+            SortKeyKind::Pid => {
+                (a.pids.is_empty(), a.pids.first()).cmp(&(b.pids.is_empty(), b.pids.first()))
+            }
+            SortKeyKind::Process => Column::Exe
+                .cell(a)
+                .to_ascii_lowercase()
+                .cmp(&Column::Exe.cell(b).to_ascii_lowercase()),
+            _ => self.metric(a).total_cmp(&self.metric(b)),
+        }
     }
 }
 
-#[derive(Clone, Default)]
-struct ProcessStats {
-    cpu_pct: f32,
-    read_rate_bps: f64,
-    write_rate_bps: f64,
-    net_rx_rate_bps: f64,
-    net_tx_rate_bps: f64,
-    total_read_bytes: u64,
-    total_written_bytes: u64,
+// A `--sort` key: `-KEY` sorts descending, `+KEY` ascending; without a sign metrics
+// sort descending and the rest ascending.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct SortKey {
+    kind: SortKeyKind,
+    descending: bool,
 }
 
-fn human_readable_rate(bps: f64) -> String {
-    if !bps.is_finite() || bps < 0.0 {
-        return "N/A".to_string();
-    }
-    const UNITS: [&str; 5] = ["B/s", "KB/s", "MB/s", "GB/s", "TB/s"];
-    let mut v = bps;
-    let mut idx = 0usize;
-    while v >= 1024.0 && idx < UNITS.len() - 1 {
-        v /= 1024.0;
-        idx += 1;
-    }
-    if idx == 0 {
-        format!("{:.0} {}", v, UNITS[idx])
-    } else {
-        format!("{:.1} {}", v, UNITS[idx])
-    }
+// "-cpu,+lport,state" -> the keys in order.
+fn parse_sort_keys(v: &str) -> Result<Vec<SortKey>, String> {
+    v.split(',')
+        .map(|part| {
+            let part = part.trim();
+            let (sign, name) = match part.strip_prefix('-') {
+                Some(name) => (Some(true), name),
+                None => (
+                    part.strip_prefix('+').map(|_| false),
+                    part.trim_start_matches('+'),
+                ),
+            };
+            let kind = cli::SORT_KEYS
+                .iter()
+                .position(|n| n.eq_ignore_ascii_case(name))
+                .map(|i| SORT_KEY_KINDS[i])
+                .ok_or_else(|| {
+                    format!(
+                        "unknown key '{}' (expected {})",
+                        name,
+                        cli::SORT_KEYS.join(", ")
+                    )
+                })?;
+            Ok(SortKey {
+                kind,
+                descending: sign.unwrap_or(kind.is_metric()),
+            })
+        })
+        .collect()
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-enum SortKeyKind { Cpu, R, W, Rx, Tx }
+struct Options {
+    show_stats: bool,
+    sample_interval_ms: u64,
+    top_n: Option<usize>,
+    sort_keys: Vec<SortKey>,
+    exec: Option<exec::ExecOptions>,
+    seen_db: Option<PathBuf>,
+    port_report: bool,
+    hide_synth: bool,
+    forensic: bool,
+    json_warnings: bool,
+    show_ids: bool,
+    columns: Option<Vec<Column>>,
+    filter: filter::Filter,
+    ids_from_stdin: bool,
+    by_user: bool,
+    group_by: Option<report::GroupBy>,
+    // `--compare-rates FILE`, and the baseline's rates once loaded.
+    compare_rates_path: Option<PathBuf>,
+    compare_rates: Option<compare::Rates>,
+    summary: bool,
+    overview: bool,
+    env_hints: bool,
+    output: Option<PathBuf>,
+    format: String,
+    record: Option<PathBuf>,
+    replay: Option<PathBuf>,
+    watch: Option<Duration>,
+    // Mark opened and closed connections between --watch refreshes of the table.
+    changes: bool,
+    // `--max-age`: reuse a cached enumeration this young.
+    max_age: Option<Duration>,
+    alerts: alert::Thresholds,
+    // --alert-every: report each alert key at most once per window.
+    alert_every: Option<Duration>,
+    data_cap: Option<cap::Watchdog>,
+    // `--idle-after N`: refreshes without traffic before a connection is IDLE; None
+    // when idle connections aren't tracked.
+    idle_after: Option<u32>,
+    idle_only: Option<idle::Only>,
+    // Set when the table is colored.
+    palette: Option<color::Palette>,
+    json: bool,
+    oneshot_json: bool,
+    cmdline: bool,
+    include_self: bool,
+    correlate: bool,
+    tui: bool,
+    capabilities: bool,
+    merge_dualstack: bool,
+    show_inode: bool,
+    show_scope: bool,
+    show_fd: bool,
+    families: AddressFamilyFlags,
+    protocols: ProtocolFlags,
+    timeout: Option<Duration>,
+    resolve: bool,
+    // `--no-idn`: show resolved names in their raw xn-- form.
+    no_idn: bool,
+    names: bool,
+    probe_rtt: bool,
+    path_hints: bool,
+    csv: bool,
+    delimiter: char,
+    tag_rules_path: Option<PathBuf>,
+    geoip_paths: Vec<PathBuf>,
+    geoip: geoip::Lookup,
+    tag_rules: tags::Rules,
+    // `--host-policy [FILE]`: Some(None) for the config file's rules.
+    host_policy_path: Option<Option<PathBuf>>,
+    host_policy: policy::Policy,
+}
 
-fn parse_args() -> (bool, u64, Option<usize>, Vec<SortKeyKind>) {
-    // Returns (show_stats, sample_interval_ms, top_n, sort_keys)
+fn parse_args(argv: Vec<String>) -> Result<Options, String> {
     let mut show_stats = false;
     let mut sample_interval_ms: u64 = 800;
     let mut top_n: Option<usize> = None;
-    let mut sort_keys: Vec<SortKeyKind> = Vec::new();
+    let mut sort_keys: Vec<SortKey> = Vec::new();
+    let mut exec_template: Option<String> = None;
+    let mut exec_max: usize = 4;
+    let mut exec_throttle_ms: u64 = 200;
+    let mut seen_db: Option<PathBuf> = None;
+    let mut port_report = false;
+    let mut hide_synth = false;
+    let mut forensic = false;
+    let mut json_warnings = false;
+    let mut show_ids = false;
+    let mut filter = filter::Filter::default();
+    // Set by `--not`; applies to the filter option that immediately follows.
+    let mut negate = false;
+    let mut ids_from_stdin = false;
+    let mut by_user = false;
+    let mut group_by: Option<report::GroupBy> = None;
+    let mut compare_rates_path: Option<PathBuf> = None;
+    let mut summary = false;
+    let mut columns: Option<Vec<Column>> = None;
+    let mut color_when = color::When::Auto;
+    let mut theme: Option<color::Theme> = None;
+    let mut palette = color::Palette {
+        theme: color::Theme::Dark,
+        hot_cpu: 50.0,
+        hot_rate: (1 << 20) as f64,
+    };
+    let mut overview = false;
+    let mut env_hints = false;
+    let mut output: Option<PathBuf> = None;
+    let mut format = "json".to_string();
+    let mut record: Option<PathBuf> = None;
+    let mut replay: Option<PathBuf> = None;
+    let mut watch: Option<Duration> = None;
+    let mut no_changes = false;
+    let mut alerts = alert::Thresholds::default();
+    let mut alert_every: Option<Duration> = None;
+    let mut data_cap: Option<(u64, Duration)> = None;
+    let mut cap_action = cap::Action::Alert;
+    let mut idle_after: Option<u32> = None;
+    let mut idle_only: Option<idle::Only> = None;
+    let mut dry_run = false;
+    let mut audit_log: Option<PathBuf> = None;
+    let mut json = false;
+    let mut oneshot_json = false;
+    let mut max_age: Option<Duration> = None;
+    let mut cmdline = false;
+    let mut include_self = false;
+    let mut correlate = false;
+    let mut tui = false;
+    let mut capabilities = false;
+    let mut merge_dualstack = false;
+    let mut show_inode = false;
+    let mut show_scope = false;
+    let mut show_fd = false;
+    let mut families = AddressFamilyFlags::empty();
+    let mut protocols = ProtocolFlags::empty();
+    let mut timeout: Option<Duration> = None;
+    let mut resolve = false;
+    let mut no_idn = false;
+    let mut probe_rtt = false;
+    let mut path_hints = false;
+    let mut names = false;
+    let mut csv = false;
+    let mut delimiter = ',';
+    let mut tag_rules_path: Option<PathBuf> = None;
+    let mut geoip_paths: Vec<PathBuf> = Vec::new();
+    let mut country_filter = false;
+    let mut host_policy_path: Option<Option<PathBuf>> = None;
+    // Loaded on first use, for `--port` templates.
+    let mut port_config: Option<config::Config> = None;
 
-    let mut args = env::args().skip(1).peekable();
+    let mut args = cli::Args::new(&cli::LIST, argv)?;
     while let Some(arg) = args.next() {
+        let negate_this = std::mem::take(&mut negate);
         match arg.as_str() {
-            "--full" => show_stats = true,
-            "--sample-interval" => {
-                if let Some(v) = args.next() && let Ok(ms) = v.parse::<u64>() {
-                    sample_interval_ms = ms.max(1);
+            "--full" | "-f" => show_stats = true,
+            "--sample-interval" | "-i" => sample_interval_ms = args.parse::<u64>(&arg)?.max(1),
+            "--top" | "-t" => top_n = Some(args.parse(&arg)?),
+            "--exec" | "-e" => exec_template = Some(args.value(&arg)?),
+            "--exec-max" => exec_max = args.parse::<usize>(&arg)?.max(1),
+            "--exec-throttle" => exec_throttle_ms = args.parse(&arg)?,
+            "--seen-db" => seen_db = Some(PathBuf::from(args.value(&arg)?)),
+            "--port-report" => port_report = true,
+            "--by-user" => by_user = true,
+            "--summary" => summary = true,
+            "--group-by" => {
+                let v = args.value(&arg)?;
+                group_by = Some(report::GroupBy::parse(&v).ok_or_else(|| {
+                    format!("--group-by: unknown grouping '{}' (expected process, remote-host, remote-port, state, tag)", v)
+                })?);
+            }
+            "--compare-rates" => compare_rates_path = Some(PathBuf::from(args.value(&arg)?)),
+            "--overview" => overview = true,
+            "--env-hints" => env_hints = true,
+            "--output" | "-o" => output = Some(PathBuf::from(args.value(&arg)?)),
+            "--record" => record = Some(PathBuf::from(args.value(&arg)?)),
+            "--replay" => replay = Some(PathBuf::from(args.value(&arg)?)),
+            // The interval is optional: `-w` alone refreshes every 2 seconds.
+            "--watch" | "-w" => {
+                let secs = match args.optional() {
+                    None => 2.0,
+                    Some(v) => match v.parse::<f64>() {
+                        Ok(secs) if secs > 0.0 && secs.is_finite() => secs,
+                        _ => {
+                            return Err(format!(
+                                "{} expects a positive number of seconds, got '{}'",
+                                arg, v
+                            ));
+                        }
+                    },
+                };
+                watch = Some(
+                    Duration::try_from_secs_f64(secs.max(0.1))
+                        .map_err(|e| format!("{}: {}", arg, e))?,
+                );
+            }
+            "--no-changes" => no_changes = true,
+            "--alert-conn-change" => alerts.established = Some(args.parse::<usize>(&arg)?.max(1)),
+            "--alert-listen-change" => alerts.listen = Some(args.parse::<usize>(&arg)?.max(1)),
+            "--alert-every" => {
+                alert_every = Some(
+                    cap::parse_duration(&args.value(&arg)?)
+                        .map_err(|e| format!("{}: {}", arg, e))?,
+                )
+            }
+            "--data-cap" => {
+                data_cap = Some(
+                    cap::parse_cap(&args.value(&arg)?).map_err(|e| format!("{}: {}", arg, e))?,
+                )
+            }
+            "--cap-action" => {
+                let v = args.value(&arg)?;
+                cap_action = cap::Action::parse(&v).ok_or_else(|| {
+                    format!(
+                        "{}: unknown action '{}' (expected alert, kill, block)",
+                        arg, v
+                    )
+                })?;
+            }
+            "--idle-after" => idle_after = Some(args.parse::<u32>(&arg)?.max(1)),
+            "--idle-only" | "--active-only" => {
+                let only = match arg.as_str() {
+                    "--idle-only" => idle::Only::Idle,
+                    _ => idle::Only::Active,
+                };
+                if idle_only.is_some_and(|o| o != only) {
+                    return Err("--idle-only and --active-only exclude each other".to_string());
                 }
+                idle_only = Some(only);
+            }
+            "--dry-run" => dry_run = true,
+            "--audit-log" => audit_log = Some(PathBuf::from(args.value(&arg)?)),
+            "--json" => json = true,
+            "--oneshot-json" => oneshot_json = true,
+            "--max-age" => {
+                max_age = Some(
+                    cap::parse_duration(&args.value(&arg)?)
+                        .map_err(|e| format!("{}: {}", arg, e))?,
+                )
             }
-            "--top" => {
-                if let Some(v) = args.next() && let Ok(n) = v.parse::<usize>() {
-                    top_n = Some(n);
+            "--cmdline" => cmdline = true,
+            "--include-self" => include_self = true,
+            "--csv" => csv = true,
+            "--delimiter" => {
+                let v = args.value(&arg)?;
+                delimiter = parse_delimiter(&v).ok_or_else(|| {
+                    format!(
+                        "--delimiter expects a single character (or 'tab'), got '{}'",
+                        v
+                    )
+                })?;
+            }
+            "--capabilities" => capabilities = true,
+            "--merge-dualstack" => merge_dualstack = true,
+            "--inode" => show_inode = true,
+            "--scope" => show_scope = true,
+            "--fd" => show_fd = true,
+            "--correlate" => correlate = true,
+            "--tui" => tui = true,
+            "--format" => format = args.value(&arg)?.to_ascii_lowercase(),
+            "--hide-synth" => hide_synth = true,
+            "--forensic" => forensic = true,
+            "--json-warnings" => json_warnings = true,
+            "--ids" => show_ids = true,
+            "--color" => {
+                let v = args.value(&arg)?;
+                color_when = color::When::parse(&v).ok_or_else(|| {
+                    format!("{}: expected auto, always or never, got '{}'", arg, v)
+                })?;
+            }
+            "--theme" => {
+                let v = args.value(&arg)?;
+                theme = Some(color::Theme::parse(&v).ok_or_else(|| {
+                    format!(
+                        "{}: expected one of {}, got '{}'",
+                        arg,
+                        color::THEMES.join(", "),
+                        v
+                    )
+                })?);
+            }
+            "--color-cpu" => palette.hot_cpu = args.parse::<f64>(&arg)?,
+            "--color-rate" => {
+                palette.hot_rate = cap::parse_size(args.value(&arg)?.trim_end_matches("/s"))
+                    .map_err(|e| format!("{}: {}", arg, e))?
+                    as f64;
+            }
+            "--columns" => {
+                columns =
+                    Some(parse_columns(&args.value(&arg)?).map_err(|e| format!("{}: {}", arg, e))?)
+            }
+            "--stdin" => ids_from_stdin = true,
+            "--not" => negate = true,
+            "--id" => filter.add(
+                Criterion::Id(args.value(&arg)?.to_ascii_lowercase()),
+                negate_this,
+            ),
+            "--process" => filter.add(Criterion::Process(args.value(&arg)?), negate_this),
+            "--tag" => filter.add(Criterion::Tag(args.value(&arg)?), negate_this),
+            "--user" => filter.add(Criterion::User(args.value(&arg)?), negate_this),
+            "--zone" => filter.add(Criterion::Zone(args.value(&arg)?), negate_this),
+            "--tag-rules" => tag_rules_path = Some(PathBuf::from(args.value(&arg)?)),
+            "--geoip" => geoip_paths.push(PathBuf::from(args.value(&arg)?)),
+            "--country" => {
+                for code in args.value(&arg)?.split(',') {
+                    filter.add(Criterion::Country(code.trim().to_string()), negate_this);
                 }
+                country_filter = true;
             }
-            "-f" => show_stats = true,
-            "--sort" | "-s" => {
-                if let Some(v) = args.next() {
-                    let key = v.to_ascii_lowercase();
-                    match key.as_str() {
-                        "cpu" => sort_keys.push(SortKeyKind::Cpu),
-                        "r" => sort_keys.push(SortKeyKind::R),
-                        "w" => sort_keys.push(SortKeyKind::W),
-                        "rx" => sort_keys.push(SortKeyKind::Rx),
-                        "tx" => sort_keys.push(SortKeyKind::Tx),
-                        _ => {}
+            "--host-policy" => {
+                host_policy_path = Some(args.optional().map(PathBuf::from));
+                resolve = true;
+            }
+            "--port" | "--lport" | "--rport" => {
+                let v = args.value(&arg)?;
+                // The config file is read on the first template name.
+                let mut config_error = None;
+                let template = |name: &str| {
+                    if port_config.is_none() {
+                        match config::Config::load() {
+                            Ok(cfg) => port_config = Some(cfg),
+                            Err(e) => {
+                                config_error = Some(e);
+                                return None;
+                            }
+                        }
                     }
+                    port_config.as_ref()?.port_template(name)
+                };
+                let ports = filter::parse_ports(&v, template);
+                if let Some(e) = config_error {
+                    return Err(format!("config: {}", e));
                 }
-            }
-            "-i" => {
-                if let Some(v) = args.next() && let Ok(ms) = v.parse::<u64>() {
-                    sample_interval_ms = ms.max(1);
+                let ports = ports.map_err(|e| format!("{}: {}", arg, e))?;
+                for port in ports {
+                    let criterion = match arg.as_str() {
+                        "--lport" => Criterion::LocalPort(port),
+                        "--rport" => Criterion::RemotePort(port),
+                        _ => Criterion::Port(port),
+                    };
+                    filter.add(criterion, negate_this);
                 }
             }
-            "-t" => {
-                if let Some(v) = args.next() && let Ok(n) = v.parse::<usize>() {
-                    top_n = Some(n);
+            "--local-net" | "--remote-net" => {
+                for net in
+                    filter::parse_nets(&args.value(&arg)?).map_err(|e| format!("{}: {}", arg, e))?
+                {
+                    let criterion = match arg.as_str() {
+                        "--local-net" => Criterion::LocalNet(net),
+                        _ => Criterion::RemoteNet(net),
+                    };
+                    filter.add(criterion, negate_this);
                 }
             }
-            _ => {}
-        }
-
-        // Support attached short options like -i500 or -t3 or -scpu/-sRx
-        if arg.starts_with('-') && !arg.starts_with("--") && arg.len() > 2 {
-            let flag = &arg[1..2];
-            let rest = &arg[2..];
-            match flag {
-                "i" => {
-                    if let Ok(ms) = rest.parse::<u64>() { sample_interval_ms = ms.max(1); }
-                }
-                "t" => {
-                    if let Ok(n) = rest.parse::<usize>() { top_n = Some(n); }
+            "-4" => families |= AddressFamilyFlags::IPV4,
+            "-6" => families |= AddressFamilyFlags::IPV6,
+            "--timeout" => {
+                let v = args.value(&arg)?;
+                match v.parse::<f64>() {
+                    Ok(secs) if secs > 0.0 => {
+                        timeout = Some(
+                            Duration::try_from_secs_f64(secs)
+                                .map_err(|e| format!("--timeout: {}", e))?,
+                        )
+                    }
+                    _ => {
+                        return Err(format!(
+                            "--timeout expects a positive number of seconds, got '{}'",
+                            v
+                        ));
+                    }
                 }
-                "s" => {
-                    let key = rest.to_ascii_lowercase();
-                    match key.as_str() {
-                        "cpu" => sort_keys.push(SortKeyKind::Cpu),
-                        "r" => sort_keys.push(SortKeyKind::R),
-                        "w" => sort_keys.push(SortKeyKind::W),
-                        "rx" => sort_keys.push(SortKeyKind::Rx),
-                        "tx" => sort_keys.push(SortKeyKind::Tx),
-                        _ => {}
+            }
+            "--resolve" => resolve = true,
+            "--no-idn" => no_idn = true,
+            "--probe-rtt" => probe_rtt = true,
+            "--path-hints" => path_hints = true,
+            "--names" => names = true,
+            "--tcp" => protocols |= ProtocolFlags::TCP,
+            "--udp" => protocols |= ProtocolFlags::UDP,
+            "-p" | "--proto" => {
+                for p in args.value(&arg)?.split(',') {
+                    match p.trim().to_ascii_lowercase().as_str() {
+                        "tcp" => protocols |= ProtocolFlags::TCP,
+                        "udp" => protocols |= ProtocolFlags::UDP,
+                        other => {
+                            return Err(format!(
+                                "{}: unknown protocol '{}' (expected tcp, udp)",
+                                arg, other
+                            ));
+                        }
                     }
                 }
-                _ => {}
             }
+            "-l" | "--listening" => filter.add(Criterion::Listening, negate_this),
+            // An exclusion itself, so `--not --no-loopback` keeps only loopback traffic.
+            "--no-loopback" => filter.add(Criterion::Loopback, !negate_this),
+            "--external-only" => filter.add(Criterion::External, negate_this),
+            "--state" => {
+                for state in filter::parse_states(&args.value(&arg)?)
+                    .map_err(|e| format!("--state: {}", e))?
+                {
+                    filter.add(state, negate_this);
+                }
+            }
+            "--sort" | "-s" => {
+                let v = args.value(&arg)?;
+                sort_keys.extend(parse_sort_keys(&v).map_err(|e| format!("{}: {}", arg, e))?);
+            }
+            other if other.starts_with('-') => return Err(format!("unknown option '{}'", other)),
+            other => return Err(format!("unexpected argument '{}'", other)),
         }
     }
-    // If sorting by metrics is requested, ensure stats are computed.
-    if !sort_keys.is_empty() {
+    if (dry_run || audit_log.is_some()) && (data_cap.is_none() || cap_action == cap::Action::Alert)
+    {
+        return Err("--dry-run/--audit-log apply to --cap-action kill or block".to_string());
+    }
+    if (idle_after.is_some() || idle_only.is_some()) && watch.is_none() {
+        return Err("--idle-after/--idle-only/--active-only need --watch".to_string());
+    }
+    if country_filter && geoip_paths.is_empty() {
+        return Err("--country needs a country database (--geoip FILE)".to_string());
+    }
+    if max_age.is_some() && (replay.is_some() || tui) {
+        return Err(
+            "--max-age caches live enumerations; it can't be used with --replay or --tui"
+                .to_string(),
+        );
+    }
+    if oneshot_json && (watch.is_some() || tui) {
+        return Err(
+            "--oneshot-json prints a single snapshot; it can't be used with --watch or --tui"
+                .to_string(),
+        );
+    }
+    if compare_rates_path.is_some() && tui {
+        return Err("--compare-rates prints a report; it can't be used with --tui".to_string());
+    }
+    let comparing = compare_rates_path.is_some();
+    // If sorting by metrics (or the overview, correlation or a rate comparison) is
+    // requested, ensure stats are computed.
+    let stat_column = |c: &Column| {
+        matches!(
+            c,
+            Column::Cpu | Column::R | Column::W | Column::Rx | Column::Tx | Column::Corr
+        )
+    };
+    if sort_keys.iter().any(|k| k.kind.is_metric())
+        || overview
+        || correlate
+        || comparing
+        || columns.iter().flatten().any(stat_column)
+    {
         show_stats = true;
     }
-    (show_stats, sample_interval_ms, top_n, sort_keys)
+    // An IDLE column without --watch has nothing to compare with and stays blank.
+    let idle_after = watch
+        .filter(|_| {
+            idle_after.is_some()
+                || idle_only.is_some()
+                || columns.iter().flatten().any(|c| *c == Column::Idle)
+        })
+        .map(|_| idle_after.unwrap_or(3));
+    let colored = color::enabled(color_when);
+    if colored {
+        palette.theme = theme.unwrap_or_else(|| config_theme(port_config.as_ref()));
+    }
+    Ok(Options {
+        show_stats,
+        sample_interval_ms,
+        top_n,
+        sort_keys,
+        exec: exec_template.map(|template| {
+            exec::ExecOptions::new(template, exec_max, Duration::from_millis(exec_throttle_ms))
+        }),
+        seen_db,
+        port_report,
+        hide_synth,
+        forensic,
+        json_warnings,
+        show_ids,
+        columns,
+        palette: colored.then_some(palette),
+        filter,
+        ids_from_stdin,
+        by_user,
+        group_by,
+        compare_rates_path,
+        compare_rates: None,
+        summary,
+        overview,
+        env_hints,
+        output,
+        format,
+        record,
+        replay,
+        watch,
+        // The marks are a table feature; reports and machine output go without.
+        changes: watch.is_some()
+            && !no_changes
+            && !json
+            && !csv
+            && !port_report
+            && !overview
+            && !by_user
+            && group_by.is_none()
+            && !comparing,
+        alerts,
+        alert_every,
+        data_cap: data_cap.map(|(bytes, window)| {
+            let mut dog = cap::Watchdog::new(bytes, window, cap_action);
+            dog.dry_run = dry_run;
+            dog.audit_log = audit_log;
+            dog
+        }),
+        idle_after,
+        idle_only,
+        json,
+        max_age,
+        oneshot_json,
+        cmdline,
+        include_self,
+        correlate,
+        tui,
+        capabilities,
+        merge_dualstack,
+        show_inode,
+        show_scope,
+        show_fd,
+        // Neither -4 nor -6 (or both): all families.
+        families: if families.is_empty() {
+            AddressFamilyFlags::IPV4 | AddressFamilyFlags::IPV6
+        } else {
+            families
+        },
+        protocols: if protocols.is_empty() {
+            ProtocolFlags::TCP | ProtocolFlags::UDP
+        } else {
+            protocols
+        },
+        timeout,
+        resolve,
+        no_idn,
+        names,
+        probe_rtt,
+        path_hints,
+        csv,
+        delimiter,
+        tag_rules_path,
+        geoip_paths,
+        geoip: geoip::Lookup::default(),
+        tag_rules: tags::Rules::default(),
+        host_policy_path,
+        host_policy: policy::Policy::default(),
+    })
 }
 
-fn print_help() {
-    let exe = env::args().next().unwrap_or_else(|| "netstatw".to_string());
-    println!("Usage: {} [OPTIONS]", exe);
-    println!();
-    println!("Options:");
-    println!("  -h, --help                 Show this help and exit");
-    println!("  -f, --full                Show CPU/Disk/IO and per-process net columns");
-    println!("  -s, --sort KEY            Sort by metric (repeatable): cpu | R | W | Rx | Tx");
-    println!("  -i, --sample-interval MS   Sampling interval in milliseconds (default: 800)");
-    println!("  -t, --top N                Limit number of PIDs shown and included per row");
+// One character other than a quote or line break; `tab` and `\t` name the tab.
+fn parse_delimiter(v: &str) -> Option<char> {
+    let c = match v {
+        "tab" | "\\t" => '\t',
+        _ => {
+            let mut chars = v.chars();
+            let c = chars.next()?;
+            if chars.next().is_some() {
+                return None;
+            }
+            c
+        }
+    };
+    (!matches!(c, '"' | '\n' | '\r')).then_some(c)
 }
 
-fn collect_process_stats(
-    system: &mut System,
-    pids: &HashSet<u32>,
-    interval: Duration,
-) -> HashMap<u32, ProcessStats> {
-    // sysinfo notes:
-    // - Process CPU% becomes meaningful after at least two refreshes.
-    // - Disk usage totals are cumulative; compute deltas over `interval` for per-second rates.
-    // - Some platforms may not expose all counters; such values may remain 0.
-    // Initial refresh to capture baseline totals.
-    system.refresh_processes();
-
-    let mut base_totals: HashMap<u32, (u64, u64)> = HashMap::new();
-    for &pid in pids {
-        if let Some(proc_) = system.process(Pid::from(pid as usize)) {
-            let du = proc_.disk_usage();
-            base_totals.insert(pid, (du.total_read_bytes, du.total_written_bytes));
-        }
-    }
-
-    let start = Instant::now();
-    let sleep_dur = if interval.is_zero() {
-        Duration::from_millis(1)
-    } else {
-        interval
-    };
-    thread::sleep(sleep_dur);
-
-    // Second refresh to compute deltas; also makes cpu_usage meaningful.
-    system.refresh_processes();
-
-    let elapsed = start.elapsed().as_secs_f64().max(0.001);
-    let mut out: HashMap<u32, ProcessStats> = HashMap::new();
-
-    for &pid in pids {
-        if let Some(proc_) = system.process(Pid::from(pid as usize)) {
-            let cpu = proc_.cpu_usage();
-            let du = proc_.disk_usage();
-            let (base_r, base_w) = base_totals
-                .get(&pid)
-                .copied()
-                .unwrap_or((du.total_read_bytes, du.total_written_bytes));
-            let read_delta = du.total_read_bytes.saturating_sub(base_r) as f64;
-            let write_delta = du.total_written_bytes.saturating_sub(base_w) as f64;
-            let read_rate = read_delta / elapsed;
-            let write_rate = write_delta / elapsed;
-            out.insert(
-                pid,
-                ProcessStats {
-                    cpu_pct: cpu,
-                    read_rate_bps: read_rate,
-                    write_rate_bps: write_rate,
-                    net_rx_rate_bps: 0.0,
-                    net_tx_rate_bps: 0.0,
-                    total_read_bytes: du.total_read_bytes,
-                    total_written_bytes: du.total_written_bytes,
-                },
-            );
+// Connection IDs piped in from another netstatw run: the first whitespace-separated
+// field of each line, if it looks like an ID. Header and separator lines of
+// `--ids` table output are skipped that way, so the table can be piped as-is.
+fn read_ids_from_stdin() -> Vec<String> {
+    io::stdin()
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| {
+            let first = line.split_whitespace().next()?.to_ascii_lowercase();
+            (first.len() == 8 && first.bytes().all(|b| b.is_ascii_hexdigit())).then_some(first)
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Column {
+    Id,
+    Proto,
+    Local,
+    Remote,
+    State,
+    Cpu,
+    R,
+    W,
+    Rx,
+    Tx,
+    Accept,
+    New,
+    Idle,
+    EnvHint,
+    Corr,
+    Inode,
+    Scope,
+    Fd,
+    Ping,
+    Path,
+    Tags,
+    Pid,
+    User,
+    Geo,
+    Asn,
+    Exe,
+    Process,
+    Change,
+}
+
+// `--columns` in the order of `cli::COLUMNS`.
+const COLUMN_KINDS: [Column; cli::COLUMNS.len()] = [
+    Column::Id,
+    Column::Proto,
+    Column::Local,
+    Column::Remote,
+    Column::State,
+    Column::Pid,
+    Column::User,
+    Column::Exe,
+    Column::Process,
+    Column::Cpu,
+    Column::R,
+    Column::W,
+    Column::Rx,
+    Column::Tx,
+    Column::Accept,
+    Column::New,
+    Column::Idle,
+    Column::EnvHint,
+    Column::Corr,
+    Column::Inode,
+    Column::Scope,
+    Column::Fd,
+    Column::Ping,
+    Column::Path,
+    Column::Tags,
+    Column::Geo,
+    Column::Asn,
+    Column::Change,
+];
+
+// `--columns proto,laddr,...`: the columns in the order given.
+fn parse_columns(v: &str) -> Result<Vec<Column>, String> {
+    v.split(',')
+        .map(|name| {
+            let name = name.trim().to_ascii_lowercase();
+            let name = match name.as_str() {
+                "local" => "laddr",
+                "remote" => "raddr",
+                other => other,
+            };
+            cli::COLUMNS
+                .iter()
+                .position(|n| *n == name)
+                .map(|i| COLUMN_KINDS[i])
+                .ok_or_else(|| {
+                    format!(
+                        "unknown column '{}' (expected {})",
+                        name,
+                        cli::COLUMNS.join(", ")
+                    )
+                })
+        })
+        .collect()
+}
+
+impl Column {
+    // (header, minimum width, right-aligned)
+    fn layout(self) -> (&'static str, usize, bool) {
+        match self {
+            Column::Id => ("ID", 9, false),
+            Column::Proto => ("PROTO", 10, false),
+            Column::Local => ("LOCAL ADDRESS", 34, false),
+            Column::Remote => ("REMOTE ADDRESS", 27, false),
+            Column::State => ("STATE", 17, false),
+            Column::Cpu => ("CPU%", 7, true),
+            Column::R => ("R/s", 10, true),
+            Column::W => ("W/s", 10, true),
+            Column::Rx => ("Rx/s", 10, true),
+            Column::Tx => ("Tx/s", 10, true),
+            Column::Accept => ("ACCEPTS", 9, true),
+            Column::New => ("NEW", 4, false),
+            Column::Idle => ("IDLE", 4, false),
+            Column::EnvHint => ("ENV HINT", 28, false),
+            Column::Corr => ("DISK~NET", 20, false),
+            Column::Inode => ("INODE", 11, true),
+            Column::Scope => ("SCOPE", 10, false),
+            Column::Fd => ("FD", 8, true),
+            Column::Ping => ("PING", 9, true),
+            Column::Path => ("PATH", 11, false),
+            Column::Tags => ("TAGS", 20, false),
+            Column::Pid => ("PID", 7, true),
+            Column::User => ("USER", 12, false),
+            Column::Geo => ("GEO", 4, false),
+            Column::Asn => ("ASN", 24, false),
+            Column::Exe => ("EXE", 30, false),
+            Column::Process => ("PROCESS", 40, false),
+            Column::Change => ("+/-", 3, false),
         }
     }
 
-out
+    fn cell(self, entry: &SocketEntry) -> String {
+        let stat = |f: fn(&ProcessStats) -> String| {
+            entry
+                .agg_stats
+                .as_ref()
+                .map(f)
+                .unwrap_or_else(|| "N/A".to_string())
+        };
+        match self {
+            Column::Id => entry.conn_id(),
+            Column::Proto => entry.proto.clone(),
+            Column::Local => with_service(entry.local_addr.clone(), &entry.local_service),
+            Column::Remote => {
+                let addr = match &entry.remote_host {
+                    Some(host) => {
+                        join_addr(&idn::display(host), parse_addr_port(&entry.remote_addr).1)
+                    }
+                    None => entry.remote_addr.clone(),
+                };
+                with_service(addr, &entry.remote_service)
+            }
+            Column::State => entry.state.clone(),
+            Column::Cpu => stat(|s| format!("{:.1}", s.cpu_pct)),
+            Column::R => stat(|s| human_readable_rate(s.read_rate_bps)),
+            Column::W => stat(|s| human_readable_rate(s.write_rate_bps)),
+            Column::Rx => stat(|s| human_readable_rate(s.net_rx_rate_bps)),
+            Column::Tx => stat(|s| human_readable_rate(s.net_tx_rate_bps)),
+            Column::Accept => entry.accept.map(|a| a.label()).unwrap_or_default(),
+            Column::New => if entry.new_remote { "NEW" } else { "" }.to_string(),
+            Column::Idle => match entry.idle {
+                Some(true) => "IDLE",
+                Some(false) => "",
+                None => "-",
+            }
+            .to_string(),
+            Column::EnvHint => entry.env_hint.clone().unwrap_or_default(),
+            Column::Corr => entry
+                .agg_stats
+                .as_ref()
+                .and_then(ProcessStats::disk_net_flag)
+                .unwrap_or_default(),
+            Column::Inode => entry.inode.map_or("-".to_string(), |i| i.to_string()),
+            Column::Scope => filter::scope(&entry.remote_addr)
+                .map_or("-", filter::Scope::label)
+                .to_string(),
+            Column::Fd if entry.fds.is_empty() => "-".to_string(),
+            Column::Fd => {
+                let fds: Vec<String> = entry.fds.iter().map(|(_, fd)| fd.to_string()).collect();
+                fds.join(",")
+            }
+            Column::Ping => match entry.rtt_ms {
+                Some(ms) if ms < 1.0 => format!("{:.2} ms", ms),
+                Some(ms) => format!("{:.1} ms", ms),
+                None => "-".to_string(),
+            },
+            Column::Path => entry.path.as_ref().map_or("-", |p| p.hint()).to_string(),
+            Column::Tags => entry.tags.join(","),
+            Column::Change => entry.change.map_or("", changes::Change::marker).to_string(),
+            Column::Pid if entry.pids.is_empty() => "-".to_string(),
+            Column::Pid => {
+                let pids: Vec<String> = entry.pids.iter().map(u32::to_string).collect();
+                pids.join(",")
+            }
+            Column::User => entry.user.clone().unwrap_or_else(|| "-".to_string()),
+            Column::Geo => entry
+                .geo
+                .as_ref()
+                .and_then(|g| g.country.clone())
+                .unwrap_or_else(|| "-".to_string()),
+            Column::Asn => entry
+                .geo
+                .as_ref()
+                .and_then(geoip::Geo::as_label)
+                .unwrap_or_else(|| "-".to_string()),
+            // PROCESS without the `PID: ` prefixes.
+            Column::Exe => {
+                let exes: Vec<&str> = entry
+                    .process_info
+                    .split(", ")
+                    .map(|p| p.split_once(": ").map_or(p, |(_, exe)| exe))
+                    .collect();
+                exes.join(", ")
+            }
+            Column::Process => {
+                let mut text = entry.process_info.clone();
+                if let Some(target) = &entry.forward {
+                    text.push_str(&format!(" [FORWARD -> {}]", target));
+                }
+                if entry.synth {
+                    text.push_str(" [synth]");
+                }
+                text
+            }
+        }
+    }
 }
 
-fn build_socket_entries(
-    sockets_info: Vec<SocketInfo>,
-    system: &System,
-    top_n: Option<usize>,
-) -> Vec<SocketEntry> {
-    let mut entries: Vec<SocketEntry> = Vec::new();
-    for si in sockets_info {
-        let process_info_list: Vec<String> = si
-            .associated_pids
-            .iter()
-            .take(top_n.unwrap_or(usize::MAX))
-            .map(|&pid| get_process_info(system, pid))
+// `addr (service)` when the port has a name (`--names`).
+fn with_service(addr: String, service: &Option<String>) -> String {
+    match service {
+        Some(name) => format!("{} ({})", addr, name),
+        None => addr,
+    }
+}
+
+fn pad(text: &str, width: usize, right: bool) -> String {
+    if right {
+        format!("{:>width$}", text, width = width)
+    } else {
+        format!("{:<width$}", text, width = width)
+    }
+}
+
+// The table as text. With --watch on a terminal it goes to the pager, which keeps the
+// two header lines in place while the rows scroll.
+fn print_table(columns: &[Column], entries: &[SocketEntry], palette: Option<&color::Palette>) {
+    let text = table_text(columns, entries, palette);
+    if pager::active() {
+        pager::show(&text, 2);
+    } else {
+        print!("{}", text);
+    }
+}
+
+fn table_text(
+    columns: &[Column],
+    entries: &[SocketEntry],
+    palette: Option<&color::Palette>,
+) -> String {
+    let mut out = String::new();
+    let rows: Vec<Vec<String>> = entries
+        .iter()
+        .map(|e| columns.iter().map(|c| c.cell(e)).collect())
+        .collect();
+    // Each column is as wide as its widest cell, but not narrower than its layout
+    // width, so a refreshing table keeps still.
+    let widths: Vec<usize> = columns
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            let (name, min, _) = c.layout();
+            rows.iter()
+                .map(|cells| cells[i].chars().count())
+                .fold(min.max(name.len()), usize::max)
+        })
+        .collect();
+    let line = |cells: &mut dyn Iterator<Item = (usize, &str)>| -> String {
+        let padded: Vec<String> = cells
+            .map(|(i, text)| pad(text, widths[i], columns[i].layout().2))
             .collect();
-        let process_info = if process_info_list.is_empty() {
-            "Unknown".to_string()
-        } else {
-            process_info_list.join(", ")
+        padded.join(" ")
+    };
+
+    out.push_str(&line(&mut columns.iter().map(|c| c.layout().0).enumerate()));
+    out.push('\n');
+    // Separators are one char shorter than the column and joined by two spaces,
+    // which keeps them aligned with the single-space-joined cells.
+    let seps: Vec<String> = widths.iter().map(|w| "-".repeat(w - 1)).collect();
+    out.push_str(&seps.join("  "));
+    out.push('\n');
+    // A busy metric cell is highlighted; other cells take the row's state color.
+    let style = |c: Column, e: &SocketEntry| -> Option<&'static str> {
+        let palette = palette?;
+        match (c, e.change) {
+            (_, Some(changes::Change::Closed)) => return palette.closed(),
+            (Column::Change, Some(changes::Change::Opened)) => return palette.opened(),
+            _ => {}
+        }
+        let stats = e.agg_stats.as_ref();
+        let hot = match c {
+            Column::Cpu => stats.and_then(|s| palette.cpu(s.cpu_pct as f64)),
+            Column::Rx => stats.and_then(|s| palette.rate(s.net_rx_rate_bps)),
+            Column::Tx => stats.and_then(|s| palette.rate(s.net_tx_rate_bps)),
+            _ => None,
         };
-        let pids: Vec<u32> = si
-            .associated_pids
+        hot.or_else(|| palette.row(&e.state))
+    };
+    for (e, cells) in entries.iter().zip(&rows) {
+        if palette.is_none() {
+            out.push_str(&line(&mut cells.iter().map(String::as_str).enumerate()));
+            out.push('\n');
+            continue;
+        }
+        let painted: Vec<String> = cells
             .iter()
-            .cloned()
-            .take(top_n.unwrap_or(usize::MAX))
+            .enumerate()
+            .map(|(i, text)| {
+                color::paint(
+                    &pad(text, widths[i], columns[i].layout().2),
+                    style(columns[i], e),
+                )
+            })
             .collect();
+        out.push_str(&painted.join(" "));
+        out.push('\n');
+    }
+    out
+}
 
-        match si.protocol_socket_info {
-            ProtocolSocketInfo::Tcp(tcp_si) => {
-                let local_addr = format!("{}:{}", tcp_si.local_addr, tcp_si.local_port);
-                let remote_addr = format!("{}:{}", tcp_si.remote_addr, tcp_si.remote_port);
-                let state = format!("{:?}", tcp_si.state);
-
-                entries.push(SocketEntry {
-                    proto: "TCP".to_string(),
-                    local_addr,
-                    remote_addr,
-                    state,
-                    process_info,
-                    pids,
-                    agg_stats: None,
-                });
+// `theme` in the config file's [display] section when there's no `--theme`; dark when
+// neither names one. A config file that can't be read is a warning here, not an error.
+fn config_theme(loaded: Option<&config::Config>) -> color::Theme {
+    let read;
+    let cfg = match loaded {
+        Some(cfg) => cfg,
+        None => match config::Config::load() {
+            Ok(cfg) => {
+                read = cfg;
+                &read
             }
-            ProtocolSocketInfo::Udp(udp_si) => {
-                let local_addr = format!("{}:{}", udp_si.local_addr, udp_si.local_port);
-
-                entries.push(SocketEntry {
-                    proto: "UDP".to_string(),
-                    local_addr,
-                    remote_addr: "*:*".to_string(),
-                    state: "-".to_string(),
-                    process_info,
-                    pids,
-                    agg_stats: None,
-                });
+            Err(e) => {
+                warn::warn("config", format!("config: {}", e));
+                return color::Theme::Dark;
             }
+        },
+    };
+    let Some((_, value)) = cfg
+        .section("display")
+        .unwrap_or_default()
+        .iter()
+        .find(|(k, _)| k == "theme")
+    else {
+        return color::Theme::Dark;
+    };
+    color::Theme::parse(&value.to_arg()).unwrap_or_else(|| {
+        warn::warn(
+            "config",
+            format!(
+                "config: unknown theme '{}' (expected one of {})",
+                value.to_arg(),
+                color::THEMES.join(", ")
+            ),
+        );
+        color::Theme::Dark
+    })
+}
+
+// `netstatw view NAME [ARGS...]`: replaces the view name with the arguments stored
+// in `[view.NAME]` of the config file; extra ARGS are appended after them.
+fn expand_view(argv: Vec<String>) -> Vec<String> {
+    if argv.get(1).is_some_and(|a| a == "--help" || a == "-h") {
+        cli::print_help(&cli::VIEW);
+        std::process::exit(0);
+    }
+    let cfg = match config::Config::load() {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            eprintln!("netstatw: config: {}", e);
+            std::process::exit(2);
+        }
+    };
+    let Some(name) = argv.get(1) else {
+        let views = cfg.subsections("view");
+        if views.is_empty() {
+            println!("No views defined; add [view.NAME] sections to the config file.");
+        }
+        for v in views {
+            println!("{}", v);
+        }
+        std::process::exit(0);
+    };
+    match config::view_args(&cfg, name) {
+        Some(mut args) => {
+            args.extend(argv.into_iter().skip(2));
+            args
+        }
+        None => {
+            eprintln!("netstatw: no view named `{}` in the config file", name);
+            std::process::exit(2);
         }
     }
+}
 
-    entries
+// Arguments implied by the name the binary was started as, so task-specific links
+// (`lsportw`, `nettopw`) start directly in their mode; the user's arguments follow.
+fn preset_for_invocation_name(argv0: &str) -> Vec<String> {
+    let name = std::path::Path::new(argv0)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    let preset: &[&str] = match name.as_str() {
+        "lsportw" => &["inventory"],
+        "nettopw" => &["--tui", "--sort", "tx"],
+        _ => &[],
+    };
+    preset.iter().map(|s| s.to_string()).collect()
 }
 
 fn main() {
+    // --timeout counts from here.
+    let started = Instant::now();
+    // The binary leaves its own sockets out unless `--include-self`; library callers
+    // don't.
+    own::exclude_self(true);
+    let argv0 = env::args().next().unwrap_or_default();
+    let mut argv = preset_for_invocation_name(&argv0);
+    argv.extend(env::args().skip(1));
+    // The static container build is usually run bare (`docker run img`): one JSON
+    // snapshot.
+    if cfg!(feature = "static") && argv.is_empty() {
+        argv.push("--oneshot-json".to_string());
+    }
+    match argv.first().map(String::as_str) {
+        Some("synth") => std::process::exit(synth::run(argv.split_off(1))),
+        Some("inventory") => std::process::exit(inventory::run(argv.split_off(1))),
+        Some("inventory-diff") => std::process::exit(inventory::run_diff(argv.split_off(1))),
+        Some("diff") => std::process::exit(diff::run(argv.split_off(1))),
+        Some("events") => std::process::exit(events::run(argv.split_off(1))),
+        Some("graph") => std::process::exit(graph::run(argv.split_off(1))),
+        Some("kill") => std::process::exit(kill::run(argv.split_off(1))),
+        Some("blocks") => std::process::exit(block::run_blocks(argv.split_off(1))),
+        Some("compact") => std::process::exit(compact::run(argv.split_off(1))),
+        Some("assert") => std::process::exit(assert::run(argv.split_off(1))),
+        Some("serve") => std::process::exit(serve::run(argv.split_off(1))),
+        Some("view") => argv = expand_view(argv),
+        Some("--help-json") => {
+            println!("{}", cli::help_json());
+            return;
+        }
+        _ => {}
+    }
+    // `list`, `watch` and `stats` name the main command's modes and have their own help.
+    let command = match argv.first().map(String::as_str) {
+        Some("list") => {
+            argv.remove(0);
+            "list"
+        }
+        Some("watch") => {
+            argv[0] = "--watch".to_string();
+            "watch"
+        }
+        Some("stats") => {
+            argv[0] = "--full".to_string();
+            "stats"
+        }
+        _ => "list",
+    };
+
     // Help flag handling
-    if env::args().skip(1).any(|a| a == "--help" || a == "-h") {
-        print_help();
+    if argv.iter().any(|a| a == "--help" || a == "-h") {
+        let command = match command {
+            "watch" => &cli::WATCH,
+            "stats" => &cli::STATS,
+            _ => &cli::LIST,
+        };
+        print!("{}", cli::help(command, &argv0));
         return;
     }
 
-    let (show_stats, sample_interval_ms, top_n, sort_keys) = parse_args();
+    let mut opts = parse_args(argv).unwrap_or_else(|e| {
+        eprintln!("netstatw: {} (see --help)", e);
+        std::process::exit(2);
+    });
+    own::exclude_self(!opts.include_self);
+    idn::decode_names(!opts.no_idn);
+    if let Some(window) = opts.alert_every {
+        alert::limit(window);
+    }
+    if opts.output.is_some() && opts.format != "json" && opts.format != "csv" {
+        eprintln!(
+            "netstatw: unsupported --format '{}' (expected: json or csv)",
+            opts.format
+        );
+        std::process::exit(2);
+    }
+    if let Some(path) = &opts.compare_rates_path {
+        match compare::load(path) {
+            Ok(rates) => opts.compare_rates = Some(rates),
+            Err(e) => {
+                eprintln!("netstatw: --compare-rates: {}", e);
+                std::process::exit(2);
+            }
+        }
+    }
+    // An explicit rules file has to load; a broken config file only costs the tags.
+    opts.tag_rules = match tags::Rules::load(opts.tag_rules_path.as_deref()) {
+        Ok(rules) => rules,
+        Err(e) if opts.tag_rules_path.is_none() => {
+            warn::warn("tag_rules", format!("tag rules not loaded: {}", e));
+            tags::Rules::default()
+        }
+        Err(e) => {
+            eprintln!("netstatw: --tag-rules: {}", e);
+            std::process::exit(2);
+        }
+    };
+    opts.geoip = geoip::Lookup::open(&opts.geoip_paths).unwrap_or_else(|e| {
+        eprintln!("netstatw: --geoip: {}", e);
+        std::process::exit(2);
+    });
+    if let Some(path) = &opts.host_policy_path {
+        opts.host_policy = match policy::Policy::load(path.as_deref()) {
+            Ok(rules) if rules.is_empty() => {
+                eprintln!("netstatw: --host-policy: no [policy.NAME] rules");
+                std::process::exit(2);
+            }
+            Ok(rules) => rules,
+            Err(e) => {
+                eprintln!("netstatw: --host-policy: {}", e);
+                std::process::exit(2);
+            }
+        };
+    }
+    if !opts.alerts.is_empty() && opts.watch.is_none() {
+        eprintln!("netstatw: --alert-conn-change/--alert-listen-change need --watch");
+        std::process::exit(2);
+    }
+    if opts.data_cap.is_some() && opts.watch.is_none() {
+        eprintln!("netstatw: --data-cap needs --watch");
+        std::process::exit(2);
+    }
+    // With --stdin an empty selection means nothing matched upstream, so no row is shown.
+    let mut select_nothing = false;
+    if opts.ids_from_stdin {
+        let stdin_ids = read_ids_from_stdin();
+        select_nothing = stdin_ids.is_empty();
+        for id in stdin_ids {
+            opts.filter.add(Criterion::Id(id), false);
+        }
+    }
+
+    // Forensic mode must not modify the system: refuse options that spawn commands
+    // or write files rather than silently dropping them.
+    if opts.forensic {
+        if opts.exec.is_some() {
+            eprintln!("netstatw: --exec is not allowed with --forensic");
+            std::process::exit(2);
+        }
+        if opts.seen_db.is_some() {
+            eprintln!("netstatw: --seen-db writes to disk and is not allowed with --forensic");
+            std::process::exit(2);
+        }
+        if opts.output.is_some() {
+            eprintln!("netstatw: --output writes to disk and is not allowed with --forensic");
+            std::process::exit(2);
+        }
+        if opts.record.is_some() {
+            eprintln!("netstatw: --record writes to disk and is not allowed with --forensic");
+            std::process::exit(2);
+        }
+        if opts.max_age.is_some() {
+            eprintln!("netstatw: --max-age writes a cache file and is not allowed with --forensic");
+            std::process::exit(2);
+        }
+        if opts
+            .data_cap
+            .as_ref()
+            .is_some_and(|c| c.action != cap::Action::Alert && !c.dry_run)
+        {
+            eprintln!(
+                "netstatw: --cap-action kill/block modifies the system and is not allowed with --forensic"
+            );
+            std::process::exit(2);
+        }
+        if opts.probe_rtt {
+            eprintln!(
+                "netstatw: --probe-rtt sends traffic to remote hosts and is not allowed with --forensic"
+            );
+            std::process::exit(2);
+        }
+    }
+
+    if let Some(path) = &opts.replay {
+        // Only what was recorded can be shown; options that need the live host are
+        // refused rather than ignored.
+        let live = [
+            (opts.watch.is_some(), "--watch"),
+            (opts.tui, "--tui"),
+            (opts.record.is_some(), "--record"),
+            (opts.output.is_some(), "--output"),
+            (opts.csv, "--csv"),
+            (opts.exec.is_some(), "--exec"),
+            (opts.seen_db.is_some(), "--seen-db"),
+            (opts.host_policy_path.is_some(), "--host-policy"),
+            (opts.data_cap.is_some(), "--data-cap"),
+            (opts.resolve, "--resolve"),
+            (opts.probe_rtt, "--probe-rtt"),
+            (opts.path_hints, "--path-hints"),
+            (opts.overview, "--overview"),
+            (opts.by_user, "--by-user"),
+            (
+                opts.group_by == Some(report::GroupBy::Process),
+                "--group-by process",
+            ),
+        ];
+        if let Some((_, name)) = live.iter().find(|(set, _)| *set) {
+            eprintln!("netstatw: {} is not available with --replay", name);
+            std::process::exit(2);
+        }
+        std::process::exit(replay(&opts, path));
+    }
+
+    if opts.tui {
+        // The TUI sorts by one of its columns; other keys start in table order.
+        let sort = opts.sort_keys.first().and_then(|k| {
+            let col = match k.kind {
+                SortKeyKind::Cpu => tui::Col::Cpu,
+                SortKeyKind::R => tui::Col::R,
+                SortKeyKind::W => tui::Col::W,
+                SortKeyKind::Rx => tui::Col::Rx,
+                SortKeyKind::Tx => tui::Col::Tx,
+                SortKeyKind::State => tui::Col::State,
+                SortKeyKind::Proto => tui::Col::Proto,
+                SortKeyKind::Laddr => tui::Col::Local,
+                SortKeyKind::Raddr => tui::Col::Remote,
+                SortKeyKind::Process => tui::Col::Process,
+                SortKeyKind::Lport | SortKeyKind::Rport | SortKeyKind::Pid => return None,
+            };
+            Some((col, k.descending))
+        });
+        let code = tui::run(tui::TuiOptions {
+            refresh: opts.watch.unwrap_or(Duration::from_secs(2)),
+            sample_interval: Duration::from_millis(opts.sample_interval_ms),
+            top_n: opts.top_n,
+            families: opts.families,
+            protocols: opts.protocols,
+            filter: opts.filter.clone(),
+            sort,
+            enable_estats: !opts.forensic,
+            cmdline: opts.cmdline,
+            persist: !opts.forensic,
+            palette: opts.palette,
+        });
+        if opts.forensic {
+            forensic::print_report();
+        }
+        std::process::exit(code);
+    }
 
+    forensic::record("sysinfo::System::refresh_all (process table)");
     let mut system = System::new_all();
     system.refresh_all();
 
-    let af_flags = AddressFamilyFlags::IPV4 | AddressFamilyFlags::IPV6;
-    let proto_flags = ProtocolFlags::TCP | ProtocolFlags::UDP;
-    let sockets_info = get_sockets_info(af_flags, proto_flags).unwrap();
-    //println!("Found {} sockets", sockets_info.len());
-    //println!("sockets info: {:#?}", sockets_info);
-
-    // Collect all socket entries
-    let mut socket_entries: Vec<SocketEntry> = build_socket_entries(sockets_info, &system, top_n);
-
-    // If stats requested, sample process stats once for all involved PIDs and aggregate per row.
-    // Also compute network per-process rates on Windows; on other platforms remain N/A.
-    if show_stats {
-        let mut pid_set: HashSet<u32> = HashSet::new();
-        for e in &socket_entries {
-            for &p in &e.pids {
-                pid_set.insert(p);
-            }
-        }
-        if !pid_set.is_empty() {
-            // Windows-specific per-process TCP network sampling.
-            #[cfg(windows)]
-            let net_rates: std::collections::HashMap<u32, (f64, f64)> = {
-                let dur = Duration::from_millis(sample_interval_ms);
-                crate::win_net::sample_per_process_tcp_estats(dur)
-            };
-            #[cfg(not(windows))]
-            let net_rates: std::collections::HashMap<u32, (f64, f64)> = Default::default();
-            let stats_map = collect_process_stats(
-                &mut system,
-                &pid_set,
-                Duration::from_millis(sample_interval_ms),
-            );
-            for entry in &mut socket_entries {
-                let mut agg = ProcessStats::default();
-                let mut any = false;
-                let mut net_any = false;
-                for &p in &entry.pids {
-                    if let Some(s) = stats_map.get(&p) {
-                        any = true;
-                        agg.cpu_pct += s.cpu_pct;
-                        agg.read_rate_bps += s.read_rate_bps;
-                        agg.write_rate_bps += s.write_rate_bps;
-                        agg.total_read_bytes =
-                            agg.total_read_bytes.saturating_add(s.total_read_bytes);
-                        agg.total_written_bytes = agg
-                            .total_written_bytes
-                            .saturating_add(s.total_written_bytes);
-                    }
-                    if let Some((rx, tx)) = net_rates.get(&p) {
-                        net_any = true;
-                        agg.net_rx_rate_bps += *rx;
-                        agg.net_tx_rate_bps += *tx;
-                    }
-                }
-                if !net_any {
-                    // Mark network as not available so formatting shows N/A
-                    agg.net_rx_rate_bps = f64::NAN;
-                    agg.net_tx_rate_bps = f64::NAN;
-                }
-                if any {
-                    entry.agg_stats = Some(agg);
-                }
+    if opts.capabilities {
+        caps::print(&caps::probe(&system), opts.json);
+        if opts.forensic {
+            forensic::print_report();
+        }
+        return;
+    }
+
+    // Watch mode keeps the same System between refreshes so per-process CPU and
+    // disk counters carry over from one iteration to the next.
+    // Ctrl-C cuts sampling short instead of killing the process: the rows are still
+    // printed, with partial rates, and then the program exits.
+    interrupt::install();
+    if let Some(timeout) = opts.timeout {
+        interrupt::set_deadline(started + timeout);
+    }
+    let Some(every) = opts.watch else {
+        let exit_code = run_once(&opts, &mut system, select_nothing, None, None, None);
+        if opts.forensic {
+            forensic::print_report();
+        }
+        if interrupt::interrupted() {
+            std::process::exit(130);
+        }
+        if exit_code != 0 {
+            std::process::exit(exit_code);
+        }
+        return;
+    };
+    let mut tracker = alert::Tracker::default();
+    let mut changes = opts.changes.then(changes::Tracker::default);
+    let mut idle = opts.idle_after.map(idle::Tracker::new);
+    if table_output(&opts) {
+        pager::start();
+    }
+    loop {
+        run_once(
+            &opts,
+            &mut system,
+            select_nothing,
+            Some(&mut tracker),
+            changes.as_mut(),
+            idle.as_mut(),
+        );
+        if !pager::wait(every) || interrupt::should_stop() {
+            pager::stop();
+            if opts.forensic {
+                forensic::print_report();
             }
+            std::process::exit(if interrupt::interrupted() { 130 } else { 0 });
         }
+        forensic::record("sysinfo::System::refresh_all (process table)");
+        system.refresh_all();
     }
+}
+
+// How long --resolve waits for reverse lookups before printing numeric addresses.
+const RESOLVE_TIMEOUT: Duration = Duration::from_secs(1);
+
+// How long --probe-rtt waits for each handshake.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Sampling shorter than this finishes before a progress message would be noticed.
+const SPINNER_MIN: Duration = Duration::from_millis(500);
 
-    // Sort
+// The sort keys in order (unavailable metrics last either way), then STATE, PROTO
+// and LOCAL ADDRESS.
+fn sort_entries(entries: &mut [SocketEntry], sort_keys: &[SortKey]) {
     if !sort_keys.is_empty() {
-        socket_entries.sort_by(|a, b| {
-            for key in &sort_keys {
-                let av = match (key, &a.agg_stats) {
-                    (SortKeyKind::Cpu, Some(s)) => s.cpu_pct as f64,
-                    (SortKeyKind::R, Some(s)) => s.read_rate_bps,
-                    (SortKeyKind::W, Some(s)) => s.write_rate_bps,
-                    (SortKeyKind::Rx, Some(s)) => s.net_rx_rate_bps,
-                    (SortKeyKind::Tx, Some(s)) => s.net_tx_rate_bps,
-                    _ => f64::NAN,
-                };
-                let bv = match (key, &b.agg_stats) {
-                    (SortKeyKind::Cpu, Some(s)) => s.cpu_pct as f64,
-                    (SortKeyKind::R, Some(s)) => s.read_rate_bps,
-                    (SortKeyKind::W, Some(s)) => s.write_rate_bps,
-                    (SortKeyKind::Rx, Some(s)) => s.net_rx_rate_bps,
-                    (SortKeyKind::Tx, Some(s)) => s.net_tx_rate_bps,
-                    _ => f64::NAN,
-                };
-                // Descending; treat NaN as smallest
-                let ord = if av.is_nan() && bv.is_nan() {
-                    std::cmp::Ordering::Equal
-                } else if av.is_nan() {
-                    std::cmp::Ordering::Greater
-                } else if bv.is_nan() {
-                    std::cmp::Ordering::Less
-                } else {
-                    bv.partial_cmp(&av).unwrap_or(std::cmp::Ordering::Equal)
+        entries.sort_by(|a, b| {
+            for key in sort_keys {
+                let nan = |e| key.kind.is_metric() && key.kind.metric(e).is_nan();
+                let ord = match (nan(a), nan(b)) {
+                    (true, true) => std::cmp::Ordering::Equal,
+                    (true, false) => std::cmp::Ordering::Greater,
+                    (false, true) => std::cmp::Ordering::Less,
+                    _ if key.descending => key.kind.compare(b, a),
+                    _ => key.kind.compare(a, b),
                 };
                 if ord != std::cmp::Ordering::Equal {
                     return ord;
@@ -432,83 +1459,488 @@ fn main() {
         });
     } else {
         // Default: by STATE, PROTO, LOCAL ADDRESS
-        socket_entries.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+        entries.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
     }
+}
 
-    // Print header
-    if show_stats {
-        println!(
-            "{:<10} {:<34} {:<27} {:<17} {:>7} {:>10} {:>10} {:>10} {:>10} {:<40}",
-            "PROTO", "LOCAL ADDRESS", "REMOTE ADDRESS", "STATE", "CPU%", "R/s", "W/s", "Rx/s", "Tx/s", "PROCESS"
-        );
+// The rows in the chosen output: JSON, CSV, one of the reports, or the table. The
+// table also shows the connections `closed` since the last --watch refresh.
+fn print_entries(
+    opts: &Options,
+    entries: &[SocketEntry],
+    closed: &[SocketEntry],
+    pid_stats: &HashMap<u32, ProcessStats>,
+    system: &System,
+) {
+    let &Options {
+        show_stats,
+        ref seen_db,
+        port_report,
+        show_ids,
+        by_user,
+        group_by,
+        summary,
+        overview,
+        env_hints,
+        json,
+        correlate,
+        merge_dualstack,
+        show_inode,
+        show_scope,
+        show_fd,
+        probe_rtt,
+        path_hints,
+        csv,
+        delimiter,
+        ref tag_rules,
+        ..
+    } = opts;
+    if let Some(base) = &opts.compare_rates {
+        let now = compare::rates(entries);
+        if json {
+            println!("{}", compare::to_json(&now, base));
+        } else {
+            compare::print(&now, base);
+        }
+    } else if opts.oneshot_json {
+        print!("{}", json::snapshot(entries));
+    } else if json {
+        println!("{}", json::entries(entries));
+    } else if csv {
+        print!("{}", netstatw::csv::entries(entries, delimiter));
+    } else if port_report {
+        report::print_port_category_report(entries, pid_stats, show_stats);
+    } else if overview {
+        report::print_overview(entries, pid_stats, system);
+    } else if by_user {
+        report::print_user_report(entries, pid_stats, system, show_stats);
+    } else if let Some(by) = group_by {
+        report::print_group_report(entries, pid_stats, system, by, show_stats);
+    } else if let Some(columns) = &opts.columns {
+        let rows = match columns.contains(&Column::Change) {
+            true => with_closed(entries, closed, &opts.sort_keys),
+            false => Cow::Borrowed(entries),
+        };
+        print_table(columns, &rows, opts.palette.as_ref());
+        if summary {
+            report::print_summary(entries, pid_stats, show_stats);
+        }
     } else {
-        println!(
-            "{:<10} {:<34} {:<27} {:<17} {:<40}",
-            "PROTO", "LOCAL ADDRESS", "REMOTE ADDRESS", "STATE", "PROCESS"
-        );
+        let mut columns: Vec<Column> = Vec::new();
+        if opts.changes {
+            columns.push(Column::Change);
+        }
+        if show_ids {
+            columns.push(Column::Id);
+        }
+        columns.extend([Column::Proto, Column::Local, Column::Remote, Column::State]);
+        if show_stats {
+            columns.extend([
+                Column::Cpu,
+                Column::R,
+                Column::W,
+                Column::Rx,
+                Column::Tx,
+                Column::Accept,
+            ]);
+        }
+        if seen_db.is_some() {
+            columns.push(Column::New);
+        }
+        if opts.idle_after.is_some() {
+            columns.push(Column::Idle);
+        }
+        if env_hints {
+            columns.push(Column::EnvHint);
+        }
+        if correlate {
+            columns.push(Column::Corr);
+        }
+        if show_inode {
+            columns.push(Column::Inode);
+        }
+        if show_scope {
+            columns.push(Column::Scope);
+        }
+        if show_fd {
+            columns.push(Column::Fd);
+        }
+        if probe_rtt {
+            columns.push(Column::Ping);
+        }
+        if path_hints {
+            columns.push(Column::Path);
+        }
+        if !tag_rules.is_empty() {
+            columns.push(Column::Tags);
+        }
+        if !opts.geoip.is_empty() {
+            columns.extend([Column::Geo, Column::Asn]);
+        }
+        columns.push(Column::Process);
+        let rows = with_closed(entries, closed, &opts.sort_keys);
+        if merge_dualstack {
+            print_table(
+                &columns,
+                &netstatw::merge_dualstack(&rows),
+                opts.palette.as_ref(),
+            );
+        } else {
+            print_table(&columns, &rows, opts.palette.as_ref());
+        }
+        if summary {
+            report::print_summary(entries, pid_stats, show_stats);
+        }
+    }
+}
+
+// Whether the rows come out as the table, the output --watch pages on a terminal:
+// not JSON or CSV, no report, no summary under it.
+fn table_output(opts: &Options) -> bool {
+    !(opts.oneshot_json
+        || opts.json
+        || opts.csv
+        || opts.port_report
+        || opts.overview
+        || opts.by_user
+        || opts.group_by.is_some()
+        || opts.compare_rates.is_some()
+        || opts.summary)
+}
+
+// `entries` and, in their sort position, the `closed` rows.
+fn with_closed<'a>(
+    entries: &'a [SocketEntry],
+    closed: &[SocketEntry],
+    sort_keys: &[SortKey],
+) -> Cow<'a, [SocketEntry]> {
+    if closed.is_empty() {
+        return Cow::Borrowed(entries);
     }
+    let mut rows = [entries, closed].concat();
+    sort_entries(&mut rows, sort_keys);
+    Cow::Owned(rows)
+}
 
-    // Create aligned separator line
-    let proto_sep = "-".repeat(9);
-    let local_addr_sep = "-".repeat(33);
-    let remote_addr_sep = "-".repeat(26);
-    let state_sep = "-".repeat(16);
-    let process_sep = "-".repeat(39);
-    if show_stats {
-        let cpu_sep = "-".repeat(6);
-        let r_sep = "-".repeat(9);
-        let w_sep = "-".repeat(9);
-        let rx_sep = "-".repeat(9);
-        let tx_sep = "-".repeat(9);
+// `--replay FILE`: every recorded snapshot, filtered, sorted and printed like a live
+// one under a header with its time. `--json` prints the snapshots as JSON Lines again.
+fn replay(opts: &Options, path: &Path) -> i32 {
+    let snapshots = match record::read(path) {
+        Ok(snapshots) => snapshots,
+        Err(e) => {
+            eprintln!("netstatw: --replay {}: {}", path.display(), e);
+            return 2;
+        }
+    };
+    let system = System::new();
+    let count = snapshots.len();
+    for (i, snapshot) in snapshots.into_iter().enumerate() {
+        let mut rows = snapshot.entries;
+        if opts.hide_synth {
+            rows.retain(|e| !e.synth);
+        }
+        if !opts.filter.is_empty() {
+            rows.retain(|e| opts.filter.matches(e));
+        }
+        sort_entries(&mut rows, &opts.sort_keys);
+        if opts.names {
+            services::label(&mut rows);
+        }
+        if opts.json {
+            print!("{}", record::line(snapshot.timestamp, &rows));
+            continue;
+        }
+        if i > 0 {
+            println!();
+        }
         println!(
-            "{}  {}  {}  {}  {}  {}  {}  {}  {}  {}",
-            proto_sep, local_addr_sep, remote_addr_sep, state_sep, cpu_sep, r_sep, w_sep, rx_sep, tx_sep, process_sep
+            "Snapshot {}/{} at {}: {} rows",
+            i + 1,
+            count,
+            record::utc(snapshot.timestamp),
+            rows.len()
         );
+        println!();
+        print_entries(opts, &rows, &[], &HashMap::new(), &system);
+    }
+    warn::emit(opts.json_warnings);
+    0
+}
+
+// One snapshot: collect sockets, enrich, filter, sample, sort and print them.
+// Returns the process exit code.
+fn run_once(
+    opts: &Options,
+    system: &mut System,
+    select_nothing: bool,
+    tracker: Option<&mut alert::Tracker>,
+    changes: Option<&mut changes::Tracker>,
+    idle: Option<&mut idle::Tracker>,
+) -> i32 {
+    let &Options {
+        show_stats,
+        sample_interval_ms,
+        top_n,
+        ref sort_keys,
+        ref exec,
+        ref seen_db,
+        hide_synth,
+        json_warnings,
+        ref filter,
+        env_hints,
+        ref output,
+        watch,
+        json,
+        resolve,
+        names,
+        probe_rtt,
+        path_hints,
+        csv,
+        delimiter,
+        ref format,
+        ref tag_rules,
+        ..
+    } = opts;
+
+    let cached = opts
+        .max_age
+        .and_then(|age| cache::load(age, opts.families, opts.protocols, top_n));
+    let mut socket_entries = match cached {
+        Some(entries) => entries,
+        None => match collect_entries(system, top_n, opts.families, opts.protocols) {
+            Ok(entries) => {
+                if opts.max_age.is_some()
+                    && let Err(e) = cache::store(&entries, opts.families, opts.protocols, top_n)
+                {
+                    warn::warn("cache", format!("not caching the socket list: {}", e));
+                }
+                entries
+            }
+            Err(e) => {
+                eprintln!("netstatw: failed to read socket tables: {}", e);
+                return 1;
+            }
+        },
+    };
+
+    // Before the filters, so `--process` also matches arguments.
+    if opts.cmdline {
+        show_cmdlines(&mut socket_entries, system);
+    }
+
+    // Tag connections made by `netstatw synth` (and their server-side peers).
+    let synth_pids = synth::synth_pids(system);
+    if !synth_pids.is_empty() {
+        let synth_addrs: HashSet<String> = socket_entries
+            .iter()
+            .filter(|e| e.pids.iter().any(|p| synth_pids.contains(p)))
+            .map(|e| e.local_addr.clone())
+            .collect();
+        for e in &mut socket_entries {
+            e.synth = e.pids.iter().any(|p| synth_pids.contains(p))
+                || synth_addrs.contains(&e.remote_addr);
+        }
+        if hide_synth {
+            socket_entries.retain(|e| !e.synth);
+        }
+    }
+
+    // Label rows owned by port forwarders (ssh -L, kubectl port-forward, socat, ...).
+    for e in &mut socket_entries {
+        let port = parse_addr_port(&e.local_addr).1;
+        e.forward = e
+            .pids
+            .iter()
+            .find_map(|&pid| forward::label(system, pid, port));
+    }
+
+    // Tags and countries first, so `--tag` and `--country` can filter on them.
+    tag_rules.apply(&mut socket_entries);
+    opts.geoip.annotate(&mut socket_entries);
+
+    // Filter stage: drop rows before sampling so only displayed PIDs are measured.
+    if select_nothing {
+        socket_entries.clear();
+    } else if !filter.is_empty() {
+        socket_entries.retain(|e| filter.matches(e));
+    }
+
+    // Mark idle connections; --idle-only and --active-only filter on the marks.
+    if let Some(tracker) = idle {
+        if let Err(e) = tracker.update(&mut socket_entries) {
+            warn::warn("idle_unavailable", format!("{}; IDLE unavailable", e));
+        }
+        if let Some(only) = opts.idle_only {
+            socket_entries.retain(|e| only.keeps(e));
+        }
+    }
+
+    // Compare listeners' ports with the ports their environment asked for.
+    if env_hints {
+        let hints = hints::EnvHints::new(system);
+        for e in socket_entries.iter_mut().filter(|e| e.state == "Listen") {
+            let port = parse_addr_port(&e.local_addr).1;
+            e.env_hint = e.pids.iter().find_map(|&pid| hints.hint(system, pid, port));
+        }
+    }
+
+    // Map sockets to the descriptors their owners hold them on.
+    #[cfg(target_os = "linux")]
+    if opts.show_fd {
+        netstatw::procfd::fill(&mut socket_entries);
+    }
+
+    // Sample process stats once for the PIDs left after filtering.
+    let pid_stats = if show_stats {
+        let interval = Duration::from_millis(sample_interval_ms);
+        // Windows samples network counters and process counters one after the other.
+        let total = if cfg!(windows) {
+            interval * 2
+        } else {
+            interval
+        };
+        let _spinner = (total >= SPINNER_MIN && !socket_entries.is_empty())
+            .then(|| progress::Spinner::start(total));
+        // Listeners' accept rates: the TCP table before and after the window.
+        let listening = socket_entries
+            .iter()
+            .any(|e| e.proto == "TCP" && e.state == "Listen");
+        let before = listening
+            .then(|| accept::connections(opts.families))
+            .and_then(Result::ok);
+        let started = Instant::now();
+        let stats = sample_stats(system, &mut socket_entries, interval, !opts.forensic);
+        if let Some(before) = before {
+            match accept::connections(opts.families) {
+                Ok(after) => accept::fill(&mut socket_entries, &before, &after, started.elapsed()),
+                Err(e) => warn::warn(
+                    "accept_rate",
+                    format!("failed to read the TCP table: {}; no accept rates", e),
+                ),
+            }
+        }
+        stats
     } else {
-        println!(
-            "{}  {}  {}  {}  {}",
-            proto_sep, local_addr_sep, remote_addr_sep, state_sep, process_sep
+        HashMap::new()
+    };
+    // Flag remotes never seen before and remember this snapshot's remotes.
+    if let Some(path) = &seen_db {
+        match seen::SeenDb::open(path) {
+            Ok(mut db) => {
+                db.mark_and_update(&mut socket_entries);
+                if let Err(e) = db.save() {
+                    warn::warn(
+                        "seen_db",
+                        format!("failed to write {}: {}", path.display(), e),
+                    );
+                }
+            }
+            Err(e) => warn::warn(
+                "seen_db",
+                format!("failed to read {}: {}", path.display(), e),
+            ),
+        }
+    }
+
+    sort_entries(&mut socket_entries, sort_keys);
+
+    if resolve {
+        resolve::resolve_remotes(&mut socket_entries, RESOLVE_TIMEOUT);
+    }
+    if names {
+        services::label(&mut socket_entries);
+    }
+    if probe_rtt {
+        probe::probe_remotes(&mut socket_entries, PROBE_TIMEOUT);
+    }
+    if path_hints && let Err(e) = path::annotate(&mut socket_entries) {
+        warn::warn("path_hints_unavailable", format!("{}; PATH unavailable", e));
+    }
+    let closed = changes
+        .map(|t| t.update(&mut socket_entries))
+        .unwrap_or_default();
+
+    if let Some(every) = watch
+        && !json
+        && !csv
+    {
+        // Clear only once the new snapshot is ready, so the previous one stays up
+        // while sampling. Logs and dumb terminals get the snapshots one after another.
+        let opened = socket_entries.iter().filter(|e| e.change.is_some()).count();
+        let churn = match opts.changes {
+            true => format!(" ({} opened, {} closed)", opened, closed.len()),
+            false => String::new(),
+        };
+        let title = format!(
+            "Every {}s: {} rows{}. Press Ctrl-C to quit.",
+            every.as_secs_f64(),
+            socket_entries.len(),
+            churn
         );
+        if pager::active() {
+            pager::title(vec![title, String::new()]);
+        } else {
+            if color::interactive(&std::io::stdout()) {
+                print!("\x1b[2J\x1b[H");
+            }
+            println!("{}", title);
+            println!();
+        }
     }
+    print_entries(opts, &socket_entries, &closed, &pid_stats, system);
 
-    // Print sorted entries
-    for entry in socket_entries {
-        if show_stats {
-            let (cpu_s, r_s, w_s, rx_s, tx_s) = if let Some(s) = &entry.agg_stats {
-                (
-                    format!("{:.1}", s.cpu_pct),
-                    human_readable_rate(s.read_rate_bps),
-                    human_readable_rate(s.write_rate_bps),
-                    human_readable_rate(s.net_rx_rate_bps),
-                    human_readable_rate(s.net_tx_rate_bps),
-                )
+    // Machine output goes to the file alongside the human output on stdout, from the
+    // same sample.
+    let mut exit_code = 0;
+    if let Some(path) = &output
+        && let Err(e) = std::fs::write(
+            path,
+            if format == "csv" {
+                netstatw::csv::entries(&socket_entries, delimiter)
             } else {
-                (
-                    "N/A".to_string(),
-                    "N/A".to_string(),
-                    "N/A".to_string(),
-                    "N/A".to_string(),
-                    "N/A".to_string(),
-                )
-            };
-            println!(
-                "{:<10} {:<34} {:<27} {:<17} {:>7} {:>10} {:>10} {:>10} {:>10} {:<40}",
-                entry.proto,
-                entry.local_addr,
-                entry.remote_addr,
-                entry.state,
-                cpu_s,
-                r_s,
-                w_s,
-                rx_s,
-                tx_s,
-                entry.process_info
-            );
-        } else {
-            println!(
-                "{:<10} {:<34} {:<27} {:<17} {:<40}",
-                entry.proto, entry.local_addr, entry.remote_addr, entry.state, entry.process_info
-            );
+                json::snapshot(&socket_entries)
+            },
+        )
+    {
+        eprintln!("netstatw: failed to write {}: {}", path.display(), e);
+        exit_code = 1;
+    }
+    if let Some(path) = &opts.record
+        && let Err(e) = record::append(path, &socket_entries)
+    {
+        eprintln!("netstatw: failed to append to {}: {}", path.display(), e);
+        exit_code = 1;
+    }
+
+    // Run the --exec command for every listed row, after the table is out (not after
+    // Ctrl-C).
+    if let Some(exec_opts) = &exec
+        && !interrupt::interrupted()
+    {
+        exec::run_for_entries(exec_opts, &socket_entries);
+    }
+
+    if !opts.host_policy.is_empty() {
+        policy::emit(&opts.host_policy.check(&socket_entries), json_warnings);
+    }
+    if let Some(cap) = &opts.data_cap {
+        match cap.check(&socket_entries) {
+            Ok(breaches) => cap::emit(&breaches, cap, json_warnings),
+            Err(e) => warn::warn(
+                "data_cap_unavailable",
+                format!("{}; --data-cap inactive", e),
+            ),
         }
     }
+
+    if let (Some(tracker), Some(every)) = (tracker, watch)
+        && !opts.alerts.is_empty()
+    {
+        let alerts = tracker.update(opts.alerts, &socket_entries);
+        alert::emit(&alerts, every, json_warnings);
+    }
+    alert::emit_held_back(json_warnings);
+
+    warn::emit(json_warnings);
+    exit_code
 }
@@ -0,0 +1,132 @@
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{SocketEntry, zone};
+
+// netstatw's own sockets: the connections and listeners of this process (`serve`'s
+// exporter, `--probe-rtt` handshakes) and what they leave behind without an owner
+// (TIME_WAIT after the probes and the exporter's closed requests). They are left out
+// of the binary's tables by default so the tool doesn't show up in its own
+// measurements; `--include-self` keeps them. Exclusion is off until the binary turns it
+// on, so library callers of `snapshot()` see every socket, their own included.
+
+static EXCLUDE: AtomicBool = AtomicBool::new(false);
+// Local ends of probe connections.
+static PROBES: Mutex<Option<HashSet<SocketAddr>>> = Mutex::new(None);
+// Addresses this process listens on.
+static LISTENERS: Mutex<Vec<SocketAddr>> = Mutex::new(Vec::new());
+
+pub fn exclude_self(exclude: bool) {
+    EXCLUDE.store(exclude, Ordering::Relaxed);
+}
+
+fn excluding() -> bool {
+    EXCLUDE.load(Ordering::Relaxed)
+}
+
+// The probe and listener addresses are only kept while exclusion is on.
+pub fn note_probe(local: SocketAddr) {
+    if !excluding() {
+        return;
+    }
+    if let Ok(mut probes) = PROBES.lock() {
+        probes.get_or_insert_with(HashSet::new).insert(local);
+    }
+}
+
+pub fn note_listener(addr: SocketAddr) {
+    if !excluding() {
+        return;
+    }
+    if let Ok(mut listeners) = LISTENERS.lock() {
+        listeners.push(addr);
+    }
+}
+
+fn is_own(
+    e: &SocketEntry,
+    pid: u32,
+    probes: &HashSet<SocketAddr>,
+    listeners: &[SocketAddr],
+) -> bool {
+    if e.pids.contains(&pid) {
+        return true;
+    }
+    if !e.pids.is_empty() || e.proto != "TCP" {
+        return false;
+    }
+    let Some(local) = zone::socket_addr(&e.local_addr) else {
+        return false;
+    };
+    probes.contains(&local)
+        || listeners.iter().any(|l| {
+            l.port() == local.port()
+                && (l.ip().is_unspecified() || l.ip().to_canonical() == local.ip().to_canonical())
+        })
+}
+
+// Drops this process's sockets if the binary turned exclusion on.
+pub fn exclude(entries: &mut Vec<SocketEntry>) {
+    if !excluding() {
+        return;
+    }
+    let probes = PROBES
+        .lock()
+        .ok()
+        .and_then(|p| p.clone())
+        .unwrap_or_default();
+    let listeners = LISTENERS.lock().map(|l| l.clone()).unwrap_or_default();
+    let pid = std::process::id();
+    entries.retain(|e| !is_own(e, pid, &probes, &listeners));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(local: &str, remote: &str, state: &str, pids: &[u32]) -> SocketEntry {
+        SocketEntry {
+            proto: "TCP".to_string(),
+            local_addr: local.to_string(),
+            remote_addr: remote.to_string(),
+            state: state.to_string(),
+            pids: pids.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn own_sockets_and_their_leftovers_are_recognized() {
+        let probes = HashSet::from(["10.0.0.1:40000".parse().unwrap()]);
+        let listeners = ["0.0.0.0:9184".parse().unwrap()];
+        let own = |e: &SocketEntry| is_own(e, 7, &probes, &listeners);
+        assert!(own(&row("0.0.0.0:9184", "0.0.0.0:0", "Listen", &[7])));
+        assert!(own(&row(
+            "10.0.0.1:40000",
+            "203.0.113.5:443",
+            "TimeWait",
+            &[]
+        )));
+        assert!(own(&row(
+            "127.0.0.1:9184",
+            "127.0.0.1:51000",
+            "TimeWait",
+            &[]
+        )));
+        // The scraper's end of the same connection is its own.
+        assert!(!own(&row(
+            "127.0.0.1:51000",
+            "127.0.0.1:9184",
+            "TimeWait",
+            &[]
+        )));
+        assert!(!own(&row(
+            "10.0.0.1:40000",
+            "203.0.113.5:443",
+            "Established",
+            &[8]
+        )));
+    }
+}
@@ -0,0 +1,183 @@
+use std::io::{self, IsTerminal, Write};
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+use crate::tui::term::{self, Key};
+use crate::{color, interrupt};
+
+// `--watch` on a terminal: the table is kept to the screen instead of scrolling off
+// its top or wrapping. The title and the column headers stay put while the rows
+// scroll (Up/Down, PgUp/PgDn, Home/End) and everything pans sideways (Left/Right)
+// when the table is wider than the terminal. Keys are read between refreshes; the
+// last table is redrawn at once for each. Ctrl-C still interrupts as usual, `q`
+// quits.
+
+// Rows and characters moved per key.
+const PAN_STEP: usize = 8;
+// How often `wait` checks for Ctrl-C and the deadline.
+const POLL: Duration = Duration::from_millis(50);
+
+struct Pager {
+    // Restores the terminal when dropped.
+    _raw: term::RawMode,
+    keys: Receiver<Key>,
+    title: Vec<String>,
+    // Table lines: the headers (`pinned` of them), then the rows.
+    lines: Vec<String>,
+    pinned: usize,
+    top: usize,
+    left: usize,
+}
+
+static PAGER: Mutex<Option<Pager>> = Mutex::new(None);
+
+// Takes over the terminal when stdin and stdout are interactive; false otherwise.
+pub fn start() -> bool {
+    if !io::stdin().is_terminal() || !color::interactive(&io::stdout()) {
+        return false;
+    }
+    let Ok(raw) = term::RawMode::enable(true) else {
+        return false;
+    };
+    let (tx, keys) = mpsc::channel();
+    term::spawn_reader(tx, |key| key);
+    if let Ok(mut pager) = PAGER.lock() {
+        *pager = Some(Pager {
+            _raw: raw,
+            keys,
+            title: Vec::new(),
+            lines: Vec::new(),
+            pinned: 0,
+            top: 0,
+            left: 0,
+        });
+    }
+    true
+}
+
+pub fn active() -> bool {
+    PAGER.lock().is_ok_and(|p| p.is_some())
+}
+
+// Gives the terminal back.
+pub fn stop() {
+    if let Ok(mut pager) = PAGER.lock() {
+        pager.take();
+    }
+}
+
+// The lines above the table for the next `show`.
+pub fn title(lines: Vec<String>) {
+    if let Ok(mut pager) = PAGER.lock()
+        && let Some(p) = pager.as_mut()
+    {
+        p.title = lines;
+    }
+}
+
+// Replaces the table and draws it, keeping the scroll position.
+pub fn show(table: &str, pinned: usize) {
+    let Ok(mut pager) = PAGER.lock() else { return };
+    let Some(p) = pager.as_mut() else { return };
+    p.lines = table.lines().map(str::to_string).collect();
+    p.pinned = pinned;
+    p.draw();
+}
+
+// Sleeps for `d` like `interrupt::sleep`, handling keys meanwhile. Returns false if
+// cut short: Ctrl-C, the deadline or `q`.
+pub fn wait(d: Duration) -> bool {
+    let until = Instant::now() + d;
+    loop {
+        if interrupt::should_stop() {
+            return false;
+        }
+        let left = until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return true;
+        }
+        let Ok(mut pager) = PAGER.lock() else {
+            return interrupt::sleep(left);
+        };
+        let Some(p) = pager.as_mut() else {
+            drop(pager);
+            return interrupt::sleep(left);
+        };
+        match p.keys.recv_timeout(left.min(POLL)) {
+            Ok(Key::Char('q')) => return false,
+            Ok(key) => {
+                p.on_key(key);
+                p.draw();
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            // stdin closed: no more keys, plain sleeps.
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                drop(pager);
+                return interrupt::sleep(left);
+            }
+        }
+    }
+}
+
+impl Pager {
+    // Table rows that fit under the title, the headers and the status line.
+    fn body(&self, height: usize) -> usize {
+        height.saturating_sub(self.title.len() + self.pinned + 1)
+    }
+
+    fn on_key(&mut self, key: Key) {
+        let (_, height) = term::size();
+        let page = self.body(height).max(1);
+        match key {
+            Key::Up | Key::Char('k') => self.top = self.top.saturating_sub(1),
+            Key::Down | Key::Char('j') => self.top += 1,
+            Key::PageUp => self.top = self.top.saturating_sub(page),
+            Key::PageDown => self.top += page,
+            Key::Home | Key::Char('g') => self.top = 0,
+            Key::End | Key::Char('G') => self.top = usize::MAX,
+            Key::Left | Key::Char('h') => self.left = self.left.saturating_sub(PAN_STEP),
+            Key::Right | Key::Char('l') => self.left += PAN_STEP,
+            _ => {}
+        }
+    }
+
+    fn draw(&mut self) {
+        let (width, height) = term::size();
+        let body = self.body(height);
+        let rows = self.lines.len().saturating_sub(self.pinned);
+        self.top = self.top.min(rows.saturating_sub(body));
+        let widest = self
+            .lines
+            .iter()
+            .map(|l| color::slice(l, 0, usize::MAX).chars().count())
+            .max()
+            .unwrap_or(0);
+        self.left = self.left.min(widest.saturating_sub(width));
+
+        let mut frame = String::from("\x1b[H");
+        for line in &self.title {
+            frame.push_str(&color::slice(line, 0, width));
+            frame.push_str("\x1b[K\r\n");
+        }
+        let shown = self.lines[..self.pinned.min(self.lines.len())]
+            .iter()
+            .chain(self.lines.iter().skip(self.pinned + self.top).take(body));
+        for line in shown {
+            frame.push_str(&color::slice(line, self.left, width));
+            frame.push_str("\x1b[K\r\n");
+        }
+        let status = format!(
+            " rows {}-{} of {}  column {}   Up/Down PgUp/PgDn scroll  Left/Right pan  q quit",
+            (self.top + 1).min(rows),
+            (self.top + body).min(rows),
+            rows,
+            self.left + 1
+        );
+        frame.push_str(&color::slice(&status, 0, width));
+        frame.push_str("\x1b[K\x1b[J");
+        let mut out = io::stdout();
+        let _ = out.write_all(frame.as_bytes());
+        let _ = out.flush();
+    }
+}
@@ -0,0 +1,145 @@
+use crate::SocketEntry;
+#[cfg(target_os = "linux")]
+use crate::zone;
+
+// `--path-hints`: a heuristic PATH column for TCP connections whose kernel state looks
+// like a path problem rather than a slow peer. The signals come from tcp_info (Linux,
+// sock_diag): the sender's MSS and path MTU, the congestion window, and how many
+// segments are in flight, lost and being retransmitted. The classic PMTUD blackhole
+// (a router drops full-size packets and the ICMP "fragmentation needed" never arrives)
+// shows up as a connection whose small packets went through (the handshake, requests)
+// but whose full-size segments keep timing out: repeated RTOs, a collapsed cwnd, and
+// an MSS still at the interface's size because the MTU was never lowered.
+
+// Consecutive retransmission timeouts before a connection counts as stalled.
+const STALL_RTOS: u8 = 2;
+// MSS above which segments are "full size" for a 1500-byte link (tunnels and IPv6
+// minimum-MTU paths sit below it).
+const FULL_SIZE_MSS: u32 = 1200;
+// Congestion window (in segments) a run of timeouts leaves behind.
+const COLLAPSED_CWND: u32 = 2;
+// Retransmitted share of sent segments flagged as lossy, and the minimum sample.
+const LOSSY_PERCENT: u64 = 5;
+const LOSSY_MIN_SEGS: u32 = 200;
+
+// The tcp_info fields the heuristic reads.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PathInfo {
+    pub mss: u32,
+    pub pmtu: u32,
+    pub cwnd: u32,
+    // Segments sent and not yet acknowledged, and those of them considered lost.
+    pub unacked: u32,
+    pub lost: u32,
+    // Consecutive timeouts of the segment at the head of the queue (0 when healthy).
+    pub retransmits: u8,
+    pub total_retrans: u32,
+    // Segments sent; None on kernels before 4.2.
+    pub segs_out: Option<u32>,
+}
+
+impl PathInfo {
+    // The PATH column: "blackhole?", "stalled", "lossy", or "ok".
+    pub fn hint(&self) -> &'static str {
+        let stalled = self.unacked > 0 && self.retransmits >= STALL_RTOS;
+        if stalled && self.cwnd <= COLLAPSED_CWND && self.mss >= FULL_SIZE_MSS {
+            "blackhole?"
+        } else if stalled {
+            "stalled"
+        } else if self.segs_out.is_some_and(|segs| {
+            segs >= LOSSY_MIN_SEGS && self.total_retrans as u64 * 100 >= segs as u64 * LOSSY_PERCENT
+        }) {
+            "lossy"
+        } else {
+            "ok"
+        }
+    }
+
+    // The counters and what the hint means, for the TUI details pane.
+    pub fn detail(&self) -> String {
+        let mut text = format!(
+            "mss {}  pmtu {}  cwnd {}  unacked {}  lost {}  rto x{}  retrans {}",
+            self.mss,
+            self.pmtu,
+            self.cwnd,
+            self.unacked,
+            self.lost,
+            self.retransmits,
+            self.total_retrans
+        );
+        if let Some(segs) = self.segs_out {
+            text.push_str(&format!("/{} segs", segs));
+        }
+        let why = match self.hint() {
+            "blackhole?" => {
+                "full-size segments keep timing out: likely PMTUD blackhole (ICMP filtered; \
+                 try a lower MTU or MSS clamping)"
+            }
+            "stalled" => "repeated retransmission timeouts: peer down or path broken",
+            "lossy" => "high retransmission rate",
+            _ => "",
+        };
+        if !why.is_empty() {
+            text.push_str(&format!("  - {}", why));
+        }
+        text
+    }
+}
+
+// Attaches the tcp_info of each TCP connection to its row. Only Linux exposes it
+// without enabling collection; elsewhere this reports why there are no hints.
+#[cfg(target_os = "linux")]
+pub fn annotate(entries: &mut [SocketEntry]) -> Result<(), String> {
+    let infos = crate::linux_net::path_info().map_err(|e| format!("netlink sock_diag: {}", e))?;
+    for e in entries.iter_mut().filter(|e| e.proto == "TCP") {
+        e.path = infos
+            .get(&(zone::strip(&e.local_addr), zone::strip(&e.remote_addr)))
+            .cloned();
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn annotate(_: &mut [SocketEntry]) -> Result<(), String> {
+    Err("path hints need tcp_info (Linux only)".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(mss: u32, cwnd: u32, retransmits: u8) -> PathInfo {
+        PathInfo {
+            mss,
+            pmtu: 1500,
+            cwnd,
+            unacked: 10,
+            lost: 10,
+            retransmits,
+            total_retrans: 4,
+            segs_out: Some(40),
+        }
+    }
+
+    #[test]
+    fn hint_separates_blackholes_from_other_stalls() {
+        assert_eq!(info(1448, 1, 3).hint(), "blackhole?");
+        assert!(info(1448, 1, 3).detail().contains("PMTUD blackhole"));
+        assert_eq!(info(536, 1, 3).hint(), "stalled");
+        assert_eq!(info(1448, 10, 0).hint(), "ok");
+        let lossy = PathInfo {
+            total_retrans: 30,
+            segs_out: Some(400),
+            ..info(1448, 10, 0)
+        };
+        assert_eq!(lossy.hint(), "lossy");
+        assert_eq!(
+            PathInfo {
+                segs_out: None,
+                ..lossy
+            }
+            .hint(),
+            "ok"
+        );
+    }
+}
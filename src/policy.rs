@@ -0,0 +1,292 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::config::{self, Config, Value};
+use crate::{SocketEntry, alert, json, parse_addr_port};
+
+// `--host-policy`: alerts on connections to hosts a policy rules out by name, since
+// many security policies are written in domains rather than addresses. Each
+// `[policy.<name>]` section, from `--host-policy FILE` or else the config file, is a
+// rule:
+//
+//   [policy.no-ru]
+//   deny = ["*.ru"]
+//   allow = ["yandex.ru", "mirror.example.ru"]
+//
+// A pattern `example.com` matches that name and every name under it, `*.example.com`
+// (or `.example.com`) only the names under it, and `*` any name; matching ignores case
+// and a trailing dot. A connection violates a rule when its reverse-DNS name matches
+// a `deny` pattern and no `allow` pattern. Names come from `--resolve` (which this
+// option turns on); connections whose address has no name can't be judged and pass.
+
+#[derive(Clone, Debug, PartialEq)]
+enum Pattern {
+    Any,
+    // The name itself and its subdomains.
+    Domain(String),
+    // Subdomains only.
+    Subdomains(String),
+}
+
+fn normalize(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+impl Pattern {
+    fn parse(v: &str) -> Result<Pattern, String> {
+        let v = normalize(v.trim());
+        let pattern = if v == "*" {
+            Pattern::Any
+        } else if let Some(domain) = v.strip_prefix("*.").or_else(|| v.strip_prefix('.')) {
+            Pattern::Subdomains(domain.to_string())
+        } else {
+            Pattern::Domain(v.clone())
+        };
+        match &pattern {
+            Pattern::Domain(d) | Pattern::Subdomains(d)
+                if d.is_empty() || d.contains(['*', ' ', '/']) =>
+            {
+                Err(format!("'{}' is not a domain pattern", v))
+            }
+            _ => Ok(pattern),
+        }
+    }
+
+    // `host` is normalized.
+    fn matches(&self, host: &str) -> bool {
+        let under = |domain: &str| {
+            host.strip_suffix(domain)
+                .is_some_and(|rest| rest.ends_with('.'))
+        };
+        match self {
+            Pattern::Any => true,
+            Pattern::Domain(d) => host == d || under(d),
+            Pattern::Subdomains(d) => under(d),
+        }
+    }
+}
+
+struct Rule {
+    name: String,
+    deny: Vec<Pattern>,
+    allow: Vec<Pattern>,
+}
+
+impl Rule {
+    fn violated_by(&self, host: &str) -> bool {
+        self.deny.iter().any(|p| p.matches(host)) && !self.allow.iter().any(|p| p.matches(host))
+    }
+}
+
+// A connection a rule rules out.
+pub struct Violation {
+    pub rule: String,
+    pub host: String,
+    pub remote: String,
+    pub process: String,
+}
+
+#[derive(Default)]
+pub struct Policy {
+    rules: Vec<Rule>,
+    // Host name -> names of the rules it violates; names repeat across connections
+    // and refreshes.
+    verdicts: Mutex<HashMap<String, Vec<String>>>,
+    // Connections already reported, so `--watch` alerts once per connection.
+    reported: Mutex<HashSet<(String, String)>>,
+}
+
+fn patterns(value: &Value) -> Result<Vec<Pattern>, String> {
+    match value {
+        Value::List(items) => items.iter().map(|v| Pattern::parse(&v.to_arg())).collect(),
+        other => Ok(vec![Pattern::parse(&other.to_arg())?]),
+    }
+}
+
+impl Policy {
+    pub fn from_config(cfg: &Config) -> Result<Policy, String> {
+        let mut rules = Vec::new();
+        for name in cfg.subsections("policy") {
+            let mut rule = Rule {
+                name: name.to_string(),
+                deny: Vec::new(),
+                allow: Vec::new(),
+            };
+            for (key, value) in cfg.section(&format!("policy.{}", name)).unwrap_or_default() {
+                let list = match key.as_str() {
+                    "deny" => &mut rule.deny,
+                    "allow" => &mut rule.allow,
+                    _ => return Err(format!("[policy.{}] unknown key '{}'", name, key)),
+                };
+                list.extend(patterns(value).map_err(|e| format!("[policy.{}] {}", name, e))?);
+            }
+            if rule.deny.is_empty() {
+                return Err(format!("[policy.{}] has no deny patterns", name));
+            }
+            rules.push(rule);
+        }
+        Ok(Policy {
+            rules,
+            ..Default::default()
+        })
+    }
+
+    // Rules from `path`, or from the config file without one.
+    pub fn load(path: Option<&Path>) -> Result<Policy, String> {
+        let cfg = match path {
+            Some(path) => fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|text| Config::parse(&text))
+                .map_err(|e| format!("{}: {}", path.display(), e))?,
+            None => Config::load()?,
+        };
+        Policy::from_config(&cfg).map_err(|e| {
+            match path.map(Path::to_path_buf).or_else(config::config_path) {
+                Some(p) => format!("{}: {}", p.display(), e),
+                None => e,
+            }
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    fn verdict(&self, host: &str) -> Vec<String> {
+        let host = normalize(host);
+        let mut cache = self.verdicts.lock().unwrap_or_else(|e| e.into_inner());
+        cache
+            .entry(host)
+            .or_insert_with_key(|host| {
+                self.rules
+                    .iter()
+                    .filter(|r| r.violated_by(host))
+                    .map(|r| r.name.clone())
+                    .collect()
+            })
+            .clone()
+    }
+
+    // Violations among `entries` not reported by an earlier call.
+    pub fn check(&self, entries: &[SocketEntry]) -> Vec<Violation> {
+        let mut reported = self.reported.lock().unwrap_or_else(|e| e.into_inner());
+        let mut out = Vec::new();
+        for e in entries {
+            let Some(host) = &e.remote_host else { continue };
+            if parse_addr_port(&e.remote_addr).1 == 0 {
+                continue;
+            }
+            for rule in self.verdict(host) {
+                if reported.insert((e.conn_id(), rule.clone())) {
+                    out.push(Violation {
+                        rule,
+                        host: host.clone(),
+                        remote: e.remote_addr.clone(),
+                        process: e.process_info.clone(),
+                    });
+                }
+            }
+        }
+        out
+    }
+}
+
+pub fn emit(violations: &[Violation], as_json: bool) {
+    for v in violations {
+        let key = format!("policy {} {}", v.rule, alert::remote_host(&v.remote));
+        let Some(held) = alert::admit(&key) else {
+            continue;
+        };
+        if as_json {
+            eprintln!(
+                "{}",
+                json::object(&[
+                    ("level", json::str("policy")),
+                    ("rule", json::str(&v.rule)),
+                    ("host", json::str(&v.host)),
+                    ("remote", json::str(&v.remote)),
+                    ("process", json::str(&v.process)),
+                    ("held_back", held.to_string()),
+                ])
+            );
+        } else {
+            eprintln!(
+                "netstatw: policy {}: {} connected to {} ({}){}",
+                v.rule,
+                v.process,
+                v.host,
+                v.remote,
+                alert::held_back_note(held)
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conn(remote: &str, host: Option<&str>) -> SocketEntry {
+        SocketEntry {
+            proto: "TCP".to_string(),
+            local_addr: "10.0.0.1:40000".to_string(),
+            remote_addr: remote.to_string(),
+            state: "Established".to_string(),
+            process_info: "7: /usr/bin/curl".to_string(),
+            remote_host: host.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn deny_patterns_match_suffixes_minus_the_allowlist() {
+        let cfg = Config::parse(
+            "[policy.no-ru]\n\
+             deny = [\"*.ru\", \"ru\"]\n\
+             allow = \"Yandex.ru.\"\n\
+             [policy.no-paste]\n\
+             deny = \"pastebin.com\"\n",
+        )
+        .unwrap();
+        let policy = Policy::from_config(&cfg).unwrap();
+        let rows = [
+            conn("203.0.113.5:443", Some("files.evil.RU.")),
+            conn("203.0.113.6:443", Some("mail.yandex.ru")),
+            conn("203.0.113.7:443", Some("rubygems.org")),
+            conn("203.0.113.8:443", Some("pastebin.com")),
+            conn("203.0.113.9:443", Some("notpastebin.com")),
+            conn("203.0.113.10:443", None),
+        ];
+        let found: Vec<(String, String)> = policy
+            .check(&rows)
+            .into_iter()
+            .map(|v| (v.rule, v.host))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("no-ru".to_string(), "files.evil.RU.".to_string()),
+                ("no-paste".to_string(), "pastebin.com".to_string()),
+            ]
+        );
+        // Each connection is reported once.
+        assert!(policy.check(&rows).is_empty());
+    }
+
+    #[test]
+    fn rules_need_deny_patterns_and_known_keys() {
+        for text in [
+            "[policy.x]\nallow = \"example.com\"\n",
+            "[policy.x]\ndeny = \"*.ru\"\nport = 443\n",
+            "[policy.x]\ndeny = \"a*.ru\"\n",
+        ] {
+            assert!(
+                Policy::from_config(&Config::parse(text).unwrap()).is_err(),
+                "{}",
+                text
+            );
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{SocketEntry, forensic, interrupt, own, zone};
+
+// `--probe-rtt`: an active round-trip measurement to the remote hosts in view. Each
+// host gets a few TCP handshakes to the port one of its connections uses; the time to
+// the SYN-ACK (connect succeeds) or RST (refused) is one round trip, and the fastest
+// of them is shown. Handshakes that complete are closed right away. The number of
+// hosts and probes is bounded so a busy table doesn't turn into a scan.
+
+// Distinct remote hosts probed per snapshot; the rest show no PING.
+pub const MAX_HOSTS: usize = 32;
+// Handshakes per host.
+const PROBES: usize = 3;
+
+// The remote address of a TCP connection, if it has one worth probing.
+fn target(e: &SocketEntry) -> Option<SocketAddr> {
+    if e.proto != "TCP" || e.state == "Listen" {
+        return None;
+    }
+    let addr = zone::socket_addr(&e.remote_addr)?;
+    (!addr.ip().is_unspecified() && addr.port() != 0).then_some(addr)
+}
+
+// One port per host, first come first served, at most MAX_HOSTS hosts.
+fn targets(entries: &[SocketEntry]) -> Vec<SocketAddr> {
+    let mut out: Vec<SocketAddr> = Vec::new();
+    for addr in entries.iter().filter_map(target) {
+        if out.len() == MAX_HOSTS {
+            break;
+        }
+        if !out.iter().any(|t| t.ip() == addr.ip()) {
+            out.push(addr);
+        }
+    }
+    out
+}
+
+// Fastest handshake to `addr` within `timeout` per attempt.
+fn rtt(addr: SocketAddr, timeout: Duration) -> Option<Duration> {
+    let mut best: Option<Duration> = None;
+    for _ in 0..PROBES {
+        if interrupt::should_stop() {
+            break;
+        }
+        let started = Instant::now();
+        let answered = match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => {
+                // Our end lingers in TIME_WAIT; keep it out of the table.
+                if let Ok(local) = stream.local_addr() {
+                    own::note_probe(local);
+                }
+                true
+            }
+            Err(e) => e.kind() == io::ErrorKind::ConnectionRefused,
+        };
+        if answered {
+            let took = started.elapsed();
+            best = Some(best.map_or(took, |b| b.min(took)));
+        }
+    }
+    best
+}
+
+// Sets `rtt_ms` on every TCP connection whose remote host answered. Hosts are probed
+// in parallel; each handshake waits at most `timeout`.
+pub fn probe_remotes(entries: &mut [SocketEntry], timeout: Duration) {
+    let timeout = interrupt::remaining().map_or(timeout, |left| left.min(timeout));
+    let targets = targets(entries);
+    if targets.is_empty() || timeout.is_zero() {
+        return;
+    }
+    forensic::record("TCP connect (RTT probes to remote hosts)");
+    let results: HashMap<IpAddr, Duration> = thread::scope(|s| {
+        let probes: Vec<_> = targets
+            .iter()
+            .map(|&addr| s.spawn(move || (addr.ip(), rtt(addr, timeout))))
+            .collect();
+        probes
+            .into_iter()
+            .filter_map(|p| p.join().ok())
+            .filter_map(|(ip, rtt)| Some((ip, rtt?)))
+            .collect()
+    });
+    for e in entries.iter_mut() {
+        if let Some(took) = target(e).and_then(|addr| results.get(&addr.ip())) {
+            e.rtt_ms = Some(took.as_secs_f64() * 1000.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    fn tcp(remote: &str, state: &str) -> SocketEntry {
+        SocketEntry {
+            proto: "TCP".to_string(),
+            local_addr: "10.0.0.1:40000".to_string(),
+            remote_addr: remote.to_string(),
+            state: state.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn targets_take_one_port_per_remote_host() {
+        let rows = [
+            tcp("0.0.0.0:0", "Listen"),
+            tcp("10.0.0.5:5432", "Established"),
+            tcp("10.0.0.5:6379", "Established"),
+            tcp("10.0.0.6:443", "SynSent"),
+        ];
+        assert_eq!(
+            targets(&rows),
+            [
+                "10.0.0.5:5432".parse::<SocketAddr>().unwrap(),
+                "10.0.0.6:443".parse().unwrap()
+            ]
+        );
+    }
+
+    #[test]
+    fn probe_remotes_measures_a_listening_peer() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut rows = [
+            tcp(&addr.to_string(), "Established"),
+            tcp("0.0.0.0:0", "Listen"),
+        ];
+        probe_remotes(&mut rows, Duration::from_secs(1));
+        assert!(rows[0].rtt_ms.is_some_and(|ms| ms >= 0.0));
+        assert_eq!(rows[1].rtt_ms, None);
+    }
+}
@@ -0,0 +1,67 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+use crate::{SocketEntry, forensic};
+
+// Linux only: which file descriptor each owning process holds a socket on, found by
+// reading the `socket:[<inode>]` links under /proc/<pid>/fd. Only the PIDs already in
+// the table are scanned, so this stays cheap after filtering.
+
+// Inode of a `/proc/<pid>/fd/<n>` link target such as `socket:[123456]`.
+fn socket_inode(link: &str) -> Option<u32> {
+    link.strip_prefix("socket:[")?
+        .strip_suffix(']')?
+        .parse()
+        .ok()
+}
+
+// inode -> [(pid, fd)] for the sockets held by `pids`. Unreadable fd directories
+// (other users' processes without root) are skipped.
+pub fn socket_fds(pids: &HashSet<u32>) -> HashMap<u32, Vec<(u32, u32)>> {
+    let mut out: HashMap<u32, Vec<(u32, u32)>> = HashMap::new();
+    for &pid in pids {
+        let Ok(dir) = fs::read_dir(format!("/proc/{}/fd", pid)) else {
+            continue;
+        };
+        for fd in dir.flatten() {
+            let Some(n) = fd.file_name().to_str().and_then(|n| n.parse::<u32>().ok()) else {
+                continue;
+            };
+            if let Some(inode) = fs::read_link(fd.path())
+                .ok()
+                .and_then(|l| socket_inode(&l.to_string_lossy()))
+            {
+                out.entry(inode).or_default().push((pid, n));
+            }
+        }
+    }
+    out
+}
+
+// Fills `fds` for every row that has an inode.
+pub fn fill(entries: &mut [SocketEntry]) {
+    let pids: HashSet<u32> = entries
+        .iter()
+        .flat_map(|e| e.pids.iter().copied())
+        .collect();
+    forensic::record("/proc/<pid>/fd (socket descriptors)");
+    let mut by_inode = socket_fds(&pids);
+    for e in entries.iter_mut() {
+        if let Some(fds) = e.inode.and_then(|i| by_inode.get_mut(&i)) {
+            fds.sort_unstable();
+            e.fds = fds.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn socket_inode_parses_socket_links_only() {
+        assert_eq!(socket_inode("socket:[123456]"), Some(123456));
+        assert_eq!(socket_inode("pipe:[99]"), None);
+        assert_eq!(socket_inode("/dev/null"), None);
+    }
+}
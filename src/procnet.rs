@@ -0,0 +1,166 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use netstat2::{
+    AddressFamilyFlags, ProtocolFlags, ProtocolSocketInfo, SocketInfo, TcpSocketInfo, TcpState,
+    UdpSocketInfo,
+};
+
+use crate::{forensic, procfd};
+
+// Linux only: the socket tables read from /proc/net/{tcp,tcp6,udp,udp6} instead of
+// sock_diag netlink, for the `procfs` feature (the static container build), with
+// owners found through the `socket:[inode]` links under /proc/<pid>/fd like netstat2
+// does. Produces netstat2's own types so the rest of the pipeline doesn't change.
+
+// A kernel address: 8 hex digits (IPv4) or 32 (IPv6), each 32-bit word in host
+// byte order.
+fn parse_addr(hex: &str) -> Option<IpAddr> {
+    let word = |i: usize| u32::from_str_radix(hex.get(i * 8..i * 8 + 8)?, 16).ok();
+    match hex.len() {
+        8 => Some(IpAddr::V4(Ipv4Addr::from(word(0)?.to_ne_bytes()))),
+        32 => {
+            let mut bytes = [0u8; 16];
+            for i in 0..4 {
+                bytes[i * 4..i * 4 + 4].copy_from_slice(&word(i)?.to_ne_bytes());
+            }
+            Some(IpAddr::V6(Ipv6Addr::from(bytes)))
+        }
+        _ => None,
+    }
+}
+
+// "0100007F:0016" -> (127.0.0.1, 22).
+fn parse_endpoint(v: &str) -> Option<(IpAddr, u16)> {
+    let (addr, port) = v.split_once(':')?;
+    Some((parse_addr(addr)?, u16::from_str_radix(port, 16).ok()?))
+}
+
+// The `st` column (include/net/tcp_states.h).
+fn tcp_state(v: &str) -> TcpState {
+    match u8::from_str_radix(v, 16).unwrap_or(0) {
+        1 => TcpState::Established,
+        2 => TcpState::SynSent,
+        3 => TcpState::SynReceived,
+        4 => TcpState::FinWait1,
+        5 => TcpState::FinWait2,
+        6 => TcpState::TimeWait,
+        7 => TcpState::Closed,
+        8 => TcpState::CloseWait,
+        9 => TcpState::LastAck,
+        10 => TcpState::Listen,
+        11 => TcpState::Closing,
+        _ => TcpState::Unknown,
+    }
+}
+
+// The rows of one /proc/net table, without owners.
+fn parse_table(text: &str, tcp: bool) -> Vec<SocketInfo> {
+    let mut out = Vec::new();
+    // sl local_address rem_address st tx_queue:rx_queue tr:tm->when retrnsmt uid timeout inode
+    for line in text.lines().skip(1) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 10 {
+            continue;
+        }
+        let (Some((local_addr, local_port)), Some((remote_addr, remote_port))) =
+            (parse_endpoint(fields[1]), parse_endpoint(fields[2]))
+        else {
+            continue;
+        };
+        let protocol_socket_info = if tcp {
+            ProtocolSocketInfo::Tcp(TcpSocketInfo {
+                local_addr,
+                local_port,
+                remote_addr,
+                remote_port,
+                state: tcp_state(fields[3]),
+            })
+        } else {
+            ProtocolSocketInfo::Udp(UdpSocketInfo {
+                local_addr,
+                local_port,
+            })
+        };
+        out.push(SocketInfo {
+            protocol_socket_info,
+            associated_pids: Vec::new(),
+            inode: fields[9].parse().unwrap_or(0),
+            uid: fields[7].parse().unwrap_or(0),
+        });
+    }
+    out
+}
+
+pub fn sockets_info(
+    af_flags: AddressFamilyFlags,
+    proto_flags: ProtocolFlags,
+) -> Result<Vec<SocketInfo>, netstat2::error::Error> {
+    forensic::record("/proc/net/{tcp,tcp6,udp,udp6} (socket tables)");
+    let mut out = Vec::new();
+    for (file, family, proto) in [
+        ("tcp", AddressFamilyFlags::IPV4, ProtocolFlags::TCP),
+        ("tcp6", AddressFamilyFlags::IPV6, ProtocolFlags::TCP),
+        ("udp", AddressFamilyFlags::IPV4, ProtocolFlags::UDP),
+        ("udp6", AddressFamilyFlags::IPV6, ProtocolFlags::UDP),
+    ] {
+        if !af_flags.contains(family) || !proto_flags.contains(proto) {
+            continue;
+        }
+        match fs::read_to_string(format!("/proc/net/{}", file)) {
+            Ok(text) => out.extend(parse_table(&text, proto == ProtocolFlags::TCP)),
+            // A kernel without IPv6 has no tcp6/udp6.
+            Err(e) if e.kind() == io::ErrorKind::NotFound && family == AddressFamilyFlags::IPV6 => {
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    forensic::record("/proc/<pid>/fd (socket owners)");
+    let pids: HashSet<u32> = fs::read_dir("/proc")?
+        .flatten()
+        .filter_map(|d| d.file_name().to_str()?.parse().ok())
+        .collect();
+    let owners = procfd::socket_fds(&pids);
+    for s in out.iter_mut().filter(|s| s.inode != 0) {
+        if let Some(fds) = owners.get(&s.inode) {
+            let mut pids: Vec<u32> = fds.iter().map(|&(pid, _)| pid).collect();
+            pids.sort_unstable();
+            pids.dedup();
+            s.associated_pids = pids;
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proc_net_rows_parse_into_socket_info() {
+        let tcp = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n   \
+            0: 0100007F:0016 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 4242 1 0 100 0 0 10 0\n   \
+            1: 0F02000A:9C40 0571A8C0:01BB 01 00000000:00000000 02:000A7D2B 00000000  1000        0 4243 1 0 20 4 30 10 -1\n";
+        let rows = parse_table(tcp, true);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].local_addr(), IpAddr::from([127, 0, 0, 1]));
+        assert_eq!(rows[0].local_port(), 22);
+        assert_eq!(rows[0].inode, 4242);
+        let ProtocolSocketInfo::Tcp(conn) = &rows[1].protocol_socket_info else {
+            panic!("expected a TCP row");
+        };
+        assert_eq!(conn.local_addr, IpAddr::from([10, 0, 2, 15]));
+        assert_eq!(conn.remote_addr, IpAddr::from([192, 168, 113, 5]));
+        assert_eq!((conn.local_port, conn.remote_port), (40000, 443));
+        assert_eq!(conn.state, TcpState::Established);
+        assert_eq!(rows[1].uid, 1000);
+
+        assert_eq!(
+            parse_addr("B80D0120000000000000000001000000"),
+            "2001:db8::1".parse().ok()
+        );
+    }
+}
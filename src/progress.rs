@@ -0,0 +1,49 @@
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::color;
+
+// A one-line spinner on stderr for the sampling sleep, so a long `--sample-interval`
+// doesn't look like a hang. Nothing is drawn when stderr is not a terminal (or a dumb
+// one), and the line is erased again when the value is dropped.
+
+const FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+
+pub struct Spinner {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Spinner {
+    pub fn start(total: Duration) -> Spinner {
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = color::interactive(&io::stderr()).then(|| {
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                let mut frame = 0;
+                while !stop.load(Ordering::Relaxed) {
+                    let spin = FRAMES[frame % FRAMES.len()];
+                    eprint!("\r{} sampling {:.1}s...", spin, total.as_secs_f64());
+                    frame += 1;
+                    thread::park_timeout(Duration::from_millis(100));
+                }
+                eprint!("\r\x1b[K");
+                let _ = io::stderr().flush();
+            })
+        });
+        Spinner { stop, handle }
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            handle.thread().unpark();
+            let _ = handle.join();
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::accept::AcceptRate;
+use crate::geoip::Geo;
+use crate::json::{self, Value};
+use crate::path::PathInfo;
+use crate::{ProcessStats, SocketEntry};
+
+// `--record FILE` / `--replay FILE`: a history of snapshots for looking at an incident
+// after the fact. Each snapshot is appended as one JSON Lines record with the same
+// fields as `--output` (`{"timestamp":..,"connections":[..]}` on a single line), so
+// the file can also be read with `jq` or line tools. Replay turns the rows back into
+// socket entries and runs them through the normal filter, sort and output stages.
+
+pub struct Snapshot {
+    // Seconds since the epoch.
+    pub timestamp: u64,
+    pub entries: Vec<SocketEntry>,
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// One snapshot as a single JSON line.
+pub fn line(timestamp: u64, rows: &[SocketEntry]) -> String {
+    let rows: Vec<String> = rows.iter().map(json::entry).collect();
+    format!(
+        "{{\"timestamp\":{},\"connections\":{}}}\n",
+        timestamp,
+        json::array(&rows)
+    )
+}
+
+// Appends the snapshot taken now to `path`, creating the file if needed.
+pub fn append(path: &Path, rows: &[SocketEntry]) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(line(now(), rows).as_bytes())
+}
+
+fn num(v: &Value, key: &str) -> f64 {
+    v.get(key).and_then(Value::as_f64).unwrap_or(f64::NAN)
+}
+
+fn text(v: &Value, key: &str) -> Option<String> {
+    v.get(key).and_then(Value::as_str).map(str::to_string)
+}
+
+fn strings(v: &Value, key: &str) -> Vec<String> {
+    v.get(key)
+        .map(Value::items)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|s| s.as_str().map(str::to_string))
+        .collect()
+}
+
+fn path_info(v: &Value) -> Option<PathInfo> {
+    v.get("hint")?;
+    let n = |key| num(v, key);
+    Some(PathInfo {
+        mss: n("mss") as u32,
+        pmtu: n("pmtu") as u32,
+        cwnd: n("cwnd") as u32,
+        unacked: n("unacked") as u32,
+        lost: n("lost") as u32,
+        retransmits: n("retransmits") as u8,
+        total_retrans: n("total_retrans") as u32,
+        segs_out: v.get("segs_out").and_then(Value::as_f64).map(|s| s as u32),
+    })
+}
+
+// A row of `json::entry` back as a socket entry. Statistics come back only if they
+// were recorded (`--full`).
+fn entry(v: &Value) -> SocketEntry {
+    let flag = |key| v.get(key).and_then(Value::as_bool) == Some(true);
+    let count = |value: &Value| value.as_f64().map(|n| n as u32);
+    SocketEntry {
+        proto: text(v, "proto").unwrap_or_default(),
+        local_addr: text(v, "local").unwrap_or_default(),
+        remote_addr: text(v, "remote").unwrap_or_default(),
+        state: text(v, "state").unwrap_or_default(),
+        process_info: text(v, "process").unwrap_or_default(),
+        user: text(v, "user"),
+        pids: v
+            .get("pids")
+            .map(Value::items)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(count)
+            .collect(),
+        agg_stats: v.get("cpu_pct").map(|_| ProcessStats {
+            cpu_pct: num(v, "cpu_pct") as f32,
+            read_rate_bps: num(v, "read_bps"),
+            write_rate_bps: num(v, "write_bps"),
+            net_rx_rate_bps: num(v, "rx_bps"),
+            net_tx_rate_bps: num(v, "tx_bps"),
+            ..Default::default()
+        }),
+        new_remote: flag("new_remote"),
+        synth: flag("synth"),
+        env_hint: text(v, "env_hint"),
+        forward: text(v, "forward"),
+        inode: v.get("inode").and_then(count),
+        fds: v
+            .get("fds")
+            .map(Value::items)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|fd| Some((count(fd.get("pid")?)?, count(fd.get("fd")?)?)))
+            .collect(),
+        remote_host: text(v, "remote_host"),
+        tags: strings(v, "tags"),
+        rtt_ms: v.get("rtt_ms").and_then(Value::as_f64),
+        path: v.get("path").and_then(path_info),
+        geo: v.get("geo").filter(|g| **g != Value::Null).map(|g| Geo {
+            country: text(g, "country"),
+            asn: g.get("asn").and_then(count),
+            as_org: text(g, "as_org"),
+        }),
+        accept: v
+            .get("accept")
+            .filter(|a| **a != Value::Null)
+            .map(|a| AcceptRate {
+                per_sec: num(a, "per_sec"),
+                syn_received: a.get("syn_received").and_then(count).unwrap_or(0),
+            }),
+        ..Default::default()
+    }
+}
+
+fn snapshot(doc: &Value) -> Result<Snapshot, String> {
+    let Some(connections) = doc.get("connections") else {
+        return Err("not a snapshot (no \"connections\")".to_string());
+    };
+    Ok(Snapshot {
+        timestamp: doc.get("timestamp").and_then(Value::as_f64).unwrap_or(0.0) as u64,
+        entries: connections.items().iter().map(entry).collect(),
+    })
+}
+
+// Every snapshot in a `--record` file, oldest first. A single `--output` document is
+// read as a one-snapshot recording.
+pub fn read(path: &Path) -> Result<Vec<Snapshot>, String> {
+    parse(&fs::read_to_string(path).map_err(|e| e.to_string())?)
+}
+
+// The snapshots of a recording's text.
+pub fn parse(text: &str) -> Result<Vec<Snapshot>, String> {
+    if let Ok(doc) = json::parse(text) {
+        return Ok(vec![snapshot(&doc)?]);
+    }
+    let mut out = Vec::new();
+    for (i, l) in text.lines().enumerate() {
+        if l.trim().is_empty() {
+            continue;
+        }
+        out.push(
+            json::parse(l)
+                .and_then(|doc| snapshot(&doc))
+                .map_err(|e| format!("line {}: {}", i + 1, e))?,
+        );
+    }
+    Ok(out)
+}
+
+// "2026-03-01 14:05:09 UTC", for the snapshot headers.
+pub fn utc(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    // Civil date from days since 1970-01-01 (proleptic Gregorian).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recorded_lines_read_back_as_snapshots() {
+        let row = SocketEntry {
+            proto: "TCP".to_string(),
+            local_addr: "10.0.0.1:40000".to_string(),
+            remote_addr: "10.0.0.5:5432".to_string(),
+            state: "Established".to_string(),
+            process_info: "42: /usr/bin/api".to_string(),
+            pids: vec![42],
+            agg_stats: Some(ProcessStats {
+                cpu_pct: 1.5,
+                net_rx_rate_bps: 2048.0,
+                read_rate_bps: f64::NAN,
+                ..Default::default()
+            }),
+            tags: vec!["db".to_string()],
+            fds: vec![(42, 7)],
+            ..Default::default()
+        };
+        let file = format!(
+            "{}\n{}",
+            line(100, std::slice::from_ref(&row)),
+            line(160, &[])
+        );
+        let path = std::env::temp_dir().join(format!("netstatw-record-{}", std::process::id()));
+        fs::write(&path, file).unwrap();
+        let snapshots = read(&path).unwrap();
+        fs::remove_file(&path).ok();
+
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!((snapshots[0].timestamp, snapshots[1].timestamp), (100, 160));
+        let back = &snapshots[0].entries[0];
+        assert_eq!(back.conn_id(), row.conn_id());
+        assert_eq!(json::entry(back), json::entry(&row));
+        assert!(snapshots[1].entries.is_empty());
+    }
+
+    #[test]
+    fn utc_formats_civil_time() {
+        assert_eq!(utc(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(utc(1_709_301_909), "2024-03-01 14:05:09 UTC");
+    }
+}
@@ -0,0 +1,793 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use sysinfo::{Pid, System, Users};
+
+use crate::{ProcessStats, SocketEntry, human_readable_rate, idn, parse_addr_port, process_user};
+
+const CATEGORIES: [&str; 7] = [
+    "web",
+    "mail",
+    "dns",
+    "database",
+    "remote-access",
+    "other-system",
+    "high-ports",
+];
+
+fn port_category(port: u16) -> &'static str {
+    match port {
+        80 | 443 | 8080 | 8443 => "web",
+        25 | 110 | 143 | 465 | 587 | 993 | 995 => "mail",
+        53 | 853 => "dns",
+        1433 | 1521 | 3306 | 5432 | 5984 | 6379 | 9042 | 27017 => "database",
+        22 | 23 | 3389 | 5900 => "remote-access",
+        0..=1023 => "other-system",
+        _ => "high-ports",
+    }
+}
+
+// Rx/Tx cells summed over `pids`. NaN marks "no network data", which keeps the cell
+// N/A when no PID had any.
+fn net_cells(
+    pids: &HashSet<u32>,
+    pid_stats: &HashMap<u32, ProcessStats>,
+    show_stats: bool,
+) -> (String, String) {
+    if !show_stats {
+        return ("N/A".to_string(), "N/A".to_string());
+    }
+    let mut rx = f64::NAN;
+    let mut tx = f64::NAN;
+    for pid in pids {
+        if let Some(s) = pid_stats.get(pid)
+            && !s.net_rx_rate_bps.is_nan()
+        {
+            rx = rx.max(0.0) + s.net_rx_rate_bps;
+            tx = tx.max(0.0) + s.net_tx_rate_bps;
+        }
+    }
+    (human_readable_rate(rx), human_readable_rate(tx))
+}
+
+#[derive(Default)]
+struct CategoryTotals {
+    connections: usize,
+    pids: HashSet<u32>,
+}
+
+// Groups connected rows by the class of their destination port. A connection whose
+// local port is one of this host's listeners is inbound, so its destination is the
+// local port; otherwise the remote port is the service being talked to.
+// Rates are per-process, so each category sums the Rx/Tx of the distinct PIDs that
+// have at least one connection in it.
+pub fn print_port_category_report(
+    entries: &[SocketEntry],
+    pid_stats: &HashMap<u32, ProcessStats>,
+    show_stats: bool,
+) {
+    let listening: HashSet<(&str, u16)> = entries
+        .iter()
+        .filter(|e| e.state == "Listen")
+        .map(|e| (e.proto.as_str(), parse_addr_port(&e.local_addr).1))
+        .collect();
+
+    let mut totals: HashMap<&'static str, CategoryTotals> = HashMap::new();
+    for e in entries {
+        if e.state == "Listen" || e.proto == "UDP" {
+            continue;
+        }
+        let (_, lport) = parse_addr_port(&e.local_addr);
+        let (_, rport) = parse_addr_port(&e.remote_addr);
+        let dest = if listening.contains(&(e.proto.as_str(), lport)) {
+            lport
+        } else {
+            rport
+        };
+        let t = totals.entry(port_category(dest)).or_default();
+        t.connections += 1;
+        t.pids.extend(e.pids.iter().copied());
+    }
+
+    println!(
+        "{:<15} {:>11} {:>9} {:>10} {:>10}",
+        "CATEGORY", "CONNECTIONS", "PROCESSES", "Rx/s", "Tx/s"
+    );
+    println!(
+        "{}  {}  {}  {}  {}",
+        "-".repeat(14),
+        "-".repeat(10),
+        "-".repeat(8),
+        "-".repeat(9),
+        "-".repeat(9)
+    );
+    for cat in CATEGORIES {
+        let Some(t) = totals.get(cat) else { continue };
+        let (rx, tx) = net_cells(&t.pids, pid_stats, show_stats);
+        println!(
+            "{:<15} {:>11} {:>9} {:>10} {:>10}",
+            cat,
+            t.connections,
+            t.pids.len(),
+            rx,
+            tx
+        );
+    }
+}
+
+#[derive(Default)]
+struct UserTotals {
+    connections: usize,
+    listeners: usize,
+    pids: HashSet<u32>,
+}
+
+// One line per account owning the listed sockets. A row counts once for each
+// distinct user among its PIDs; rows without a PID are grouped under "Unknown".
+pub fn print_user_report(
+    entries: &[SocketEntry],
+    pid_stats: &HashMap<u32, ProcessStats>,
+    system: &System,
+    show_stats: bool,
+) {
+    let users = Users::new_with_refreshed_list();
+    let mut totals: HashMap<String, UserTotals> = HashMap::new();
+    for e in entries {
+        let mut names: Vec<String> = e
+            .pids
+            .iter()
+            .map(|&pid| process_user(system, &users, pid).unwrap_or_else(|| "Unknown".to_string()))
+            .collect();
+        if names.is_empty() {
+            names.push("Unknown".to_string());
+        }
+        names.sort();
+        names.dedup();
+        for name in names {
+            let t = totals.entry(name).or_default();
+            if e.state == "Listen" || e.proto == "UDP" {
+                t.listeners += 1;
+            } else {
+                t.connections += 1;
+            }
+            t.pids.extend(e.pids.iter().copied());
+        }
+    }
+
+    // Busiest accounts first.
+    let mut rows: Vec<(String, UserTotals)> = totals.into_iter().collect();
+    rows.sort_by(|a, b| {
+        (b.1.connections + b.1.listeners)
+            .cmp(&(a.1.connections + a.1.listeners))
+            .then_with(|| a.0.cmp(&b.0))
+    });
+
+    println!(
+        "{:<24} {:>11} {:>9} {:>9} {:>10} {:>10}",
+        "USER", "CONNECTIONS", "LISTENERS", "PROCESSES", "Rx/s", "Tx/s"
+    );
+    println!(
+        "{}  {}  {}  {}  {}  {}",
+        "-".repeat(23),
+        "-".repeat(10),
+        "-".repeat(8),
+        "-".repeat(8),
+        "-".repeat(9),
+        "-".repeat(9)
+    );
+    for (name, t) in rows {
+        let (rx, tx) = net_cells(&t.pids, pid_stats, show_stats);
+        println!(
+            "{:<24} {:>11} {:>9} {:>9} {:>10} {:>10}",
+            name,
+            t.connections,
+            t.listeners,
+            t.pids.len(),
+            rx,
+            tx
+        );
+    }
+}
+
+// `--group-by`: what one line of the grouped view stands for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GroupBy {
+    Process,
+    RemoteHost,
+    RemotePort,
+    State,
+    Tag,
+}
+
+impl GroupBy {
+    pub fn parse(v: &str) -> Option<GroupBy> {
+        match v.to_ascii_lowercase().as_str() {
+            "process" => Some(GroupBy::Process),
+            "remote-host" => Some(GroupBy::RemoteHost),
+            "remote-port" => Some(GroupBy::RemotePort),
+            "state" => Some(GroupBy::State),
+            "tag" => Some(GroupBy::Tag),
+            _ => None,
+        }
+    }
+
+    fn header(self) -> &'static str {
+        match self {
+            GroupBy::Process => "PROCESS",
+            GroupBy::RemoteHost => "REMOTE HOST",
+            GroupBy::RemotePort => "REMOTE PORT",
+            GroupBy::State => "STATE",
+            GroupBy::Tag => "TAG",
+        }
+    }
+
+    // The groups a row belongs to: one per process name for `process` (a row shared
+    // by several processes counts in each) and one per tag for `tag`, otherwise
+    // exactly one. Listeners and UDP sockets have no remote end and group under "-",
+    // like untagged rows.
+    fn keys(self, e: &SocketEntry, name: &dyn Fn(u32) -> String) -> Vec<String> {
+        let (remote_ip, remote_port) = parse_addr_port(&e.remote_addr);
+        let unconnected = remote_port == 0;
+        match self {
+            GroupBy::Process => {
+                let mut names: Vec<String> = e.pids.iter().map(|&pid| name(pid)).collect();
+                if names.is_empty() {
+                    names.push("Unknown".to_string());
+                }
+                names.sort();
+                names.dedup();
+                names
+            }
+            GroupBy::RemoteHost if unconnected => vec!["-".to_string()],
+            GroupBy::RemoteHost => vec![
+                e.remote_host
+                    .as_deref()
+                    .map_or_else(|| remote_ip.to_string(), |h| idn::display(h).into_owned()),
+            ],
+            GroupBy::RemotePort if unconnected => vec!["-".to_string()],
+            GroupBy::RemotePort => vec![remote_port.to_string()],
+            GroupBy::State => vec![e.state.clone()],
+            GroupBy::Tag if e.tags.is_empty() => vec!["-".to_string()],
+            GroupBy::Tag => e.tags.clone(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct GroupTotals {
+    connections: usize,
+    pids: HashSet<u32>,
+    // Rx/Tx of the rows measured as single connections.
+    conn_rates: Option<(f64, f64)>,
+}
+
+// Adds a row's Rx/Tx to `sum` if they are its own connection's.
+fn add_conn_rates(sum: &mut Option<(f64, f64)>, e: &SocketEntry) {
+    if let Some(s) = e.agg_stats.as_ref().filter(|s| s.net_per_conn) {
+        let (rx, tx) = sum.get_or_insert((0.0, 0.0));
+        *rx += s.net_rx_rate_bps;
+        *tx += s.net_tx_rate_bps;
+    }
+}
+
+// CPU, R, W, Rx and Tx summed over `pids`, as cells. A rate no PID could measure
+// stays N/A.
+fn stat_cells(
+    pids: &HashSet<u32>,
+    pid_stats: &HashMap<u32, ProcessStats>,
+    show_stats: bool,
+) -> [String; 5] {
+    if !show_stats {
+        return std::array::from_fn(|_| "N/A".to_string());
+    }
+    let fields: [fn(&ProcessStats) -> f64; 5] = [
+        |s| s.cpu_pct as f64,
+        |s| s.read_rate_bps,
+        |s| s.write_rate_bps,
+        |s| s.net_rx_rate_bps,
+        |s| s.net_tx_rate_bps,
+    ];
+    let sums = fields.map(|f| {
+        pids.iter()
+            .filter_map(|pid| pid_stats.get(pid))
+            .map(f)
+            .filter(|v| !v.is_nan())
+            .fold(f64::NAN, |acc, v| acc.max(0.0) + v)
+    });
+    let cpu = if sums[0].is_nan() {
+        "N/A".to_string()
+    } else {
+        format!("{:.1}", sums[0])
+    };
+    [
+        cpu,
+        human_readable_rate(sums[1]),
+        human_readable_rate(sums[2]),
+        human_readable_rate(sums[3]),
+        human_readable_rate(sums[4]),
+    ]
+}
+
+// Rows per group, busiest first. `name` gives a process's name by PID.
+fn group_totals(
+    entries: &[SocketEntry],
+    by: GroupBy,
+    name: &dyn Fn(u32) -> String,
+) -> Vec<(String, GroupTotals)> {
+    let mut totals: HashMap<String, GroupTotals> = HashMap::new();
+    for e in entries {
+        for key in by.keys(e, name) {
+            let t = totals.entry(key).or_default();
+            t.connections += 1;
+            t.pids.extend(e.pids.iter().copied());
+            add_conn_rates(&mut t.conn_rates, e);
+        }
+    }
+    let mut rows: Vec<(String, GroupTotals)> = totals.into_iter().collect();
+    rows.sort_by(|a, b| {
+        b.1.connections
+            .cmp(&a.1.connections)
+            .then_with(|| a.0.cmp(&b.0))
+    });
+    rows
+}
+
+// One line per group with its socket count and the statistics of the processes in
+// it. Rates are per process, so a group sums the distinct PIDs among its rows; a tag
+// stands for connections rather than programs, so its Rx/Tx sum the tagged
+// connections measured on their own.
+pub fn print_group_report(
+    entries: &[SocketEntry],
+    pid_stats: &HashMap<u32, ProcessStats>,
+    system: &System,
+    by: GroupBy,
+    show_stats: bool,
+) {
+    let name = |pid: u32| {
+        system
+            .process(Pid::from(pid as usize))
+            .map(|p| p.name().to_string())
+            .unwrap_or_else(|| "Unknown".to_string())
+    };
+    println!(
+        "{:<32} {:>7} {:>9} {:>7} {:>10} {:>10} {:>10} {:>10}",
+        by.header(),
+        "SOCKETS",
+        "PROCESSES",
+        "CPU%",
+        "R/s",
+        "W/s",
+        "Rx/s",
+        "Tx/s"
+    );
+    println!(
+        "{}  {}  {}  {}  {}  {}  {}  {}",
+        "-".repeat(31),
+        "-".repeat(6),
+        "-".repeat(8),
+        "-".repeat(6),
+        "-".repeat(9),
+        "-".repeat(9),
+        "-".repeat(9),
+        "-".repeat(9)
+    );
+    for (key, t) in group_totals(entries, by, &name) {
+        let [cpu, r, w, mut rx, mut tx] = stat_cells(&t.pids, pid_stats, show_stats);
+        if by == GroupBy::Tag && show_stats {
+            (rx, tx) = conn_rate_cells(t.conn_rates);
+        }
+        println!(
+            "{:<32} {:>7} {:>9} {:>7} {:>10} {:>10} {:>10} {:>10}",
+            key,
+            t.connections,
+            t.pids.len(),
+            cpu,
+            r,
+            w,
+            rx,
+            tx
+        );
+    }
+}
+
+// Counts behind the `--summary` footer.
+struct Summary {
+    total: usize,
+    // (name, count), most common first.
+    states: Vec<(String, usize)>,
+    protocols: Vec<(String, usize)>,
+    remote_hosts: usize,
+    pids: HashSet<u32>,
+}
+
+fn by_count(counts: HashMap<&str, usize>) -> Vec<(String, usize)> {
+    let mut v: Vec<(String, usize)> = counts
+        .into_iter()
+        .map(|(k, n)| (k.to_string(), n))
+        .collect();
+    v.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    v
+}
+
+fn summarize(entries: &[SocketEntry]) -> Summary {
+    let mut states: HashMap<&str, usize> = HashMap::new();
+    let mut protocols: HashMap<&str, usize> = HashMap::new();
+    let mut hosts: HashSet<&str> = HashSet::new();
+    let mut pids = HashSet::new();
+    for e in entries {
+        // UDP sockets have no state.
+        if e.state != "-" {
+            *states.entry(&e.state).or_default() += 1;
+        }
+        *protocols.entry(&e.proto).or_default() += 1;
+        let (ip, port) = parse_addr_port(&e.remote_addr);
+        if port != 0 {
+            hosts.insert(ip);
+        }
+        pids.extend(e.pids.iter().copied());
+    }
+    Summary {
+        total: entries.len(),
+        states: by_count(states),
+        protocols: by_count(protocols),
+        remote_hosts: hosts.len(),
+        pids,
+    }
+}
+
+fn counts_line(counts: &[(String, usize)]) -> String {
+    counts
+        .iter()
+        .map(|(name, n)| format!("{} {}", name, n))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn conn_rate_cells(rates: Option<(f64, f64)>) -> (String, String) {
+    let (rx, tx) = rates.unwrap_or((f64::NAN, f64::NAN));
+    (human_readable_rate(rx), human_readable_rate(tx))
+}
+
+// The rows of one tag, for the `--summary` footer and the exporter.
+#[derive(Debug, Default, PartialEq)]
+pub struct TagTotals {
+    // (protocol, sockets), most common first.
+    pub protocols: Vec<(String, usize)>,
+    // Rx/Tx summed over the tagged connections measured on their own (`--full`);
+    // process totals would count a process once per row.
+    pub rates: Option<(f64, f64)>,
+}
+
+#[derive(Default)]
+struct TagRows<'a> {
+    protocols: HashMap<&'a str, usize>,
+    rates: Option<(f64, f64)>,
+}
+
+// Totals per tag, in tag order; untagged rows are left out.
+pub fn tag_totals(entries: &[SocketEntry]) -> Vec<(String, TagTotals)> {
+    let mut tags: BTreeMap<&str, TagRows> = BTreeMap::new();
+    for e in entries {
+        for tag in &e.tags {
+            let t = tags.entry(tag).or_default();
+            *t.protocols.entry(&e.proto).or_default() += 1;
+            add_conn_rates(&mut t.rates, e);
+        }
+    }
+    tags.into_iter()
+        .map(|(tag, t)| {
+            let protocols = by_count(t.protocols);
+            (
+                tag.to_string(),
+                TagTotals {
+                    protocols,
+                    rates: t.rates,
+                },
+            )
+        })
+        .collect()
+}
+
+// `--summary`: totals printed under the table, like `ss -s`.
+pub fn print_summary(
+    entries: &[SocketEntry],
+    pid_stats: &HashMap<u32, ProcessStats>,
+    show_stats: bool,
+) {
+    let s = summarize(entries);
+    let (rx, tx) = net_cells(&s.pids, pid_stats, show_stats);
+    println!();
+    println!("Total: {} sockets ({})", s.total, counts_line(&s.protocols));
+    if !s.states.is_empty() {
+        println!("TCP states: {}", counts_line(&s.states));
+    }
+    println!("Remote hosts: {} unique", s.remote_hosts);
+    println!("Rx/s: {}  Tx/s: {}", rx, tx);
+    for (tag, t) in tag_totals(entries) {
+        let sockets: usize = t.protocols.iter().map(|(_, n)| n).sum();
+        let (rx, tx) = match show_stats {
+            true => conn_rate_cells(t.rates),
+            false => ("N/A".to_string(), "N/A".to_string()),
+        };
+        println!(
+            "Tag {}: {} sockets ({})  Rx/s: {}  Tx/s: {}",
+            tag,
+            sockets,
+            counts_line(&t.protocols),
+            rx,
+            tx
+        );
+    }
+}
+
+const OVERVIEW_TOP: usize = 5;
+const OVERVIEW_WIDTH: usize = 44;
+
+fn short_process_name(system: &System, pid: u32) -> String {
+    system
+        .process(Pid::from(pid as usize))
+        .map(|p| format!("{} {}", pid, p.name()))
+        .unwrap_or_else(|| format!("{} Unknown", pid))
+}
+
+// One small table as lines: title, header, separator, then up to OVERVIEW_TOP rows of
+// (label, value) padded to OVERVIEW_WIDTH so tables can be laid out side by side.
+fn overview_table(
+    title: &str,
+    label_header: &str,
+    value_header: &str,
+    rows: &[(String, String)],
+) -> Vec<String> {
+    let label_width = OVERVIEW_WIDTH - 12;
+    let mut lines = vec![
+        format!("{:<w$}", title, w = OVERVIEW_WIDTH),
+        format!(
+            "{:<lw$} {:>11}",
+            label_header,
+            value_header,
+            lw = label_width
+        ),
+        format!("{}  {}", "-".repeat(label_width - 1), "-".repeat(10)),
+    ];
+    if rows.is_empty() {
+        lines.push("(no data)".to_string());
+    }
+    for (label, value) in rows.iter().take(OVERVIEW_TOP) {
+        let label: String = label.chars().take(label_width).collect();
+        lines.push(format!("{:<lw$} {:>11}", label, value, lw = label_width));
+    }
+    lines
+}
+
+fn print_side_by_side(left: &[String], right: &[String]) {
+    for i in 0..left.len().max(right.len()) {
+        let l = left.get(i).map(String::as_str).unwrap_or("");
+        let r = right.get(i).map(String::as_str).unwrap_or("");
+        println!("{:<w$}    {}", l, r, w = OVERVIEW_WIDTH);
+    }
+}
+
+// "First minute of an incident" screen: top processes by Tx, Rx and connection
+// count, and the listeners with the most established connections.
+pub fn print_overview(
+    entries: &[SocketEntry],
+    pid_stats: &HashMap<u32, ProcessStats>,
+    system: &System,
+) {
+    let by_rate = |rate: fn(&ProcessStats) -> f64| -> Vec<(String, String)> {
+        let mut v: Vec<(u32, f64)> = pid_stats
+            .iter()
+            .map(|(pid, s)| (*pid, rate(s)))
+            .filter(|(_, r)| !r.is_nan())
+            .collect();
+        v.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        v.into_iter()
+            .map(|(pid, r)| (short_process_name(system, pid), human_readable_rate(r)))
+            .collect()
+    };
+    let tx = by_rate(|s| s.net_tx_rate_bps);
+    let rx = by_rate(|s| s.net_rx_rate_bps);
+
+    let mut conn_counts: HashMap<u32, usize> = HashMap::new();
+    for e in entries
+        .iter()
+        .filter(|e| e.proto == "TCP" && e.state != "Listen")
+    {
+        for &pid in &e.pids {
+            *conn_counts.entry(pid).or_default() += 1;
+        }
+    }
+    let mut conns: Vec<(u32, usize)> = conn_counts.into_iter().collect();
+    conns.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    let conns: Vec<(String, String)> = conns
+        .into_iter()
+        .map(|(pid, n)| (short_process_name(system, pid), n.to_string()))
+        .collect();
+
+    let mut established_per_port: HashMap<u16, usize> = HashMap::new();
+    for e in entries
+        .iter()
+        .filter(|e| e.proto == "TCP" && e.state == "Established")
+    {
+        *established_per_port
+            .entry(parse_addr_port(&e.local_addr).1)
+            .or_default() += 1;
+    }
+    let mut listeners: Vec<(String, usize)> = entries
+        .iter()
+        .filter(|e| e.state == "Listen")
+        .map(|e| {
+            let port = parse_addr_port(&e.local_addr).1;
+            let owner = e
+                .pids
+                .first()
+                .map(|&pid| short_process_name(system, pid))
+                .unwrap_or_else(|| "Unknown".to_string());
+            let n = established_per_port.get(&port).copied().unwrap_or(0);
+            (format!(":{} {}", port, owner), n)
+        })
+        .collect();
+    listeners.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    listeners.dedup_by(|a, b| a.0 == b.0);
+    let listeners: Vec<(String, String)> = listeners
+        .into_iter()
+        .map(|(label, n)| (label, n.to_string()))
+        .collect();
+
+    print_side_by_side(
+        &overview_table("TOP TRANSMIT", "PROCESS", "Tx/s", &tx),
+        &overview_table("TOP RECEIVE", "PROCESS", "Rx/s", &rx),
+    );
+    println!();
+    print_side_by_side(
+        &overview_table("TOP CONNECTION COUNT", "PROCESS", "CONNECTIONS", &conns),
+        &overview_table("BUSIEST LISTENERS", "LISTENER", "ESTABLISHED", &listeners),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(remote: &str, state: &str, pids: &[u32]) -> SocketEntry {
+        SocketEntry {
+            proto: "TCP".to_string(),
+            local_addr: "10.0.0.1:40000".to_string(),
+            remote_addr: remote.to_string(),
+            state: state.to_string(),
+            pids: pids.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn summary_counts_states_protocols_and_remote_hosts() {
+        let mut udp = row("0.0.0.0:0", "-", &[3]);
+        udp.proto = "UDP".to_string();
+        let rows = [
+            row("10.0.0.5:5432", "Established", &[1]),
+            row("10.0.0.5:5433", "Established", &[2]),
+            row("[2001:db8::7]:443", "TimeWait", &[]),
+            row("0.0.0.0:0", "Listen", &[1]),
+            udp,
+        ];
+        let s = summarize(&rows);
+        assert_eq!(s.total, 5);
+        assert_eq!(
+            counts_line(&s.states),
+            "Established 2, Listen 1, TimeWait 1"
+        );
+        assert_eq!(counts_line(&s.protocols), "TCP 4, UDP 1");
+        assert_eq!(s.remote_hosts, 2);
+        assert_eq!(s.pids.len(), 3);
+    }
+
+    #[test]
+    fn group_by_sums_distinct_processes_per_group() {
+        let rows = [
+            row("10.0.0.5:5432", "Established", &[1]),
+            row("10.0.0.5:5432", "Established", &[2]),
+            row("[2001:db8::7]:443", "TimeWait", &[]),
+            row("0.0.0.0:0", "Listen", &[1]),
+        ];
+        let name = |pid: u32| if pid == 3 { "api" } else { "postgres" }.to_string();
+        let groups = |by| -> Vec<(String, usize, usize)> {
+            group_totals(&rows, by, &name)
+                .into_iter()
+                .map(|(key, t)| (key, t.connections, t.pids.len()))
+                .collect()
+        };
+        assert_eq!(
+            groups(GroupBy::RemoteHost),
+            [
+                ("10.0.0.5".to_string(), 2, 2),
+                ("-".to_string(), 1, 1),
+                ("2001:db8::7".to_string(), 1, 0),
+            ]
+        );
+        assert_eq!(groups(GroupBy::RemotePort)[1].0, "-");
+        assert_eq!(
+            groups(GroupBy::Process),
+            [
+                ("postgres".to_string(), 3, 2),
+                ("Unknown".to_string(), 1, 0)
+            ]
+        );
+        assert_eq!(GroupBy::parse("Remote-Port"), Some(GroupBy::RemotePort));
+
+        let stats = HashMap::from([
+            (
+                1,
+                ProcessStats {
+                    cpu_pct: 1.5,
+                    net_rx_rate_bps: 100.0,
+                    read_rate_bps: f64::NAN,
+                    ..Default::default()
+                },
+            ),
+            (
+                2,
+                ProcessStats {
+                    cpu_pct: 2.0,
+                    net_rx_rate_bps: 200.0,
+                    read_rate_bps: f64::NAN,
+                    ..Default::default()
+                },
+            ),
+        ]);
+        let cells = stat_cells(&HashSet::from([1, 2]), &stats, true);
+        assert_eq!(cells[0], "3.5");
+        assert_eq!(cells[1], "N/A");
+        assert_eq!(cells[3], human_readable_rate(300.0));
+    }
+
+    #[test]
+    fn tags_roll_up_sockets_and_connection_rates() {
+        let measured = |remote: &str, rx: f64, per_conn: bool| SocketEntry {
+            tags: vec!["payments-db".to_string()],
+            agg_stats: Some(ProcessStats {
+                net_rx_rate_bps: rx,
+                net_tx_rate_bps: rx / 2.0,
+                net_per_conn: per_conn,
+                ..Default::default()
+            }),
+            ..row(remote, "Established", &[1])
+        };
+        let mut both = measured("10.0.0.5:5432", 100.0, true);
+        both.tags.push("internal".to_string());
+        let mut udp = row("10.0.0.9:53", "-", &[2]);
+        udp.proto = "UDP".to_string();
+        udp.tags = vec!["internal".to_string()];
+        let rows = [
+            both,
+            measured("10.0.0.6:5432", 300.0, true),
+            // Process totals: a process with several tagged rows would count twice.
+            measured("10.0.0.7:5432", 5000.0, false),
+            udp,
+            row("203.0.113.1:443", "Established", &[3]),
+        ];
+        assert_eq!(
+            tag_totals(&rows),
+            [
+                (
+                    "internal".to_string(),
+                    TagTotals {
+                        protocols: vec![("TCP".to_string(), 1), ("UDP".to_string(), 1)],
+                        rates: Some((100.0, 50.0)),
+                    }
+                ),
+                (
+                    "payments-db".to_string(),
+                    TagTotals {
+                        protocols: vec![("TCP".to_string(), 3)],
+                        rates: Some((400.0, 200.0)),
+                    }
+                ),
+            ]
+        );
+        let groups = group_totals(&rows, GroupBy::Tag, &|_| String::new());
+        assert_eq!(groups[0].0, "payments-db");
+        assert_eq!(groups[0].1.connections, 3);
+        assert_eq!(groups[0].1.conn_rates, Some((400.0, 200.0)));
+        assert_eq!(groups.last().unwrap().0, "-");
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{SocketEntry, forensic, idn, interrupt, parse_addr_port, warn};
+
+// `--resolve`: reverse DNS for remote addresses. Lookups run on a small pool of worker
+// threads and the caller waits at most `timeout`; addresses still pending then keep
+// their numeric form. Results (including failures) are cached for the life of the
+// process, and lookups that finish late still land in the cache, so later `--watch`
+// refreshes pick them up without asking again.
+
+// Concurrent lookups; reverse lookups mostly wait on the network.
+const WORKERS: usize = 16;
+// How often the caller checks for finished lookups.
+const POLL: Duration = Duration::from_millis(20);
+
+#[derive(Clone)]
+enum Lookup {
+    Pending,
+    Done(Option<String>),
+}
+
+static CACHE: Mutex<Option<HashMap<IpAddr, Lookup>>> = Mutex::new(None);
+// Look-alike names already warned about, so `--watch` warns once per name.
+static FLAGGED: Mutex<Option<HashSet<String>>> = Mutex::new(None);
+
+// Addresses worth looking up: not the wildcard or the `*` of unconnected sockets.
+fn resolvable(addr: &str) -> Option<IpAddr> {
+    let ip = crate::zone::parse_ip(parse_addr_port(addr).0)?;
+    (!ip.is_unspecified()).then_some(ip)
+}
+
+// Sets `remote_host` on every row whose remote address resolved within `timeout`.
+pub fn resolve_remotes(entries: &mut [SocketEntry], timeout: Duration) {
+    let timeout = interrupt::remaining().map_or(timeout, |left| left.min(timeout));
+    let ips: Vec<IpAddr> = entries
+        .iter()
+        .filter_map(|e| resolvable(&e.remote_addr))
+        .collect();
+    let names = hostnames(&ips, timeout);
+    for e in entries.iter_mut() {
+        if let Some(name) = resolvable(&e.remote_addr).and_then(|ip| names.get(&ip)) {
+            e.remote_host = Some(name.clone());
+        }
+    }
+    flag_homographs(names.values());
+}
+
+fn flag_homographs<'a>(names: impl Iterator<Item = &'a String>) {
+    let mut guard = FLAGGED.lock().unwrap_or_else(|e| e.into_inner());
+    let flagged = guard.get_or_insert_with(HashSet::new);
+    for name in names {
+        let Some(decoded) = idn::decode(name) else {
+            continue;
+        };
+        if idn::homograph(&decoded) && flagged.insert(name.clone()) {
+            warn::warn(
+                "idn_homograph",
+                format!(
+                    "{} decodes to {}, which looks like another name (mixed or look-alike scripts)",
+                    name, decoded
+                ),
+            );
+        }
+    }
+}
+
+// Host names for `ips`, as far as known within `timeout`.
+pub fn hostnames(ips: &[IpAddr], timeout: Duration) -> HashMap<IpAddr, String> {
+    let deadline = Instant::now() + timeout;
+    let mut todo: Vec<IpAddr> = Vec::new();
+    {
+        let mut guard = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+        let cache = guard.get_or_insert_with(HashMap::new);
+        for &ip in ips {
+            if let Entry::Vacant(slot) = cache.entry(ip) {
+                slot.insert(Lookup::Pending);
+                todo.push(ip);
+            }
+        }
+    }
+
+    if !todo.is_empty() {
+        forensic::record("getnameinfo (reverse DNS of remote addresses)");
+        let workers = WORKERS.min(todo.len());
+        let queue = Arc::new(Mutex::new(todo));
+        for _ in 0..workers {
+            let queue = Arc::clone(&queue);
+            thread::spawn(move || {
+                while let Some(ip) = queue.lock().ok().and_then(|mut q| q.pop()) {
+                    let name = reverse(ip);
+                    if let Ok(mut guard) = CACHE.lock()
+                        && let Some(cache) = guard.as_mut()
+                    {
+                        cache.insert(ip, Lookup::Done(name));
+                    }
+                }
+            });
+        }
+    }
+
+    // Wait for this call's lookups (or ones an earlier call started) until the
+    // deadline; the workers finish in the background either way.
+    loop {
+        let guard = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+        let cache = guard.as_ref();
+        let pending = ips
+            .iter()
+            .any(|ip| matches!(cache.and_then(|c| c.get(ip)), Some(Lookup::Pending)));
+        if !pending || Instant::now() >= deadline || interrupt::should_stop() {
+            return ips
+                .iter()
+                .filter_map(|ip| match cache?.get(ip) {
+                    Some(Lookup::Done(Some(name))) => Some((*ip, name.clone())),
+                    _ => None,
+                })
+                .collect();
+        }
+        drop(guard);
+        thread::sleep(POLL);
+    }
+}
+
+#[cfg(unix)]
+fn reverse(ip: IpAddr) -> Option<String> {
+    use std::ffi::CStr;
+    use std::mem::{size_of, zeroed};
+
+    let mut host = [0 as libc::c_char; 1025];
+    // SAFETY: the sockaddr structs are plain data, fully initialized here and passed
+    // with their own size; getnameinfo NUL-terminates `host` on success.
+    unsafe {
+        let rc = match ip {
+            IpAddr::V4(v4) => {
+                let mut sa: libc::sockaddr_in = zeroed();
+                sa.sin_family = libc::AF_INET as libc::sa_family_t;
+                sa.sin_addr.s_addr = u32::from_ne_bytes(v4.octets());
+                libc::getnameinfo(
+                    &sa as *const _ as *const libc::sockaddr,
+                    size_of::<libc::sockaddr_in>() as libc::socklen_t,
+                    host.as_mut_ptr(),
+                    host.len() as libc::socklen_t,
+                    std::ptr::null_mut(),
+                    0,
+                    libc::NI_NAMEREQD,
+                )
+            }
+            IpAddr::V6(v6) => {
+                let mut sa: libc::sockaddr_in6 = zeroed();
+                sa.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+                sa.sin6_addr.s6_addr = v6.octets();
+                libc::getnameinfo(
+                    &sa as *const _ as *const libc::sockaddr,
+                    size_of::<libc::sockaddr_in6>() as libc::socklen_t,
+                    host.as_mut_ptr(),
+                    host.len() as libc::socklen_t,
+                    std::ptr::null_mut(),
+                    0,
+                    libc::NI_NAMEREQD,
+                )
+            }
+        };
+        (rc == 0).then(|| CStr::from_ptr(host.as_ptr()).to_string_lossy().into_owned())
+    }
+}
+
+#[cfg(windows)]
+fn reverse(ip: IpAddr) -> Option<String> {
+    use std::ffi::CStr;
+    use std::mem::{size_of, zeroed};
+    use std::sync::Once;
+    use windows_sys::Win32::Networking::WinSock::{
+        AF_INET, AF_INET6, NI_NAMEREQD, SOCKADDR, SOCKADDR_IN, SOCKADDR_IN6, WSADATA, WSAStartup,
+        getnameinfo,
+    };
+
+    static WSA: Once = Once::new();
+    let mut host = [0u8; 1025];
+    // SAFETY: as in the Unix version; WSAStartup runs once before the first lookup.
+    unsafe {
+        WSA.call_once(|| {
+            let mut data: WSADATA = zeroed();
+            WSAStartup(0x0202, &mut data);
+        });
+        let rc = match ip {
+            IpAddr::V4(v4) => {
+                let mut sa: SOCKADDR_IN = zeroed();
+                sa.sin_family = AF_INET;
+                sa.sin_addr.S_un.S_addr = u32::from_ne_bytes(v4.octets());
+                getnameinfo(
+                    &sa as *const _ as *const SOCKADDR,
+                    size_of::<SOCKADDR_IN>() as i32,
+                    host.as_mut_ptr(),
+                    host.len() as u32,
+                    std::ptr::null_mut(),
+                    0,
+                    NI_NAMEREQD as i32,
+                )
+            }
+            IpAddr::V6(v6) => {
+                let mut sa: SOCKADDR_IN6 = zeroed();
+                sa.sin6_family = AF_INET6;
+                sa.sin6_addr.u.Byte = v6.octets();
+                getnameinfo(
+                    &sa as *const _ as *const SOCKADDR,
+                    size_of::<SOCKADDR_IN6>() as i32,
+                    host.as_mut_ptr(),
+                    host.len() as u32,
+                    std::ptr::null_mut(),
+                    0,
+                    NI_NAMEREQD as i32,
+                )
+            }
+        };
+        (rc == 0).then(|| {
+            CStr::from_ptr(host.as_ptr().cast())
+                .to_string_lossy()
+                .into_owned()
+        })
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn reverse(_ip: IpAddr) -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolvable_skips_wildcards() {
+        assert_eq!(resolvable("1.2.3.4:443"), "1.2.3.4".parse().ok());
+        assert_eq!(resolvable("2001:db8::1:443"), "2001:db8::1".parse().ok());
+        assert_eq!(resolvable("[2001:db8::1]:443"), "2001:db8::1".parse().ok());
+        assert_eq!(resolvable("0.0.0.0:0"), None);
+        assert_eq!(resolvable(":::0"), None);
+        assert_eq!(resolvable("[::]:0"), None);
+        assert_eq!(resolvable("*:*"), None);
+    }
+}
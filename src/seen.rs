@@ -0,0 +1,221 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{SocketEntry, parse_addr_port};
+
+struct SeenRecord {
+    first_seen: u64,
+    last_seen: u64,
+    connections: u64,
+    // IDs of the connections open at the last update, so a connection that stays
+    // open across runs or --watch refreshes is counted once.
+    open: HashSet<String>,
+}
+
+// On-disk store of remote IPs, one tab-separated line per host:
+//   <ip>\t<first_seen unix secs>\t<last_seen unix secs>\t<connections observed>\t<IDs>
+// where IDs lists the connections open at the last update, comma-separated (files
+// without the field still load). Malformed lines are skipped so a damaged file only
+// loses those hosts.
+pub struct SeenDb {
+    path: PathBuf,
+    hosts: HashMap<IpAddr, SeenRecord>,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn remote_ip(entry: &SocketEntry) -> Option<IpAddr> {
+    let ip = crate::zone::parse_ip(parse_addr_port(&entry.remote_addr).0)?;
+    if ip.is_unspecified() { None } else { Some(ip) }
+}
+
+impl SeenDb {
+    pub fn open(path: &Path) -> io::Result<SeenDb> {
+        let mut hosts: HashMap<IpAddr, SeenRecord> = HashMap::new();
+        let content = match fs::read_to_string(path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        for line in content.lines() {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if !(4..=5).contains(&fields.len()) {
+                continue;
+            }
+            if let (Ok(ip), Ok(first_seen), Ok(last_seen), Ok(connections)) = (
+                fields[0].parse::<IpAddr>(),
+                fields[1].parse::<u64>(),
+                fields[2].parse::<u64>(),
+                fields[3].parse::<u64>(),
+            ) {
+                hosts.insert(
+                    ip,
+                    SeenRecord {
+                        first_seen,
+                        last_seen,
+                        connections,
+                        open: fields
+                            .get(4)
+                            .into_iter()
+                            .flat_map(|ids| ids.split(','))
+                            .filter(|id| !id.is_empty())
+                            .map(str::to_string)
+                            .collect(),
+                    },
+                );
+            }
+        }
+        Ok(SeenDb {
+            path: path.to_path_buf(),
+            hosts,
+        })
+    }
+
+    // Flag rows whose remote IP has never been recorded, then record every remote
+    // in this snapshot. Rows sharing a new remote are all flagged. A host's connection
+    // count grows by the connections (by ID) that weren't open at the last update.
+    pub fn mark_and_update(&mut self, entries: &mut [SocketEntry]) {
+        self.update_at(entries, now_secs());
+    }
+
+    fn update_at(&mut self, entries: &mut [SocketEntry], now: u64) {
+        let known: Vec<bool> = entries
+            .iter()
+            .map(|e| remote_ip(e).is_none_or(|ip| self.hosts.contains_key(&ip)))
+            .collect();
+        let mut open: HashMap<IpAddr, HashSet<String>> = HashMap::new();
+        for (entry, known) in entries.iter_mut().zip(known) {
+            entry.new_remote = !known;
+            if let Some(ip) = remote_ip(entry) {
+                let rec = self.hosts.entry(ip).or_insert(SeenRecord {
+                    first_seen: now,
+                    last_seen: now,
+                    connections: 0,
+                    open: HashSet::new(),
+                });
+                rec.last_seen = now;
+                let id = entry.conn_id();
+                if !rec.open.contains(&id) && !open.get(&ip).is_some_and(|ids| ids.contains(&id)) {
+                    rec.connections = rec.connections.saturating_add(1);
+                }
+                open.entry(ip).or_default().insert(id);
+            }
+        }
+        for (ip, rec) in self.hosts.iter_mut() {
+            rec.open = open.remove(ip).unwrap_or_default();
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let mut ips: Vec<&IpAddr> = self.hosts.keys().collect();
+        ips.sort();
+        let mut out = String::new();
+        for ip in ips {
+            let r = &self.hosts[ip];
+            let mut open: Vec<&str> = r.open.iter().map(String::as_str).collect();
+            open.sort();
+            out.push_str(&format!(
+                "{}\t{}\t{}\t{}\t{}\n",
+                ip,
+                r.first_seen,
+                r.last_seen,
+                r.connections,
+                open.join(",")
+            ));
+        }
+        // Write next to the target and rename so an interrupted run never truncates the db.
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, out)?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(local: &str, remote: &str) -> SocketEntry {
+        SocketEntry {
+            proto: "TCP".to_string(),
+            local_addr: local.to_string(),
+            remote_addr: remote.to_string(),
+            state: "Established".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn temp(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("netstatw-seen-{}-{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn hosts_survive_a_save_and_old_lines_still_load() {
+        let path = temp("tsv");
+        fs::write(
+            &path,
+            "10.0.0.5\t100\t200\t3\n\
+             not an ip\t1\t2\t3\n\
+             2001:db8::1\t150\t250\t1\tab12cd34\n",
+        )
+        .unwrap();
+        let mut db = SeenDb::open(&path).unwrap();
+        assert_eq!(db.hosts.len(), 2);
+        let v4: IpAddr = "10.0.0.5".parse().unwrap();
+        assert!(db.hosts[&v4].open.is_empty());
+        db.update_at(&mut [row("10.0.0.1:40000", "10.0.0.5:443")], 300);
+        db.save().unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        let back = SeenDb::open(&path).unwrap();
+        fs::remove_file(&path).ok();
+
+        let id = row("10.0.0.1:40000", "10.0.0.5:443").conn_id();
+        assert_eq!(
+            text,
+            format!(
+                "10.0.0.5\t100\t300\t4\t{}\n2001:db8::1\t150\t250\t1\t\n",
+                id
+            )
+        );
+        let r = &back.hosts[&v4];
+        assert_eq!((r.first_seen, r.last_seen, r.connections), (100, 300, 4));
+        assert!(r.open.contains(&id));
+    }
+
+    #[test]
+    fn a_connection_counts_once_while_it_stays_open() {
+        let mut db = SeenDb {
+            path: temp("count"),
+            hosts: HashMap::new(),
+        };
+        let a = row("10.0.0.1:40000", "10.0.0.5:443");
+        let b = row("10.0.0.1:40001", "10.0.0.5:443");
+        let ip: IpAddr = "10.0.0.5".parse().unwrap();
+
+        let mut first = [a.clone()];
+        db.update_at(&mut first, 100);
+        assert!(first[0].new_remote);
+        // Refreshes while `a` stays open don't count it again.
+        for now in [102, 104] {
+            let mut rows = [a.clone()];
+            db.update_at(&mut rows, now);
+            assert!(!rows[0].new_remote);
+        }
+        assert_eq!(db.hosts[&ip].connections, 1);
+        db.update_at(&mut [a.clone(), b.clone()], 106);
+        assert_eq!(db.hosts[&ip].connections, 2);
+        // Closed, then the same address pair again: a new connection.
+        db.update_at(&mut [b.clone()], 108);
+        db.update_at(&mut [a, b], 110);
+        let r = &db.hosts[&ip];
+        assert_eq!((r.first_seen, r.last_seen, r.connections), (100, 110, 3));
+    }
+}
@@ -0,0 +1,625 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use sysinfo::{Pid, System};
+
+use crate::{
+    AddressFamilyFlags, ProcessStats, ProtocolFlags, SocketEntry, cli, collect_entries, forensic,
+    harden, interrupt, own, report, sample_stats, tags, warn,
+};
+
+// `netstatw serve --prometheus ADDR`: an exporter. A collector thread takes a snapshot
+// (sockets plus the `--full` process statistics, the same pipeline as the table) every
+// interval and renders it in the Prometheus text format; the HTTP side only hands out
+// the last rendering, so a scrape never waits for a sampling window.
+
+// `:9184` -> 127.0.0.1:9184, anything else as given (`0.0.0.0:9184`, `[::]:9184`). The
+// metrics name processes and their peers, so only an explicit host listens beyond this
+// one.
+fn listen_addr(v: &str) -> Option<SocketAddr> {
+    let full = if v.starts_with(':') {
+        format!("127.0.0.1{}", v)
+    } else {
+        v.to_string()
+    };
+    full.to_socket_addrs().ok()?.next()
+}
+
+// Label values escape backslash, quote and newline.
+fn label(v: &str) -> String {
+    v.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, samples: &[(String, f64)]) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+    for (labels, value) in samples {
+        if value.is_finite() {
+            let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+        }
+    }
+}
+
+// Label dimensions of `netstatw_connections`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Label {
+    Proto,
+    State,
+    Process,
+}
+
+impl Label {
+    fn parse(v: &str) -> Option<Label> {
+        match v {
+            "proto" => Some(Label::Proto),
+            "state" => Some(Label::State),
+            "process" => Some(Label::Process),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Label::Proto => "proto",
+            Label::State => "state",
+            Label::Process => "process",
+        }
+    }
+}
+
+// How finely the metrics break down. Every distinct label set is a series Prometheus
+// keeps, so sockets are only counted by these labels (never per connection), and the
+// processes past the `top` with the most sockets share one series, in the socket
+// counts and in the per-process gauges alike. `top` 0 keeps every process.
+#[derive(Clone, Debug, PartialEq)]
+struct Cardinality {
+    labels: Vec<Label>,
+    top: usize,
+}
+
+impl Default for Cardinality {
+    fn default() -> Cardinality {
+        Cardinality {
+            labels: vec![Label::Proto, Label::State],
+            top: 20,
+        }
+    }
+}
+
+// The rolled-up series drop the process labels for this one, so no executable (one
+// named "other" included) can land in them.
+const OTHER: &str = "other=\"true\"";
+
+// The `top` keys with the most sockets (all of them for 0), ties by key.
+fn busiest<K: Ord + Clone>(sockets: &BTreeMap<K, usize>, top: usize) -> BTreeSet<K> {
+    let mut ranked: Vec<(&K, &usize)> = sockets.iter().collect();
+    ranked.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    if top > 0 {
+        ranked.truncate(top);
+    }
+    ranked.into_iter().map(|(k, _)| k.clone()).collect()
+}
+
+// The metrics page for one snapshot. `exe` names a PID's executable.
+fn render(
+    entries: &[SocketEntry],
+    stats: &BTreeMap<u32, ProcessStats>,
+    exe: &dyn Fn(u32) -> String,
+    cardinality: &Cardinality,
+    timestamp: f64,
+) -> String {
+    // A socket's process is the executable of its first owner; "-" for none.
+    let process = |e: &SocketEntry| e.pids.first().map_or("-".to_string(), |&pid| exe(pid));
+    let mut by_process: BTreeMap<String, usize> = BTreeMap::new();
+    if cardinality.labels.contains(&Label::Process) {
+        for e in entries {
+            *by_process.entry(process(e)).or_default() += 1;
+        }
+    }
+    let named = busiest(&by_process, cardinality.top);
+    // None: a process rolled up into the other series.
+    let mut counts: BTreeMap<Vec<Option<String>>, usize> = BTreeMap::new();
+    for e in entries {
+        let values = cardinality
+            .labels
+            .iter()
+            .map(|l| match l {
+                Label::Proto => Some(e.proto.clone()),
+                Label::State => Some(e.state.clone()),
+                Label::Process => Some(process(e)).filter(|p| named.contains(p)),
+            })
+            .collect();
+        *counts.entry(values).or_default() += 1;
+    }
+    let connections: Vec<(String, f64)> = counts
+        .into_iter()
+        .map(|(values, n)| {
+            let mut labels: Vec<String> = cardinality
+                .labels
+                .iter()
+                .zip(&values)
+                .filter_map(|(l, v)| Some(format!("{}=\"{}\"", l.name(), label(v.as_ref()?))))
+                .collect();
+            if values.contains(&None) {
+                labels.push(OTHER.to_string());
+            }
+            (labels.join(","), n as f64)
+        })
+        .collect();
+    // Per tag: sockets by protocol, and Rx/Tx of the tagged connections measured on
+    // their own, so e.g. the traffic to a database is one series.
+    let mut tag_connections = Vec::new();
+    let mut tag_rx = Vec::new();
+    let mut tag_tx = Vec::new();
+    for (tag, t) in report::tag_totals(entries) {
+        for (proto, n) in &t.protocols {
+            tag_connections.push((
+                format!("tag=\"{}\",proto=\"{}\"", label(&tag), label(proto)),
+                *n as f64,
+            ));
+        }
+        let (rx, tx) = t.rates.unwrap_or((f64::NAN, f64::NAN));
+        tag_rx.push((format!("tag=\"{}\"", label(&tag)), rx));
+        tag_tx.push((format!("tag=\"{}\"", label(&tag)), tx));
+    }
+    let mut by_pid: BTreeMap<u32, usize> = stats.keys().map(|&pid| (pid, 0)).collect();
+    for e in entries {
+        for pid in &e.pids {
+            if let Some(n) = by_pid.get_mut(pid) {
+                *n += 1;
+            }
+        }
+    }
+    let shown = busiest(&by_pid, cardinality.top);
+    let per_process = |f: fn(&ProcessStats) -> f64| -> Vec<(String, f64)> {
+        let mut samples = Vec::new();
+        // The sum of what the platform measured for the rest.
+        let mut other: Option<f64> = None;
+        for (pid, s) in stats {
+            if shown.contains(pid) {
+                samples.push((
+                    format!("pid=\"{}\",exe=\"{}\"", pid, label(&exe(*pid))),
+                    f(s),
+                ));
+            } else if f(s).is_finite() {
+                other = Some(other.unwrap_or(0.0) + f(s));
+            }
+        }
+        if let Some(v) = other {
+            samples.push((OTHER.to_string(), v));
+        }
+        samples
+    };
+
+    let mut out = String::new();
+    gauge(
+        &mut out,
+        "netstatw_connections",
+        "Sockets by protocol and state (UDP sockets have state \"-\").",
+        &connections,
+    );
+    gauge(
+        &mut out,
+        "netstatw_tag_connections",
+        "Sockets carrying a tag, by protocol.",
+        &tag_connections,
+    );
+    gauge(
+        &mut out,
+        "netstatw_tag_rx_bytes_per_sec",
+        "Receive rate of the TCP connections carrying a tag.",
+        &tag_rx,
+    );
+    gauge(
+        &mut out,
+        "netstatw_tag_tx_bytes_per_sec",
+        "Transmit rate of the TCP connections carrying a tag.",
+        &tag_tx,
+    );
+    gauge(
+        &mut out,
+        "netstatw_process_rx_bytes_per_sec",
+        "Network receive rate of a process with sockets.",
+        &per_process(|s| s.net_rx_rate_bps),
+    );
+    gauge(
+        &mut out,
+        "netstatw_process_tx_bytes_per_sec",
+        "Network transmit rate of a process with sockets.",
+        &per_process(|s| s.net_tx_rate_bps),
+    );
+    gauge(
+        &mut out,
+        "netstatw_process_cpu_percent",
+        "CPU usage of a process with sockets.",
+        &per_process(|s| s.cpu_pct as f64),
+    );
+    gauge(
+        &mut out,
+        "netstatw_process_disk_read_bytes_per_sec",
+        "Disk read rate of a process with sockets.",
+        &per_process(|s| s.read_rate_bps),
+    );
+    gauge(
+        &mut out,
+        "netstatw_process_disk_write_bytes_per_sec",
+        "Disk write rate of a process with sockets.",
+        &per_process(|s| s.write_rate_bps),
+    );
+    gauge(
+        &mut out,
+        "netstatw_snapshot_timestamp_seconds",
+        "When the snapshot behind these metrics was taken.",
+        &[(String::new(), timestamp)],
+    );
+    out
+}
+
+fn snapshot(
+    system: &mut System,
+    sample: Duration,
+    tag_rules: &tags::Rules,
+    cardinality: &Cardinality,
+) -> String {
+    forensic::record("sysinfo::System::refresh_all (process table)");
+    system.refresh_all();
+    let mut entries = match collect_entries(
+        system,
+        None,
+        AddressFamilyFlags::IPV4 | AddressFamilyFlags::IPV6,
+        ProtocolFlags::TCP | ProtocolFlags::UDP,
+    ) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn::warn(
+                "socket_tables",
+                format!("failed to read socket tables: {}", e),
+            );
+            Vec::new()
+        }
+    };
+    tag_rules.apply(&mut entries);
+    let stats: BTreeMap<u32, ProcessStats> = sample_stats(system, &mut entries, sample, true)
+        .into_iter()
+        .collect();
+    let exe = |pid: u32| {
+        system
+            .process(Pid::from(pid as usize))
+            .map(|p| match p.exe() {
+                Some(path) => path.display().to_string(),
+                None => p.name().to_string(),
+            })
+            .unwrap_or_default()
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |d| d.as_secs_f64());
+    render(&entries, &stats, &exe, cardinality, now)
+}
+
+// Answers one HTTP request: the metrics on /metrics, 404 elsewhere.
+fn respond(mut stream: TcpStream, page: &Mutex<String>) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut request = String::new();
+    BufReader::new(&stream).read_line(&mut request)?;
+    let path = request.split_whitespace().nth(1).unwrap_or("");
+    let (status, body) = if path == "/metrics" || path.starts_with("/metrics?") {
+        ("200 OK", page.lock().map(|p| p.clone()).unwrap_or_default())
+    } else {
+        ("404 Not Found", "metrics are on /metrics\n".to_string())
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+struct Options {
+    addr: SocketAddr,
+    interval: Duration,
+    sample: Duration,
+    tag_rules_path: Option<PathBuf>,
+    include_self: bool,
+    cardinality: Cardinality,
+    harden: bool,
+}
+
+fn parse_args(mut args: cli::Args) -> Result<Options, String> {
+    let mut addr: Option<SocketAddr> = None;
+    let mut interval = Duration::from_secs(15);
+    let mut sample = Duration::from_millis(800);
+    let mut tag_rules_path: Option<PathBuf> = None;
+    let mut include_self = false;
+    let mut cardinality = Cardinality::default();
+    let mut harden = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--prometheus" => match listen_addr(&args.value(&arg)?) {
+                Some(a) => addr = Some(a),
+                None => return Err("--prometheus needs an address like :9184".to_string()),
+            },
+            "--interval" => match args.secs(&arg)? {
+                s if s >= Duration::from_secs(1) => interval = s,
+                _ => return Err("--interval needs a number of seconds (at least 1)".to_string()),
+            },
+            "--sample-interval" | "-i" => {
+                sample = Duration::from_millis(args.parse::<u64>(&arg)?.max(1));
+            }
+            "--tag-rules" => tag_rules_path = Some(PathBuf::from(args.value(&arg)?)),
+            "--include-self" => include_self = true,
+            "--harden" => harden = true,
+            "--exporter-labels" => {
+                cardinality.labels = Vec::new();
+                for v in args.value(&arg)?.split(',') {
+                    let l = Label::parse(v.trim()).ok_or_else(|| {
+                        format!(
+                            "{}: unknown label '{}' (expected proto, state, process)",
+                            arg, v
+                        )
+                    })?;
+                    if !cardinality.labels.contains(&l) {
+                        cardinality.labels.push(l);
+                    }
+                }
+            }
+            "--top-processes" => cardinality.top = args.parse::<usize>(&arg)?,
+            "--forensic" => {
+                return Err(
+                    "serving opens a listening socket and is not allowed with --forensic"
+                        .to_string(),
+                );
+            }
+            other => return Err(format!("unexpected argument '{}'", other)),
+        }
+    }
+    let Some(addr) = addr else {
+        return Err("choose an exporter (--prometheus ADDR)".to_string());
+    };
+    Ok(Options {
+        addr,
+        interval,
+        sample,
+        tag_rules_path,
+        include_self,
+        cardinality,
+        harden,
+    })
+}
+
+pub fn run(args: Vec<String>) -> i32 {
+    let Options {
+        addr,
+        interval,
+        sample,
+        tag_rules_path,
+        include_self,
+        cardinality,
+        harden,
+    } = match cli::parse_command(&cli::SERVE, args, parse_args) {
+        Ok(opts) => opts,
+        Err(status) => return status,
+    };
+    if include_self {
+        own::exclude_self(false);
+    }
+    // As in the table: an explicit rules file has to load.
+    let tag_rules = match tags::Rules::load(tag_rules_path.as_deref()) {
+        Ok(rules) => rules,
+        Err(e) if tag_rules_path.is_none() => {
+            warn::warn("tag_rules", format!("tag rules not loaded: {}", e));
+            tags::Rules::default()
+        }
+        Err(e) => {
+            eprintln!("netstatw serve: --tag-rules: {}", e);
+            return 2;
+        }
+    };
+    let listener = match TcpListener::bind(addr) {
+        Ok(l) => l,
+        Err(e) => {
+            eprintln!("netstatw serve: cannot listen on {}: {}", addr, e);
+            return 1;
+        }
+    };
+    if let Ok(local) = listener.local_addr() {
+        own::note_listener(local);
+    }
+    // Polled, so Ctrl-C is noticed between requests.
+    if let Err(e) = listener.set_nonblocking(true) {
+        eprintln!("netstatw serve: {}", e);
+        return 1;
+    }
+    // Before any thread starts, so the filter covers them all.
+    if harden && let Err(e) = harden::apply() {
+        eprintln!("netstatw serve: --harden: {}", e);
+        return 1;
+    }
+    interrupt::install();
+    eprintln!("netstatw: serving metrics on http://{}/metrics", addr);
+
+    let page = Arc::new(Mutex::new(String::new()));
+    let collector = Arc::clone(&page);
+    thread::spawn(move || {
+        let mut system = System::new_all();
+        loop {
+            let text = snapshot(&mut system, sample, &tag_rules, &cardinality);
+            if let Ok(mut p) = collector.lock() {
+                *p = text;
+            }
+            warn::emit(false);
+            if !interrupt::sleep(interval) {
+                return;
+            }
+        }
+    });
+
+    while !interrupt::should_stop() {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let _ = stream
+                    .set_nonblocking(false)
+                    .and_then(|()| respond(stream, &page));
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(50));
+            }
+            Err(e) => eprintln!("netstatw serve: accept: {}", e),
+        }
+    }
+    if interrupt::interrupted() { 130 } else { 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_counts_sockets_and_labels_processes() {
+        let row = |proto: &str, state: &str| SocketEntry {
+            proto: proto.to_string(),
+            state: state.to_string(),
+            ..Default::default()
+        };
+        let entries = [
+            row("TCP", "Established"),
+            row("TCP", "Established"),
+            row("UDP", "-"),
+        ];
+        let stats = BTreeMap::from([(
+            7,
+            ProcessStats {
+                net_rx_rate_bps: 1024.0,
+                read_rate_bps: f64::NAN,
+                ..Default::default()
+            },
+        )]);
+        let page = render(
+            &entries,
+            &stats,
+            &|_| "C:\\srv\\\"api\".exe".to_string(),
+            &Cardinality::default(),
+            5.0,
+        );
+        assert!(page.contains("# TYPE netstatw_connections gauge\n"));
+        assert!(page.contains("netstatw_connections{proto=\"TCP\",state=\"Established\"} 2\n"));
+        assert!(page.contains(
+            "netstatw_process_rx_bytes_per_sec{pid=\"7\",exe=\"C:\\\\srv\\\\\\\"api\\\".exe\"} 1024\n"
+        ));
+        // Unavailable values are left out rather than exported as NaN.
+        assert!(!page.contains("netstatw_process_disk_read_bytes_per_sec{"));
+        assert!(page.contains("netstatw_snapshot_timestamp_seconds{} 5\n"));
+        assert_eq!(listen_addr(":9184"), "127.0.0.1:9184".parse().ok());
+    }
+
+    #[test]
+    fn render_rolls_up_tags() {
+        let tagged = |proto: &str, rates: Option<f64>| SocketEntry {
+            proto: proto.to_string(),
+            tags: vec!["payments-db".to_string()],
+            agg_stats: rates.map(|rx| ProcessStats {
+                net_rx_rate_bps: rx,
+                net_tx_rate_bps: rx * 2.0,
+                net_per_conn: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let entries = [
+            tagged("TCP", Some(1000.0)),
+            tagged("TCP", Some(24.0)),
+            tagged("UDP", None),
+        ];
+        let page = render(
+            &entries,
+            &BTreeMap::new(),
+            &|_| String::new(),
+            &Cardinality::default(),
+            5.0,
+        );
+        assert!(page.contains("netstatw_tag_connections{tag=\"payments-db\",proto=\"TCP\"} 2\n"));
+        assert!(page.contains("netstatw_tag_connections{tag=\"payments-db\",proto=\"UDP\"} 1\n"));
+        assert!(page.contains("netstatw_tag_rx_bytes_per_sec{tag=\"payments-db\"} 1024\n"));
+        assert!(page.contains("netstatw_tag_tx_bytes_per_sec{tag=\"payments-db\"} 2048\n"));
+    }
+
+    #[test]
+    fn labels_are_chosen_and_quiet_processes_share_other() {
+        let row = |state: &str, pid: u32| SocketEntry {
+            proto: "TCP".to_string(),
+            state: state.to_string(),
+            pids: vec![pid],
+            ..Default::default()
+        };
+        let entries = [
+            row("Established", 1),
+            row("Established", 1),
+            row("Listen", 2),
+            row("Established", 3),
+        ];
+        let stats = BTreeMap::from([
+            (1, ProcessStats::default()),
+            (
+                2,
+                ProcessStats {
+                    net_rx_rate_bps: 10.0,
+                    ..Default::default()
+                },
+            ),
+            (
+                3,
+                ProcessStats {
+                    net_rx_rate_bps: 5.0,
+                    ..Default::default()
+                },
+            ),
+        ]);
+        let cardinality = Cardinality {
+            labels: vec![Label::Process, Label::State],
+            top: 1,
+        };
+        let exe = |pid: u32| format!("/bin/p{}", pid);
+        let page = render(&entries, &stats, &exe, &cardinality, 5.0);
+        assert!(
+            page.contains("netstatw_connections{process=\"/bin/p1\",state=\"Established\"} 2\n")
+        );
+        assert!(page.contains("netstatw_connections{state=\"Established\",other=\"true\"} 1\n"));
+        assert!(page.contains("netstatw_connections{state=\"Listen\",other=\"true\"} 1\n"));
+        assert!(!page.contains("proto=\"TCP\",state"));
+        assert!(page.contains("netstatw_process_rx_bytes_per_sec{pid=\"1\",exe=\"/bin/p1\"} 0\n"));
+        assert!(page.contains("netstatw_process_rx_bytes_per_sec{other=\"true\"} 15\n"));
+
+        // A process whose executable is called "other" keeps a series of its own.
+        let other = |pid: u32| match pid {
+            1 => "other".to_string(),
+            _ => format!("/bin/p{}", pid),
+        };
+        let page = render(&entries, &stats, &other, &cardinality, 5.0);
+        assert!(page.contains("netstatw_connections{process=\"other\",state=\"Established\"} 2\n"));
+        assert!(page.contains("netstatw_connections{state=\"Established\",other=\"true\"} 1\n"));
+        assert!(page.contains("netstatw_process_rx_bytes_per_sec{pid=\"1\",exe=\"other\"} 0\n"));
+        assert!(page.contains("netstatw_process_rx_bytes_per_sec{other=\"true\"} 15\n"));
+
+        let argv = [
+            "--prometheus",
+            ":9184",
+            "--exporter-labels",
+            "state,process,state",
+        ]
+        .map(String::from)
+        .to_vec();
+        let opts = parse_args(cli::Args::new(&cli::SERVE, argv).unwrap()).unwrap();
+        assert_eq!(opts.cardinality.labels, [Label::State, Label::Process]);
+        assert_eq!(opts.cardinality.top, 20);
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::fs;
+use std::sync::OnceLock;
+
+use crate::{SocketEntry, forensic, parse_addr_port};
+
+// `--names`: service names for port numbers, e.g. `:443 (https)`. The system services
+// file is read once per process; the embedded table covers ports it doesn't list (or
+// the whole range when the file is missing, as on stripped-down containers).
+
+#[cfg(windows)]
+const SERVICES_FILE: &str = r"C:\Windows\System32\drivers\etc\services";
+#[cfg(not(windows))]
+const SERVICES_FILE: &str = "/etc/services";
+
+// Well-known ports from the IANA registry (name, port, protocol).
+const EMBEDDED: &[(&str, u16, &str)] = &[
+    ("ftp-data", 20, "tcp"),
+    ("ftp", 21, "tcp"),
+    ("ssh", 22, "tcp"),
+    ("telnet", 23, "tcp"),
+    ("smtp", 25, "tcp"),
+    ("domain", 53, "tcp"),
+    ("domain", 53, "udp"),
+    ("bootps", 67, "udp"),
+    ("bootpc", 68, "udp"),
+    ("tftp", 69, "udp"),
+    ("http", 80, "tcp"),
+    ("kerberos", 88, "tcp"),
+    ("kerberos", 88, "udp"),
+    ("pop3", 110, "tcp"),
+    ("sunrpc", 111, "tcp"),
+    ("sunrpc", 111, "udp"),
+    ("ntp", 123, "udp"),
+    ("epmap", 135, "tcp"),
+    ("netbios-ns", 137, "udp"),
+    ("netbios-dgm", 138, "udp"),
+    ("netbios-ssn", 139, "tcp"),
+    ("imap", 143, "tcp"),
+    ("snmp", 161, "udp"),
+    ("snmptrap", 162, "udp"),
+    ("ldap", 389, "tcp"),
+    ("https", 443, "tcp"),
+    ("https", 443, "udp"),
+    ("microsoft-ds", 445, "tcp"),
+    ("submissions", 465, "tcp"),
+    ("isakmp", 500, "udp"),
+    ("syslog", 514, "udp"),
+    ("submission", 587, "tcp"),
+    ("ipp", 631, "tcp"),
+    ("ldaps", 636, "tcp"),
+    ("domain-s", 853, "tcp"),
+    ("imaps", 993, "tcp"),
+    ("pop3s", 995, "tcp"),
+    ("socks", 1080, "tcp"),
+    ("openvpn", 1194, "udp"),
+    ("ms-sql-s", 1433, "tcp"),
+    ("oracle", 1521, "tcp"),
+    ("l2tp", 1701, "udp"),
+    ("mqtt", 1883, "tcp"),
+    ("nfs", 2049, "tcp"),
+    ("nfs", 2049, "udp"),
+    ("zookeeper", 2181, "tcp"),
+    ("etcd-client", 2379, "tcp"),
+    ("mysql", 3306, "tcp"),
+    ("ms-wbt-server", 3389, "tcp"),
+    ("ms-wbt-server", 3389, "udp"),
+    ("ipsec-nat-t", 4500, "udp"),
+    ("sip", 5060, "tcp"),
+    ("sip", 5060, "udp"),
+    ("xmpp-client", 5222, "tcp"),
+    ("mdns", 5353, "udp"),
+    ("llmnr", 5355, "udp"),
+    ("postgresql", 5432, "tcp"),
+    ("amqp", 5672, "tcp"),
+    ("rfb", 5900, "tcp"),
+    ("wsman", 5985, "tcp"),
+    ("wsmans", 5986, "tcp"),
+    ("redis", 6379, "tcp"),
+    ("http-alt", 8080, "tcp"),
+    ("https-alt", 8443, "tcp"),
+    ("memcache", 11211, "tcp"),
+    ("mongodb", 27017, "tcp"),
+];
+
+pub struct Services {
+    by_port: HashMap<(String, u16), String>,
+}
+
+impl Services {
+    // The services file first (the local admin's names win), then the embedded table.
+    fn load() -> Services {
+        forensic::record("services file (port names)");
+        let mut by_port = fs::read_to_string(SERVICES_FILE)
+            .map(|text| parse(&text))
+            .unwrap_or_default();
+        for &(name, port, proto) in EMBEDDED {
+            by_port
+                .entry((proto.to_string(), port))
+                .or_insert_with(|| name.to_string());
+        }
+        Services { by_port }
+    }
+
+    // Name for `port` under `proto` ("TCP"/"UDP", any case); None for port 0.
+    pub fn name(&self, proto: &str, port: u16) -> Option<&str> {
+        self.by_port
+            .get(&(proto.to_ascii_lowercase(), port))
+            .map(String::as_str)
+    }
+}
+
+// `name port/proto [aliases] [# comment]` lines; the first entry for a port wins, as
+// with getservbyport.
+fn parse(text: &str) -> HashMap<(String, u16), String> {
+    let mut out = HashMap::new();
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap_or("");
+        let mut fields = line.split_whitespace();
+        let (Some(name), Some(spec)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+        let Some((port, proto)) = spec.split_once('/') else {
+            continue;
+        };
+        if let Ok(port) = port.parse::<u16>()
+            && port != 0
+        {
+            out.entry((proto.to_ascii_lowercase(), port))
+                .or_insert_with(|| name.to_string());
+        }
+    }
+    out
+}
+
+pub fn services() -> &'static Services {
+    static SERVICES: OnceLock<Services> = OnceLock::new();
+    SERVICES.get_or_init(Services::load)
+}
+
+// Sets `local_service`/`remote_service` on every row whose ports have a name.
+pub fn label(entries: &mut [SocketEntry]) {
+    let services = services();
+    for e in entries.iter_mut() {
+        let local = parse_addr_port(&e.local_addr).1;
+        let remote = parse_addr_port(&e.remote_addr).1;
+        e.local_service = services.name(&e.proto, local).map(str::to_string);
+        e.remote_service = services.name(&e.proto, remote).map(str::to_string);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_reads_services_lines() {
+        let table = parse(
+            "# comment\n\
+             ssh\t\t22/tcp\t\t\t# SSH Remote Login Protocol\n\
+             http\t\t80/tcp\t\twww\n\
+             www-alt\t\t80/tcp\n\
+             domain\t\t53/udp\n\
+             bogus\t\tnot-a-port\n",
+        );
+        assert_eq!(
+            table.get(&("tcp".to_string(), 22)).map(String::as_str),
+            Some("ssh")
+        );
+        assert_eq!(
+            table.get(&("tcp".to_string(), 80)).map(String::as_str),
+            Some("http")
+        );
+        assert_eq!(
+            table.get(&("udp".to_string(), 53)).map(String::as_str),
+            Some("domain")
+        );
+        assert_eq!(table.get(&("tcp".to_string(), 53)), None);
+        assert_eq!(table.len(), 3);
+    }
+}
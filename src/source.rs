@@ -0,0 +1,181 @@
+#[cfg(any(windows, test))]
+use std::collections::HashMap;
+use std::fmt;
+#[cfg(any(windows, test))]
+use std::net::IpAddr;
+
+#[cfg(any(windows, test))]
+use netstat2::ProtocolSocketInfo;
+use netstat2::{AddressFamilyFlags, ProtocolFlags, SocketInfo};
+
+// Where the socket rows come from. The table source lists every socket with its owning
+// PIDs: netstat2's per-platform API, or /proc/net in the `procfs` build. Owner sources
+// only name the owners of sockets the table source returned without one; on Windows
+// that is the UDP owner table, which the netstat2 crate reads incompletely.
+pub(crate) trait SocketSource {
+    type Error: fmt::Display;
+
+    // Name in warnings.
+    #[cfg(windows)]
+    fn name(&self) -> &'static str;
+
+    fn sockets(
+        &self,
+        af_flags: AddressFamilyFlags,
+        proto_flags: ProtocolFlags,
+    ) -> Result<Vec<SocketInfo>, Self::Error>;
+}
+
+#[cfg(not(all(target_os = "linux", feature = "procfs")))]
+pub(crate) struct Netstat2;
+
+#[cfg(not(all(target_os = "linux", feature = "procfs")))]
+impl SocketSource for Netstat2 {
+    type Error = netstat2::error::Error;
+
+    #[cfg(windows)]
+    fn name(&self) -> &'static str {
+        "netstat2"
+    }
+
+    fn sockets(
+        &self,
+        af_flags: AddressFamilyFlags,
+        proto_flags: ProtocolFlags,
+    ) -> Result<Vec<SocketInfo>, Self::Error> {
+        crate::forensic::record("netstat2::get_sockets_info (socket tables with owning PIDs)");
+        netstat2::get_sockets_info(af_flags, proto_flags)
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "procfs"))]
+pub(crate) struct ProcNet;
+
+#[cfg(all(target_os = "linux", feature = "procfs"))]
+impl SocketSource for ProcNet {
+    type Error = netstat2::error::Error;
+
+    #[cfg(windows)]
+    fn name(&self) -> &'static str {
+        "/proc/net"
+    }
+
+    fn sockets(
+        &self,
+        af_flags: AddressFamilyFlags,
+        proto_flags: ProtocolFlags,
+    ) -> Result<Vec<SocketInfo>, Self::Error> {
+        crate::procnet::sockets_info(af_flags, proto_flags)
+    }
+}
+
+// The table source of this build.
+#[cfg(all(target_os = "linux", feature = "procfs"))]
+pub(crate) const TABLE: ProcNet = ProcNet;
+#[cfg(not(all(target_os = "linux", feature = "procfs")))]
+pub(crate) const TABLE: Netstat2 = Netstat2;
+
+// The Windows UDP owner tables (GetExtendedUdpTable, IPv4 and IPv6).
+#[cfg(windows)]
+pub(crate) struct UdpOwnerTable;
+
+#[cfg(windows)]
+impl SocketSource for UdpOwnerTable {
+    type Error = crate::win_net::TableError;
+
+    fn name(&self) -> &'static str {
+        "udp_owner_table"
+    }
+
+    fn sockets(
+        &self,
+        af_flags: AddressFamilyFlags,
+        proto_flags: ProtocolFlags,
+    ) -> Result<Vec<SocketInfo>, Self::Error> {
+        if !proto_flags.contains(ProtocolFlags::UDP) {
+            return Ok(Vec::new());
+        }
+        let owners = crate::win_net::udp_owners(
+            af_flags.contains(AddressFamilyFlags::IPV4),
+            af_flags.contains(AddressFamilyFlags::IPV6),
+        )?;
+        Ok(owners
+            .into_iter()
+            .map(|(local_addr, local_port, pid)| SocketInfo {
+                protocol_socket_info: ProtocolSocketInfo::Udp(netstat2::UdpSocketInfo {
+                    local_addr,
+                    local_port,
+                }),
+                associated_pids: vec![pid],
+            })
+            .collect())
+    }
+}
+
+// What identifies a socket across sources: protocol and both endpoints (UDP has no
+// remote end).
+#[cfg(any(windows, test))]
+fn key(s: &SocketInfo) -> (bool, IpAddr, u16, Option<(IpAddr, u16)>) {
+    match &s.protocol_socket_info {
+        ProtocolSocketInfo::Tcp(t) => (
+            true,
+            t.local_addr,
+            t.local_port,
+            Some((t.remote_addr, t.remote_port)),
+        ),
+        ProtocolSocketInfo::Udp(u) => (false, u.local_addr, u.local_port, None),
+    }
+}
+
+// Gives the rows without an owner the PIDs `owners` lists for the same socket.
+#[cfg(any(windows, test))]
+pub(crate) fn attribute(rows: &mut [SocketInfo], owners: &[SocketInfo]) {
+    let by_key: HashMap<_, &Vec<u32>> = owners
+        .iter()
+        .filter(|o| !o.associated_pids.is_empty())
+        .map(|o| (key(o), &o.associated_pids))
+        .collect();
+    for row in rows.iter_mut().filter(|r| r.associated_pids.is_empty()) {
+        if let Some(pids) = by_key.get(&key(row)) {
+            row.associated_pids = pids.to_vec();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use netstat2::UdpSocketInfo;
+
+    fn udp(addr: &str, port: u16, pids: &[u32]) -> SocketInfo {
+        SocketInfo {
+            protocol_socket_info: ProtocolSocketInfo::Udp(UdpSocketInfo {
+                local_addr: addr.parse().unwrap(),
+                local_port: port,
+            }),
+            associated_pids: pids.to_vec(),
+            #[cfg(target_os = "linux")]
+            inode: 0,
+            #[cfg(target_os = "linux")]
+            uid: 0,
+        }
+    }
+
+    #[test]
+    fn owner_sources_fill_only_rows_without_pids() {
+        let mut rows = vec![
+            udp("0.0.0.0", 53, &[]),
+            udp("::", 5353, &[]),
+            udp("127.0.0.1", 123, &[4]),
+            udp("::1", 9000, &[]),
+        ];
+        let owners = [
+            udp("0.0.0.0", 53, &[7]),
+            udp("::", 5353, &[8]),
+            udp("127.0.0.1", 123, &[9]),
+        ];
+        attribute(&mut rows, &owners);
+        let pids: Vec<Vec<u32>> = rows.into_iter().map(|r| r.associated_pids).collect();
+        assert_eq!(pids, vec![vec![7], vec![8], vec![4], vec![]]);
+    }
+}
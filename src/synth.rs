@@ -0,0 +1,134 @@
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use sysinfo::System;
+
+use crate::cli;
+
+// `netstatw synth`: a small load generator. Its connections are recognised by the
+// main view (see `synth_pids`) so churn can be told apart from real traffic.
+
+struct Options {
+    target: String,
+    rate: u32,
+    hold_ms: u64,
+    duration: Option<Duration>,
+}
+
+fn parse_args(mut args: cli::Args) -> Result<Options, String> {
+    let mut target: Option<String> = None;
+    let mut rate: u32 = 10;
+    let mut hold_ms: u64 = 1000;
+    let mut duration: Option<Duration> = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--connect" | "-c" => target = Some(args.value(&arg)?),
+            "--rate" | "-r" => rate = args.parse::<u32>(&arg)?.max(1),
+            "--hold" => hold_ms = args.parse(&arg)?,
+            "--duration" | "-d" => duration = Some(args.secs(&arg)?),
+            other => return Err(format!("unexpected argument '{}'", other)),
+        }
+    }
+    let Some(target) = target else {
+        return Err("--connect HOST:PORT is required".to_string());
+    };
+    Ok(Options {
+        target,
+        rate,
+        hold_ms,
+        duration,
+    })
+}
+
+pub fn run(args: Vec<String>) -> i32 {
+    let Options {
+        target,
+        rate,
+        hold_ms,
+        duration,
+    } = match cli::parse_command(&cli::SYNTH, args, parse_args) {
+        Ok(opts) => opts,
+        Err(status) => return status,
+    };
+    let addr: SocketAddr = match target.to_socket_addrs().ok().and_then(|mut a| a.next()) {
+        Some(a) => a,
+        None => {
+            eprintln!("netstatw synth: cannot resolve {}", target);
+            return 2;
+        }
+    };
+
+    let ok = Arc::new(AtomicUsize::new(0));
+    let failed = Arc::new(AtomicUsize::new(0));
+    let period = Duration::from_secs_f64(1.0 / rate as f64);
+    let hold = Duration::from_millis(hold_ms);
+    let start = Instant::now();
+    let mut next_launch = start;
+    let mut next_report = start + Duration::from_secs(1);
+
+    eprintln!(
+        "netstatw synth: {} conn/s to {} (pid {})",
+        rate,
+        addr,
+        std::process::id()
+    );
+    loop {
+        let now = Instant::now();
+        if duration.is_some_and(|d| now.duration_since(start) >= d) {
+            break;
+        }
+        if now >= next_report {
+            eprintln!(
+                "netstatw synth: {} connected, {} failed",
+                ok.load(Ordering::Relaxed),
+                failed.load(Ordering::Relaxed)
+            );
+            next_report += Duration::from_secs(1);
+        }
+        if now < next_launch {
+            thread::sleep(next_launch - now);
+            continue;
+        }
+        next_launch += period;
+
+        let ok = Arc::clone(&ok);
+        let failed = Arc::clone(&failed);
+        thread::spawn(
+            move || match TcpStream::connect_timeout(&addr, Duration::from_secs(2)) {
+                Ok(stream) => {
+                    ok.fetch_add(1, Ordering::Relaxed);
+                    thread::sleep(hold);
+                    drop(stream);
+                }
+                Err(_) => {
+                    failed.fetch_add(1, Ordering::Relaxed);
+                }
+            },
+        );
+    }
+
+    // Let in-flight connections finish their hold time before reporting.
+    thread::sleep(hold);
+    eprintln!(
+        "netstatw synth: done, {} connected, {} failed",
+        ok.load(Ordering::Relaxed),
+        failed.load(Ordering::Relaxed)
+    );
+    0
+}
+
+// PIDs of running `netstatw synth` load generators.
+pub fn synth_pids(system: &System) -> Vec<u32> {
+    system
+        .processes()
+        .iter()
+        .filter(|(_, p)| {
+            let cmd = p.cmd();
+            p.name().starts_with("netstatw") && cmd.get(1).is_some_and(|a| a == "synth")
+        })
+        .map(|(pid, _)| pid.as_u32())
+        .collect()
+}
@@ -0,0 +1,171 @@
+use std::fs;
+use std::path::Path;
+
+use crate::SocketEntry;
+use crate::config::{self, Config, Value};
+use crate::filter::{self, Cidr, Criterion, Filter};
+
+// Tag rules overlay a team's service topology on raw connections. Each
+// `[tag.<name>]` section, from `--tag-rules FILE` or else the config file, is a rule:
+//
+//   [tag.payments-db]
+//   cidr = "10.20.0.0/16"
+//   port = 5432
+//
+// Keys are the filter options of the command line (cidr, port, lport, rport, process,
+// state, listening) with the same meaning: different keys must all match, a list
+// matches any of its values and a `not-` prefix excludes. A row gets the tag of every
+// rule it matches, in file order.
+
+struct Rule {
+    tag: String,
+    filter: Filter,
+}
+
+#[derive(Default)]
+pub struct Rules {
+    rules: Vec<Rule>,
+}
+
+fn criteria(cfg: &Config, key: &str, value: &Value) -> Result<Vec<Criterion>, String> {
+    let items: Vec<String> = match value {
+        Value::List(items) => items.iter().map(Value::to_arg).collect(),
+        other => vec![other.to_arg()],
+    };
+    let ports = |v: &str| filter::parse_ports(v, |name| cfg.port_template(name));
+    let mut out = Vec::new();
+    for item in &items {
+        match key {
+            "cidr" => {
+                let net = Cidr::parse(item)
+                    .ok_or_else(|| format!("'{}' is not an address or network", item))?;
+                out.push(Criterion::Cidr(net));
+            }
+            "port" => out.extend(ports(item)?.into_iter().map(Criterion::Port)),
+            "lport" => out.extend(ports(item)?.into_iter().map(Criterion::LocalPort)),
+            "rport" => out.extend(ports(item)?.into_iter().map(Criterion::RemotePort)),
+            "process" => out.push(Criterion::Process(item.clone())),
+            "state" => out.extend(filter::parse_states(item)?),
+            "listening" if *value == Value::Bool(true) => out.push(Criterion::Listening),
+            "listening" => return Err("listening only takes `true`".to_string()),
+            _ => return Err(format!("unknown key '{}'", key)),
+        }
+    }
+    Ok(out)
+}
+
+impl Rules {
+    pub fn from_config(cfg: &Config) -> Result<Rules, String> {
+        let mut rules = Vec::new();
+        for tag in cfg.subsections("tag") {
+            let mut filter = Filter::default();
+            for (key, value) in cfg.section(&format!("tag.{}", tag)).unwrap_or_default() {
+                let (negate, key) = match key.strip_prefix("not-") {
+                    Some(k) => (true, k),
+                    None => (false, key.as_str()),
+                };
+                for c in criteria(cfg, key, value).map_err(|e| format!("[tag.{}] {}", tag, e))? {
+                    filter.add(c, negate);
+                }
+            }
+            if filter.is_empty() {
+                return Err(format!("[tag.{}] has no matchers", tag));
+            }
+            rules.push(Rule {
+                tag: tag.to_string(),
+                filter,
+            });
+        }
+        Ok(Rules { rules })
+    }
+
+    // Rules from `path`, or from the config file without one.
+    pub fn load(path: Option<&Path>) -> Result<Rules, String> {
+        let cfg = match path {
+            Some(path) => fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|text| Config::parse(&text))
+                .map_err(|e| format!("{}: {}", path.display(), e))?,
+            None => Config::load()?,
+        };
+        Rules::from_config(&cfg).map_err(|e| {
+            match path.map(Path::to_path_buf).or_else(config::config_path) {
+                Some(p) => format!("{}: {}", p.display(), e),
+                None => e,
+            }
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn apply(&self, entries: &mut [SocketEntry]) {
+        for e in entries.iter_mut() {
+            e.tags = self
+                .rules
+                .iter()
+                .filter(|r| r.filter.matches(e))
+                .map(|r| r.tag.clone())
+                .collect();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conn(local: &str, remote: &str, process: &str) -> SocketEntry {
+        SocketEntry {
+            proto: "TCP".to_string(),
+            local_addr: local.to_string(),
+            remote_addr: remote.to_string(),
+            state: "Established".to_string(),
+            process_info: process.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn rules_tag_matching_rows_in_file_order() {
+        let cfg = Config::parse(
+            "[ports]\n\
+             db = [5432, 3306]\n\
+             [tag.payments-db]\n\
+             cidr = \"10.20.0.0/16\"\n\
+             port = \"db\"\n\
+             [tag.internal]\n\
+             cidr = [\"10.0.0.0/8\", \"192.168.0.0/16\"]\n\
+             not-process = \"backup\"\n",
+        )
+        .unwrap();
+        let rules = Rules::from_config(&cfg).unwrap();
+        let mut rows = [
+            conn("192.168.1.5:40000", "10.20.1.1:5432", "1: /usr/bin/app"),
+            conn("192.168.1.5:40001", "10.20.1.1:6379", "1: /usr/bin/app"),
+            conn("192.168.1.5:40002", "10.20.1.1:5432", "2: /usr/bin/backup"),
+            conn("172.16.0.5:40003", "8.8.8.8:53", "3: dig"),
+        ];
+        rules.apply(&mut rows);
+        assert_eq!(rows[0].tags, ["payments-db", "internal"]);
+        assert_eq!(rows[1].tags, ["internal"]);
+        assert_eq!(rows[2].tags, ["payments-db"]);
+        assert!(rows[3].tags.is_empty());
+    }
+
+    #[test]
+    fn rules_reject_unknown_keys_and_empty_rules() {
+        for text in [
+            "[tag.x]\nhost = \"db\"\n",
+            "[tag.x]\ncidr = \"10.0.0.0/40\"\n",
+            "[tag.x]\n[view.y]\nfull = true\n",
+        ] {
+            assert!(
+                Rules::from_config(&Config::parse(text).unwrap()).is_err(),
+                "{}",
+                text
+            );
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
+
+use crate::{SocketEntry, color, human_readable_rate};
+
+// The bandwidth tab: the Rx+Tx rate of the ten busiest processes over the last
+// refreshes, stacked (busiest at the bottom) and scrolling left as refreshes arrive.
+// Braille cells hold two refreshes side by side and four levels each; the ASCII form
+// (for fonts without Braille) holds one refresh per cell and draws each process with
+// its legend digit. Processes are told apart by the palette's series colors.
+
+// Refreshes kept: more than the widest terminal shows in Braille.
+const KEEP: usize = 1024;
+const TOP: usize = 10;
+
+// Braille dot bits by row, top to bottom, for the left and the right column.
+const LEFT_DOTS: [u32; 4] = [0x01, 0x02, 0x04, 0x40];
+const RIGHT_DOTS: [u32; 4] = [0x08, 0x10, 0x20, 0x80];
+
+pub type Span = (String, Option<&'static str>);
+
+#[derive(Default)]
+pub struct History {
+    // Per refresh, the Rx+Tx bytes per second of each process with network rates.
+    samples: VecDeque<BTreeMap<String, f64>>,
+}
+
+impl History {
+    // Rows of one process carry the same per-process rates; they count once.
+    pub fn push<'a>(&mut self, rows: impl Iterator<Item = &'a SocketEntry>) {
+        let mut sample = BTreeMap::new();
+        for e in rows {
+            let Some(s) = &e.agg_stats else { continue };
+            let rate = s.net_rx_rate_bps + s.net_tx_rate_bps;
+            if rate.is_finite() {
+                sample.insert(e.process_info.clone(), rate);
+            }
+        }
+        if self.samples.len() == KEEP {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    // The last `n` refreshes, oldest first.
+    fn window(&self, n: usize) -> impl Iterator<Item = &BTreeMap<String, f64>> {
+        self.samples
+            .iter()
+            .skip(self.samples.len().saturating_sub(n))
+    }
+
+    // The processes with the most traffic over the last `n` refreshes, busiest first.
+    fn top(&self, n: usize) -> Vec<String> {
+        let mut totals: BTreeMap<&str, f64> = BTreeMap::new();
+        for sample in self.window(n) {
+            for (process, rate) in sample {
+                *totals.entry(process).or_default() += rate;
+            }
+        }
+        let mut top: Vec<(&str, f64)> = totals.into_iter().filter(|&(_, t)| t > 0.0).collect();
+        top.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(b.0)));
+        top.truncate(TOP);
+        top.into_iter().map(|(p, _)| p.to_string()).collect()
+    }
+}
+
+// The legend digit of the i-th busiest process: 1 to 9, then 0.
+fn glyph(i: usize) -> char {
+    char::from_digit(((i + 1) % 10) as u32, 10).unwrap_or('?')
+}
+
+// The process whose band covers height `h` of a stacked column `full` units high.
+fn owner(rates: &[f64], max: f64, full: f64, h: f64) -> Option<usize> {
+    let mut top = 0.0;
+    for (i, rate) in rates.iter().enumerate() {
+        top += rate / max * full;
+        if h < top {
+            return Some(i);
+        }
+    }
+    None
+}
+
+// Joins neighbouring cells of the same style, to keep the escape sequences down.
+fn push(spans: &mut Vec<Span>, c: char, style: Option<&'static str>) {
+    match spans.last_mut() {
+        Some((text, s)) if *s == style => text.push(c),
+        _ => spans.push((c.to_string(), style)),
+    }
+}
+
+// The tab's `height` lines of `width` cells: a caption, the graph and a legend line
+// per process.
+pub fn render(
+    history: &History,
+    ascii: bool,
+    palette: Option<&color::Palette>,
+    refresh: Duration,
+    width: usize,
+    height: usize,
+) -> Vec<Vec<Span>> {
+    let per_cell = if ascii { 1 } else { 2 };
+    let shown = width * per_cell;
+    let top = history.top(shown);
+    let window: Vec<Vec<f64>> = history
+        .window(shown)
+        .map(|s| {
+            top.iter()
+                .map(|p| s.get(p).copied().unwrap_or(0.0))
+                .collect()
+        })
+        .collect();
+    let max = window
+        .iter()
+        .map(|rates| rates.iter().sum::<f64>())
+        .fold(0.0, f64::max);
+    if top.is_empty() || max <= 0.0 {
+        return vec![vec![(
+            " No network traffic measured yet (Rx/Tx are sampled on Windows and Linux)".to_string(),
+            None,
+        )]];
+    }
+    // The caption and at least two graph rows come before the legend.
+    let legend = top.len().min(height.saturating_sub(3));
+    let rows = height.saturating_sub(1 + legend);
+    let mut lines = vec![vec![(
+        format!(
+            " Rx+Tx of the top {} processes   full height {}   {}s per {}",
+            top.len(),
+            human_readable_rate(max),
+            refresh.as_secs_f64() * per_cell as f64,
+            if ascii { "column" } else { "cell" }
+        ),
+        None,
+    )]];
+
+    // Newest refresh at the right edge.
+    let blank = shown.saturating_sub(window.len());
+    let sample = |i: usize| i.checked_sub(blank).and_then(|i| window.get(i));
+    let style = |i: usize| palette.and_then(|p| p.series(i));
+    let dots = if ascii { 1 } else { 4 };
+    let full = (rows * dots) as f64;
+    for r in 0..rows {
+        let mut spans = Vec::new();
+        // Height of the cell's lowest dot row above the bottom.
+        let base = ((rows - 1 - r) * dots) as f64;
+        for c in 0..width {
+            if ascii {
+                let owner = sample(c).and_then(|s| owner(s, max, full, base + 0.5));
+                match owner {
+                    Some(i) => push(&mut spans, glyph(i), style(i)),
+                    None => push(&mut spans, ' ', None),
+                }
+                continue;
+            }
+            let mut bits = 0;
+            let mut first = None;
+            for (side, dot_bits) in [LEFT_DOTS, RIGHT_DOTS].iter().enumerate() {
+                let Some(s) = sample(c * 2 + side) else {
+                    continue;
+                };
+                for (d, bit) in dot_bits.iter().enumerate() {
+                    let h = base + (3 - d) as f64 + 0.5;
+                    if let Some(i) = owner(s, max, full, h) {
+                        bits |= bit;
+                        first = first.or(Some(i));
+                    }
+                }
+            }
+            match (char::from_u32(0x2800 + bits), first) {
+                (Some(cell), Some(i)) => push(&mut spans, cell, style(i)),
+                _ => push(&mut spans, ' ', None),
+            }
+        }
+        lines.push(spans);
+    }
+
+    let now = window.last();
+    for (i, process) in top.iter().take(legend).enumerate() {
+        let rate = now.and_then(|rates| rates.get(i)).copied().unwrap_or(0.0);
+        lines.push(vec![
+            (" ".to_string(), None),
+            (glyph(i).to_string(), style(i)),
+            (
+                format!(" {:>10}  {}", human_readable_rate(rate), process),
+                None,
+            ),
+        ]);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProcessStats;
+
+    fn row(process: &str, rx: f64, tx: f64) -> SocketEntry {
+        SocketEntry {
+            process_info: process.to_string(),
+            agg_stats: Some(ProcessStats {
+                net_rx_rate_bps: rx,
+                net_tx_rate_bps: tx,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn text(line: &[Span]) -> String {
+        line.iter().map(|(t, _)| t.as_str()).collect()
+    }
+
+    #[test]
+    fn processes_stack_busiest_first_and_scroll_in_from_the_right() {
+        let mut history = History::default();
+        history.push([row("2: db", 300.0, 0.0), row("1: web", 100.0, 0.0)].iter());
+        history.push([row("2: db", 100.0, 0.0), row("2: db", 100.0, 0.0)].iter());
+        let lines = render(&history, true, None, Duration::from_secs(2), 4, 7);
+        assert!(text(&lines[0]).contains("top 2 processes"));
+        // Four rows of graph: 400 B/s is the full height, db (digit 1) is at the bottom.
+        let graph: Vec<String> = lines[1..5].iter().map(|l| text(l)).collect();
+        assert_eq!(graph, ["  2 ", "  1 ", "  1 ", "  11"]);
+        assert_eq!(text(&lines[5]), " 1    100 B/s  2: db");
+        assert_eq!(text(&lines[6]), " 2      0 B/s  1: web");
+    }
+
+    #[test]
+    fn braille_cells_hold_two_refreshes() {
+        let mut history = History::default();
+        history.push([row("1: a", 4.0, 0.0)].iter());
+        history.push([row("1: a", 2.0, 0.0)].iter());
+        let lines = render(&history, false, None, Duration::from_secs(1), 1, 3);
+        // Two rows: the left refresh fills both, the right one the lower row.
+        assert_eq!(text(&lines[1]), "\u{2847}");
+        assert_eq!(text(&lines[2]), "\u{28ff}");
+    }
+}
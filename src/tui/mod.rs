@@ -0,0 +1,513 @@
+use std::collections::BTreeMap;
+use std::io::{self, IsTerminal, Write};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use sysinfo::System;
+
+use crate::filter::Filter;
+use crate::{
+    AddressFamilyFlags, ProtocolFlags, SocketEntry, collect_entries, color, forensic, sample_stats,
+    show_cmdlines, warn,
+};
+
+mod graph;
+mod state;
+pub(crate) mod term;
+mod view;
+
+use term::Key;
+
+// `netstatw --tui`: the socket table as a live, scrollable terminal UI. Sockets are
+// collected and sampled on a worker thread (the same pipeline as the table view),
+// keys are read on another, and the UI thread redraws whenever either delivers.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Col {
+    Proto,
+    Local,
+    Remote,
+    State,
+    Cpu,
+    R,
+    W,
+    Rx,
+    Tx,
+    Process,
+}
+
+pub const COLUMNS: [Col; 10] = [
+    Col::Proto,
+    Col::Local,
+    Col::Remote,
+    Col::State,
+    Col::Cpu,
+    Col::R,
+    Col::W,
+    Col::Rx,
+    Col::Tx,
+    Col::Process,
+];
+
+// The sidebar's filters: a checkbox per protocol and state in the rows.
+const FACETS: [(Col, &str); 2] = [(Col::Proto, "PROTOCOL"), (Col::State, "STATE")];
+// Rows moved per wheel notch.
+const WHEEL_STEP: isize = 3;
+// Characters panned per Left/Right.
+const PAN_STEP: usize = 8;
+
+impl Col {
+    // The `--sort` key naming the column.
+    fn key(self) -> &'static str {
+        match self {
+            Col::Proto => "proto",
+            Col::Local => "laddr",
+            Col::Remote => "raddr",
+            Col::State => "state",
+            Col::Cpu => "cpu",
+            Col::R => "r",
+            Col::W => "w",
+            Col::Rx => "rx",
+            Col::Tx => "tx",
+            Col::Process => "process",
+        }
+    }
+
+    fn from_key(key: &str) -> Option<Col> {
+        COLUMNS.into_iter().find(|c| c.key() == key)
+    }
+
+    fn is_numeric(self) -> bool {
+        matches!(self, Col::Cpu | Col::R | Col::W | Col::Rx | Col::Tx)
+    }
+
+    fn metric(self, e: &SocketEntry) -> f64 {
+        let Some(s) = &e.agg_stats else {
+            return f64::NAN;
+        };
+        match self {
+            Col::Cpu => s.cpu_pct as f64,
+            Col::R => s.read_rate_bps,
+            Col::W => s.write_rate_bps,
+            Col::Rx => s.net_rx_rate_bps,
+            Col::Tx => s.net_tx_rate_bps,
+            _ => f64::NAN,
+        }
+    }
+
+    fn text(self, e: &SocketEntry) -> &str {
+        match self {
+            Col::Proto => &e.proto,
+            Col::Local => &e.local_addr,
+            Col::Remote => &e.remote_addr,
+            Col::State => &e.state,
+            _ => &e.process_info,
+        }
+    }
+}
+
+pub struct TuiOptions {
+    pub refresh: Duration,
+    pub sample_interval: Duration,
+    pub top_n: Option<usize>,
+    pub families: AddressFamilyFlags,
+    pub protocols: ProtocolFlags,
+    pub filter: Filter,
+    // Initial sort column and whether it's descending; None keeps the table order.
+    pub sort: Option<(Col, bool)>,
+    // Allow enabling Windows per-connection statistics (off in forensic mode).
+    pub enable_estats: bool,
+    // `--cmdline`: full command lines in the PROCESS column.
+    pub cmdline: bool,
+    // Restore and save the sort, search and layout in tui.toml (off in forensic mode,
+    // which writes nothing).
+    pub persist: bool,
+    // Row and bar styles; None when colors are off.
+    pub palette: Option<color::Palette>,
+}
+
+enum Event {
+    Key(Key),
+    Data(Vec<SocketEntry>),
+}
+
+struct App {
+    rows: Vec<SocketEntry>,
+    // Indices into `rows` after search and sort.
+    view: Vec<usize>,
+    selected: usize,
+    offset: usize,
+    // Characters the table is panned to the right.
+    hscroll: usize,
+    sort: Option<Col>,
+    descending: bool,
+    search: String,
+    searching: bool,
+    details: bool,
+    // Rows of the details pane, divider included, as last dragged.
+    details_height: usize,
+    sidebar: bool,
+    // Protocols and states unticked in the sidebar.
+    hidden: Vec<(Col, String)>,
+    // The details divider is being dragged.
+    dragging: bool,
+    palette: Option<color::Palette>,
+    // The bandwidth tab is shown instead of the table, drawn in ASCII or Braille.
+    graph: bool,
+    ascii: bool,
+    history: graph::History,
+    refreshes: u64,
+    warnings: Vec<String>,
+    refresh: Duration,
+}
+
+impl App {
+    fn selected_id(&self) -> Option<String> {
+        self.view
+            .get(self.selected)
+            .map(|&i| self.rows[i].conn_id())
+    }
+
+    // Recomputes the visible rows, keeping the selection on the same connection.
+    fn rebuild(&mut self) {
+        let keep = self.selected_id();
+        let needle = self.search.to_ascii_lowercase();
+        self.view = (0..self.rows.len())
+            .filter(|&i| {
+                !self
+                    .hidden
+                    .iter()
+                    .any(|(col, value)| col.text(&self.rows[i]) == value)
+            })
+            .filter(|&i| {
+                needle.is_empty()
+                    || COLUMNS[..4]
+                        .iter()
+                        .chain([&Col::Process])
+                        .any(|c| c.text(&self.rows[i]).to_ascii_lowercase().contains(&needle))
+            })
+            .collect();
+        let rows = &self.rows;
+        match self.sort {
+            None => self
+                .view
+                .sort_by(|&a, &b| rows[a].sort_key().cmp(&rows[b].sort_key())),
+            Some(col) if col.is_numeric() => self.view.sort_by(|&a, &b| {
+                let (x, y) = (col.metric(&rows[a]), col.metric(&rows[b]));
+                // NaN (not sampled) always sorts last.
+                match (x.is_nan(), y.is_nan()) {
+                    (true, true) => std::cmp::Ordering::Equal,
+                    (true, false) => std::cmp::Ordering::Greater,
+                    (false, true) => std::cmp::Ordering::Less,
+                    _ if self.descending => y.total_cmp(&x),
+                    _ => x.total_cmp(&y),
+                }
+            }),
+            Some(col) => self.view.sort_by(|&a, &b| {
+                let ord = col.text(&rows[a]).cmp(col.text(&rows[b]));
+                if self.descending { ord.reverse() } else { ord }
+            }),
+        }
+        self.selected = keep
+            .and_then(|id| self.view.iter().position(|&i| rows[i].conn_id() == id))
+            .unwrap_or(0)
+            .min(self.view.len().saturating_sub(1));
+    }
+
+    fn sort_by(&mut self, col: Col) {
+        if self.sort == Some(col) {
+            self.descending = !self.descending;
+        } else {
+            self.sort = Some(col);
+            self.descending = col.is_numeric();
+        }
+        self.rebuild();
+    }
+
+    fn move_by(&mut self, delta: isize) {
+        let last = self.view.len().saturating_sub(1) as isize;
+        self.selected = (self.selected as isize + delta).clamp(0, last.max(0)) as usize;
+    }
+
+    // The values of a sidebar column among the rows, with their counts, and the hidden
+    // ones even when no row has them any more.
+    fn facet(&self, col: Col) -> Vec<(String, usize)> {
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for e in &self.rows {
+            *counts.entry(col.text(e).to_string()).or_default() += 1;
+        }
+        for (c, value) in &self.hidden {
+            if *c == col {
+                counts.entry(value.clone()).or_default();
+            }
+        }
+        counts.into_iter().collect()
+    }
+
+    fn is_hidden(&self, col: Col, value: &str) -> bool {
+        self.hidden.iter().any(|(c, v)| *c == col && v == value)
+    }
+
+    fn toggle_hidden(&mut self, col: Col, value: String) {
+        match self
+            .hidden
+            .iter()
+            .position(|(c, v)| *c == col && *v == value)
+        {
+            Some(i) => {
+                self.hidden.remove(i);
+            }
+            None => self.hidden.push((col, value)),
+        }
+        self.rebuild();
+    }
+
+    // Clicks select rows, sort by a header or toggle a sidebar filter; the details
+    // divider can be dragged.
+    fn on_mouse(&mut self, key: Key, width: usize, height: usize) {
+        if self.graph {
+            return;
+        }
+        let layout = view::Layout::of(self, width, height);
+        match key {
+            Key::WheelUp => self.move_by(-WHEEL_STEP),
+            Key::WheelDown => self.move_by(WHEEL_STEP),
+            Key::Click(_, y) if layout.divider() == Some(y) => self.dragging = true,
+            Key::Click(x, y) if x < layout.sidebar => {
+                if let Some((col, value)) = view::sidebar_value(self, y) {
+                    self.toggle_hidden(col, value);
+                }
+            }
+            Key::Click(x, y) => {
+                let x = x - layout.sidebar;
+                if y == view::HEADER_ROW {
+                    if let Some(col) = view::column_at(width - layout.sidebar, x + self.hscroll) {
+                        self.sort_by(col);
+                    }
+                } else if let Some(pos) = layout.table_row(y) {
+                    let pos = self.offset + pos;
+                    if pos < self.view.len() {
+                        self.selected = pos;
+                    }
+                }
+            }
+            // The status line stays under the pane.
+            Key::Drag(_, y) if self.dragging => {
+                self.details_height = height.saturating_sub(1 + y);
+            }
+            Key::Release => self.dragging = false,
+            _ => {}
+        }
+    }
+
+    fn state(&self) -> state::State {
+        state::State {
+            sort: self.sort.map(|col| (col, self.descending)),
+            search: self.search.clone(),
+            details: self.details,
+            details_height: self.details_height,
+            sidebar: self.sidebar,
+            hidden: self.hidden.clone(),
+        }
+    }
+
+    // Returns false when the UI should exit.
+    fn on_key(&mut self, key: Key, page: usize) -> bool {
+        if key == Key::CtrlC {
+            return false;
+        }
+        if self.searching {
+            match key {
+                Key::Char(c) => self.search.push(c),
+                Key::Backspace => {
+                    self.search.pop();
+                }
+                Key::Enter => self.searching = false,
+                Key::Esc => {
+                    self.search.clear();
+                    self.searching = false;
+                }
+                _ => return true,
+            }
+            self.rebuild();
+            return true;
+        }
+        let page = page.max(1) as isize;
+        match key {
+            Key::Char('q') => return false,
+            Key::Up | Key::Char('k') => self.move_by(-1),
+            Key::Down | Key::Char('j') => self.move_by(1),
+            Key::PageUp => self.move_by(-page),
+            Key::PageDown => self.move_by(page),
+            Key::Home | Key::Char('g') => self.selected = 0,
+            Key::End | Key::Char('G') => self.move_by(isize::MAX / 2),
+            Key::Char('/') => self.searching = true,
+            Key::Char('d') => self.details = !self.details,
+            Key::Char('f') => self.sidebar = !self.sidebar,
+            Key::Left | Key::Char('h') => self.hscroll = self.hscroll.saturating_sub(PAN_STEP),
+            Key::Right | Key::Char('l') => self.hscroll += PAN_STEP,
+            Key::Char('b') => self.graph = !self.graph,
+            Key::Char('a') => self.ascii = !self.ascii,
+            Key::Char('s') => {
+                let next = match self.sort {
+                    None => COLUMNS[0],
+                    Some(c) => {
+                        let i = COLUMNS.iter().position(|&x| x == c).unwrap_or(0);
+                        COLUMNS[(i + 1) % COLUMNS.len()]
+                    }
+                };
+                self.sort = Some(next);
+                self.descending = next.is_numeric();
+                self.rebuild();
+            }
+            Key::Char('r') => {
+                self.descending = !self.descending;
+                self.rebuild();
+            }
+            Key::Char(c @ '0'..='9') => {
+                let i = if c == '0' {
+                    9
+                } else {
+                    c as usize - '1' as usize
+                };
+                self.sort_by(COLUMNS[i]);
+            }
+            Key::Esc if !self.search.is_empty() => {
+                self.search.clear();
+                self.rebuild();
+            }
+            _ => {}
+        }
+        true
+    }
+}
+
+fn spawn_collector(opts: TuiOptions, tx: Sender<Event>) {
+    thread::spawn(move || {
+        forensic::record("sysinfo::System::refresh_all (process table)");
+        let mut system = System::new_all();
+        system.refresh_all();
+        loop {
+            let started = Instant::now();
+            let mut entries =
+                match collect_entries(&system, opts.top_n, opts.families, opts.protocols) {
+                    Ok(entries) => entries,
+                    Err(e) => {
+                        warn::warn(
+                            "socket_tables",
+                            format!("failed to read socket tables: {}", e),
+                        );
+                        Vec::new()
+                    }
+                };
+            if opts.cmdline {
+                show_cmdlines(&mut entries, &system);
+            }
+            entries.retain(|e| opts.filter.matches(e));
+            sample_stats(
+                &mut system,
+                &mut entries,
+                opts.sample_interval,
+                opts.enable_estats,
+            );
+            // Read-only and cheap; the details pane shows it where the platform has it.
+            let _ = crate::path::annotate(&mut entries);
+            if tx.send(Event::Data(entries)).is_err() {
+                return;
+            }
+            thread::sleep(opts.refresh.saturating_sub(started.elapsed()));
+            forensic::record("sysinfo::System::refresh_all (process table)");
+            system.refresh_all();
+        }
+    });
+}
+
+pub fn run(opts: TuiOptions) -> i32 {
+    if !io::stdin().is_terminal() || !color::interactive(&io::stdout()) {
+        eprintln!("netstatw: --tui needs an interactive terminal (not a pipe or TERM=dumb)");
+        return 2;
+    }
+    let raw = match term::RawMode::enable(false) {
+        Ok(raw) => raw,
+        Err(e) => {
+            eprintln!("netstatw: cannot switch the terminal to raw mode: {}", e);
+            return 1;
+        }
+    };
+    let saved = if opts.persist {
+        state::State::load().unwrap_or_else(|e| {
+            warn::warn("tui_state", format!("ignoring the saved TUI state: {}", e));
+            state::State::default()
+        })
+    } else {
+        state::State::default()
+    };
+    let sort = opts.sort.or(saved.sort);
+    let persist = opts.persist;
+    let screen = term::Screen::enter();
+
+    let mut app = App {
+        rows: Vec::new(),
+        view: Vec::new(),
+        selected: 0,
+        offset: 0,
+        hscroll: 0,
+        sort: sort.map(|(col, _)| col),
+        descending: sort.is_some_and(|(_, descending)| descending),
+        search: saved.search,
+        searching: false,
+        details: saved.details,
+        details_height: saved.details_height,
+        sidebar: saved.sidebar,
+        hidden: saved.hidden,
+        dragging: false,
+        palette: opts.palette,
+        graph: false,
+        ascii: false,
+        history: graph::History::default(),
+        refreshes: 0,
+        warnings: Vec::new(),
+        refresh: opts.refresh,
+    };
+    let (tx, rx) = mpsc::channel();
+    term::spawn_reader(tx.clone(), Event::Key);
+    spawn_collector(opts, tx);
+
+    let mut out = io::stdout();
+    loop {
+        let (width, height) = term::size();
+        let frame = view::render(&mut app, width, height);
+        let _ = out.write_all(frame.as_bytes());
+        let _ = out.flush();
+        match rx.recv() {
+            Ok(Event::Key(
+                key @ (Key::Click(..)
+                | Key::Drag(..)
+                | Key::Release
+                | Key::WheelUp
+                | Key::WheelDown),
+            )) => app.on_mouse(key, width, height),
+            Ok(Event::Key(key)) => {
+                if !app.on_key(key, view::Layout::of(&app, width, height).body) {
+                    break;
+                }
+            }
+            Ok(Event::Data(rows)) => {
+                app.rows = rows;
+                app.refreshes += 1;
+                app.warnings = warn::drain_messages();
+                app.rebuild();
+                // What the search and the sidebar leave, as in the table.
+                app.history.push(app.view.iter().map(|&i| &app.rows[i]));
+            }
+            Err(_) => break,
+        }
+    }
+    drop(screen);
+    drop(raw);
+    if persist && let Err(e) = app.state().save() {
+        eprintln!("netstatw: cannot save the TUI state: {}", e);
+    }
+    0
+}
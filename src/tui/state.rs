@@ -0,0 +1,147 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::config::{self, Config, Value};
+
+use super::Col;
+use super::view::DETAILS_HEIGHT;
+
+// What the TUI remembers between runs: the sort, the search, the protocols and states
+// unticked in the filter sidebar and the layout (which panes are open, the details
+// pane's height). Kept in tui.toml next to the config file ([tui] section, the config
+// file's syntax) and written when the TUI exits. Options on the command line win
+// over the saved state.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct State {
+    pub sort: Option<(Col, bool)>,
+    pub search: String,
+    pub details: bool,
+    pub details_height: usize,
+    pub sidebar: bool,
+    pub hidden: Vec<(Col, String)>,
+}
+
+impl Default for State {
+    fn default() -> State {
+        State {
+            sort: None,
+            search: String::new(),
+            details: true,
+            details_height: DETAILS_HEIGHT,
+            sidebar: false,
+            hidden: Vec::new(),
+        }
+    }
+}
+
+pub fn path() -> Option<PathBuf> {
+    Some(config::config_path()?.parent()?.join("tui.toml"))
+}
+
+impl State {
+    // Unknown keys and values of the wrong type are skipped, so a file written by
+    // another version still loads.
+    pub fn parse(text: &str) -> Result<State, String> {
+        let cfg = Config::parse(text)?;
+        let mut state = State::default();
+        let mut descending = false;
+        for (key, value) in cfg.section("tui").unwrap_or_default() {
+            match (key.as_str(), value) {
+                ("sort", Value::Str(s)) => state.sort = Col::from_key(s).map(|c| (c, false)),
+                ("descending", Value::Bool(b)) => descending = *b,
+                ("search", Value::Str(s)) => state.search = s.clone(),
+                ("details", Value::Bool(b)) => state.details = *b,
+                ("details-height", Value::Int(n)) => {
+                    state.details_height = usize::try_from(*n).unwrap_or(DETAILS_HEIGHT)
+                }
+                ("sidebar", Value::Bool(b)) => state.sidebar = *b,
+                // "state=TimeWait"
+                ("hidden", Value::List(items)) => {
+                    state.hidden = items
+                        .iter()
+                        .filter_map(|item| {
+                            let Value::Str(item) = item else { return None };
+                            let (key, value) = item.split_once('=')?;
+                            Some((Col::from_key(key)?, value.to_string()))
+                        })
+                        .collect()
+                }
+                _ => {}
+            }
+        }
+        if let Some((_, d)) = &mut state.sort {
+            *d = descending;
+        }
+        Ok(state)
+    }
+
+    pub fn to_toml(&self) -> String {
+        let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+        let mut text = String::from("# Written by netstatw --tui when it exits.\n[tui]\n");
+        if let Some((col, descending)) = self.sort {
+            text.push_str(&format!("sort = {}\n", quote(col.key())));
+            text.push_str(&format!("descending = {}\n", descending));
+        }
+        text.push_str(&format!("search = {}\n", quote(&self.search)));
+        text.push_str(&format!("details = {}\n", self.details));
+        text.push_str(&format!("details-height = {}\n", self.details_height));
+        text.push_str(&format!("sidebar = {}\n", self.sidebar));
+        let hidden: Vec<String> = self
+            .hidden
+            .iter()
+            .map(|(col, value)| quote(&format!("{}={}", col.key(), value)))
+            .collect();
+        text.push_str(&format!("hidden = [{}]\n", hidden.join(", ")));
+        text
+    }
+
+    // The saved state; the defaults when there is none.
+    pub fn load() -> Result<State, String> {
+        let Some(path) = path() else {
+            return Ok(State::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => State::parse(&text).map_err(|e| format!("{}: {}", path.display(), e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(State::default()),
+            Err(e) => Err(format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = path().ok_or("no config directory (HOME/APPDATA not set)")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        fs::write(&path, self.to_toml()).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_round_trips_through_toml() {
+        let state = State {
+            sort: Some((Col::Rx, true)),
+            search: "say \"hi\" \\ 443".to_string(),
+            details: false,
+            details_height: 12,
+            sidebar: true,
+            hidden: vec![
+                (Col::Proto, "UDP".to_string()),
+                (Col::State, "TimeWait".to_string()),
+            ],
+        };
+        assert_eq!(State::parse(&state.to_toml()), Ok(state));
+        assert_eq!(State::parse(""), Ok(State::default()));
+    }
+
+    #[test]
+    fn unknown_keys_and_columns_are_skipped() {
+        let state = State::parse("[tui]\nsort = \"pid\"\ndescending = true\nwidth = 3\n").unwrap();
+        assert_eq!(state, State::default());
+    }
+}
@@ -0,0 +1,313 @@
+use std::io::{self, Read, Write};
+use std::sync::mpsc::Sender;
+use std::thread;
+
+// Just enough terminal handling for the TUI: raw input, the alternate screen, the
+// window size and decoding of the few keys and mouse reports (SGR encoding) we use.
+// Input is read on a thread and delivered over a channel, so the UI loop can wait
+// for keys and data at once.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Enter,
+    Esc,
+    Backspace,
+    CtrlC,
+    // Left-button press and motion with it held at (column, row), both zero-based.
+    Click(usize, usize),
+    Drag(usize, usize),
+    Release,
+    WheelUp,
+    WheelDown,
+}
+
+#[cfg(unix)]
+pub struct RawMode {
+    saved: libc::termios,
+}
+
+// `signals` keeps Ctrl-C a signal (for `--watch`) rather than a key.
+#[cfg(unix)]
+impl RawMode {
+    pub fn enable(signals: bool) -> io::Result<RawMode> {
+        // SAFETY: termios is plain data; tcgetattr fills it for a valid descriptor.
+        unsafe {
+            let mut saved: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut saved) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut raw = saved;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::IEXTEN);
+            if !signals {
+                raw.c_lflag &= !libc::ISIG;
+            }
+            raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(RawMode { saved })
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // SAFETY: restores the attributes read in `enable`.
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.saved);
+        }
+    }
+}
+
+#[cfg(windows)]
+pub struct RawMode {
+    input: u32,
+    output: u32,
+}
+
+#[cfg(windows)]
+impl RawMode {
+    pub fn enable(signals: bool) -> io::Result<RawMode> {
+        use windows_sys::Win32::System::Console::{
+            ENABLE_ECHO_INPUT, ENABLE_EXTENDED_FLAGS, ENABLE_LINE_INPUT, ENABLE_PROCESSED_INPUT,
+            ENABLE_QUICK_EDIT_MODE, ENABLE_VIRTUAL_TERMINAL_INPUT,
+            ENABLE_VIRTUAL_TERMINAL_PROCESSING, GetConsoleMode, GetStdHandle, STD_INPUT_HANDLE,
+            STD_OUTPUT_HANDLE, SetConsoleMode,
+        };
+        // SAFETY: console handles come from GetStdHandle; modes are plain integers.
+        unsafe {
+            let (hin, hout) = (
+                GetStdHandle(STD_INPUT_HANDLE),
+                GetStdHandle(STD_OUTPUT_HANDLE),
+            );
+            let (mut input, mut output) = (0u32, 0u32);
+            if GetConsoleMode(hin, &mut input) == 0 || GetConsoleMode(hout, &mut output) == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // Quick edit would take the mouse for selecting text.
+            let mut raw_in = (input
+                & !(ENABLE_LINE_INPUT
+                    | ENABLE_ECHO_INPUT
+                    | ENABLE_PROCESSED_INPUT
+                    | ENABLE_QUICK_EDIT_MODE))
+                | ENABLE_VIRTUAL_TERMINAL_INPUT
+                | ENABLE_EXTENDED_FLAGS;
+            if signals {
+                raw_in |= ENABLE_PROCESSED_INPUT;
+            }
+            if SetConsoleMode(hin, raw_in) == 0
+                || SetConsoleMode(hout, output | ENABLE_VIRTUAL_TERMINAL_PROCESSING) == 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(RawMode { input, output })
+        }
+    }
+}
+
+#[cfg(windows)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        use windows_sys::Win32::System::Console::{
+            GetStdHandle, STD_INPUT_HANDLE, STD_OUTPUT_HANDLE, SetConsoleMode,
+        };
+        // SAFETY: restores the modes read in `enable`.
+        unsafe {
+            SetConsoleMode(GetStdHandle(STD_INPUT_HANDLE), self.input);
+            SetConsoleMode(GetStdHandle(STD_OUTPUT_HANDLE), self.output);
+        }
+    }
+}
+
+// Alternate screen with a hidden cursor and mouse reporting for the lifetime of the
+// value.
+pub struct Screen;
+
+impl Screen {
+    pub fn enter() -> Screen {
+        print!("\x1b[?1049h\x1b[?25l\x1b[?1002h\x1b[?1006h");
+        let _ = io::stdout().flush();
+        Screen
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        print!("\x1b[?1006l\x1b[?1002l\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+    }
+}
+
+// (columns, rows); 80x24 when the size cannot be read.
+#[cfg(unix)]
+pub fn size() -> (usize, usize) {
+    // SAFETY: winsize is plain data filled by the ioctl.
+    unsafe {
+        let mut ws: libc::winsize = std::mem::zeroed();
+        if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut ws) == 0 && ws.ws_col > 0 {
+            return (ws.ws_col as usize, ws.ws_row as usize);
+        }
+    }
+    (80, 24)
+}
+
+#[cfg(windows)]
+pub fn size() -> (usize, usize) {
+    use windows_sys::Win32::System::Console::{
+        CONSOLE_SCREEN_BUFFER_INFO, GetConsoleScreenBufferInfo, GetStdHandle, STD_OUTPUT_HANDLE,
+    };
+    // SAFETY: the info struct is plain data filled by the call.
+    unsafe {
+        let mut info: CONSOLE_SCREEN_BUFFER_INFO = std::mem::zeroed();
+        if GetConsoleScreenBufferInfo(GetStdHandle(STD_OUTPUT_HANDLE), &mut info) != 0 {
+            let w = info.srWindow;
+            return (
+                (w.Right - w.Left + 1).max(1) as usize,
+                (w.Bottom - w.Top + 1).max(1) as usize,
+            );
+        }
+    }
+    (80, 24)
+}
+
+// Decodes one read's worth of input. A lone ESC is the Escape key; escape
+// sequences arrive whole in a single read.
+pub fn decode(bytes: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let text = String::from_utf8_lossy(bytes);
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let key = match c {
+            '\x1b' => {
+                if chars.peek().is_none_or(|&n| n != '[' && n != 'O') {
+                    Key::Esc
+                } else {
+                    chars.next();
+                    let mut seq = String::new();
+                    while let Some(&n) = chars.peek() {
+                        chars.next();
+                        seq.push(n);
+                        if n.is_ascii_alphabetic() || n == '~' {
+                            break;
+                        }
+                    }
+                    match seq.as_str() {
+                        "A" => Key::Up,
+                        "B" => Key::Down,
+                        "C" => Key::Right,
+                        "D" => Key::Left,
+                        "H" | "1~" | "7~" => Key::Home,
+                        "F" | "4~" | "8~" => Key::End,
+                        "5~" => Key::PageUp,
+                        "6~" => Key::PageDown,
+                        seq => match mouse(seq) {
+                            Some(key) => key,
+                            None => continue,
+                        },
+                    }
+                }
+            }
+            '\r' | '\n' => Key::Enter,
+            '\x7f' | '\x08' => Key::Backspace,
+            '\x03' => Key::CtrlC,
+            c if c.is_control() => continue,
+            c => Key::Char(c),
+        };
+        keys.push(key);
+    }
+    keys
+}
+
+// An SGR mouse report without its ESC [: "<button;column;row" and M for a press or
+// m for a release, the coordinates one-based. Only the left button and the wheel are
+// used; modifier keys are ignored.
+fn mouse(seq: &str) -> Option<Key> {
+    let report = seq.strip_prefix('<')?;
+    let (report, press) = match report.strip_suffix('M') {
+        Some(r) => (r, true),
+        None => (report.strip_suffix('m')?, false),
+    };
+    let mut fields = report.split(';').map(|f| f.parse::<usize>().ok());
+    let (button, x, y) = (fields.next()??, fields.next()??, fields.next()??);
+    let (x, y) = (x.saturating_sub(1), y.saturating_sub(1));
+    match (button & !0b1_1100, press) {
+        (0, true) => Some(Key::Click(x, y)),
+        (0, false) => Some(Key::Release),
+        (32, true) => Some(Key::Drag(x, y)),
+        (64, true) => Some(Key::WheelUp),
+        (65, true) => Some(Key::WheelDown),
+        _ => None,
+    }
+}
+
+// Forwards decoded keys until stdin closes or the receiver is gone.
+pub fn spawn_reader<T: Send + 'static>(tx: Sender<T>, wrap: fn(Key) -> T) {
+    thread::spawn(move || {
+        let mut stdin = io::stdin();
+        let mut buf = [0u8; 64];
+        loop {
+            let n = match stdin.read(&mut buf) {
+                Ok(0) | Err(_) => return,
+                Ok(n) => n,
+            };
+            for key in decode(&buf[..n]) {
+                if tx.send(wrap(key)).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_arrows_and_plain_keys() {
+        assert_eq!(
+            decode(b"\x1b[A\x1b[B\x1b[D\x1b[Cq/\r"),
+            vec![
+                Key::Up,
+                Key::Down,
+                Key::Left,
+                Key::Right,
+                Key::Char('q'),
+                Key::Char('/'),
+                Key::Enter
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_lone_escape_and_page_keys() {
+        assert_eq!(decode(b"\x1b"), vec![Key::Esc]);
+        assert_eq!(decode(b"\x1b[5~\x1b[6~"), vec![Key::PageUp, Key::PageDown]);
+    }
+
+    #[test]
+    fn decode_mouse_reports() {
+        assert_eq!(
+            decode(b"\x1b[<0;12;2M\x1b[<32;12;5M\x1b[<0;12;5m\x1b[<65;3;3M\x1b[<2;1;1Mq"),
+            vec![
+                Key::Click(11, 1),
+                Key::Drag(11, 4),
+                Key::Release,
+                Key::WheelDown,
+                Key::Char('q')
+            ]
+        );
+    }
+}
@@ -0,0 +1,341 @@
+use crate::{color, human_readable_rate};
+
+use super::graph::{self, Span};
+use super::{App, COLUMNS, Col, FACETS};
+
+// Screen row of the column headers, under the title bar.
+pub const HEADER_ROW: usize = 1;
+// The filter sidebar's width, its right border included.
+const SIDEBAR_WIDTH: usize = 24;
+// The details pane's height, its divider line included: the default and the least it
+// can be dragged to.
+pub const DETAILS_HEIGHT: usize = 7;
+const MIN_DETAILS: usize = 2;
+
+// Where the parts of the screen go for the current size and panes. Top to bottom:
+// title bar, headers, `body` table rows, the details pane (`details` rows, divider
+// first) and the status line; the sidebar takes the left `sidebar` columns of the
+// header and table rows.
+pub struct Layout {
+    pub sidebar: usize,
+    pub body: usize,
+    pub details: usize,
+}
+
+impl Layout {
+    pub fn of(app: &App, width: usize, height: usize) -> Layout {
+        // The title, headers, status line and one table row always fit.
+        let room = height.saturating_sub(4);
+        let details = if app.details {
+            app.details_height.max(MIN_DETAILS).min(room)
+        } else {
+            0
+        };
+        Layout {
+            sidebar: if app.sidebar {
+                SIDEBAR_WIDTH.min(width / 2)
+            } else {
+                0
+            },
+            body: height.saturating_sub(3 + details),
+            details,
+        }
+    }
+
+    // Screen row of the details pane's divider.
+    pub fn divider(&self) -> Option<usize> {
+        (self.details > 0).then_some(HEADER_ROW + 1 + self.body)
+    }
+
+    // The position in the table of screen row `y`, if it's a table row.
+    pub fn table_row(&self, y: usize) -> Option<usize> {
+        (y > HEADER_ROW && y <= HEADER_ROW + self.body).then(|| y - HEADER_ROW - 1)
+    }
+}
+
+fn header(col: Col) -> &'static str {
+    match col {
+        Col::Proto => "PROTO",
+        Col::Local => "LOCAL ADDRESS",
+        Col::Remote => "REMOTE ADDRESS",
+        Col::State => "STATE",
+        Col::Cpu => "CPU%",
+        Col::R => "R/s",
+        Col::W => "W/s",
+        Col::Rx => "Rx/s",
+        Col::Tx => "Tx/s",
+        Col::Process => "PROCESS",
+    }
+}
+
+// Fixed widths for the short columns; the addresses and PROCESS share the rest.
+fn widths(total: usize) -> [usize; 10] {
+    let fixed = 6 + 11 + 6 + 4 * 9 + COLUMNS.len() - 1;
+    let flex = total.saturating_sub(fixed).max(45);
+    let addr = flex * 3 / 10;
+    [6, addr, addr, 11, 6, 9, 9, 9, 9, flex - 2 * addr]
+}
+
+// The column drawn at screen column `x`; None on the gaps between columns.
+pub fn column_at(total: usize, x: usize) -> Option<Col> {
+    let mut start = 0;
+    for (&col, w) in COLUMNS.iter().zip(widths(total)) {
+        if x < start + w {
+            return (x >= start).then_some(col);
+        }
+        start += w + 1;
+    }
+    None
+}
+
+fn cell(col: Col, e: &crate::SocketEntry) -> String {
+    let stat = |f: fn(&crate::ProcessStats) -> String| {
+        e.agg_stats
+            .as_ref()
+            .map(f)
+            .unwrap_or_else(|| "N/A".to_string())
+    };
+    match col {
+        Col::Cpu => stat(|s| format!("{:.1}", s.cpu_pct)),
+        Col::R => stat(|s| human_readable_rate(s.read_rate_bps)),
+        Col::W => stat(|s| human_readable_rate(s.write_rate_bps)),
+        Col::Rx => stat(|s| human_readable_rate(s.net_rx_rate_bps)),
+        Col::Tx => stat(|s| human_readable_rate(s.net_tx_rate_bps)),
+        c => c.text(e).to_string(),
+    }
+}
+
+// Pads or cuts `text` to exactly `width` characters.
+fn fit(text: &str, width: usize, right: bool) -> String {
+    let len = text.chars().count();
+    if len > width {
+        let mut s: String = text.chars().take(width.saturating_sub(1)).collect();
+        s.push('~');
+        s
+    } else if right {
+        format!("{:>width$}", text, width = width)
+    } else {
+        format!("{:<width$}", text, width = width)
+    }
+}
+
+// The last column (PROCESS) isn't cut: panning right shows the rest of it.
+fn row(cells: &[String], widths: &[usize; 10]) -> String {
+    COLUMNS
+        .iter()
+        .zip(cells)
+        .zip(widths)
+        .map(|((&c, text), &w)| {
+            if c == Col::Process && text.chars().count() > w {
+                text.clone()
+            } else {
+                fit(text, w, c.is_numeric())
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// The sidebar from its top (the header row) down: a title per facet, then a line per
+// value in the rows with the value it toggles.
+fn sidebar(app: &App) -> Vec<(String, Option<(Col, String)>)> {
+    let mut lines = Vec::new();
+    for (col, title) in FACETS {
+        if !lines.is_empty() {
+            lines.push((String::new(), None));
+        }
+        lines.push((format!(" {}", title), None));
+        for (value, count) in app.facet(col) {
+            let mark = if app.is_hidden(col, &value) { ' ' } else { 'x' };
+            let label = format!(" [{}] {}", mark, value);
+            lines.push((
+                format!("{} {:>5}", fit(&label, SIDEBAR_WIDTH - 7, false), count),
+                Some((col, value)),
+            ));
+        }
+    }
+    lines
+}
+
+// The facet value toggled by a click on sidebar row `y`.
+pub fn sidebar_value(app: &App, y: usize) -> Option<(Col, String)> {
+    let line = y.checked_sub(HEADER_ROW)?;
+    sidebar(app).into_iter().nth(line)?.1
+}
+
+fn details(app: &App) -> Vec<String> {
+    let Some(e) = app.view.get(app.selected).map(|&i| &app.rows[i]) else {
+        return vec![" (no connection selected)".to_string()];
+    };
+    let mut flags = Vec::new();
+    if let Some(target) = &e.forward {
+        flags.push(format!("forwards to {}", target));
+    }
+    if e.synth {
+        flags.push("synthetic (netstatw synth)".to_string());
+    }
+    let stats = match &e.agg_stats {
+        Some(s) => format!(
+            " CPU {:.1}%  disk R {}  W {}  net Rx {}  Tx {}",
+            s.cpu_pct,
+            human_readable_rate(s.read_rate_bps),
+            human_readable_rate(s.write_rate_bps),
+            human_readable_rate(s.net_rx_rate_bps),
+            human_readable_rate(s.net_tx_rate_bps)
+        ),
+        None => " (no process statistics)".to_string(),
+    };
+    let path = match &e.path {
+        Some(p) => format!(" path {}: {}", p.hint(), p.detail()),
+        None => String::new(),
+    };
+    let pids: Vec<String> = e.pids.iter().map(u32::to_string).collect();
+    vec![
+        format!(" ID {}   {} {}", e.conn_id(), e.proto, e.state),
+        format!(" {}  ->  {}", e.local_addr, e.remote_addr),
+        format!(" PIDs {}   {}", pids.join(","), e.process_info),
+        stats,
+        path,
+        format!(" {}", flags.join("; ")),
+    ]
+}
+
+// Draws a whole frame. Also scrolls `app.offset` so the selection stays visible.
+pub fn render(app: &mut App, width: usize, height: usize) -> String {
+    let layout = Layout::of(app, width, height);
+    let body = layout.body;
+    if app.selected < app.offset {
+        app.offset = app.selected;
+    } else if body > 0 && app.selected >= app.offset + body {
+        app.offset = app.selected + 1 - body;
+    }
+
+    // (sidebar part, table part, whether the line is a bar)
+    let bar = color::bar(app.palette.as_ref());
+    let plain = |text: String| vec![(text, None)];
+    let mut lines: Vec<(String, Vec<Span>, bool)> = Vec::new();
+    let sort = match app.sort {
+        Some(c) => format!("{} {}", header(c), if app.descending { "v" } else { "^" }),
+        None => "state".to_string(),
+    };
+    lines.push((
+        String::new(),
+        plain(format!(
+            " netstatw  {}/{} rows  sort: {}  refresh {}s  #{}",
+            app.view.len(),
+            app.rows.len(),
+            sort,
+            app.refresh.as_secs_f64(),
+            app.refreshes
+        )),
+        true,
+    ));
+    if app.graph {
+        let graph = graph::render(
+            &app.history,
+            app.ascii,
+            app.palette.as_ref(),
+            app.refresh,
+            width,
+            height.saturating_sub(2),
+        );
+        lines.extend(graph.into_iter().map(|spans| (String::new(), spans, false)));
+        lines.resize(height.saturating_sub(1), (String::new(), Vec::new(), false));
+    } else {
+        table(app, &layout, width, &mut lines);
+    }
+    let mut status = if app.searching {
+        format!(" /{}_", app.search)
+    } else if app.graph {
+        " q quit  b table  a ASCII/Braille".to_string()
+    } else if !app.search.is_empty() {
+        format!(" filter: {}   (/ edit, Esc clear, q quit)", app.search)
+    } else {
+        " q quit  up/down move  / search  1-0 or click header: sort  s next sort  r reverse  d details  f filters  b bandwidth"
+            .to_string()
+    };
+    if let Some(w) = app.warnings.first() {
+        status.push_str(&format!("  | warning: {}", w));
+    }
+    lines.push((String::new(), plain(status), false));
+
+    let mut frame = String::from("\x1b[H");
+    for (i, (side, spans, is_bar)) in lines.iter().take(height).enumerate() {
+        frame.push_str(&format!("\x1b[{};1H{}", i + 1, side));
+        let mut room = width.saturating_sub(side.chars().count());
+        if *is_bar {
+            // Bars span the whole width.
+            let text: String = spans.iter().map(|(t, _)| t.as_str()).collect();
+            let text: String = text.chars().take(room).collect();
+            let text = format!("{:<room$}", text, room = room);
+            frame.push_str(&color::paint(&text, Some(bar)));
+            continue;
+        }
+        for (text, style) in spans {
+            let text: String = text.chars().take(room).collect();
+            room -= text.chars().count();
+            frame.push_str(&color::paint(&text, *style));
+        }
+        frame.push_str("\x1b[K");
+    }
+    frame.push_str("\x1b[J");
+    frame
+}
+
+// The table tab below the title bar: headers, rows, the sidebar and the details pane.
+// The headers stay at the top while the rows scroll, and both pan sideways together.
+fn table(app: &mut App, layout: &Layout, width: usize, lines: &mut Vec<(String, Vec<Span>, bool)>) {
+    let room = width.saturating_sub(layout.sidebar);
+    let widths = widths(room);
+    let headers: Vec<String> = COLUMNS.iter().map(|&c| header(c).to_string()).collect();
+    let mut texts = vec![row(&headers, &widths)];
+    for pos in app.offset..app.offset + layout.body {
+        if let Some(&i) = app.view.get(pos) {
+            let cells: Vec<String> = COLUMNS.iter().map(|&c| cell(c, &app.rows[i])).collect();
+            texts.push(row(&cells, &widths));
+        }
+    }
+    let widest = texts.iter().map(|t| t.chars().count()).max().unwrap_or(0);
+    app.hscroll = app.hscroll.min(widest.saturating_sub(room));
+    let mut texts = texts
+        .into_iter()
+        .map(|t| t.chars().skip(app.hscroll).collect::<String>());
+
+    let first = lines.len();
+    lines.push((
+        String::new(),
+        vec![(texts.next().unwrap_or_default(), None)],
+        false,
+    ));
+    for pos in app.offset..app.offset + layout.body {
+        match app.view.get(pos) {
+            Some(&i) => {
+                let e = &app.rows[i];
+                let text = texts.next().unwrap_or_default();
+                if pos == app.selected {
+                    lines.push((String::new(), vec![(text, None)], true));
+                } else {
+                    let style = app.palette.as_ref().and_then(|p| p.row(&e.state));
+                    lines.push((String::new(), vec![(text, style)], false));
+                }
+            }
+            None => lines.push((String::new(), Vec::new(), false)),
+        }
+    }
+    if layout.sidebar > 0 {
+        let mut side = sidebar(app).into_iter().map(|(text, _)| text);
+        for line in &mut lines[first..] {
+            let text = side.next().unwrap_or_default();
+            line.0 = format!("{}|", fit(&text, layout.sidebar - 1, false));
+        }
+    }
+    if layout.details > 0 {
+        lines.push((String::new(), vec![("-".repeat(width), None)], false));
+        let mut d = details(app);
+        d.resize(layout.details - 1, String::new());
+        lines.extend(
+            d.into_iter()
+                .map(|l| (String::new(), vec![(l, None)], false)),
+        );
+    }
+}
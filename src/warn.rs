@@ -0,0 +1,80 @@
+use std::sync::Mutex;
+
+use crate::json;
+
+// Warnings about degraded or partial data. They are collected during the run and
+// written to stderr once at the end, so they never interleave with table or machine
+// output on stdout. `--json-warnings` switches them to one JSON object per line.
+
+struct Warning {
+    code: &'static str,
+    message: String,
+    count: Option<u64>,
+}
+
+impl Warning {
+    fn text(&self) -> String {
+        match self.count {
+            Some(c) => format!("{}: {}", self.message, c),
+            None => self.message.clone(),
+        }
+    }
+}
+
+static WARNINGS: Mutex<Vec<Warning>> = Mutex::new(Vec::new());
+
+pub fn warn(code: &'static str, message: String) {
+    if let Ok(mut w) = WARNINGS.lock() {
+        w.push(Warning {
+            code,
+            message,
+            count: None,
+        });
+    }
+}
+
+// Counted warnings accumulate: repeated calls with the same code add up.
+pub fn warn_count(code: &'static str, count: u64, message: &str) {
+    if count == 0 {
+        return;
+    }
+    let Ok(mut w) = WARNINGS.lock() else { return };
+    match w.iter_mut().find(|x| x.code == code && x.count.is_some()) {
+        Some(x) => x.count = x.count.map(|c| c + count),
+        None => w.push(Warning {
+            code,
+            message: message.to_string(),
+            count: Some(count),
+        }),
+    }
+}
+
+// Writes and clears the collected warnings, so each --watch refresh reports its own.
+pub fn emit(json: bool) {
+    let Ok(mut w) = WARNINGS.lock() else { return };
+    for x in w.drain(..) {
+        if json {
+            let count = x
+                .count
+                .map(|c| format!(",\"count\":{}", c))
+                .unwrap_or_default();
+            eprintln!(
+                "{{\"level\":\"warning\",\"code\":\"{}\",\"message\":\"{}\"{}}}",
+                x.code,
+                json::escape(&x.message),
+                count
+            );
+        } else {
+            eprintln!("netstatw: warning: {}", x.text());
+        }
+    }
+}
+
+// Takes the collected warnings as plain text, for front ends that show them
+// themselves (the TUI status line).
+pub fn drain_messages() -> Vec<String> {
+    let Ok(mut w) = WARNINGS.lock() else {
+        return Vec::new();
+    };
+    w.drain(..).map(|x| x.text()).collect()
+}
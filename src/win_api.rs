@@ -0,0 +1,213 @@
+use std::sync::OnceLock;
+
+use windows_sys::Win32::NetworkManagement::IpHelper::{
+    MIB_TCP6ROW, MIB_TCPROW_LH, TCP_ESTATS_TYPE,
+};
+use windows_sys::Win32::System::LibraryLoader::{
+    GetProcAddress, LOAD_LIBRARY_SEARCH_SYSTEM32, LoadLibraryExW,
+};
+
+// Optional Windows APIs, resolved at run time instead of imported at link time.
+//
+// Statically imported functions that are missing on some SKU (Server Core, Nano,
+// containers, older builds) stop the whole executable from loading. Anything not
+// needed for the basic socket table is looked up here with GetProcAddress, so one
+// build (x64 or ARM64) runs everywhere and merely loses the feature. The handful of
+// core calls (GetExtendedTcpTable/GetExtendedUdpTable) stay statically linked.
+
+pub type GetPerTcpConnectionEStatsFn = unsafe extern "system" fn(
+    row: *const MIB_TCPROW_LH,
+    estats_type: TCP_ESTATS_TYPE,
+    rw: *mut u8,
+    rw_version: u32,
+    rw_size: u32,
+    ros: *mut u8,
+    ros_version: u32,
+    ros_size: u32,
+    rod: *mut u8,
+    rod_version: u32,
+    rod_size: u32,
+) -> u32;
+
+pub type SetPerTcpConnectionEStatsFn = unsafe extern "system" fn(
+    row: *const MIB_TCPROW_LH,
+    estats_type: TCP_ESTATS_TYPE,
+    rw: *const u8,
+    rw_version: u32,
+    rw_size: u32,
+    offset: u32,
+) -> u32;
+
+// The IPv6 variants take a MIB_TCP6ROW and are otherwise identical.
+pub type GetPerTcp6ConnectionEStatsFn = unsafe extern "system" fn(
+    row: *const MIB_TCP6ROW,
+    estats_type: TCP_ESTATS_TYPE,
+    rw: *mut u8,
+    rw_version: u32,
+    rw_size: u32,
+    ros: *mut u8,
+    ros_version: u32,
+    ros_size: u32,
+    rod: *mut u8,
+    rod_version: u32,
+    rod_size: u32,
+) -> u32;
+
+pub type SetPerTcp6ConnectionEStatsFn = unsafe extern "system" fn(
+    row: *const MIB_TCP6ROW,
+    estats_type: TCP_ESTATS_TYPE,
+    rw: *const u8,
+    rw_version: u32,
+    rw_size: u32,
+    offset: u32,
+) -> u32;
+
+pub struct EStatsApi {
+    pub get: GetPerTcpConnectionEStatsFn,
+    pub set: SetPerTcpConnectionEStatsFn,
+    pub get6: GetPerTcp6ConnectionEStatsFn,
+    pub set6: SetPerTcp6ConnectionEStatsFn,
+}
+
+static ESTATS: OnceLock<Option<EStatsApi>> = OnceLock::new();
+
+// iphlpapi's per-connection statistics entry points, or None when this system
+// does not export them.
+pub fn estats() -> Option<&'static EStatsApi> {
+    ESTATS
+        .get_or_init(|| {
+            let dll: Vec<u16> = "iphlpapi.dll\0".encode_utf16().collect();
+            crate::forensic::record("kernel32!LoadLibraryExW (iphlpapi.dll, API probe)");
+            // SAFETY: the name is NUL-terminated; the module is never unloaded, so the
+            // resolved pointers stay valid for the life of the process. The transmutes
+            // give each export the signature documented for it.
+            unsafe {
+                let module = LoadLibraryExW(dll.as_ptr(), 0, LOAD_LIBRARY_SEARCH_SYSTEM32);
+                if module == 0 {
+                    return None;
+                }
+                let get = GetProcAddress(module, c"GetPerTcpConnectionEStats".as_ptr().cast())?;
+                let set = GetProcAddress(module, c"SetPerTcpConnectionEStats".as_ptr().cast())?;
+                let get6 = GetProcAddress(module, c"GetPerTcp6ConnectionEStats".as_ptr().cast())?;
+                let set6 = GetProcAddress(module, c"SetPerTcp6ConnectionEStats".as_ptr().cast())?;
+                Some(EStatsApi {
+                    get: std::mem::transmute::<
+                        unsafe extern "system" fn() -> isize,
+                        GetPerTcpConnectionEStatsFn,
+                    >(get),
+                    set: std::mem::transmute::<
+                        unsafe extern "system" fn() -> isize,
+                        SetPerTcpConnectionEStatsFn,
+                    >(set),
+                    get6: std::mem::transmute::<
+                        unsafe extern "system" fn() -> isize,
+                        GetPerTcp6ConnectionEStatsFn,
+                    >(get6),
+                    set6: std::mem::transmute::<
+                        unsafe extern "system" fn() -> isize,
+                        SetPerTcp6ConnectionEStatsFn,
+                    >(set6),
+                })
+            }
+        })
+        .as_ref()
+}
+
+// Whether this process runs with an elevated (administrator) token. Needed to switch
+// on per-connection statistics for other processes' connections.
+pub fn is_elevated() -> bool {
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+    use windows_sys::Win32::Security::{
+        GetTokenInformation, TOKEN_ELEVATION, TOKEN_QUERY, TokenElevation,
+    };
+    use windows_sys::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+    crate::forensic::record("advapi32!GetTokenInformation (TokenElevation)");
+    // SAFETY: the token handle is closed before returning; TOKEN_ELEVATION is plain
+    // data sized for the call.
+    unsafe {
+        let mut token: HANDLE = 0;
+        if OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) == 0 {
+            return false;
+        }
+        let mut elevation = TOKEN_ELEVATION { TokenIsElevated: 0 };
+        let mut len = 0u32;
+        let ok = GetTokenInformation(
+            token,
+            TokenElevation,
+            &mut elevation as *mut _ as *mut std::ffi::c_void,
+            std::mem::size_of::<TOKEN_ELEVATION>() as u32,
+            &mut len,
+        );
+        CloseHandle(token);
+        ok != 0 && elevation.TokenIsElevated != 0
+    }
+}
+
+// `DOMAIN\name` of the account owning `pid` (its token's user SID), for the USER
+// column. None when the process can't be opened (protected processes, or another
+// user's process without elevation).
+pub fn process_account(pid: u32) -> Option<String> {
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+    use windows_sys::Win32::Security::{
+        GetTokenInformation, LookupAccountSidW, SID_NAME_USE, TOKEN_QUERY, TOKEN_USER, TokenUser,
+    };
+    use windows_sys::Win32::System::Threading::{
+        OpenProcess, OpenProcessToken, PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+
+    crate::forensic::record("advapi32!LookupAccountSidW (process token user)");
+    // SAFETY: both handles are closed before returning; the token information is read
+    // into a u64 buffer (aligned for TOKEN_USER) of the size the first call asked for,
+    // and the name buffers' lengths are passed in characters.
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if process == 0 {
+            return None;
+        }
+        let mut token: HANDLE = 0;
+        let opened = OpenProcessToken(process, TOKEN_QUERY, &mut token);
+        CloseHandle(process);
+        if opened == 0 {
+            return None;
+        }
+        let mut len = 0u32;
+        GetTokenInformation(token, TokenUser, std::ptr::null_mut(), 0, &mut len);
+        let mut buf = vec![0u64; (len as usize).div_ceil(8).max(1)];
+        let ok = GetTokenInformation(
+            token,
+            TokenUser,
+            buf.as_mut_ptr() as *mut std::ffi::c_void,
+            (buf.len() * 8) as u32,
+            &mut len,
+        );
+        CloseHandle(token);
+        if ok == 0 {
+            return None;
+        }
+        let sid = (*(buf.as_ptr() as *const TOKEN_USER)).User.Sid;
+        let mut name = [0u16; 256];
+        let mut domain = [0u16; 256];
+        let (mut name_len, mut domain_len) = (name.len() as u32, domain.len() as u32);
+        let mut kind: SID_NAME_USE = 0;
+        if LookupAccountSidW(
+            std::ptr::null(),
+            sid,
+            name.as_mut_ptr(),
+            &mut name_len,
+            domain.as_mut_ptr(),
+            &mut domain_len,
+            &mut kind,
+        ) == 0
+        {
+            return None;
+        }
+        let name = String::from_utf16_lossy(&name[..name_len as usize]);
+        let domain = String::from_utf16_lossy(&domain[..domain_len as usize]);
+        Some(if domain.is_empty() {
+            name
+        } else {
+            format!("{}\\{}", domain, name)
+        })
+    }
+}
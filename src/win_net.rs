@@ -1,158 +1,566 @@
 use std::collections::HashMap;
+use std::ffi::c_void;
+use std::fmt;
 use std::mem::size_of;
-use std::ptr::{null_mut};
-use std::thread;
-use std::time::Duration;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddrV4};
+use std::ptr::null_mut;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 use windows_sys::Win32::Foundation::{BOOL, FALSE};
 use windows_sys::Win32::NetworkManagement::IpHelper::{
-    GetExtendedTcpTable, GetPerTcpConnectionEStats, SetPerTcpConnectionEStats, MIB_TCPROW_LH,
-    MIB_TCPROW_OWNER_PID, MIB_TCPTABLE_OWNER_PID, TCP_ESTATS_DATA_ROD_v0, TCP_ESTATS_TYPE,
-    TcpConnectionEstatsData, TCP_TABLE_OWNER_PID_ALL,
+    GetExtendedTcpTable, GetExtendedUdpTable, MIB_TCP_STATE_DELETE_TCB, MIB_TCP6ROW, MIB_TCPROW_LH,
+    SetTcpEntry, TCP_ESTATS_DATA_ROD_v0, TCP_ESTATS_DATA_RW_v0, TCP_ESTATS_TYPE,
+    TCP_TABLE_OWNER_PID_ALL, TcpConnectionEstatsData, UDP_TABLE_OWNER_PID,
 };
-use windows_sys::Win32::Networking::WinSock::AF_INET;
+use windows_sys::Win32::Networking::WinSock::{AF_INET, AF_INET6};
 type Ulong = u32;
 type Pulong = *mut u32;
 type Puchar = *mut u8;
 
-unsafe fn get_tcp_owner_pid_table() -> Option<Vec<MIB_TCPROW_OWNER_PID>> {
+use crate::win_api::EStatsApi;
+use crate::win_table::{
+    Tcp6OwnerRow, TcpOwnerRow, Udp6OwnerRow, UdpOwnerRow, parse_tcp_owner_pid_table,
+    parse_tcp6_owner_pid_table, parse_udp_owner_pid_table, parse_udp6_owner_pid_table,
+};
+use crate::{ConnKey, TcpRates};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TableError {
+    // The API returned an error code other than ERROR_INSUFFICIENT_BUFFER.
+    Api(&'static str, u32),
+    // The table grew between every size query and fetch, for this many attempts.
+    KeptGrowing(&'static str, usize),
+    // The returned buffer did not hold the number of rows its header claimed.
+    Malformed(&'static str),
+    // The API is not exported on this system (see win_api).
+    Unavailable(&'static str),
+}
+
+impl fmt::Display for TableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TableError::Api(api, code) => write!(f, "{} failed with error {}", api, code),
+            TableError::KeptGrowing(api, n) => {
+                write!(
+                    f,
+                    "{}: table kept growing; gave up after {} attempts",
+                    api, n
+                )
+            }
+            TableError::Malformed(api) => write!(f, "{}: returned buffer was malformed", api),
+            TableError::Unavailable(api) => write!(f, "{} is not available on this system", api),
+        }
+    }
+}
+
+// Attempts before giving up when the table keeps growing between the size query
+// and the fetch.
+const TABLE_FETCH_ATTEMPTS: usize = 4;
+// ERROR_INSUFFICIENT_BUFFER
+const ERROR_INSUFFICIENT_BUFFER: u32 = 122;
+// Extra room requested on top of the reported size so a few connections opening
+// between calls don't force another round trip.
+const TABLE_HEADROOM_ROWS: u32 = 32;
+const TCP_OWNER_ROW_BYTES: u32 = 24;
+const TCP6_OWNER_ROW_BYTES: u32 = 56;
+const UDP_OWNER_ROW_BYTES: u32 = 12;
+const UDP6_OWNER_ROW_BYTES: u32 = 28;
+
+// Size-query/fetch protocol shared by GetExtendedTcpTable and GetExtendedUdpTable.
+// Returns the filled buffer and the byte count the API reported.
+fn fetch_owner_table(
+    api: &'static str,
+    row_bytes: u32,
+    call: impl Fn(*mut c_void, Pulong) -> u32,
+) -> Result<(Vec<u8>, usize), TableError> {
     let mut size: Ulong = 0;
-    // First call to get required size
-    let ret = unsafe { GetExtendedTcpTable(
-        null_mut(),
-        &mut size as Pulong,
-        FALSE as BOOL,
-        AF_INET as u32,
-        TCP_TABLE_OWNER_PID_ALL,
-        0,
-    ) };
-    // ERROR_INSUFFICIENT_BUFFER = 122
-    if ret != 122 || size == 0 {
-        return None;
+    let mut buf: Vec<u8> = Vec::new();
+    for _ in 0..TABLE_FETCH_ATTEMPTS {
+        // First pass (empty buffer) only asks for the required size; if sockets
+        // were added since, the API reports ERROR_INSUFFICIENT_BUFFER again with the
+        // new size and we retry with a larger buffer.
+        buf.resize(size as usize, 0);
+        let ptr = if buf.is_empty() {
+            null_mut()
+        } else {
+            buf.as_mut_ptr() as *mut c_void
+        };
+        crate::forensic::record(api);
+        match call(ptr, &mut size as Pulong) {
+            0 if !buf.is_empty() => return Ok((buf, size as usize)),
+            ERROR_INSUFFICIENT_BUFFER if size > 0 => {
+                size = size.saturating_add(TABLE_HEADROOM_ROWS * row_bytes);
+            }
+            code => return Err(TableError::Api(api, code)),
+        }
     }
-    let mut buf: Vec<u8> = vec![0u8; size as usize];
-    let ret2 = unsafe { GetExtendedTcpTable(
-        buf.as_mut_ptr() as *mut _,
-        &mut size as Pulong,
-        FALSE as BOOL,
-        AF_INET as u32,
-        TCP_TABLE_OWNER_PID_ALL,
-        0,
-    ) };
-    if ret2 != 0 {
-        return None;
+    Err(TableError::KeptGrowing(api, TABLE_FETCH_ATTEMPTS))
+}
+
+fn get_tcp_owner_pid_table() -> Result<Vec<TcpOwnerRow>, TableError> {
+    const API: &str = "iphlpapi!GetExtendedTcpTable";
+    let (buf, size) = fetch_owner_table(API, TCP_OWNER_ROW_BYTES, |ptr, size| unsafe {
+        GetExtendedTcpTable(
+            ptr,
+            size,
+            FALSE as BOOL,
+            AF_INET as u32,
+            TCP_TABLE_OWNER_PID_ALL,
+            0,
+        )
+    })?;
+    parse_tcp_owner_pid_table(&buf, size).ok_or(TableError::Malformed(API))
+}
+
+fn get_tcp6_owner_pid_table() -> Result<Vec<Tcp6OwnerRow>, TableError> {
+    const API: &str = "iphlpapi!GetExtendedTcpTable (IPv6)";
+    let (buf, size) = fetch_owner_table(API, TCP6_OWNER_ROW_BYTES, |ptr, size| unsafe {
+        GetExtendedTcpTable(
+            ptr,
+            size,
+            FALSE as BOOL,
+            AF_INET6 as u32,
+            TCP_TABLE_OWNER_PID_ALL,
+            0,
+        )
+    })?;
+    parse_tcp6_owner_pid_table(&buf, size).ok_or(TableError::Malformed(API))
+}
+
+fn get_udp_owner_pid_table() -> Result<Vec<UdpOwnerRow>, TableError> {
+    const API: &str = "iphlpapi!GetExtendedUdpTable";
+    let (buf, size) = fetch_owner_table(API, UDP_OWNER_ROW_BYTES, |ptr, size| unsafe {
+        GetExtendedUdpTable(
+            ptr,
+            size,
+            FALSE as BOOL,
+            AF_INET as u32,
+            UDP_TABLE_OWNER_PID,
+            0,
+        )
+    })?;
+    parse_udp_owner_pid_table(&buf, size).ok_or(TableError::Malformed(API))
+}
+
+fn get_udp6_owner_pid_table() -> Result<Vec<Udp6OwnerRow>, TableError> {
+    const API: &str = "iphlpapi!GetExtendedUdpTable (IPv6)";
+    let (buf, size) = fetch_owner_table(API, UDP6_OWNER_ROW_BYTES, |ptr, size| unsafe {
+        GetExtendedUdpTable(
+            ptr,
+            size,
+            FALSE as BOOL,
+            AF_INET6 as u32,
+            UDP_TABLE_OWNER_PID,
+            0,
+        )
+    })?;
+    parse_udp6_owner_pid_table(&buf, size).ok_or(TableError::Malformed(API))
+}
+
+// (local address, port, owning PID) of every UDP endpoint of the requested families.
+// Used to cross-check rows the netstat2 crate left without a PID.
+pub fn udp_owners(v4: bool, v6: bool) -> Result<Vec<(IpAddr, u16, u32)>, TableError> {
+    let mut out = Vec::new();
+    if v4 {
+        // Address and port are stored in network byte order.
+        out.extend(get_udp_owner_pid_table()?.into_iter().map(|row| {
+            (
+                Ipv4Addr::from(row.local_addr.to_ne_bytes()).into(),
+                u16::from_be(row.local_port as u16),
+                row.pid,
+            )
+        }));
     }
-    let table: *const MIB_TCPTABLE_OWNER_PID = buf.as_ptr() as *const _;
-    if table.is_null() {
-        return None;
+    if v6 {
+        out.extend(get_udp6_owner_pid_table()?.into_iter().map(|row| {
+            (
+                Ipv6Addr::from(row.local_addr).into(),
+                u16::from_be(row.local_port as u16),
+                row.pid,
+            )
+        }));
     }
-    let num = unsafe { (*table).dwNumEntries } as usize;
-    let first_row = unsafe { &(*table).table as *const MIB_TCPROW_OWNER_PID };
-    let slice = unsafe { std::slice::from_raw_parts(first_row, num) };
-    Some(slice.to_vec())
+    Ok(out)
 }
 
-#[allow(dead_code)]
-unsafe fn owner_to_row(row: &MIB_TCPROW_OWNER_PID) -> MIB_TCPROW_LH {
-    let mut r: MIB_TCPROW_LH = unsafe { std::mem::zeroed() };
-    // MIB_TCPROW_LH has an anonymous union for State in windows-sys
-    r.Anonymous.State = row.dwState as i32;
-    r.dwLocalAddr = row.dwLocalAddr;
-    r.dwLocalPort = row.dwLocalPort;
-    r.dwRemoteAddr = row.dwRemoteAddr;
-    r.dwRemotePort = row.dwRemotePort;
-    r
+// Scope ID (interface index) of each local IPv6 address in the TCP table; the rendered
+// addresses drop it.
+pub fn ipv6_scopes() -> Result<HashMap<Ipv6Addr, u32>, TableError> {
+    Ok(get_tcp6_owner_pid_table()?
+        .into_iter()
+        .filter(|row| row.local_scope_id != 0)
+        .map(|row| (Ipv6Addr::from(row.local_addr), row.local_scope_id))
+        .collect())
+}
+
+// How long the service list is reused before asking the service control manager again.
+const SERVICES_TTL: Duration = Duration::from_secs(5);
+// ERROR_MORE_DATA
+const ERROR_MORE_DATA: u32 = 234;
+
+// A NUL-terminated UTF-16 string from an API buffer.
+unsafe fn wide_str(p: *const u16) -> String {
+    if p.is_null() {
+        return String::new();
+    }
+    let mut len = 0;
+    // SAFETY: the caller passes a NUL-terminated string.
+    while unsafe { *p.add(len) } != 0 {
+        len += 1;
+    }
+    String::from_utf16_lossy(unsafe { std::slice::from_raw_parts(p, len) })
 }
 
-pub fn sample_per_process_tcp_estats(interval: Duration) -> HashMap<u32, (f64, f64)> {
-    // Returns pid -> (rx_rate_bps, tx_rate_bps)
-    // Strategy: sum per-PID throughput counters at T0 and T1, compute deltas/second.
+// Running Win32 services by hosting process, from EnumServicesStatusExW.
+fn services_by_pid() -> Result<HashMap<u32, Vec<String>>, TableError> {
+    use windows_sys::Win32::Foundation::GetLastError;
+    use windows_sys::Win32::System::Services::{
+        CloseServiceHandle, ENUM_SERVICE_STATUS_PROCESSW, EnumServicesStatusExW, OpenSCManagerW,
+        SC_ENUM_PROCESS_INFO, SC_MANAGER_ENUMERATE_SERVICE, SERVICE_ACTIVE, SERVICE_WIN32,
+    };
+    const API: &str = "EnumServicesStatusExW";
+
+    crate::forensic::record("advapi32!EnumServicesStatusExW (services per process)");
+    // SAFETY: the manager handle is closed before returning. The buffer is u64-aligned
+    // for the ENUM_SERVICE_STATUS_PROCESSW records, which the API writes at its start
+    // (their strings point further into the same buffer); only `returned` records are
+    // read, each call resuming after the previous one's.
     unsafe {
-        let rows = match get_tcp_owner_pid_table() {
-            Some(v) => v,
-            None => return HashMap::new(),
-        };
-        let mut base_pid: HashMap<u32, (u64, u64)> = HashMap::new();
-        for row in &rows {
-            let mut lwrow = owner_to_row(row);
-            // Try enabling collection; if it fails, skip this connection to avoid bogus deltas.
-            let rw = windows_sys::Win32::NetworkManagement::IpHelper::TCP_ESTATS_DATA_RW_v0 {
-                EnableCollection: 1,
-            };
-            let set_res = SetPerTcpConnectionEStats(
-                &mut lwrow as *mut MIB_TCPROW_LH,
-                TcpConnectionEstatsData as TCP_ESTATS_TYPE,
-                &rw as *const _ as Puchar,
-                0,
-                size_of::<windows_sys::Win32::NetworkManagement::IpHelper::TCP_ESTATS_DATA_RW_v0>()
-                    as Ulong,
-                0,
+        let scm = OpenSCManagerW(
+            std::ptr::null(),
+            std::ptr::null(),
+            SC_MANAGER_ENUMERATE_SERVICE,
+        );
+        if scm == 0 {
+            return Err(TableError::Api("OpenSCManagerW", GetLastError()));
+        }
+        let mut out: HashMap<u32, Vec<String>> = HashMap::new();
+        let mut buf: Vec<u64> = vec![0; 8 * 1024];
+        let mut resume = 0u32;
+        let result = loop {
+            let (mut needed, mut returned) = (0u32, 0u32);
+            let ok = EnumServicesStatusExW(
+                scm,
+                SC_ENUM_PROCESS_INFO,
+                SERVICE_WIN32,
+                SERVICE_ACTIVE,
+                buf.as_mut_ptr() as *mut u8,
+                (buf.len() * 8) as u32,
+                &mut needed,
+                &mut returned,
+                &mut resume,
+                std::ptr::null(),
             );
-            if set_res != 0 { continue; }
-
-            let mut rod: TCP_ESTATS_DATA_ROD_v0 = std::mem::zeroed();
-            let res = GetPerTcpConnectionEStats(
-                &mut lwrow as *mut MIB_TCPROW_LH,
-                TcpConnectionEstatsData as TCP_ESTATS_TYPE,
-                std::ptr::null_mut(),
-                0,
-                0,
-                std::ptr::null_mut(),
-                0,
-                0,
-                &mut rod as *mut _ as Puchar,
-                0,
-                size_of::<TCP_ESTATS_DATA_ROD_v0>() as Ulong,
+            let error = if ok == 0 { GetLastError() } else { 0 };
+            if ok == 0 && error != ERROR_MORE_DATA {
+                break Err(TableError::Api(API, error));
+            }
+            let records = std::slice::from_raw_parts(
+                buf.as_ptr() as *const ENUM_SERVICE_STATUS_PROCESSW,
+                returned as usize,
             );
-            if res == 0 {
-                let pid = row.dwOwningPid;
-                let e = base_pid.entry(pid).or_insert((0, 0));
-                e.0 = e.0.saturating_add(rod.ThruBytesReceived as u64);
-                e.1 = e.1.saturating_add(rod.ThruBytesAcked as u64);
+            for r in records {
+                let pid = r.ServiceStatusProcess.dwProcessId;
+                if pid != 0 {
+                    out.entry(pid).or_default().push(wide_str(r.lpServiceName));
+                }
+            }
+            if ok != 0 {
+                break Ok(());
             }
+            // Not even one record fit: grow to what the next one needs.
+            if returned == 0 {
+                buf = vec![0; (needed as usize).div_ceil(8).max(buf.len() * 2)];
+            }
+        };
+        CloseServiceHandle(scm);
+        result?;
+        for names in out.values_mut() {
+            names.sort_by_key(|n| n.to_ascii_lowercase());
         }
+        Ok(out)
+    }
+}
 
-        let elapsed = if interval.is_zero() { Duration::from_millis(1) } else { interval };
-        thread::sleep(elapsed);
+// Names of the services running in `pid` (several for a shared svchost.exe, none for
+// ordinary processes), for the PROCESS column. The list is cached for a few seconds
+// and is empty when the service control manager can't be queried.
+struct ServiceCache {
+    at: Instant,
+    by_pid: HashMap<u32, Vec<String>>,
+}
 
-        let rows_after = match get_tcp_owner_pid_table() {
-            Some(v) => v,
-            None => return HashMap::new(),
-        };
-        let secs = elapsed.as_secs_f64().max(0.001);
-        let mut now_pid: HashMap<u32, (u64, u64)> = HashMap::new();
-        for row in &rows_after {
-            let mut lwrow = owner_to_row(row);
-            let mut rod: TCP_ESTATS_DATA_ROD_v0 = std::mem::zeroed();
-            let res = GetPerTcpConnectionEStats(
-                &mut lwrow as *mut MIB_TCPROW_LH,
-                TcpConnectionEstatsData as TCP_ESTATS_TYPE,
-                std::ptr::null_mut(),
-                0,
-                0,
-                std::ptr::null_mut(),
-                0,
-                0,
-                &mut rod as *mut _ as Puchar,
-                0,
-                size_of::<TCP_ESTATS_DATA_ROD_v0>() as Ulong,
+pub fn services_in(pid: u32) -> Vec<String> {
+    static CACHE: Mutex<Option<ServiceCache>> = Mutex::new(None);
+    let Ok(mut cache) = CACHE.lock() else {
+        return Vec::new();
+    };
+    if cache
+        .as_ref()
+        .is_none_or(|c| c.at.elapsed() >= SERVICES_TTL)
+    {
+        let by_pid = services_by_pid().unwrap_or_else(|e| {
+            crate::warn::warn(
+                "services_unavailable",
+                format!("{}; service names not shown", e),
             );
-            if res == 0 {
-                let pid = row.dwOwningPid;
-                let e = now_pid.entry(pid).or_insert((0, 0));
-                e.0 = e.0.saturating_add(rod.ThruBytesReceived as u64);
-                e.1 = e.1.saturating_add(rod.ThruBytesAcked as u64);
+            HashMap::new()
+        });
+        *cache = Some(ServiceCache {
+            at: Instant::now(),
+            by_pid,
+        });
+    }
+    cache
+        .as_ref()
+        .and_then(|c| c.by_pid.get(&pid).cloned())
+        .unwrap_or_default()
+}
+
+unsafe fn owner_to_row(row: &TcpOwnerRow) -> MIB_TCPROW_LH {
+    let mut r: MIB_TCPROW_LH = unsafe { std::mem::zeroed() };
+    // MIB_TCPROW_LH has an anonymous union for State in windows-sys
+    r.Anonymous.State = row.state as i32;
+    r.dwLocalAddr = row.local_addr;
+    r.dwLocalPort = row.local_port;
+    r.dwRemoteAddr = row.remote_addr;
+    r.dwRemotePort = row.remote_port;
+    r
+}
+
+// Closes one IPv4 TCP connection (SetTcpEntry with MIB_TCP_STATE_DELETE_TCB); needs an
+// elevated token. Windows has no IPv6 counterpart. Returns the API's error code.
+pub fn close_tcp(local: SocketAddrV4, remote: SocketAddrV4) -> Result<(), u32> {
+    let mut r: MIB_TCPROW_LH = unsafe { std::mem::zeroed() };
+    r.Anonymous.State = MIB_TCP_STATE_DELETE_TCB;
+    // Addresses and ports in network byte order, as in the owner tables.
+    r.dwLocalAddr = u32::from_ne_bytes(local.ip().octets());
+    r.dwLocalPort = local.port().to_be() as u32;
+    r.dwRemoteAddr = u32::from_ne_bytes(remote.ip().octets());
+    r.dwRemotePort = remote.port().to_be() as u32;
+    crate::forensic::record_change("iphlpapi!SetTcpEntry (close connection)");
+    match unsafe { SetTcpEntry(&r) } {
+        0 => Ok(()),
+        e => Err(e),
+    }
+}
+
+unsafe fn owner6_to_row(row: &Tcp6OwnerRow) -> MIB_TCP6ROW {
+    let mut r: MIB_TCP6ROW = unsafe { std::mem::zeroed() };
+    r.State = row.state as i32;
+    r.LocalAddr.u.Byte = row.local_addr;
+    r.dwLocalScopeId = row.local_scope_id;
+    r.dwLocalPort = row.local_port;
+    r.RemoteAddr.u.Byte = row.remote_addr;
+    r.dwRemoteScopeId = row.remote_scope_id;
+    r.dwRemotePort = row.remote_port;
+    r
+}
+
+// A connection in the form the EStats calls take: the IPv4 and IPv6 variants of each
+// call differ only in the row type.
+#[derive(Clone, Copy)]
+enum EStatsRow {
+    V4(MIB_TCPROW_LH),
+    V6(MIB_TCP6ROW),
+}
+
+// One TCP connection from the owner tables.
+struct Conn {
+    row: EStatsRow,
+    pid: u32,
+    key: ConnKey,
+}
+
+// The TCP connections of the requested families.
+fn tcp_connections(ipv4: bool, ipv6: bool) -> Result<Vec<Conn>, TableError> {
+    let mut conns = Vec::new();
+    if ipv4 {
+        for row in get_tcp_owner_pid_table()? {
+            conns.push(Conn {
+                row: EStatsRow::V4(unsafe { owner_to_row(&row) }),
+                pid: row.pid,
+                key: row.addrs(),
+            });
+        }
+    }
+    if ipv6 {
+        for row in get_tcp6_owner_pid_table()? {
+            conns.push(Conn {
+                row: EStatsRow::V6(unsafe { owner6_to_row(&row) }),
+                pid: row.pid,
+                key: row.addrs(),
+            });
+        }
+    }
+    Ok(conns)
+}
+
+// GetPerTcp(6)ConnectionEStats for TcpConnectionEstatsData, reading the RW and/or
+// ROD structure (a null pointer skips one).
+unsafe fn get_estats(
+    api: &EStatsApi,
+    row: &EStatsRow,
+    rw: Puchar,
+    rw_size: usize,
+    rod: Puchar,
+    rod_size: usize,
+) -> u32 {
+    let kind = TcpConnectionEstatsData as TCP_ESTATS_TYPE;
+    let null = std::ptr::null_mut();
+    let (rw_size, rod_size) = (rw_size as Ulong, rod_size as Ulong);
+    unsafe {
+        match row {
+            EStatsRow::V4(r) => {
+                crate::forensic::record("iphlpapi!GetPerTcpConnectionEStats");
+                (api.get)(r, kind, rw, 0, rw_size, null, 0, 0, rod, 0, rod_size)
             }
+            EStatsRow::V6(r) => {
+                crate::forensic::record("iphlpapi!GetPerTcp6ConnectionEStats");
+                (api.get6)(r, kind, rw, 0, rw_size, null, 0, 0, rod, 0, rod_size)
+            }
+        }
+    }
+}
+
+// Whether EStats data collection is already on for the connection.
+unsafe fn collection_enabled(api: &EStatsApi, row: &EStatsRow) -> bool {
+    let mut rw = TCP_ESTATS_DATA_RW_v0 {
+        EnableCollection: 0,
+    };
+    let res = unsafe {
+        get_estats(
+            api,
+            row,
+            &mut rw as *mut _ as Puchar,
+            size_of::<TCP_ESTATS_DATA_RW_v0>(),
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+    res == 0 && rw.EnableCollection != 0
+}
+
+// Switches EStats data collection on or off; returns the API's error code.
+unsafe fn set_collection(api: &EStatsApi, row: &EStatsRow, on: bool) -> u32 {
+    let rw = TCP_ESTATS_DATA_RW_v0 {
+        EnableCollection: on as u8,
+    };
+    let kind = TcpConnectionEstatsData as TCP_ESTATS_TYPE;
+    let rw_ptr = &rw as *const _ as *const u8;
+    let size = size_of::<TCP_ESTATS_DATA_RW_v0>() as Ulong;
+    unsafe {
+        match row {
+            EStatsRow::V4(r) => {
+                crate::forensic::record_change("iphlpapi!SetPerTcpConnectionEStats");
+                (api.set)(r, kind, rw_ptr, 0, size, 0)
+            }
+            EStatsRow::V6(r) => {
+                crate::forensic::record_change("iphlpapi!SetPerTcp6ConnectionEStats");
+                (api.set6)(r, kind, rw_ptr, 0, size, 0)
+            }
+        }
+    }
+}
+
+// Cumulative (received, acked) bytes of one connection; None when EStats data is not
+// available for it.
+unsafe fn read_counters(api: &EStatsApi, row: &EStatsRow) -> Option<(u64, u64)> {
+    let mut rod: TCP_ESTATS_DATA_ROD_v0 = unsafe { std::mem::zeroed() };
+    let res = unsafe {
+        get_estats(
+            api,
+            row,
+            std::ptr::null_mut(),
+            0,
+            &mut rod as *mut _ as Puchar,
+            size_of::<TCP_ESTATS_DATA_ROD_v0>(),
+        )
+    };
+    (res == 0).then_some((rod.ThruBytesReceived, rod.ThruBytesAcked))
+}
+
+pub fn sample_tcp_estats(
+    interval: Duration,
+    enable_collection: bool,
+    ipv4: bool,
+    ipv6: bool,
+) -> Result<TcpRates, TableError> {
+    // Strategy: read each connection's throughput counters at T0 and T1 and divide the
+    // deltas by the elapsed time. Only connections present at both reads count, so one
+    // opened in between doesn't add its whole history to the window.
+    // With `enable_collection` false (forensic mode) EStats collection is never switched
+    // on, so only connections that already have it enabled report counters.
+    let Some(api) = crate::win_api::estats() else {
+        return Err(TableError::Unavailable(
+            "iphlpapi!GetPerTcpConnectionEStats",
+        ));
+    };
+    unsafe {
+        let conns = tcp_connections(ipv4, ipv6)?;
+        let mut base: HashMap<ConnKey, (u32, u64, u64)> = HashMap::new();
+        let mut denied: u64 = 0;
+        // Connections we switched collection on for; switched off again at the end.
+        let mut enabled: Vec<EStatsRow> = Vec::new();
+        for conn in &conns {
+            if crate::interrupt::should_stop() {
+                break;
+            }
+            if enable_collection && !collection_enabled(api, &conn.row) {
+                // Try enabling collection; if it fails, skip this connection to avoid bogus deltas.
+                if set_collection(api, &conn.row, true) != 0 {
+                    denied += 1;
+                    continue;
+                }
+                enabled.push(conn.row);
+            }
+            if let Some((rx, tx)) = read_counters(api, &conn.row) {
+                base.insert(conn.key.clone(), (conn.pid, rx, tx));
+            }
+        }
+
+        crate::warn::warn_count(
+            "estats_denied",
+            denied,
+            "TCP connections refused EStats collection (run elevated for Rx/Tx)",
+        );
+
+        let started = Instant::now();
+        crate::interrupt::sleep(if interval.is_zero() {
+            Duration::from_millis(1)
+        } else {
+            interval
+        });
+
+        let conns_after = tcp_connections(ipv4, ipv6);
+        let secs = started.elapsed().as_secs_f64().max(0.001);
+        // Read the counters before switching collection off again; leave EStats as we
+        // found it, also when the second table read fails.
+        let mut now: HashMap<ConnKey, (u64, u64)> = HashMap::new();
+        if let Ok(conns_after) = &conns_after {
+            for conn in conns_after {
+                if let Some(counters) = read_counters(api, &conn.row) {
+                    now.insert(conn.key.clone(), counters);
+                }
+            }
+        }
+        for row in &enabled {
+            set_collection(api, row, false);
         }
+        conns_after?;
 
-        let mut per_pid: HashMap<u32, (f64, f64)> = HashMap::new();
-        for (pid, (b_rx, b_tx)) in base_pid.into_iter() {
-            if let Some((n_rx, n_tx)) = now_pid.get(&pid).copied() {
+        let mut rates = TcpRates::default();
+        for (key, (pid, b_rx, b_tx)) in base {
+            if let Some(&(n_rx, n_tx)) = now.get(&key) {
                 let rx = n_rx.saturating_sub(b_rx) as f64 / secs;
                 let tx = n_tx.saturating_sub(b_tx) as f64 / secs;
-                per_pid.insert(pid, (rx, tx));
+                let sum = rates.per_pid.entry(pid).or_insert((0.0, 0.0));
+                sum.0 += rx;
+                sum.1 += tx;
+                rates.per_conn.insert(key, (rx, tx));
             }
         }
-        per_pid
+        Ok(rates)
     }
 }
@@ -0,0 +1,347 @@
+// Safe parsing of the raw buffers filled by GetExtendedTcpTable/GetExtendedUdpTable
+// (IPv4 and IPv6).
+//
+// The buffer is only trusted as far as the size the API reported: the entry
+// count in the header is checked against it before any row is read, and rows are
+// decoded field by field so the (byte-aligned) Vec never has to be reinterpreted
+// as a Win32 struct. Kept free of windows-sys types so it can be unit tested on
+// any platform.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+const DWORD: usize = 4;
+// MIB_TCPTABLE_OWNER_PID: DWORD dwNumEntries, then MIB_TCPROW_OWNER_PID rows of
+// six DWORDs (state, local addr, local port, remote addr, remote port, pid).
+const TCP_OWNER_ROW_SIZE: usize = 6 * DWORD;
+// MIB_TCP6TABLE_OWNER_PID: DWORD dwNumEntries, then MIB_TCP6ROW_OWNER_PID rows: local
+// address (16 bytes), scope ID, port, remote address (16 bytes), scope ID, port,
+// state, pid.
+const TCP6_OWNER_ROW_SIZE: usize = 16 + 2 * DWORD + 16 + 4 * DWORD;
+// MIB_UDPTABLE_OWNER_PID: DWORD dwNumEntries, then MIB_UDPROW_OWNER_PID rows of
+// three DWORDs (local addr, local port, pid).
+const UDP_OWNER_ROW_SIZE: usize = 3 * DWORD;
+// MIB_UDP6TABLE_OWNER_PID: DWORD dwNumEntries, then MIB_UDP6ROW_OWNER_PID rows: local
+// address (16 bytes), scope ID, port, pid.
+const UDP6_OWNER_ROW_SIZE: usize = 16 + 3 * DWORD;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TcpOwnerRow {
+    pub state: u32,
+    pub local_addr: u32,
+    pub local_port: u32,
+    pub remote_addr: u32,
+    pub remote_port: u32,
+    pub pid: u32,
+}
+
+impl TcpOwnerRow {
+    // ("addr:port", "addr:port") as rendered in the LOCAL/REMOTE ADDRESS columns, so a
+    // row can be matched to its SocketEntry. Addresses and ports are stored in network
+    // byte order.
+    pub fn addrs(&self) -> (String, String) {
+        let render = |addr: u32, port: u32| {
+            crate::addr_string(
+                Ipv4Addr::from(addr.to_ne_bytes()).into(),
+                u16::from_be(port as u16),
+            )
+        };
+        (
+            render(self.local_addr, self.local_port),
+            render(self.remote_addr, self.remote_port),
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tcp6OwnerRow {
+    pub local_addr: [u8; 16],
+    pub local_scope_id: u32,
+    pub local_port: u32,
+    pub remote_addr: [u8; 16],
+    pub remote_scope_id: u32,
+    pub remote_port: u32,
+    pub state: u32,
+    pub pid: u32,
+}
+
+impl Tcp6OwnerRow {
+    // As `TcpOwnerRow::addrs`; the scope ID is not part of the rendered address.
+    pub fn addrs(&self) -> (String, String) {
+        let render = |addr: [u8; 16], port: u32| {
+            crate::addr_string(Ipv6Addr::from(addr).into(), u16::from_be(port as u16))
+        };
+        (
+            render(self.local_addr, self.local_port),
+            render(self.remote_addr, self.remote_port),
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UdpOwnerRow {
+    pub local_addr: u32,
+    pub local_port: u32,
+    pub pid: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Udp6OwnerRow {
+    pub local_addr: [u8; 16],
+    pub local_scope_id: u32,
+    pub local_port: u32,
+    pub pid: u32,
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> Option<u32> {
+    let bytes = buf.get(offset..offset.checked_add(DWORD)?)?;
+    Some(u32::from_ne_bytes(bytes.try_into().ok()?))
+}
+
+// Validates the header of an owner table and returns the bytes of its `num` rows.
+// `returned_size` is the byte count reported by the API; anything past it (or past
+// the end of `buf`) is ignored. Returns None when the header claims more rows than fit.
+fn table_rows(buf: &[u8], returned_size: usize, row_size: usize) -> Option<(usize, &[u8])> {
+    let valid = &buf[..returned_size.min(buf.len())];
+    let num = read_u32(valid, 0)? as usize;
+    let needed = num.checked_mul(row_size)?.checked_add(DWORD)?;
+    if needed > valid.len() {
+        return None;
+    }
+    Some((num, &valid[DWORD..needed]))
+}
+
+pub fn parse_tcp_owner_pid_table(buf: &[u8], returned_size: usize) -> Option<Vec<TcpOwnerRow>> {
+    let (num, rows_buf) = table_rows(buf, returned_size, TCP_OWNER_ROW_SIZE)?;
+    let mut rows = Vec::with_capacity(num);
+    for i in 0..num {
+        let base = i * TCP_OWNER_ROW_SIZE;
+        let field = |n: usize| read_u32(rows_buf, base + n * DWORD);
+        rows.push(TcpOwnerRow {
+            state: field(0)?,
+            local_addr: field(1)?,
+            local_port: field(2)?,
+            remote_addr: field(3)?,
+            remote_port: field(4)?,
+            pid: field(5)?,
+        });
+    }
+    Some(rows)
+}
+
+pub fn parse_tcp6_owner_pid_table(buf: &[u8], returned_size: usize) -> Option<Vec<Tcp6OwnerRow>> {
+    let (num, rows_buf) = table_rows(buf, returned_size, TCP6_OWNER_ROW_SIZE)?;
+    let mut rows = Vec::with_capacity(num);
+    for i in 0..num {
+        let base = i * TCP6_OWNER_ROW_SIZE;
+        let addr = |offset: usize| -> Option<[u8; 16]> {
+            rows_buf
+                .get(base + offset..base + offset + 16)?
+                .try_into()
+                .ok()
+        };
+        let field = |offset: usize| read_u32(rows_buf, base + offset);
+        rows.push(Tcp6OwnerRow {
+            local_addr: addr(0)?,
+            local_scope_id: field(16)?,
+            local_port: field(20)?,
+            remote_addr: addr(24)?,
+            remote_scope_id: field(40)?,
+            remote_port: field(44)?,
+            state: field(48)?,
+            pid: field(52)?,
+        });
+    }
+    Some(rows)
+}
+
+pub fn parse_udp_owner_pid_table(buf: &[u8], returned_size: usize) -> Option<Vec<UdpOwnerRow>> {
+    let (num, rows_buf) = table_rows(buf, returned_size, UDP_OWNER_ROW_SIZE)?;
+    let mut rows = Vec::with_capacity(num);
+    for i in 0..num {
+        let base = i * UDP_OWNER_ROW_SIZE;
+        let field = |n: usize| read_u32(rows_buf, base + n * DWORD);
+        rows.push(UdpOwnerRow {
+            local_addr: field(0)?,
+            local_port: field(1)?,
+            pid: field(2)?,
+        });
+    }
+    Some(rows)
+}
+
+pub fn parse_udp6_owner_pid_table(buf: &[u8], returned_size: usize) -> Option<Vec<Udp6OwnerRow>> {
+    let (num, rows_buf) = table_rows(buf, returned_size, UDP6_OWNER_ROW_SIZE)?;
+    let mut rows = Vec::with_capacity(num);
+    for i in 0..num {
+        let base = i * UDP6_OWNER_ROW_SIZE;
+        let field = |offset: usize| read_u32(rows_buf, base + offset);
+        rows.push(Udp6OwnerRow {
+            local_addr: rows_buf.get(base..base + 16)?.try_into().ok()?,
+            local_scope_id: field(16)?,
+            local_port: field(20)?,
+            pid: field(24)?,
+        });
+    }
+    Some(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table<const N: usize>(num: u32, rows: &[[u32; N]]) -> Vec<u8> {
+        let mut buf = num.to_ne_bytes().to_vec();
+        for row in rows {
+            for v in row {
+                buf.extend_from_slice(&v.to_ne_bytes());
+            }
+        }
+        buf
+    }
+
+    #[test]
+    fn parse_tcp_owner_pid_table_valid_buffer_returns_rows() {
+        let buf = table(2, &[[2, 1, 80, 0, 0, 4], [5, 2, 443, 3, 5000, 1234]]);
+        let rows = parse_tcp_owner_pid_table(&buf, buf.len()).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].local_port, 80);
+        assert_eq!(rows[0].pid, 4);
+        assert_eq!(
+            rows[1],
+            TcpOwnerRow {
+                state: 5,
+                local_addr: 2,
+                local_port: 443,
+                remote_addr: 3,
+                remote_port: 5000,
+                pid: 1234,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_tcp_owner_pid_table_zero_entries_returns_empty() {
+        let buf = table::<6>(0, &[]);
+        assert_eq!(parse_tcp_owner_pid_table(&buf, buf.len()), Some(Vec::new()));
+    }
+
+    #[test]
+    fn parse_tcp_owner_pid_table_count_exceeds_buffer_returns_none() {
+        let buf = table(3, &[[2, 1, 80, 0, 0, 4], [2, 1, 81, 0, 0, 4]]);
+        assert_eq!(parse_tcp_owner_pid_table(&buf, buf.len()), None);
+    }
+
+    #[test]
+    fn parse_tcp_owner_pid_table_count_exceeds_returned_size_returns_none() {
+        // Buffer is large enough, but the API only reported one row's worth of data.
+        let buf = table(2, &[[2, 1, 80, 0, 0, 4], [2, 1, 81, 0, 0, 4]]);
+        let returned = DWORD + TCP_OWNER_ROW_SIZE;
+        assert_eq!(parse_tcp_owner_pid_table(&buf, returned), None);
+    }
+
+    #[test]
+    fn parse_tcp_owner_pid_table_huge_count_returns_none() {
+        let buf = table(u32::MAX, &[[2, 1, 80, 0, 0, 4]]);
+        assert_eq!(parse_tcp_owner_pid_table(&buf, buf.len()), None);
+    }
+
+    #[test]
+    fn parse_tcp_owner_pid_table_short_header_returns_none() {
+        assert_eq!(parse_tcp_owner_pid_table(&[1, 0], 2), None);
+        assert_eq!(parse_tcp_owner_pid_table(&[], 0), None);
+    }
+
+    #[test]
+    fn tcp_owner_row_addrs_match_rendered_columns() {
+        let row = TcpOwnerRow {
+            state: 5,
+            local_addr: u32::from_ne_bytes([10, 0, 0, 5]),
+            local_port: 51234u16.to_be() as u32,
+            remote_addr: u32::from_ne_bytes([203, 0, 113, 7]),
+            remote_port: 443u16.to_be() as u32,
+            pid: 42,
+        };
+        assert_eq!(
+            row.addrs(),
+            ("10.0.0.5:51234".to_string(), "203.0.113.7:443".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_tcp6_owner_pid_table_reads_addresses_and_ports() {
+        let local: Ipv6Addr = "2001:db8::5".parse().unwrap();
+        let remote: Ipv6Addr = "2001:db8::7".parse().unwrap();
+        let mut buf = 1u32.to_ne_bytes().to_vec();
+        buf.extend_from_slice(&local.octets());
+        for v in [0u32, 51234u16.to_be() as u32] {
+            buf.extend_from_slice(&v.to_ne_bytes());
+        }
+        buf.extend_from_slice(&remote.octets());
+        for v in [3u32, 443u16.to_be() as u32, 5, 42] {
+            buf.extend_from_slice(&v.to_ne_bytes());
+        }
+        let rows = parse_tcp6_owner_pid_table(&buf, buf.len()).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(
+            (rows[0].remote_scope_id, rows[0].state, rows[0].pid),
+            (3, 5, 42)
+        );
+        assert_eq!(
+            rows[0].addrs(),
+            (
+                "[2001:db8::5]:51234".to_string(),
+                "[2001:db8::7]:443".to_string()
+            )
+        );
+        // One byte short of the row the header claims.
+        assert_eq!(parse_tcp6_owner_pid_table(&buf, buf.len() - 1), None);
+    }
+
+    #[test]
+    fn parse_udp_owner_pid_table_valid_buffer_returns_rows() {
+        let buf = table(2, &[[1, 53, 900], [0, 5353, 1200]]);
+        let rows = parse_udp_owner_pid_table(&buf, buf.len()).unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                UdpOwnerRow {
+                    local_addr: 1,
+                    local_port: 53,
+                    pid: 900,
+                },
+                UdpOwnerRow {
+                    local_addr: 0,
+                    local_port: 5353,
+                    pid: 1200,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_udp_owner_pid_table_count_exceeds_buffer_returns_none() {
+        let buf = table(2, &[[1, 53, 900]]);
+        assert_eq!(parse_udp_owner_pid_table(&buf, buf.len()), None);
+    }
+
+    #[test]
+    fn parse_udp6_owner_pid_table_reads_addresses_and_ports() {
+        let local: Ipv6Addr = "fe80::1".parse().unwrap();
+        let mut buf = 1u32.to_ne_bytes().to_vec();
+        buf.extend_from_slice(&local.octets());
+        for v in [4u32, 5353u16.to_be() as u32, 1200] {
+            buf.extend_from_slice(&v.to_ne_bytes());
+        }
+        let rows = parse_udp6_owner_pid_table(&buf, buf.len()).unwrap();
+        assert_eq!(
+            rows,
+            vec![Udp6OwnerRow {
+                local_addr: local.octets(),
+                local_scope_id: 4,
+                local_port: 5353u16.to_be() as u32,
+                pid: 1200,
+            }]
+        );
+        assert_eq!(parse_udp6_owner_pid_table(&buf, buf.len() - 1), None);
+    }
+}
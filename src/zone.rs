@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV6};
+
+use crate::{SocketEntry, join_addr, parse_addr_port};
+
+// IPv6 zone IDs. A link-local address (fe80::/10) only means something together with
+// the interface it is on, so the address columns show it the way `ping`/`ip` take it:
+// `[fe80::1%eth0]:22` on Unix (interface name) and `[fe80::1%12]:22` on Windows
+// (interface index). The socket tables netstat2 reads drop the scope, so it is looked up here;
+// everything that parses an address column goes through `split`/`parse_ip` so the
+// zone doesn't break the IP parse.
+
+// "fe80::1%eth0" -> ("fe80::1", Some("eth0")).
+pub fn split(ip: &str) -> (&str, Option<&str>) {
+    match ip.split_once('%') {
+        Some((ip, zone)) => (ip, Some(zone)),
+        None => (ip, None),
+    }
+}
+
+// The IP of an address column's host part, zone or not.
+pub fn parse_ip(ip: &str) -> Option<IpAddr> {
+    split(ip).0.parse().ok()
+}
+
+// An `addr:port` column value as a socket address, with the zone as scope ID (an
+// interface index, or a name looked up on Unix) so link-local peers can be reached.
+pub fn socket_addr(rendered: &str) -> Option<SocketAddr> {
+    let (host, port) = parse_addr_port(rendered);
+    let (ip, zone) = split(host);
+    match ip.parse::<IpAddr>().ok()? {
+        IpAddr::V6(v6) => {
+            let scope = zone.and_then(scope_id).unwrap_or(0);
+            Some(SocketAddr::V6(SocketAddrV6::new(v6, port, 0, scope)))
+        }
+        v4 => Some(SocketAddr::new(v4, port)),
+    }
+}
+
+// The address column without its zone, as the kernel-side tables key connections.
+pub fn strip(rendered: &str) -> String {
+    let (host, port) = parse_addr_port(rendered);
+    match split(host) {
+        (ip, Some(_)) => join_addr(ip, port),
+        (_, None) => rendered.to_string(),
+    }
+}
+
+fn scope_id(zone: &str) -> Option<u32> {
+    if let Ok(index) = zone.parse() {
+        return Some(index);
+    }
+    #[cfg(unix)]
+    {
+        let name = std::ffi::CString::new(zone).ok()?;
+        let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+        (index != 0).then_some(index)
+    }
+    #[cfg(not(unix))]
+    None
+}
+
+fn link_local(host: &str) -> Option<Ipv6Addr> {
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V6(ip)) if ip.is_unicast_link_local() => Some(ip),
+        _ => None,
+    }
+}
+
+// Link-local addresses of this host's interfaces and their zones.
+#[cfg(unix)]
+fn zones() -> HashMap<Ipv6Addr, String> {
+    crate::forensic::record("libc getifaddrs (IPv6 interface zones)");
+    let mut out = HashMap::new();
+    let mut list: *mut libc::ifaddrs = std::ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut list) } != 0 {
+        return out;
+    }
+    let mut cur = list;
+    while !cur.is_null() {
+        let ifa = unsafe { &*cur };
+        cur = ifa.ifa_next;
+        if ifa.ifa_addr.is_null() || unsafe { (*ifa.ifa_addr).sa_family } as i32 != libc::AF_INET6 {
+            continue;
+        }
+        let sin6 = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_in6) };
+        let ip = Ipv6Addr::from(sin6.sin6_addr.s6_addr);
+        if ip.is_unicast_link_local() {
+            let name = unsafe { std::ffi::CStr::from_ptr(ifa.ifa_name) };
+            out.insert(ip, name.to_string_lossy().into_owned());
+        }
+    }
+    unsafe { libc::freeifaddrs(list) };
+    out
+}
+
+// Windows: the scope IDs (interface indexes) of the TCP table's IPv6 rows.
+#[cfg(windows)]
+fn zones() -> HashMap<Ipv6Addr, String> {
+    match crate::win_net::ipv6_scopes() {
+        Ok(scopes) => scopes
+            .into_iter()
+            .filter(|(ip, _)| ip.is_unicast_link_local())
+            .map(|(ip, scope)| (ip, scope.to_string()))
+            .collect(),
+        Err(e) => {
+            crate::warn::warn("ipv6_zones", format!("{}; IPv6 zones not shown", e));
+            HashMap::new()
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn zones() -> HashMap<Ipv6Addr, String> {
+    HashMap::new()
+}
+
+// Appends `%zone` to link-local hosts. The remote end of a connection is on the same
+// link as the local one, so it takes the local address's zone.
+fn add_zones(entries: &mut [SocketEntry], zones: &HashMap<Ipv6Addr, String>) {
+    for e in entries.iter_mut() {
+        let (local, port) = parse_addr_port(&e.local_addr);
+        let Some(zone) = link_local(local).and_then(|ip| zones.get(&ip)) else {
+            continue;
+        };
+        e.local_addr = join_addr(&format!("{}%{}", local, zone), port);
+        let (remote, port) = parse_addr_port(&e.remote_addr);
+        if link_local(remote).is_some() {
+            e.remote_addr = join_addr(&format!("{}%{}", remote, zone), port);
+        }
+    }
+}
+
+// Adds zones to the link-local rows, if there are any.
+pub fn apply(entries: &mut [SocketEntry]) {
+    if entries
+        .iter()
+        .any(|e| link_local(parse_addr_port(&e.local_addr).0).is_some())
+    {
+        add_zones(entries, &zones());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zones_are_added_and_parsed_back() {
+        let mut rows = [
+            SocketEntry {
+                local_addr: "[fe80::1]:22".to_string(),
+                remote_addr: "[fe80::2]:51000".to_string(),
+                ..Default::default()
+            },
+            SocketEntry {
+                local_addr: "[2001:db8::1]:443".to_string(),
+                remote_addr: "[fe80::2]:51000".to_string(),
+                ..Default::default()
+            },
+        ];
+        let zones = HashMap::from([("fe80::1".parse().unwrap(), "eth0".to_string())]);
+        add_zones(&mut rows, &zones);
+        assert_eq!(rows[0].local_addr, "[fe80::1%eth0]:22");
+        assert_eq!(rows[0].remote_addr, "[fe80::2%eth0]:51000");
+        assert_eq!(rows[1].local_addr, "[2001:db8::1]:443");
+
+        assert_eq!(parse_addr_port(&rows[0].local_addr), ("fe80::1%eth0", 22));
+        assert_eq!(parse_ip("fe80::1%eth0"), "fe80::1".parse().ok());
+        assert_eq!(strip(&rows[0].remote_addr), "[fe80::2]:51000");
+        assert_eq!(
+            socket_addr("[fe80::2%7]:51000"),
+            Some(SocketAddr::V6(SocketAddrV6::new(
+                "fe80::2".parse().unwrap(),
+                51000,
+                0,
+                7
+            )))
+        );
+    }
+}